@@ -0,0 +1,146 @@
+//! Benchmarks for merging and filtering
+//!
+//! Run with `cargo bench`. See the README for the performance targets.
+
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BenchmarkId;
+use criterion::Criterion;
+use criterion::Throughput;
+use ini_merge::filter::filter_ini_from_str;
+use ini_merge::filter::FilterAction;
+use ini_merge::filter::FilterActionsBuilder;
+use ini_merge::merge::merge_ini_from_str;
+use ini_merge::merge::mutations::transforms::TransformKdeShortcut;
+use ini_merge::merge::mutations::transforms::TransformUnsortedLists;
+use ini_merge::merge::mutations::Action;
+use ini_merge::merge::mutations::Mutations;
+use ini_merge::merge::mutations::MutationsBuilder;
+use ini_merge::merge::mutations::SectionAction;
+use std::fmt::Write;
+use std::hint::black_box;
+
+/// Generate a document with `sections` sections of `keys` keys each.
+///
+/// `variant` changes some of the values, so that source and target differ.
+fn generate(sections: usize, keys: usize, variant: usize) -> String {
+    let mut doc = String::new();
+    for section in 0..sections {
+        // Writing to a String can not fail
+        let _ = writeln!(doc, "[Section {section}]");
+        for key in 0..keys {
+            let value = if (section + key) % 7 == variant % 7 {
+                "changed"
+            } else {
+                "value"
+            };
+            let _ = writeln!(doc, "key_{key}={value},{section},{key}");
+        }
+        doc.push('\n');
+    }
+    doc
+}
+
+/// A document the size of a typical KDE `kdeglobals` (about 300 lines)
+fn kdeglobals(variant: usize) -> String {
+    generate(20, 14, variant)
+}
+
+/// A big game config (about 50k lines)
+fn game_config(variant: usize) -> String {
+    generate(500, 99, variant)
+}
+
+/// A small rule set, similar to what is used for KDE files
+fn kde_rules() -> Mutations {
+    let mut builder = MutationsBuilder::new();
+    builder
+        .add_section_action("Section 3", SectionAction::Ignore)
+        .add_section_action("Section 5", SectionAction::Delete);
+    builder.add_literal_action("Section 1", "key_1", Action::Ignore);
+    builder.add_regex_action("Section 2", "key_1.*", Action::Delete);
+    builder.add_regex_action(
+        "Section 4",
+        "key_.*",
+        Action::Transform(TransformKdeShortcut.into()),
+    );
+    builder.add_regex_action(
+        "Section 6",
+        "key_.*",
+        Action::Transform(TransformUnsortedLists::new(',').into()),
+    );
+    builder.add_setter("Section 7", "forced", "1", "=");
+    builder.build().expect("Failed to build rules")
+}
+
+/// Many rules, either all literal or all regex
+fn many_rules(count: usize, regex: bool) -> Mutations {
+    let mut builder = MutationsBuilder::new();
+    builder.warn_on_multiple_matches(false);
+    for idx in 0..count {
+        let section = format!("Section {}", idx % 500);
+        let key = format!("key_{}", idx % 99);
+        if regex {
+            builder.add_regex_action(format!("^{section}$"), format!("^{key}$"), Action::Ignore);
+        } else {
+            builder.add_literal_action(section, key, Action::Ignore);
+        }
+    }
+    builder.build().expect("Failed to build rules")
+}
+
+fn bench_merge(c: &mut Criterion) {
+    let mut group = c.benchmark_group("merge");
+    let rules = kde_rules();
+    for (name, target, source) in [
+        ("kdeglobals", kdeglobals(0), kdeglobals(1)),
+        ("game_config", game_config(0), game_config(1)),
+    ] {
+        group.throughput(Throughput::Bytes(target.len() as u64));
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                merge_ini_from_str(black_box(&target), black_box(&source), &rules)
+                    .expect("Merge failed")
+            });
+        });
+    }
+    group.finish();
+}
+
+fn bench_rules(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rules");
+    let target = game_config(0);
+    let source = game_config(1);
+    group.throughput(Throughput::Bytes(target.len() as u64));
+    for count in [10, 1000] {
+        for (kind, regex) in [("literal", false), ("regex", true)] {
+            let rules = many_rules(count, regex);
+            group.bench_with_input(BenchmarkId::new(kind, count), &rules, |b, rules| {
+                b.iter(|| {
+                    merge_ini_from_str(black_box(&target), black_box(&source), rules)
+                        .expect("Merge failed")
+                });
+            });
+        }
+    }
+    group.finish();
+}
+
+fn bench_filter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("filter");
+    let input = game_config(0);
+    let mut builder = FilterActionsBuilder::new();
+    builder
+        .add_section_action("Section 3", FilterAction::Remove)
+        .add_literal_action("Section 1", "key_1", FilterAction::Replace("HIDDEN".into()))
+        .add_regex_action("Section 2.*", "key_1.*", FilterAction::Remove);
+    let actions = builder.build().expect("Failed to build filter");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.bench_function("game_config", |b| {
+        b.iter(|| filter_ini_from_str(black_box(&input), &actions).expect("Filter failed"));
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_merge, bench_rules, bench_filter);
+criterion_main!(benches);
@@ -1,9 +1,24 @@
 //! Action matching framework for INI processing
 
+use crate::dialect::Dialect;
+use crate::limits::Limits;
+use crate::lint::LintFinding;
+use crate::lint::LintKind;
+use crate::lint::RuleLabel;
+#[cfg(not(feature = "regex-rules"))]
+use crate::no_regex::RegexSet;
+use crate::SectionName;
 use log::warn;
+#[cfg(feature = "regex-rules")]
 use regex::RegexSet;
+#[cfg(feature = "regex-rules")]
+use regex::RegexSetBuilder;
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::fmt::Display;
+use std::mem::size_of;
+use std::sync::PoisonError;
+use std::sync::RwLock;
 use thiserror::Error;
 
 /// Handles matching on INI lines and mapping the matches to generic actions
@@ -12,15 +27,112 @@ use thiserror::Error;
 pub struct Actions<Action, SectionAction> {
     /// Actions for whole sections.
     section_actions: HashMap<String, SectionAction>,
-    /// Literal matches and associated actions on (section, key)
-    literal_actions: HashMap<String, Action>,
+    /// Actions for sections by prefix of the section name
+    section_prefix_actions: HashMap<String, SectionAction>,
+    /// Distinct lengths of the keys in `section_prefix_actions`, longest first
+    section_prefix_lengths: Vec<usize>,
+    /// Regex matches on whole sections
+    section_regex_matches: RegexSet,
+    /// Associated actions for section regex matches
+    section_regex_actions: Vec<SectionAction>,
+    /// Action for sections without any other section action
+    default_section_action: Option<SectionAction>,
+    /// Literal matches and associated actions, by section and then key
+    literal_actions: HashMap<String, HashMap<String, Action>>,
     /// Regex matches on (section, key)
     /// We use the null byte as a separator between the key and value here.
     regex_matches: RegexSet,
     /// Associated actions for regex matches
     regex_actions: Vec<Action>,
+    /// Section and key patterns of each regex match (for linting)
+    regex_labels: Vec<(String, String)>,
+    /// Regex matches on the section, for raw line matches
+    raw_section_matches: RegexSet,
+    /// Regex matches on the raw line (separate from the section, so that the
+    /// line regex can be anchored)
+    raw_line_matches: RegexSet,
+    /// Associated actions for raw line matches
+    raw_line_actions: Vec<Action>,
+    /// Action for lines that fail to parse
+    parse_error_action: Option<Action>,
+    /// Literal actions that were replaced by later rules for the same section
+    /// and key (for linting)
+    replaced_literals: Vec<(String, Action)>,
     /// Warn on multiple matches (default: true)
     warn_on_multiple_matches: bool,
+    /// Cache of regex match results keyed on section and key. Only used when
+    /// enabled.
+    regex_cache: Option<RwLock<HashMap<String, RegexMatch>>>,
+    /// Resource limits for input processed with these actions
+    limits: Limits,
+    /// Dialect of input processed with these actions
+    dialect: Dialect,
+    /// Remove trailing whitespace from output lines
+    trim_trailing_whitespace: bool,
+    /// Collapse runs of blank lines in the output
+    squash_blank_lines: bool,
+    /// Remove the comments directly before removed sections
+    remove_section_comments: bool,
+    /// If regex rules can match keys before the first section header
+    regexes_match_top_level: bool,
+    /// Callback for keys without any other action
+    classifier: Option<Classifier<Action>>,
+}
+
+/// Lists the rules one per line, in a stable order: section rules first,
+/// then key rules (literal rules sorted, regex rules in the order they were
+/// added)
+impl<Action: Display, SectionAction: Display> Display for Actions<Action, SectionAction> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(ref action) = self.default_section_action {
+            writeln!(f, "default section: {action}")?;
+        }
+        let mut sections: Vec<_> = self.section_actions.iter().collect();
+        sections.sort_unstable_by_key(|(section, _)| *section);
+        for (section, action) in sections {
+            writeln!(f, "section {section:?}: {action}")?;
+        }
+        let mut prefixes: Vec<_> = self.section_prefix_actions.iter().collect();
+        prefixes.sort_unstable_by_key(|(prefix, _)| *prefix);
+        for (prefix, action) in prefixes {
+            writeln!(f, "section prefix {prefix:?}: {action}")?;
+        }
+        for (section, action) in self
+            .section_regex_matches
+            .patterns()
+            .iter()
+            .zip(&self.section_regex_actions)
+        {
+            writeln!(f, "section regex {section:?}: {action}")?;
+        }
+        let mut literals: Vec<_> = self
+            .literal_actions
+            .iter()
+            .flat_map(|(section, keys)| {
+                keys.iter().map(move |(key, action)| (section, key, action))
+            })
+            .collect();
+        literals.sort_unstable_by_key(|(section, key, _)| (*section, *key));
+        for (section, key, action) in literals {
+            writeln!(f, "{section:?} {key:?}: {action}")?;
+        }
+        for ((section, key), action) in self.regex_labels.iter().zip(&self.regex_actions) {
+            writeln!(f, "regex {section:?} {key:?}: {action}")?;
+        }
+        for ((section, line), action) in self
+            .raw_section_matches
+            .patterns()
+            .iter()
+            .zip(self.raw_line_matches.patterns())
+            .zip(&self.raw_line_actions)
+        {
+            writeln!(f, "raw line {section:?} {line:?}: {action}")?;
+        }
+        if let Some(ref action) = self.parse_error_action {
+            writeln!(f, "parse error: {action}")?;
+        }
+        Ok(())
+    }
 }
 
 impl<Action, SectionAction> Actions<Action, SectionAction> {
@@ -30,12 +142,178 @@ impl<Action, SectionAction> Actions<Action, SectionAction> {
         ActionsBuilder::<Action, SectionAction>::new()
     }
 
+    /// Counts and approximate memory usage of the rules
+    #[must_use]
+    pub fn stats(&self) -> ActionsStats {
+        fn map_bytes<V>(map: &HashMap<String, V>) -> usize {
+            map.capacity() * size_of::<(String, V)>()
+                + map.keys().map(String::capacity).sum::<usize>()
+        }
+        let pattern_bytes = |set: &RegexSet| set.patterns().iter().map(String::len).sum::<usize>();
+        ActionsStats {
+            section_literals: self.section_actions.len(),
+            section_prefixes: self.section_prefix_actions.len(),
+            section_regexes: self.section_regex_actions.len(),
+            literals: self.literal_actions.values().map(HashMap::len).sum(),
+            regexes: self.regex_actions.len(),
+            raw_lines: self.raw_line_actions.len(),
+            literal_bytes: map_bytes(&self.section_actions)
+                + map_bytes(&self.section_prefix_actions)
+                + map_bytes(&self.literal_actions)
+                + self.literal_actions.values().map(map_bytes).sum::<usize>(),
+            regex_pattern_bytes: pattern_bytes(&self.section_regex_matches)
+                + pattern_bytes(&self.regex_matches)
+                + pattern_bytes(&self.raw_section_matches)
+                + pattern_bytes(&self.raw_line_matches),
+        }
+    }
+
     /// Lookup if there is a section action for the whole section
     pub(crate) fn find_section_action(&self, section: &str) -> Option<&SectionAction> {
-        self.section_actions.get(section)
+        if let Some(action) = self.section_actions.get(section) {
+            return Some(action);
+        }
+        let name = match_name(section);
+        for len in &self.section_prefix_lengths {
+            let prefix = name.get(..*len);
+            if let Some(action) = prefix.and_then(|prefix| self.section_prefix_actions.get(prefix))
+            {
+                return Some(action);
+            }
+        }
+        if !self.section_regex_actions.is_empty() && self.regexes_apply_to(section) {
+            if let Some(idx) = self.section_regex_matches.matches(name).iter().next() {
+                return self.section_regex_actions.get(idx);
+            }
+        }
+        self.default_section_action.as_ref()
+    }
+
+    /// If regex rules apply to the section
+    fn regexes_apply_to(&self, section: &str) -> bool {
+        self.regexes_match_top_level || section != crate::OUTSIDE_SECTION
+    }
+
+    /// If there is a warning on multiple matches
+    pub(crate) const fn warns_on_multiple_matches(&self) -> bool {
+        self.warn_on_multiple_matches
+    }
+
+    /// If regex rules can match the keys before the first section header
+    pub(crate) const fn regexes_match_top_level(&self) -> bool {
+        self.regexes_match_top_level
+    }
+
+    /// Resource limits for input processed with these actions
+    pub(crate) const fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Dialect of input processed with these actions
+    pub(crate) const fn dialect(&self) -> &Dialect {
+        &self.dialect
+    }
+
+    /// If runs of blank lines in the output should be collapsed
+    pub(crate) const fn squashes_blank_lines(&self) -> bool {
+        self.squash_blank_lines
+    }
+
+    /// If the comments directly before removed sections should be removed
+    pub(crate) const fn removes_section_comments(&self) -> bool {
+        self.remove_section_comments
+    }
+
+    /// If trailing whitespace should be removed from output lines
+    pub(crate) const fn trims_trailing_whitespace(&self) -> bool {
+        self.trim_trailing_whitespace && !self.dialect.has_significant_trailing_whitespace()
+    }
+
+    /// Remember regex match results between lookups.
+    ///
+    /// Useful when the same rules are applied to similar files many times.
+    /// The cache is cleared when it reaches [`REGEX_CACHE_CAPACITY`] entries.
+    pub(crate) fn enable_regex_cache(&mut self) {
+        if self.regex_cache.is_none() {
+            self.regex_cache = Some(RwLock::default());
+        }
+    }
+
+    /// Lookup if there is an action matching the raw line
+    pub(crate) fn find_raw_line_action(&self, section: &str, raw: &str) -> Option<&Action> {
+        if self.raw_line_actions.is_empty() || !self.regexes_apply_to(section) {
+            return None;
+        }
+        let sections = self.raw_section_matches.matches(match_name(section));
+        if !sections.matched_any() {
+            return None;
+        }
+        self.raw_line_matches
+            .matches(raw)
+            .iter()
+            .find(|idx| sections.matched(*idx))
+            .and_then(|idx| self.raw_line_actions.get(idx))
+    }
+
+    /// Lookup the action for a line that failed to parse
+    pub(crate) fn find_parse_error_action(&self, section: &str, raw: &str) -> Option<&Action> {
+        self.find_raw_line_action(section, raw)
+            .or(self.parse_error_action.as_ref())
+    }
+
+    /// The literal action for a section and key, if any
+    pub(crate) fn literal_action(&self, section: &str, key: &str) -> Option<&Action> {
+        self.literal_actions.get(section)?.get(key)
+    }
+
+    /// Match the regex rules against a section and key (joined with a null
+    /// byte)
+    fn find_regex_match(&self, sec_key: &str) -> RegexMatch {
+        let re_matches = self.regex_matches.matches(sec_key);
+        let mut matches = re_matches.iter();
+        RegexMatch {
+            first: matches.next(),
+            overlapping: matches.next().is_some(),
+        }
+    }
+
+    /// Like [`Self::find_regex_match`], but looking in the cache first
+    fn cached_regex_match(
+        &self,
+        cache: &RwLock<HashMap<String, RegexMatch>>,
+        sec_key: String,
+    ) -> RegexMatch {
+        let cached = cache
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&sec_key)
+            .copied();
+        if let Some(re_match) = cached {
+            return re_match;
+        }
+        let re_match = self.find_regex_match(&sec_key);
+        let mut cache = cache.write().unwrap_or_else(PoisonError::into_inner);
+        if cache.len() >= REGEX_CACHE_CAPACITY {
+            cache.clear();
+        }
+        cache.insert(sec_key, re_match);
+        re_match
     }
 }
 
+/// Maximum number of entries in the regex cache (see
+/// [`Actions::enable_regex_cache`])
+const REGEX_CACHE_CAPACITY: usize = 4096;
+
+/// Result of matching the regex rules against a section and key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RegexMatch {
+    /// Index of the first matching rule (into `regex_actions`)
+    first: Option<usize>,
+    /// If more than one rule matched
+    overlapping: bool,
+}
+
 impl<Action, SectionAction> Actions<Action, SectionAction>
 where
     for<'a> Action: From<&'a SectionAction> + From<SectionAction> + Clone,
@@ -51,29 +329,176 @@ where
         if let Some(sec_act) = self.find_section_action(section) {
             return Some(Cow::Owned(sec_act.into()));
         }
-        // Then literal actions
-        let sec_key = section.to_string() + "\0" + key;
-        if let Some(act) = self.literal_actions.get(sec_key.as_str()) {
+        self.find_key_action(section, key)
+    }
+
+    /// Lookup the action for a line, where matches on the raw line have
+    /// priority and the classifier is used as a fallback
+    pub(crate) fn find_line_action<'this>(
+        &'this self,
+        section: &str,
+        key: &str,
+        val: Option<&str>,
+        raw: &str,
+    ) -> Option<Cow<'this, Action>> {
+        if let Some(action) = self.find_raw_line_action(section, raw) {
+            return Some(Cow::Borrowed(action));
+        }
+        self.find_action(section, key).or_else(|| {
+            let classifier = self.classifier.as_ref()?;
+            (classifier.0)(match_name(section), key, val).map(Cow::Owned)
+        })
+    }
+
+    /// Lookup if there is an action for a specific section and key, not
+    /// considering actions for the whole section
+    pub(crate) fn find_key_action<'this>(
+        &'this self,
+        section: &str,
+        key: &str,
+    ) -> Option<Cow<'this, Action>> {
+        // Literal actions first
+        if let Some(act) = self.literal_action(section, key) {
             return Some(Cow::Borrowed(act));
         }
+        // Fast path: Skip building the joined string when there are no regexes
+        if self.regex_actions.is_empty() || !self.regexes_apply_to(section) {
+            return None;
+        }
         // Finally regex matches
-        let re_matches = self.regex_matches.matches(sec_key.as_str());
-        if re_matches.matched_any() {
-            let matches: Vec<_> = re_matches.iter().collect();
-            if matches.len() != 1 && self.warn_on_multiple_matches {
-                warn!(target: "ini-merge",
-                      "Overlapping regex matches for {section}/{key}, first action taken. If this is intentional add the no-warn-multiple-key-matches directive");
+        let section = match_name(section);
+        let sec_key = section.to_string() + "\0" + key;
+        let re_match = match self.regex_cache {
+            Some(ref cache) => self.cached_regex_match(cache, sec_key),
+            None => self.find_regex_match(&sec_key),
+        };
+        // Warn on every lookup, also when the match was cached
+        if re_match.overlapping && self.warn_on_multiple_matches {
+            warn!(target: "ini-merge",
+                  "Overlapping regex matches for {section}/{key}, first action taken. If this is intentional add the no-warn-multiple-key-matches directive");
+        }
+        let index = re_match.first?;
+        Some(Cow::Borrowed(self.regex_actions.get(index).expect(
+            "Impossible: At least one action exists for each match",
+        )))
+    }
+}
+
+impl<Action: PartialEq, SectionAction> Actions<Action, SectionAction> {
+    /// Check the rules for common mistakes
+    #[must_use]
+    pub fn lint(&self) -> Vec<LintFinding> {
+        let mut findings = Vec::new();
+        #[cfg(feature = "regex-rules")]
+        for (index, pattern) in self.regex_matches.patterns().iter().enumerate() {
+            let never_matches = regex_syntax::parse(pattern)
+                .is_ok_and(|hir| hir.properties().minimum_len().is_none());
+            if never_matches {
+                findings.push(LintFinding::new(
+                    LintKind::RegexNeverMatches,
+                    self.regex_label(index),
+                    None,
+                ));
             }
-            let m = matches
-                .first()
-                .expect("Impossible: At least one match exists");
-            return Some(Cow::Borrowed(
-                self.regex_actions
-                    .get(*m)
-                    .expect("Impossible: At least one action exists for each match"),
-            ));
         }
-        None
+        let mut literals: Vec<_> = self
+            .literal_actions
+            .iter()
+            .flat_map(|(section, keys)| {
+                keys.iter()
+                    .map(move |(key, action)| (section.as_str(), key.as_str(), action))
+            })
+            .collect();
+        literals.sort_unstable_by_key(|(section, key, _)| (*section, *key));
+        for (section, key, action) in literals {
+            let label = literal_label(section, key);
+            if self.find_section_action(section).is_some() {
+                findings.push(LintFinding::new(
+                    LintKind::ShadowedBySection,
+                    label,
+                    Some(RuleLabel::Section(match_name(section).into())),
+                ));
+                continue;
+            }
+            if !self.regexes_apply_to(section) {
+                continue;
+            }
+            let sec_key = match_name(section).to_string() + "\0" + key;
+            if let Some(index) = self
+                .regex_matches
+                .matches(&sec_key)
+                .iter()
+                .find(|idx| self.regex_actions[*idx] != *action)
+            {
+                findings.push(LintFinding::new(
+                    LintKind::OverridesRegex,
+                    label,
+                    Some(self.regex_label(index)),
+                ));
+            }
+        }
+        for (sec_key, old) in &self.replaced_literals {
+            let (section, key) = sec_key.split_once('\0').unwrap_or((sec_key, ""));
+            if self.literal_action(section, key) != Some(old) {
+                let label = literal_label(section, key);
+                findings.push(LintFinding::new(
+                    LintKind::ConflictingDuplicate,
+                    label.clone(),
+                    Some(label),
+                ));
+            }
+        }
+        findings
+    }
+
+    /// Literal actions for a section and key that were replaced by later
+    /// rules
+    pub(crate) fn replaced_literal_actions<'this>(
+        &'this self,
+        section: &str,
+        key: &str,
+    ) -> impl Iterator<Item = &'this Action> + 'this {
+        let sec_key = section.to_string() + "\0" + key;
+        self.replaced_literals
+            .iter()
+            .filter(move |(k, _)| *k == sec_key)
+            .map(|(_, action)| action)
+    }
+
+    fn regex_label(&self, index: usize) -> RuleLabel {
+        let (section, key) = &self.regex_labels[index];
+        RuleLabel::Regex {
+            index,
+            section: section.clone(),
+            key: key.clone(),
+        }
+    }
+}
+
+/// The name a section (given by its key, see [`SectionName::key`]) is
+/// matched as by prefix and regex rules
+fn match_name(section: &str) -> &str {
+    SectionName::from_key(section).as_str()
+}
+
+/// Label of a literal rule for a section (given by its key) and key
+fn literal_label(section: &str, key: &str) -> RuleLabel {
+    RuleLabel::Literal {
+        section: match_name(section).into(),
+        key: key.into(),
+    }
+}
+
+/// Callback for keys without any other action, see
+/// [`ActionsBuilder::classifier`]
+struct Classifier<Action>(Box<ClassifierFn<Action>>);
+
+/// Signature of [`Classifier`] callbacks
+type ClassifierFn<Action> = dyn Fn(&str, &str, Option<&str>) -> Option<Action> + Send + Sync;
+
+impl<Action> std::fmt::Debug for Classifier<Action> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Classifier")
     }
 }
 
@@ -81,11 +506,35 @@ where
 #[derive(Debug)]
 pub struct ActionsBuilder<Action, SectionAction> {
     section_actions: HashMap<String, SectionAction>,
+    section_prefix_actions: HashMap<String, SectionAction>,
+    section_regex_matches: Vec<String>,
+    section_regex_actions: Vec<SectionAction>,
+    default_section_action: Option<SectionAction>,
     literal_actions: HashMap<String, Action>,
     regex_matches: Vec<String>,
     regex_actions: Vec<Action>,
+    regex_labels: Vec<(String, String)>,
+    raw_section_matches: Vec<String>,
+    raw_line_matches: Vec<String>,
+    raw_line_actions: Vec<Action>,
+    parse_error_action: Option<Action>,
+    replaced_literals: Vec<(String, Action)>,
     /// Warn on multiple matches (default: true)
     warn_on_multiple_matches: bool,
+    /// Resource limits
+    limits: Limits,
+    /// Dialect of the input
+    dialect: Dialect,
+    /// Remove trailing whitespace from output lines
+    trim_trailing_whitespace: bool,
+    /// Collapse runs of blank lines in the output
+    squash_blank_lines: bool,
+    /// Remove the comments directly before removed sections
+    remove_section_comments: bool,
+    /// If regex rules can match keys before the first section header
+    regexes_match_top_level: bool,
+    /// Callback for keys without any other action
+    classifier: Option<Classifier<Action>>,
 }
 
 impl<Action, SectionAction> Default for ActionsBuilder<Action, SectionAction> {
@@ -95,15 +544,37 @@ impl<Action, SectionAction> Default for ActionsBuilder<Action, SectionAction> {
 }
 
 impl<Action, SectionAction> ActionsBuilder<Action, SectionAction> {
+    /// Section name to use for keys before the first section header.
+    ///
+    /// Same as [`crate::OUTSIDE_SECTION`].
+    pub const TOP_LEVEL: &'static str = crate::OUTSIDE_SECTION;
+
     /// Create a new builder
     #[must_use]
     pub fn new() -> Self {
         Self {
             section_actions: Default::default(),
+            section_prefix_actions: Default::default(),
+            section_regex_matches: Default::default(),
+            section_regex_actions: Default::default(),
+            default_section_action: None,
             literal_actions: Default::default(),
             regex_matches: Default::default(),
             regex_actions: Default::default(),
+            regex_labels: Default::default(),
+            raw_section_matches: Default::default(),
+            raw_line_matches: Default::default(),
+            raw_line_actions: Default::default(),
+            parse_error_action: None,
+            replaced_literals: Default::default(),
             warn_on_multiple_matches: true,
+            limits: Limits::new(),
+            dialect: Dialect::new(),
+            trim_trailing_whitespace: false,
+            squash_blank_lines: false,
+            remove_section_comments: false,
+            regexes_match_top_level: true,
+            classifier: None,
         }
     }
 
@@ -117,6 +588,41 @@ impl<Action, SectionAction> ActionsBuilder<Action, SectionAction> {
         self
     }
 
+    /// Add an action for all sections starting with a prefix.
+    ///
+    /// Exact section actions take precedence, and the longest matching prefix
+    /// is used. Prefix actions take precedence over section regexes, and are
+    /// cheap to look up even with many prefixes.
+    pub fn add_section_prefix_action(
+        &mut self,
+        prefix: impl Into<String>,
+        action: SectionAction,
+    ) -> &mut Self {
+        self.section_prefix_actions.insert(prefix.into(), action);
+        self
+    }
+
+    /// Add an action for all sections matching a regex.
+    ///
+    /// Exact section actions take precedence. This is checked once per
+    /// section and key, before any per key matching.
+    pub fn add_section_regex_action(
+        &mut self,
+        section: impl Into<String>,
+        action: SectionAction,
+    ) -> &mut Self {
+        self.section_regex_matches.push(section.into());
+        self.section_regex_actions.push(action);
+        self
+    }
+
+    /// Set the action for all sections without any other section action
+    /// (including the top level)
+    pub fn default_section_action(&mut self, action: SectionAction) -> &mut Self {
+        self.default_section_action = Some(action);
+        self
+    }
+
     /// Add an action for an exact match of section and key
     pub fn add_literal_action(
         &mut self,
@@ -124,11 +630,22 @@ impl<Action, SectionAction> ActionsBuilder<Action, SectionAction> {
         key: impl AsRef<str>,
         action: Action,
     ) -> &mut Self {
-        self.literal_actions
-            .insert(section.into() + "\0" + key.as_ref(), action);
+        let sec_key = section.into() + "\0" + key.as_ref();
+        if let Some(old) = self.literal_actions.insert(sec_key.clone(), action) {
+            self.replaced_literals.push((sec_key, old));
+        }
         self
     }
 
+    /// Add an action for a key before the first section header (exact
+    /// match)
+    ///
+    /// Same as [`Self::add_literal_action`] with [`Self::TOP_LEVEL`] as the
+    /// section.
+    pub fn add_top_level_action(&mut self, key: impl AsRef<str>, action: Action) -> &mut Self {
+        self.add_literal_action(Self::TOP_LEVEL, key, action)
+    }
+
     /// Add an action for a regex match of a section and key
     pub fn add_regex_action(
         &mut self,
@@ -144,30 +661,333 @@ impl<Action, SectionAction> ActionsBuilder<Action, SectionAction> {
         ) {
             this.regex_actions.push(action);
             this.regex_matches.push(format!("(?:{section})\0(?:{key})"));
+            this.regex_labels.push((section.into(), key.into()));
         }
         inner(self, section.as_ref(), key.as_ref(), action);
         self
     }
 
+    /// Add an action for a regex match of a section and the whole raw line
+    ///
+    /// This applies to lines that are parsed as keys as well as lines that
+    /// fail to parse, and takes priority over section and key matches. If
+    /// several raw line regexes match, the first one added is used.
+    pub fn add_raw_line_action(
+        &mut self,
+        section: impl AsRef<str>,
+        line: impl AsRef<str>,
+        action: Action,
+    ) -> &mut Self {
+        self.raw_section_matches.push(section.as_ref().into());
+        self.raw_line_matches.push(line.as_ref().into());
+        self.raw_line_actions.push(action);
+        self
+    }
+
+    /// Set the action for lines that fail to parse (without an action from
+    /// [`Self::add_raw_line_action`])
+    pub fn on_parse_error(&mut self, action: Action) -> &mut Self {
+        self.parse_error_action = Some(action);
+        self
+    }
+
     /// Set if there should be a warning on multiple matches
     pub fn warn_on_multiple_matches(&mut self, warn: bool) -> &mut Self {
         self.warn_on_multiple_matches = warn;
         self
     }
 
+    /// Set resource limits, both for the rules and for the input processed
+    /// with them.
+    pub fn limits(&mut self, limits: Limits) -> &mut Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Set the dialect of the input processed with the actions
+    pub fn dialect(&mut self, dialect: Dialect) -> &mut Self {
+        self.dialect = dialect;
+        self
+    }
+
+    /// Set if trailing whitespace should be removed from output lines
+    /// (default: false)
+    ///
+    /// Not done if trailing whitespace is significant in the dialect, see
+    /// [`Dialect::significant_trailing_whitespace`].
+    pub fn trim_trailing_whitespace(&mut self, value: bool) -> &mut Self {
+        self.trim_trailing_whitespace = value;
+        self
+    }
+
+    /// Set if runs of blank lines in the output should be collapsed into a
+    /// single blank line (default: false)
+    pub fn squash_blank_lines(&mut self, value: bool) -> &mut Self {
+        self.squash_blank_lines = value;
+        self
+    }
+
+    /// Set if the comment block directly before the header of a removed
+    /// section (including blank lines between the comments and the header)
+    /// should be removed with the section (default: false)
+    pub fn remove_section_comments(&mut self, value: bool) -> &mut Self {
+        self.remove_section_comments = value;
+        self
+    }
+
+    /// Set if the section regexes of regex rules (section, key and raw line
+    /// regexes) can match the keys before the first section header (default:
+    /// true)
+    ///
+    /// Those keys are matched with the section name [`Self::TOP_LEVEL`], so
+    /// for example `.*` matches them. Exact rules and the default section
+    /// action are not affected.
+    pub fn regexes_match_top_level(&mut self, value: bool) -> &mut Self {
+        self.regexes_match_top_level = value;
+        self
+    }
+
+    /// Set a callback that decides the action for keys that no rule matched,
+    /// given the section, key and value
+    ///
+    /// Only used when filtering. This allows dynamic policies (such as
+    /// detecting tokens by their format) without listing all keys.
+    pub fn classifier(
+        &mut self,
+        classifier: impl Fn(&str, &str, Option<&str>) -> Option<Action> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.classifier = Some(Classifier(Box::new(classifier)));
+        self
+    }
+
     /// Build the [Actions] struct
     ///
-    /// Errors if a regex fails to compile.
+    /// Errors if a regex fails to compile (or exceeds the regex size limit).
     pub fn build(self) -> Result<Actions<Action, SectionAction>, ActionsBuilderError> {
+        let mut section_prefix_lengths: Vec<_> = self
+            .section_prefix_actions
+            .keys()
+            .map(String::len)
+            .collect();
+        section_prefix_lengths.sort_unstable_by(|a, b| b.cmp(a));
+        section_prefix_lengths.dedup();
         Ok(Actions {
             section_actions: self.section_actions,
-            literal_actions: self.literal_actions,
-            regex_matches: RegexSet::new(self.regex_matches)
-                .map_err(|e| ActionsBuilderError::RegexCompile(Box::new(e)))?,
+            section_prefix_actions: self.section_prefix_actions,
+            section_prefix_lengths,
+            section_regex_matches: compile_regex_set(&self.section_regex_matches, &self.limits)?,
+            section_regex_actions: self.section_regex_actions,
+            default_section_action: self.default_section_action,
+            literal_actions: self.literal_actions.into_iter().fold(
+                HashMap::new(),
+                |mut acc: HashMap<String, HashMap<String, Action>>, (sec_key, action)| {
+                    let (section, key) = sec_key.split_once('\0').unwrap_or((&sec_key, ""));
+                    acc.entry(section.into())
+                        .or_default()
+                        .insert(key.into(), action);
+                    acc
+                },
+            ),
+            regex_matches: compile_regex_set(&self.regex_matches, &self.limits)?,
             regex_actions: self.regex_actions,
+            regex_labels: self.regex_labels,
+            raw_section_matches: compile_regex_set(&self.raw_section_matches, &self.limits)?,
+            raw_line_matches: compile_regex_set(&self.raw_line_matches, &self.limits)?,
+            raw_line_actions: self.raw_line_actions,
+            parse_error_action: self.parse_error_action,
+            replaced_literals: self.replaced_literals,
             warn_on_multiple_matches: self.warn_on_multiple_matches,
+            regex_cache: None,
+            limits: self.limits,
+            dialect: self.dialect,
+            trim_trailing_whitespace: self.trim_trailing_whitespace,
+            squash_blank_lines: self.squash_blank_lines,
+            remove_section_comments: self.remove_section_comments,
+            regexes_match_top_level: self.regexes_match_top_level,
+            classifier: self.classifier,
         })
     }
+
+    /// Add a rule in its data form
+    pub fn add_rule(&mut self, rule: Rule<Action, SectionAction>) -> &mut Self {
+        match rule {
+            Rule::Section { section, action } => self.add_section_action(section, action),
+            Rule::SectionRegex { section, action } => {
+                self.add_section_regex_action(section, action)
+            }
+            Rule::SectionPrefix { prefix, action } => {
+                self.add_section_prefix_action(prefix, action)
+            }
+            Rule::DefaultSection { action } => self.default_section_action(action),
+            Rule::Literal {
+                section,
+                key,
+                action,
+            } => self.add_literal_action(section, key, action),
+            Rule::Regex {
+                section,
+                key,
+                action,
+            } => self.add_regex_action(section, key, action),
+            Rule::RawLine {
+                section,
+                line,
+                action,
+            } => self.add_raw_line_action(section, line, action),
+            Rule::ParseError { action } => self.on_parse_error(action),
+        }
+    }
+
+    /// Create a builder from a rule set in its data form (for example
+    /// deserialized with the `serde` feature)
+    #[must_use]
+    pub fn from_serialized(ruleset: RuleSet<Action, SectionAction>) -> Self {
+        let mut builder = Self::new();
+        builder.warn_on_multiple_matches(ruleset.warn_on_multiple_matches);
+        for rule in ruleset.rules {
+            builder.add_rule(rule);
+        }
+        builder
+    }
+}
+
+/// A single rule in data form, see [`ActionsBuilder::add_rule`]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum Rule<Action, SectionAction> {
+    /// See [`ActionsBuilder::add_section_action`]
+    Section {
+        section: String,
+        action: SectionAction,
+    },
+    /// See [`ActionsBuilder::add_section_prefix_action`]
+    SectionPrefix {
+        prefix: String,
+        action: SectionAction,
+    },
+    /// See [`ActionsBuilder::add_section_regex_action`]
+    SectionRegex {
+        section: String,
+        action: SectionAction,
+    },
+    /// See [`ActionsBuilder::default_section_action`]
+    DefaultSection { action: SectionAction },
+    /// See [`ActionsBuilder::add_literal_action`]
+    Literal {
+        section: String,
+        key: String,
+        action: Action,
+    },
+    /// See [`ActionsBuilder::add_regex_action`]
+    Regex {
+        section: String,
+        key: String,
+        action: Action,
+    },
+    /// See [`ActionsBuilder::add_raw_line_action`]
+    RawLine {
+        section: String,
+        line: String,
+        action: Action,
+    },
+    /// See [`ActionsBuilder::on_parse_error`]
+    ParseError { action: Action },
+}
+
+/// A rule set in data form, see [`ActionsBuilder::from_serialized`]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct RuleSet<Action, SectionAction> {
+    /// The rules, in the order they are added
+    pub rules: Vec<Rule<Action, SectionAction>>,
+    /// Warn on multiple matches (default: true)
+    #[cfg_attr(feature = "serde", serde(default = "default_true"))]
+    pub warn_on_multiple_matches: bool,
+}
+
+impl<Action, SectionAction> RuleSet<Action, SectionAction> {
+    /// Create a rule set from a list of rules
+    #[must_use]
+    pub const fn new(rules: Vec<Rule<Action, SectionAction>>) -> Self {
+        Self {
+            rules,
+            warn_on_multiple_matches: true,
+        }
+    }
+}
+
+/// Default value for boolean options that are on by default
+#[cfg(feature = "serde")]
+pub(crate) const fn default_true() -> bool {
+    true
+}
+
+/// Compile a regex set, respecting the regex limits
+#[cfg(feature = "regex-rules")]
+fn compile_regex_set(
+    patterns: &[String],
+    limits: &Limits,
+) -> Result<RegexSet, ActionsBuilderError> {
+    let compile = |size_limit: Option<usize>| {
+        let mut builder = RegexSetBuilder::new(patterns);
+        if let Some(limit) = size_limit {
+            builder.size_limit(limit);
+        }
+        builder.build()
+    };
+    let size_limit = limits.get_regex_size_limit();
+    let warn_size = limits
+        .get_regex_warn_size()
+        .filter(|size| size_limit.map_or(true, |limit| *size < limit));
+    if let Some(size) = warn_size {
+        match compile(Some(size)) {
+            Err(regex::Error::CompiledTooBig(_)) => warn!(
+                "Regex set with {} patterns is larger than {size} bytes",
+                patterns.len()
+            ),
+            result => return result.map_err(|e| ActionsBuilderError::RegexCompile(Box::new(e))),
+        }
+    }
+    compile(size_limit).map_err(|e| ActionsBuilderError::RegexCompile(Box::new(e)))
+}
+
+/// Without the `regex-rules` feature only empty sets can be built
+#[cfg(not(feature = "regex-rules"))]
+fn compile_regex_set(
+    patterns: &[String],
+    _limits: &Limits,
+) -> Result<RegexSet, ActionsBuilderError> {
+    RegexSet::new(patterns)
+}
+
+/// Counts and approximate memory usage of the rules, see [`Actions::stats`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ActionsStats {
+    /// Number of exact section matches
+    pub section_literals: usize,
+    /// Number of section prefix matches
+    pub section_prefixes: usize,
+    /// Number of section regexes
+    pub section_regexes: usize,
+    /// Number of exact section and key matches
+    pub literals: usize,
+    /// Number of section and key regexes
+    pub regexes: usize,
+    /// Number of raw line regexes (counting the section and line regex as
+    /// one)
+    pub raw_lines: usize,
+    /// Approximate heap memory used by the literal matches, in bytes
+    pub literal_bytes: usize,
+    /// Total length of all regex patterns, in bytes
+    ///
+    /// The size of the compiled regex sets is not available, but grows with
+    /// this. Use [`Limits::regex_warn_size`] to get a warning for large
+    /// compiled regex sets.
+    pub regex_pattern_bytes: usize,
 }
 
 /// Error type for [`ActionsBuilder`]
@@ -177,4 +997,39 @@ pub enum ActionsBuilderError {
     /// A regular expression failed to compile
     #[error("Failed to compile a regular expression: {0}")]
     RegexCompile(#[source] Box<dyn std::error::Error + 'static + Send + Sync>),
+    /// A regex rule was added without the `regex-rules` feature
+    #[error("Regex rules require the regex-rules feature")]
+    RegexUnsupported,
+}
+
+#[cfg(all(test, feature = "regex-rules"))]
+mod tests {
+    use super::Actions;
+    use super::RegexMatch;
+    use super::REGEX_CACHE_CAPACITY;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn regex_cache() {
+        let mut builder = Actions::<u32, u32>::builder();
+        builder.add_regex_action("s", "a.*", 1);
+        builder.add_regex_action("s", ".*b", 2);
+        let mut actions = builder.build().unwrap();
+        actions.enable_regex_cache();
+        let cache = actions.regex_cache.as_ref().unwrap();
+
+        // Overlapping matches are remembered, so that hits warn as well
+        let expected = RegexMatch {
+            first: Some(0),
+            overlapping: true,
+        };
+        for _ in 0..2 {
+            assert_eq!(actions.cached_regex_match(cache, "s\0ab".into()), expected);
+        }
+
+        for i in 0..REGEX_CACHE_CAPACITY * 2 {
+            actions.cached_regex_match(cache, format!("s\0k{i}"));
+        }
+        assert!(cache.read().unwrap().len() <= REGEX_CACHE_CAPACITY);
+    }
 }
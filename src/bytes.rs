@@ -0,0 +1,154 @@
+//! Lossless merging and filtering of files that are not valid UTF-8
+//! (requires the `bytes` feature)
+//!
+//! Some programs write configuration files with bytes that are not valid
+//! UTF-8 in comments or values. The functions in this module preserve such
+//! bytes verbatim, as long as the structure of the file can be parsed.
+//!
+//! Internally each invalid byte is mapped to a code point in the last 256
+//! code points of Unicode (in the supplementary private use area B), and
+//! mapped back after processing. Rules only see the mapped form, so they
+//! can not match names that contain invalid bytes. Resource limits apply to
+//! the mapped form.
+
+use crate::filter::filter_ini_from_str;
+use crate::filter::FilterActions;
+use crate::filter::FilterError;
+use crate::merge::merge_ini_from_str;
+use crate::merge::mutations::Mutations;
+use crate::merge::MergeError;
+use bstr::ByteSlice;
+use std::borrow::Cow;
+
+/// First code point used to represent raw bytes
+const ESCAPE_BASE: u32 = 0x10_FF00;
+
+/// Map a raw byte to the code point that represents it
+fn escape_byte(byte: u8, out: &mut String) {
+    // All code points from ESCAPE_BASE to char::MAX are valid
+    if let Some(c) = char::from_u32(ESCAPE_BASE + u32::from(byte)) {
+        out.push(c);
+    }
+}
+
+/// Convert bytes to a string, mapping invalid UTF-8 (and code points that
+/// are used for the mapping) to the code points representing the bytes
+fn escape(input: &[u8]) -> Cow<'_, str> {
+    if let Ok(s) = std::str::from_utf8(input) {
+        if !s.chars().any(|c| u32::from(c) >= ESCAPE_BASE) {
+            return Cow::Borrowed(s);
+        }
+    }
+    let mut result = String::with_capacity(input.len());
+    // Via bstr, as the MSRV predates `<[u8]>::utf8_chunks`
+    for chunk in ByteSlice::utf8_chunks(input) {
+        for c in chunk.valid().chars() {
+            if u32::from(c) >= ESCAPE_BASE {
+                let mut buf = [0; 4];
+                for &byte in c.encode_utf8(&mut buf).as_bytes() {
+                    escape_byte(byte, &mut result);
+                }
+            } else {
+                result.push(c);
+            }
+        }
+        for &byte in chunk.invalid() {
+            escape_byte(byte, &mut result);
+        }
+    }
+    Cow::Owned(result)
+}
+
+/// Convert a string produced from [`escape`]d input back to bytes
+fn unescape(line: String) -> Vec<u8> {
+    if !line.chars().any(|c| u32::from(c) >= ESCAPE_BASE) {
+        return line.into_bytes();
+    }
+    let mut result = Vec::with_capacity(line.len());
+    for c in line.chars() {
+        match u32::from(c).checked_sub(ESCAPE_BASE) {
+            // Always in range, as char::MAX - ESCAPE_BASE is 255
+            Some(byte) => result.push(byte as u8),
+            None => {
+                let mut buf = [0; 4];
+                result.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+    }
+    result
+}
+
+/// Merge two INI files that may contain invalid UTF-8, giving the merged file
+/// as a vector of byte strings, one per line.
+///
+/// Bytes that are not valid UTF-8 are preserved. See
+/// [`crate::merge::merge_ini`] for details.
+pub fn merge_ini_bytes(
+    target: &[u8],
+    source: &[u8],
+    mutations: &Mutations,
+) -> Result<Vec<Vec<u8>>, MergeError> {
+    let lines = merge_ini_from_str(&escape(target), &escape(source), mutations)?;
+    Ok(lines.into_iter().map(unescape).collect())
+}
+
+/// Filter an INI file that may contain invalid UTF-8, giving the filtered
+/// file as a vector of byte strings, one per line.
+///
+/// Bytes that are not valid UTF-8 are preserved. See
+/// [`crate::filter::filter_ini`] for details.
+pub fn filter_ini_bytes(
+    input: &[u8],
+    actions: &FilterActions,
+) -> Result<Vec<Vec<u8>>, FilterError> {
+    let lines = filter_ini_from_str(&escape(input), actions)?;
+    Ok(lines.into_iter().map(unescape).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::escape;
+    use super::unescape;
+    use crate::filter::FilterAction;
+    use crate::filter::FilterActionsBuilder;
+    use crate::merge::mutations::Action;
+    use crate::merge::mutations::MutationsBuilder;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn roundtrip() {
+        let inputs: [&[u8]; 5] = [
+            b"plain",
+            b"a\xffb\xc3",
+            "\u{10ff41}\u{10ffff}x".as_bytes(),
+            b"\xf4\x8f\xbc\x81\xf4",
+            b"",
+        ];
+        for input in inputs {
+            assert_eq!(unescape(escape(input).into_owned()), input);
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let mut builder = MutationsBuilder::new();
+        builder.add_literal_action("a", "ignored", Action::Ignore);
+        let mutations = builder.build().unwrap();
+        let target = b"; comment \xfe\n[a]\nignored=\xff\xfe\nk=1\n";
+        let source = b"[a]\nignored=1\nk=\xc3(\n";
+        let result = super::merge_ini_bytes(target, source, &mutations).unwrap();
+        let expected: [&[u8]; 4] = [b"; comment \xfe", b"[a]", b"ignored=\xff\xfe", b"k=\xc3("];
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn filter() {
+        let mut builder = FilterActionsBuilder::new();
+        builder.add_literal_action("a", "secret", FilterAction::Remove);
+        let actions = builder.build().unwrap();
+        let input = b"[a]\nsecret=\xff\nk=\x80\n";
+        let result = super::filter_ini_bytes(input, &actions).unwrap();
+        let expected: [&[u8]; 2] = [b"[a]", b"k=\x80"];
+        assert_eq!(result, expected);
+    }
+}
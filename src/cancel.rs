@@ -0,0 +1,36 @@
+//! Cancellation of long running merges and filtering
+
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+/// A flag to abort processing from another thread, see
+/// [`crate::merge::merge_ini_cancellable`] and
+/// [`crate::filter::filter_ini_cancellable`]
+///
+/// Clones share the flag, so keep one to cancel with and pass another to the
+/// thread doing the processing. Processing stops at the next line once the
+/// flag is set.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    /// Create a token that is not cancelled
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Abort the processing using this token (or a clone of it)
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Check if the token has been cancelled
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,227 @@
+//! A single rule set driving both merging and filtering
+
+use crate::actions::ActionsBuilderError;
+use crate::filter::FilterAction;
+use crate::filter::FilterActions;
+use crate::filter::FilterActionsBuilder;
+use crate::filter::FilterRule;
+use crate::filter::FilterRuleSet;
+use crate::merge::mutations::Action;
+use crate::merge::mutations::MutationRule;
+use crate::merge::mutations::Mutations;
+use crate::merge::mutations::MutationsBuilder;
+use crate::merge::mutations::MutationsRuleSet;
+use crate::merge::mutations::SectionAction;
+use std::borrow::Cow;
+
+/// A rule set for merging that the matching filter rules are derived from
+///
+/// Use the [`Mutations`] when applying the source to the target, and the
+/// [`FilterActions`] when adding the target to the source, so that the two
+/// rule sets can't get out of sync. The rules are mapped as follows:
+///
+/// * Deleted sections and keys are removed.
+/// * Redacted keys and keys with a secret transform (see
+///   [`crate::merge::mutations::transforms::TransformerDispatch::is_secret`]) have
+///   their value replaced with [`Self::secret_placeholder`].
+/// * Everything else (ignores, other transforms, setters, conditional actions
+///   and target only policies) is kept as is by the filter.
+#[derive(Debug, Clone)]
+pub struct CombinedRules {
+    ruleset: MutationsRuleSet,
+    secret_placeholder: Cow<'static, str>,
+}
+
+impl CombinedRules {
+    /// Create from a merge rule set
+    #[must_use]
+    pub const fn new(ruleset: MutationsRuleSet) -> Self {
+        Self {
+            ruleset,
+            secret_placeholder: Cow::Borrowed("…"),
+        }
+    }
+
+    /// Set the replacement for values of keys with secret transforms
+    /// (default: `…`)
+    pub fn secret_placeholder(&mut self, placeholder: impl Into<Cow<'static, str>>) -> &mut Self {
+        self.secret_placeholder = placeholder.into();
+        self
+    }
+
+    /// The merge rule set
+    #[must_use]
+    pub const fn ruleset(&self) -> &MutationsRuleSet {
+        &self.ruleset
+    }
+
+    /// Build the mutations for merging
+    ///
+    /// Errors if a regex fails to compile.
+    pub fn mutations(&self) -> Result<Mutations, ActionsBuilderError> {
+        MutationsBuilder::from_serialized(self.ruleset.clone()).build()
+    }
+
+    /// The derived filter rules in data form
+    #[must_use]
+    pub fn filter_rules(&self) -> FilterRuleSet {
+        let mut ruleset = FilterRuleSet::new(
+            self.ruleset
+                .rules
+                .iter()
+                .filter_map(|rule| self.filter_rule(rule))
+                .collect(),
+        );
+        ruleset.warn_on_multiple_matches = self.ruleset.warn_on_multiple_matches;
+        ruleset
+    }
+
+    /// Build the derived filter actions
+    ///
+    /// Errors if a regex fails to compile.
+    pub fn filter_actions(&self) -> Result<FilterActions, ActionsBuilderError> {
+        FilterActionsBuilder::from_serialized(self.filter_rules()).build()
+    }
+
+    /// The filter action for a section action, if any
+    fn section_action(action: SectionAction) -> Option<FilterAction> {
+        match action {
+            SectionAction::Delete => Some(FilterAction::Remove),
+            SectionAction::Ignore => None,
+        }
+    }
+
+    /// The filter action for a key action, if any
+    fn action(&self, action: &Action) -> Option<FilterAction> {
+        match action {
+            Action::Delete => Some(FilterAction::Remove),
+            Action::Redact(_) => Some(FilterAction::Replace(self.secret_placeholder.clone())),
+            Action::Transform(transform) if transform.is_secret() => {
+                Some(FilterAction::Replace(self.secret_placeholder.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    /// The filter rule derived from a merge rule, if any
+    fn filter_rule(&self, rule: &MutationRule) -> Option<FilterRule> {
+        Some(match rule {
+            MutationRule::Section { section, action } => FilterRule::Section {
+                section: section.clone(),
+                action: Self::section_action(*action)?,
+            },
+            MutationRule::SectionPrefix { prefix, action } => FilterRule::SectionPrefix {
+                prefix: prefix.clone(),
+                action: Self::section_action(*action)?,
+            },
+            MutationRule::SectionRegex { section, action } => FilterRule::SectionRegex {
+                section: section.clone(),
+                action: Self::section_action(*action)?,
+            },
+            MutationRule::DefaultSection { action } => FilterRule::DefaultSection {
+                action: Self::section_action(*action)?,
+            },
+            MutationRule::Literal {
+                section,
+                key,
+                action,
+            } => FilterRule::Literal {
+                section: section.clone(),
+                key: key.clone(),
+                action: self.action(action)?,
+            },
+            MutationRule::Regex {
+                section,
+                key,
+                action,
+            } => FilterRule::Regex {
+                section: section.clone(),
+                key: key.clone(),
+                action: self.action(action)?,
+            },
+            MutationRule::RawLine {
+                section,
+                line,
+                action,
+            } => FilterRule::RawLine {
+                section: section.clone(),
+                line: line.clone(),
+                action: self.action(action)?,
+            },
+            MutationRule::ParseError { action } => FilterRule::ParseError {
+                action: self.action(action)?,
+            },
+            MutationRule::Set { .. }
+            | MutationRule::Conditional { .. }
+            | MutationRule::TargetOnly { .. } => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CombinedRules;
+    use crate::merge::mutations::Action;
+    use crate::merge::mutations::MutationRule;
+    use crate::merge::mutations::MutationsRuleSet;
+    use crate::merge::mutations::SectionAction;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn combined_rules() {
+        #[cfg_attr(not(feature = "keyring"), allow(unused_mut))]
+        let mut rules = vec![
+            MutationRule::Section {
+                section: "ign".into(),
+                action: SectionAction::Ignore,
+            },
+            MutationRule::Section {
+                section: "del".into(),
+                action: SectionAction::Delete,
+            },
+            MutationRule::Literal {
+                section: "s".into(),
+                key: "d".into(),
+                action: Action::Delete,
+            },
+            MutationRule::Literal {
+                section: "s".into(),
+                key: "i".into(),
+                action: Action::Ignore,
+            },
+        ];
+        #[cfg(feature = "keyring")]
+        rules.push(MutationRule::Literal {
+            section: "s".into(),
+            key: "pw".into(),
+            action: Action::Transform(
+                crate::merge::mutations::transforms::TransformKeyring::new(
+                    "service".into(),
+                    "user".into(),
+                    "=".into(),
+                )
+                .into(),
+            ),
+        });
+        let mut combined = CombinedRules::new(MutationsRuleSet::new(rules));
+        combined.secret_placeholder("HIDDEN");
+        let input = "[ign]\na=1\n[del]\na=1\n[s]\nd=1\ni=1\npw=secret\n";
+
+        let filter = combined.filter_actions().unwrap();
+        let result = crate::filter::filter_ini_from_str(input, &filter).unwrap();
+        #[cfg(feature = "keyring")]
+        let pw = "pw=HIDDEN";
+        #[cfg(not(feature = "keyring"))]
+        let pw = "pw=secret";
+        assert_eq!(result, vec!["[ign]", "a=1", "[s]", "i=1", pw]);
+
+        let mutations = combined.mutations().unwrap();
+        let result = crate::merge::merge_ini_from_str(
+            "[ign]\na=t\n[s]\ni=t\n",
+            "[ign]\na=s\n[del]\na=s\n[s]\nd=s\ni=s\n",
+            &mutations,
+        )
+        .unwrap();
+        assert_eq!(result, vec!["[ign]", "a=t", "[s]", "i=t"]);
+    }
+}
@@ -0,0 +1,275 @@
+//! Semantic comparison of INI files
+//!
+//! Compares the sections and keys of two INI files, independent of
+//! formatting (whitespace, comments and the order of keys). The files are
+//! parsed the same way as when merging, so the quirks of the parser and
+//! dialect apply.
+
+use crate::dialect::Dialect;
+use crate::dialect::ParsedInput;
+use crate::SectionName;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+/// A difference between two INI files, see [`diff_ini`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum IniChange {
+    /// A section only in the second file (its keys follow as
+    /// [`IniChange::KeyAdded`])
+    SectionAdded { section: String },
+    /// A section only in the first file (its keys follow as
+    /// [`IniChange::KeyRemoved`])
+    SectionRemoved { section: String },
+    /// A key only in the second file
+    KeyAdded {
+        section: String,
+        key: String,
+        value: Option<String>,
+    },
+    /// A key only in the first file
+    KeyRemoved {
+        section: String,
+        key: String,
+        value: Option<String>,
+    },
+    /// A key with different values in the two files
+    ValueChanged {
+        section: String,
+        key: String,
+        old: Option<String>,
+        new: Option<String>,
+    },
+}
+
+impl IniChange {
+    /// The section the change is in
+    #[must_use]
+    pub fn section(&self) -> &str {
+        match self {
+            Self::SectionAdded { section }
+            | Self::SectionRemoved { section }
+            | Self::KeyAdded { section, .. }
+            | Self::KeyRemoved { section, .. }
+            | Self::ValueChanged { section, .. } => section,
+        }
+    }
+
+    /// The key the change is for (`None` for changes to whole sections)
+    #[must_use]
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            Self::SectionAdded { .. } | Self::SectionRemoved { .. } => None,
+            Self::KeyAdded { key, .. }
+            | Self::KeyRemoved { key, .. }
+            | Self::ValueChanged { key, .. } => Some(key),
+        }
+    }
+}
+
+/// The keys (first occurrence) of a section of an INI file
+#[derive(Debug, Default)]
+pub(crate) struct SnapshotSection<'a> {
+    /// Name of the section, as first written
+    pub(crate) name: &'a str,
+    /// Values of the keys
+    pub(crate) keys: BTreeMap<&'a str, Option<&'a str>>,
+}
+
+/// The sections of an INI file, keyed on the (case folded if the dialect
+/// says so) name
+pub(crate) type Snapshot<'a> = BTreeMap<Cow<'a, str>, SnapshotSection<'a>>;
+
+/// Collect the sections and keys of a parsed INI file
+pub(crate) fn snapshot<'a>(parsed: &'a ParsedInput<'a>, dialect: &Dialect) -> Snapshot<'a> {
+    let folded = |name: &'a str| -> Cow<'a, str> {
+        if dialect.folds_section_case() {
+            Cow::Owned(name.to_lowercase())
+        } else {
+            Cow::Borrowed(name)
+        }
+    };
+    let mut result = Snapshot::new();
+    let mut section = Cow::Borrowed(crate::OUTSIDE_SECTION);
+    result.entry(section.clone()).or_default().name = crate::OUTSIDE_SECTION;
+    for item in parsed.items() {
+        match item {
+            ini_roundtrip::Item::Section { name, .. } => {
+                section = folded(name);
+                result
+                    .entry(section.clone())
+                    .or_insert_with(|| SnapshotSection {
+                        name: SectionName::from_key(name).as_str(),
+                        keys: BTreeMap::new(),
+                    });
+            }
+            ini_roundtrip::Item::Property { key, val, .. } => {
+                if let Some(entry) = result.get_mut(&section) {
+                    entry.keys.entry(key).or_insert(val);
+                }
+            }
+            _ => (),
+        }
+    }
+    result
+}
+
+/// Compare two snapshots, see [`diff_ini`]
+pub(crate) fn diff_snapshots(a: &Snapshot<'_>, b: &Snapshot<'_>) -> Vec<IniChange> {
+    let owned = |val: Option<&&str>| val.map(|v| (*v).to_string());
+    let sections: BTreeSet<_> = a.keys().chain(b.keys()).collect();
+    let mut changes = Vec::new();
+    for section in sections {
+        match (a.get(section), b.get(section)) {
+            (Some(a_section), Some(b_section)) => {
+                let name = a_section.name;
+                let keys: BTreeSet<_> =
+                    a_section.keys.keys().chain(b_section.keys.keys()).collect();
+                for key in keys {
+                    match (a_section.keys.get(key), b_section.keys.get(key)) {
+                        (Some(old), Some(new)) if old == new => (),
+                        (Some(old), Some(new)) => changes.push(IniChange::ValueChanged {
+                            section: name.into(),
+                            key: (*key).into(),
+                            old: old.map(Into::into),
+                            new: new.map(Into::into),
+                        }),
+                        (Some(old), None) => changes.push(IniChange::KeyRemoved {
+                            section: name.into(),
+                            key: (*key).into(),
+                            value: owned(old.as_ref()),
+                        }),
+                        (None, new) => changes.push(IniChange::KeyAdded {
+                            section: name.into(),
+                            key: (*key).into(),
+                            value: owned(new.and_then(Option::as_ref)),
+                        }),
+                    }
+                }
+            }
+            (Some(removed), None) => {
+                changes.push(IniChange::SectionRemoved {
+                    section: removed.name.into(),
+                });
+                changes.extend(
+                    removed
+                        .keys
+                        .iter()
+                        .map(|(key, value)| IniChange::KeyRemoved {
+                            section: removed.name.into(),
+                            key: (*key).into(),
+                            value: owned(value.as_ref()),
+                        }),
+                );
+            }
+            (None, Some(added)) => {
+                changes.push(IniChange::SectionAdded {
+                    section: added.name.into(),
+                });
+                changes.extend(added.keys.iter().map(|(key, value)| IniChange::KeyAdded {
+                    section: added.name.into(),
+                    key: (*key).into(),
+                    value: owned(value.as_ref()),
+                }));
+            }
+            (None, None) => (),
+        }
+    }
+    changes
+}
+
+/// Compare two INI files at the level of sections and keys, independent of
+/// formatting
+///
+/// Changes are sorted by section and key. Keys before the first section are
+/// in [`crate::OUTSIDE_SECTION`], which always exists. If a key occurs more
+/// than once in a section, the first occurrence is used.
+#[must_use]
+pub fn diff_ini(a: &str, b: &str) -> Vec<IniChange> {
+    diff_ini_with_dialect(a, b, &Dialect::new())
+}
+
+/// Compare two INI files written in the given dialect, see [`diff_ini`]
+#[must_use]
+pub fn diff_ini_with_dialect(a: &str, b: &str, dialect: &Dialect) -> Vec<IniChange> {
+    let a = dialect.parse(a);
+    let b = dialect.parse(b);
+    diff_snapshots(&snapshot(&a, dialect), &snapshot(&b, dialect))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::diff_ini;
+    use super::diff_ini_with_dialect;
+    use super::IniChange;
+    use crate::dialect::Dialect;
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn changes() {
+        let a = indoc! {"
+            top=1
+            [s]
+            same = 1
+            changed=1
+            removed=1
+            [gone]
+            x=1
+        "};
+        let b = indoc! {"
+            top=1
+            ; Formatting does not matter
+            [ s ]
+            changed=2
+            same=1
+            added
+            [new]
+        "};
+        assert_eq!(
+            diff_ini(a, b),
+            vec![
+                IniChange::SectionRemoved {
+                    section: "gone".into()
+                },
+                IniChange::KeyRemoved {
+                    section: "gone".into(),
+                    key: "x".into(),
+                    value: Some("1".into())
+                },
+                IniChange::SectionAdded {
+                    section: "new".into()
+                },
+                IniChange::KeyAdded {
+                    section: "s".into(),
+                    key: "added".into(),
+                    value: None
+                },
+                IniChange::ValueChanged {
+                    section: "s".into(),
+                    key: "changed".into(),
+                    old: Some("1".into()),
+                    new: Some("2".into())
+                },
+                IniChange::KeyRemoved {
+                    section: "s".into(),
+                    key: "removed".into(),
+                    value: Some("1".into())
+                },
+            ]
+        );
+        assert!(diff_ini(a, a).is_empty());
+    }
+
+    #[test]
+    fn dialect() {
+        let mut dialect = Dialect::new();
+        dialect.case_insensitive_sections(true);
+        let changes = diff_ini_with_dialect("[A]\nk=1\n", "[a]\nk=2\n", &dialect);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].section(), "A");
+        assert_eq!(changes[0].key(), Some("k"));
+        assert_eq!(diff_ini("[A]\nk=1\n", "[a]\nk=1\n").len(), 4);
+    }
+}
@@ -0,0 +1,678 @@
+//! Options for the INI dialect of the input
+//!
+//! The parser recognises lines starting with `;` or `#` as comments. A
+//! [`Dialect`] can change that, reclassifying the parsed lines. It can also
+//! join values continued over several lines with a trailing `\`, and decode
+//! escape sequences when comparing values.
+//!
+//! There are presets for common dialects, such as [`Dialect::KDE`] and
+//! [`Dialect::GIT_CONFIG`].
+
+use crate::SectionName;
+use ini_roundtrip::Item;
+use ini_roundtrip::Parser;
+use std::borrow::Cow;
+
+/// List of comment prefixes
+type Prefixes = Cow<'static, [Cow<'static, str>]>;
+
+/// Standard comment prefixes
+const STANDARD_PREFIXES: &[Cow<'static, str>] = &[Cow::Borrowed(";"), Cow::Borrowed("#")];
+
+/// Escape sequences used in values
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum Escapes {
+    /// Values are compared as written
+    #[default]
+    None,
+    /// Java properties: `\t`, `\n`, `\r`, `\f`, `\uXXXX`, any other
+    /// character escapes itself
+    Properties,
+    /// `KConfig`: `\s`, `\t`, `\n`, `\r`, `\xXX`, any other character
+    /// escapes itself
+    KConfig,
+}
+
+impl Escapes {
+    /// Decode the escape sequences in a value
+    fn decode(self, value: &str) -> Cow<'_, str> {
+        if self == Self::None || !value.contains('\\') {
+            return Cow::Borrowed(value);
+        }
+        let mut result = Vec::with_capacity(value.len());
+        let mut chars = value.chars();
+        let mut buf = [0; 4];
+        while let Some(chr) = chars.next() {
+            if chr != '\\' {
+                result.extend_from_slice(chr.encode_utf8(&mut buf).as_bytes());
+                continue;
+            }
+            let decoded = match (self, chars.next()) {
+                (_, None) => '\\',
+                (_, Some('t')) => '\t',
+                (_, Some('n')) => '\n',
+                (_, Some('r')) => '\r',
+                (Self::Properties, Some('f')) => '\u{c}',
+                (Self::Properties, Some('u')) => {
+                    match hex_escape(&mut chars, 4).and_then(char::from_u32) {
+                        Some(chr) => chr,
+                        None => return Cow::Borrowed(value),
+                    }
+                }
+                (Self::KConfig, Some('s')) => ' ',
+                (Self::KConfig, Some('x')) => match hex_escape(&mut chars, 2) {
+                    // A raw byte, that may be part of a multi byte character
+                    Some(byte) => {
+                        result.push(byte as u8);
+                        continue;
+                    }
+                    None => return Cow::Borrowed(value),
+                },
+                (_, Some(chr)) => chr,
+            };
+            result.extend_from_slice(decoded.encode_utf8(&mut buf).as_bytes());
+        }
+        match String::from_utf8(result) {
+            Ok(decoded) => Cow::Owned(decoded),
+            Err(err) => Cow::Owned(String::from_utf8_lossy(err.as_bytes()).into_owned()),
+        }
+    }
+}
+
+/// Parse a hex escape of `digits` digits
+fn hex_escape(chars: &mut std::str::Chars<'_>, digits: usize) -> Option<u32> {
+    let hex = chars.as_str().get(..digits)?;
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    // Hex digits are ASCII, so this skips exactly the digits
+    chars.nth(digits - 1);
+    Some(value)
+}
+
+/// Describes the INI dialect of the input, see
+/// [`crate::merge::mutations::MutationsBuilder::dialect`] and
+/// [`crate::actions::ActionsBuilder::dialect`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Dialect {
+    /// Prefixes that start a comment
+    comment_prefixes: Prefixes,
+    /// If comments may be indented
+    indented_comments: bool,
+    /// If a trailing `\` continues a value on the next line
+    line_continuation: bool,
+    /// Escape sequences used in values
+    escapes: Escapes,
+    /// If section names are compared case insensitively
+    #[cfg_attr(feature = "serde", serde(rename = "case_insensitive_sections"))]
+    fold_section_case: bool,
+    /// If trailing whitespace is part of the value
+    significant_trailing_whitespace: bool,
+    /// If whitespace around section names is ignored when matching
+    trim_section_names: bool,
+    /// If whitespace inside the brackets of section headers is removed in
+    /// the output
+    normalize_section_headers: bool,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dialect {
+    /// The standard dialect: Comments start with `;` or `#` at the start of
+    /// the line
+    pub const STANDARD: Self = Self {
+        comment_prefixes: Cow::Borrowed(STANDARD_PREFIXES),
+        indented_comments: false,
+        line_continuation: false,
+        escapes: Escapes::None,
+        fold_section_case: false,
+        significant_trailing_whitespace: false,
+        trim_section_names: true,
+        normalize_section_headers: false,
+    };
+
+    /// KDE configuration files: Comments start with `#`, values use `KConfig`
+    /// escapes
+    pub const KDE: Self = Self {
+        comment_prefixes: Cow::Borrowed(&[Cow::Borrowed("#")]),
+        indented_comments: false,
+        line_continuation: false,
+        escapes: Escapes::KConfig,
+        fold_section_case: false,
+        significant_trailing_whitespace: false,
+        trim_section_names: true,
+        normalize_section_headers: false,
+    };
+
+    /// Git configuration files: Comments start with `;` or `#` and may be
+    /// indented, values may be continued with `\`
+    pub const GIT_CONFIG: Self = Self {
+        comment_prefixes: Cow::Borrowed(STANDARD_PREFIXES),
+        indented_comments: true,
+        line_continuation: true,
+        escapes: Escapes::None,
+        fold_section_case: false,
+        significant_trailing_whitespace: false,
+        trim_section_names: true,
+        normalize_section_headers: false,
+    };
+
+    /// Java properties files: Comments start with `#` or `!` and may be
+    /// indented, values may be continued with `\` and use properties escapes.
+    /// Trailing whitespace is part of the value.
+    pub const PROPERTIES: Self = Self {
+        comment_prefixes: Cow::Borrowed(&[Cow::Borrowed("#"), Cow::Borrowed("!")]),
+        indented_comments: true,
+        line_continuation: true,
+        escapes: Escapes::Properties,
+        fold_section_case: false,
+        significant_trailing_whitespace: true,
+        trim_section_names: true,
+        normalize_section_headers: false,
+    };
+
+    /// Windows registry files: Comments start with `;`, (hex) values may be
+    /// continued with `\`
+    pub const REG: Self = Self {
+        comment_prefixes: Cow::Borrowed(&[Cow::Borrowed(";")]),
+        indented_comments: false,
+        line_continuation: true,
+        escapes: Escapes::None,
+        fold_section_case: false,
+        significant_trailing_whitespace: false,
+        trim_section_names: true,
+        normalize_section_headers: false,
+    };
+
+    /// The standard dialect, see [`Dialect::STANDARD`]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self::STANDARD
+    }
+
+    /// Set the prefixes that start a comment (for example just `#`, or `//`)
+    pub fn comment_prefixes(
+        &mut self,
+        prefixes: impl IntoIterator<Item = impl Into<String>>,
+    ) -> &mut Self {
+        self.comment_prefixes = prefixes
+            .into_iter()
+            .map(|prefix| Cow::Owned(prefix.into()))
+            .collect();
+        self
+    }
+
+    /// Set if comments may be preceded by whitespace (default: false)
+    pub fn indented_comments(&mut self, value: bool) -> &mut Self {
+        self.indented_comments = value;
+        self
+    }
+
+    /// Set if a trailing `\` continues a value on the next line, as used by
+    /// systemd units and `wpa_supplicant` (default: false)
+    ///
+    /// The continued lines are joined into one value (with the `\` replaced by
+    /// a space) when matching rules and comparing values. Unchanged lines are
+    /// written back with their original line breaks.
+    pub fn line_continuation(&mut self, value: bool) -> &mut Self {
+        self.line_continuation = value;
+        self
+    }
+
+    /// Set the escape sequences used in values (default: [`Escapes::None`])
+    ///
+    /// Values are decoded before being compared, so a value in the target that
+    /// is the same as in the source (just escaped differently) is kept as is.
+    pub fn escapes(&mut self, escapes: Escapes) -> &mut Self {
+        self.escapes = escapes;
+        self
+    }
+
+    /// Set if section names are compared case insensitively (default: false)
+    ///
+    /// This affects which sections of the source and target are considered
+    /// the same. A section in the target uses the spelling of the source for
+    /// matching rules, but the original section header is kept.
+    pub fn case_insensitive_sections(&mut self, value: bool) -> &mut Self {
+        self.fold_section_case = value;
+        self
+    }
+
+    /// Set if trailing whitespace is part of the value, so that it is never
+    /// trimmed (default: false)
+    pub fn significant_trailing_whitespace(&mut self, value: bool) -> &mut Self {
+        self.significant_trailing_whitespace = value;
+        self
+    }
+
+    /// Set if whitespace around section names (inside the brackets) is
+    /// ignored when matching rules and comparing sections (default: true)
+    ///
+    /// Some programs treat `[ name ]` and `[name]` as different sections.
+    /// The header line is written as is either way, see
+    /// [`Self::normalize_section_headers`].
+    pub fn trim_section_names(&mut self, value: bool) -> &mut Self {
+        self.trim_section_names = value;
+        self
+    }
+
+    /// Set if whitespace inside the brackets of section headers is removed
+    /// in the output, so that `[ name ]` is written as `[name]` (default:
+    /// false)
+    pub fn normalize_section_headers(&mut self, value: bool) -> &mut Self {
+        self.normalize_section_headers = value;
+        self
+    }
+
+    /// If trailing whitespace is part of the value
+    pub(crate) const fn has_significant_trailing_whitespace(&self) -> bool {
+        self.significant_trailing_whitespace
+    }
+
+    /// If section names are compared case insensitively
+    pub(crate) const fn folds_section_case(&self) -> bool {
+        self.fold_section_case
+    }
+
+    /// Check if the target line should be kept instead of the source line, as
+    /// the value is the same, just written differently (escaped or continued)
+    pub(crate) fn keeps_target(
+        &self,
+        raw: &str,
+        target: Option<&str>,
+        source: Option<&str>,
+    ) -> bool {
+        if !(self.line_continuation && raw.contains('\n')) && self.escapes == Escapes::None {
+            return false;
+        }
+        match (target, source) {
+            (Some(target), Some(source)) => {
+                self.escapes.decode(target) == self.escapes.decode(source)
+            }
+            (target, source) => target == source,
+        }
+    }
+
+    /// If the comment syntax is the standard one, which the parser handles by
+    /// itself
+    fn is_standard(&self) -> bool {
+        !self.indented_comments && *self.comment_prefixes == *STANDARD_PREFIXES
+    }
+
+    /// Check if a line is a comment
+    fn is_comment(&self, raw: &str) -> bool {
+        let line = if self.indented_comments {
+            raw.trim_start()
+        } else {
+            raw
+        };
+        self.comment_prefixes
+            .iter()
+            .any(|prefix| !prefix.is_empty() && line.starts_with(prefix.as_ref()))
+    }
+
+    /// The section header line to write for a header in the input
+    pub(crate) fn section_header<'a>(&self, raw: &'a str) -> Cow<'a, str> {
+        match untrimmed_section_name(raw) {
+            Some(name) if self.normalize_section_headers => Cow::Owned(format!("[{}]", trim(name))),
+            _ => Cow::Borrowed(raw),
+        }
+    }
+
+    /// The name of a section, given the name from the parser and the raw
+    /// header line
+    fn section_name<'a>(&self, name: &'a str, raw: &'a str) -> &'a str {
+        match untrimmed_section_name(raw) {
+            Some(untrimmed) if !self.trim_section_names => untrimmed,
+            _ => name,
+        }
+    }
+
+    /// Reclassify a parsed item according to the dialect, with section
+    /// names given by their key (see [`SectionName::key`])
+    pub(crate) fn classify<'a>(&self, item: Item<'a>) -> Item<'a> {
+        match self.classify_line(item) {
+            // Keep a section named like the top level apart from it
+            Item::Section { name, raw } => Item::Section {
+                name: SectionName::Named(name).key(),
+                raw,
+            },
+            item => item,
+        }
+    }
+
+    /// Reclassify a parsed item according to the dialect, with section
+    /// names as written
+    pub(crate) fn classify_line<'a>(&self, item: Item<'a>) -> Item<'a> {
+        let item = match item {
+            Item::Section { name, raw } => Item::Section {
+                name: self.section_name(name, raw),
+                raw,
+            },
+            item => item,
+        };
+        if self.is_standard() {
+            return item;
+        }
+        match item {
+            Item::Comment { raw } if !self.is_comment(raw) => property_from_line(raw),
+            Item::Property { raw, .. } if self.is_comment(raw) => Item::Comment { raw },
+            item => item,
+        }
+    }
+
+    /// Check if a property starts a value continued on the next line
+    fn continues(&self, item: &Item<'_>) -> bool {
+        self.line_continuation
+            && matches!(item, Item::Property { val: Some(_), raw, .. } if raw.ends_with('\\'))
+    }
+
+    /// Parse the input, joining continued lines
+    pub(crate) fn parse<'a>(&'a self, input: &'a str) -> ParsedInput<'a> {
+        let mut parsed = ParsedInput {
+            dialect: self,
+            input,
+            continued: Vec::new(),
+        };
+        if self.line_continuation {
+            let mut parser = Parser::new(input);
+            while let Some(item) = parser.next() {
+                let item = self.classify(item);
+                if !self.continues(&item) {
+                    continue;
+                }
+                let Item::Property {
+                    val: Some(val),
+                    raw,
+                    ..
+                } = item
+                else {
+                    continue;
+                };
+                let start = offset(input, raw);
+                let mut end = start + raw.len();
+                let mut value = trim(strip_continuation(val)).to_string();
+                let mut lines = 0;
+                let mut more = true;
+                while more {
+                    let Some(line) = next_line(&mut parser) else {
+                        break;
+                    };
+                    lines += 1;
+                    end = offset(input, line) + line.len();
+                    more = line.ends_with('\\');
+                    value.push(' ');
+                    value.push_str(trim(if more { strip_continuation(line) } else { line }));
+                }
+                parsed.continued.push(Continued {
+                    raw: &input[start..end],
+                    value,
+                    lines,
+                });
+            }
+        }
+        parsed
+    }
+}
+
+/// Input parsed according to a dialect
+#[derive(Debug)]
+pub(crate) struct ParsedInput<'a> {
+    dialect: &'a Dialect,
+    input: &'a str,
+    /// Values continued over several lines, in order
+    continued: Vec<Continued<'a>>,
+}
+
+/// A value continued over several lines
+#[derive(Debug)]
+struct Continued<'a> {
+    /// All the lines (with the line breaks)
+    raw: &'a str,
+    /// The joined value
+    value: String,
+    /// Number of lines after the first one
+    lines: usize,
+}
+
+impl<'a> ParsedInput<'a> {
+    /// The input that was parsed
+    pub(crate) const fn input(&self) -> &'a str {
+        self.input
+    }
+
+    /// Create a new parser for the input
+    pub(crate) fn parser(&self) -> Parser<'a> {
+        Parser::new(self.input)
+    }
+
+    /// Iterate over the items of the input
+    pub(crate) fn items(&self) -> Items<'_> {
+        Items {
+            parsed: self,
+            parser: self.parser(),
+            next_continued: 0,
+        }
+    }
+
+    /// Get the next item from `parser` (which must come from [`Self::parser`])
+    pub(crate) fn next_item<'p>(
+        &'p self,
+        parser: &mut Parser<'a>,
+        next_continued: &mut usize,
+    ) -> Option<Item<'p>> {
+        let item = self.dialect.classify(parser.next()?);
+        if !self.dialect.continues(&item) {
+            return Some(item);
+        }
+        let Item::Property { key, .. } = item else {
+            return Some(item);
+        };
+        let continued = self.continued.get(*next_continued)?;
+        *next_continued += 1;
+        for _ in 0..continued.lines {
+            next_line(parser);
+        }
+        Some(Item::Property {
+            key,
+            val: Some(&continued.value),
+            raw: continued.raw,
+        })
+    }
+}
+
+/// Iterator over the items of a [`ParsedInput`]
+#[derive(Debug, Clone)]
+pub(crate) struct Items<'p> {
+    parsed: &'p ParsedInput<'p>,
+    parser: Parser<'p>,
+    next_continued: usize,
+}
+
+impl<'p> Iterator for Items<'p> {
+    type Item = Item<'p>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.parsed
+            .next_item(&mut self.parser, &mut self.next_continued)
+    }
+}
+
+/// Get the next physical line from the parser
+fn next_line<'a>(parser: &mut Parser<'a>) -> Option<&'a str> {
+    loop {
+        match parser.next()? {
+            // Emitted before sections, not a line by itself
+            Item::SectionEnd => continue,
+            Item::Error(raw)
+            | Item::Comment { raw }
+            | Item::Blank { raw }
+            | Item::Section { raw, .. }
+            | Item::Property { raw, .. } => return Some(raw),
+        }
+    }
+}
+
+/// Offset of `part` in `input` (which it must be a slice of)
+fn offset(input: &str, part: &str) -> usize {
+    part.as_ptr() as usize - input.as_ptr() as usize
+}
+
+/// Remove the trailing `\` from a line
+fn strip_continuation(line: &str) -> &str {
+    line.strip_suffix('\\').unwrap_or(line)
+}
+
+/// Trim ASCII whitespace (like the parser)
+fn trim(s: &str) -> &str {
+    s.trim_matches(|chr: char| chr.is_ascii_whitespace())
+}
+
+/// The text between the brackets of a section header line
+pub(crate) fn untrimmed_section_name(raw: &str) -> Option<&str> {
+    raw.strip_prefix('[')?.strip_suffix(']')
+}
+
+/// Parse a line as a property (the same way as the parser does)
+fn property_from_line(raw: &str) -> Item<'_> {
+    match raw.split_once('=') {
+        Some((key, val)) => Item::Property {
+            key: trim(key),
+            val: Some(trim(val)),
+            raw,
+        },
+        None => Item::Property {
+            key: trim(raw),
+            val: None,
+            raw,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Dialect;
+    use super::Escapes;
+    use ini_roundtrip::Item;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn comment_prefixes() {
+        let mut dialect = Dialect::new();
+        dialect.comment_prefixes(["//"]).indented_comments(true);
+        let parsed = dialect.parse(";a=1\n  // b=2\n#c\n");
+        let items: Vec<_> = parsed.items().collect();
+        assert_eq!(
+            items,
+            vec![
+                Item::Property {
+                    key: ";a",
+                    val: Some("1"),
+                    raw: ";a=1"
+                },
+                Item::Comment { raw: "  // b=2" },
+                Item::Property {
+                    key: "#c",
+                    val: None,
+                    raw: "#c"
+                },
+                Item::SectionEnd,
+            ]
+        );
+    }
+
+    #[test]
+    fn section_names() {
+        let input = "[ a ]\n[\tb]\n";
+        let names = |dialect: &Dialect| -> Vec<_> {
+            dialect
+                .parse(input)
+                .items()
+                .filter_map(|item| match item {
+                    Item::Section { name, .. } => Some(name.to_string()),
+                    _ => None,
+                })
+                .collect()
+        };
+        let mut dialect = Dialect::new();
+        assert_eq!(names(&dialect), ["a", "b"]);
+        assert_eq!(dialect.section_header("[ a ]"), "[ a ]");
+        dialect
+            .trim_section_names(false)
+            .normalize_section_headers(true);
+        assert_eq!(names(&dialect), [" a ", "\tb"]);
+        assert_eq!(dialect.section_header("[ a ]"), "[a]");
+        assert_eq!(dialect.section_header("a"), "a");
+    }
+
+    #[test]
+    fn line_continuation() {
+        let mut dialect = Dialect::new();
+        dialect.line_continuation(true);
+        let parsed = dialect.parse("a = 1 \\\n  2\\\n[3]\n; c \\\nb = x\\\n");
+        let items: Vec<_> = parsed.items().collect();
+        assert_eq!(
+            items,
+            vec![
+                Item::Property {
+                    key: "a",
+                    val: Some("1 2 [3]"),
+                    raw: "a = 1 \\\n  2\\\n[3]"
+                },
+                Item::Comment { raw: "; c \\" },
+                Item::Property {
+                    key: "b",
+                    val: Some("x"),
+                    raw: "b = x\\"
+                },
+                Item::SectionEnd,
+            ]
+        );
+    }
+
+    #[test]
+    fn presets() {
+        assert_eq!(Dialect::new(), Dialect::STANDARD);
+        let input = "; a\n  ! b\nc = 1 \\\n  2\n";
+        let parsed = Dialect::KDE.parse(input);
+        assert!(matches!(
+            parsed.items().next(),
+            Some(Item::Property { key: "; a", .. })
+        ));
+        let parsed = Dialect::PROPERTIES.parse(input);
+        let items: Vec<_> = parsed.items().collect();
+        assert_eq!(
+            items,
+            vec![
+                Item::Property {
+                    key: "; a",
+                    val: None,
+                    raw: "; a"
+                },
+                Item::Comment { raw: "  ! b" },
+                Item::Property {
+                    key: "c",
+                    val: Some("1 2"),
+                    raw: "c = 1 \\\n  2"
+                },
+                Item::SectionEnd,
+            ]
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(Escapes::None.decode(r"a\tb"), r"a\tb");
+        assert_eq!(Escapes::Properties.decode(r"a\tb\u00e5\:\\"), "a\tbå:\\");
+        assert_eq!(Escapes::Properties.decode(r"\u00zz"), r"\u00zz");
+        assert_eq!(Escapes::KConfig.decode(r"\sa\xc3\xa5\;"), " aå;");
+        assert_eq!(Escapes::KConfig.decode(r"a\"), "a\\");
+    }
+}
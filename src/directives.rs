@@ -0,0 +1,394 @@
+//! Parser for the directive language used by
+//! [chezmoi_modify_manager](https://github.com/VorpalBlade/chezmoi_modify_manager)
+//!
+//! Each line is one directive. Arguments are either bare words or double
+//! quoted strings (with `\` escapes). Lines starting with `#` are comments.
+//!
+//! ```text
+//! source auto
+//! ignore section "Recent Files"
+//! ignore "General" "ColorSchemeHash"
+//! ignore regex "General" "geometry_.*"
+//! remove section "Cache"
+//! remove "General" "LastRun"
+//! set "General" "Enabled" "true" separator="="
+//! transform "Shortcuts" "playmedia" kde-shortcut
+//! transform regex ".*" "list_.*" unsorted-list separator=","
+//! add:remove "General" "Token"
+//! add:hide "General" "Password"
+//! no-warn-multiple-key-matches
+//! ```
+//!
+//! `ignore`, `remove`, `set` and `transform` produce merge mutations, while
+//! `add:remove` and `add:hide` produce filter actions (for when adding a file
+//! to the source state).
+
+use crate::filter::FilterAction;
+use crate::filter::FilterActionsBuilder;
+use crate::merge::mutations::transforms::TransformArgs;
+use crate::merge::mutations::transforms::TransformerConstructionError;
+use crate::merge::mutations::transforms::TransformerDispatch;
+use crate::merge::mutations::Action;
+use crate::merge::mutations::MutationsBuilder;
+use crate::merge::mutations::SectionAction;
+use thiserror::Error;
+
+/// Replacement value used by `add:hide`
+pub const HIDDEN: &str = "HIDDEN";
+
+/// The result of parsing directives
+#[derive(Debug, Default)]
+#[non_exhaustive]
+pub struct Directives {
+    /// The argument of the `source` directive (if any)
+    pub source: Option<String>,
+    /// Merge mutations
+    pub mutations: MutationsBuilder,
+    /// Filter actions
+    pub filter: FilterActionsBuilder,
+}
+
+/// Error type for parsing directives
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum DirectiveError {
+    /// The line could not be parsed
+    #[error("Line {line}: {message}")]
+    Syntax {
+        /// Line number (1-based)
+        line: usize,
+        /// What was wrong
+        message: String,
+    },
+    /// A transform could not be constructed
+    #[error("Line {line}: {source}")]
+    Transform {
+        /// Line number (1-based)
+        line: usize,
+        /// The underlying error
+        #[source]
+        source: TransformerConstructionError,
+    },
+}
+
+/// A token on a line
+#[derive(Debug, PartialEq, Eq)]
+struct Token {
+    text: String,
+    /// If any part of the token was quoted (quoted tokens are never keywords)
+    quoted: bool,
+}
+
+impl Token {
+    fn is_keyword(&self, keyword: &str) -> bool {
+        !self.quoted && self.text == keyword
+    }
+}
+
+/// Split a line into tokens
+fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return Ok(tokens);
+        }
+        let mut token = Token {
+            text: String::new(),
+            quoted: false,
+        };
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+            if c != '"' {
+                token.text.push(c);
+                continue;
+            }
+            token.quoted = true;
+            loop {
+                match chars.next() {
+                    None => return Err("Unterminated string".into()),
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        None => return Err("Unterminated string".into()),
+                        Some('n') => token.text.push('\n'),
+                        Some('t') => token.text.push('\t'),
+                        Some(c) => token.text.push(c),
+                    },
+                    Some(c) => token.text.push(c),
+                }
+            }
+        }
+        tokens.push(token);
+    }
+}
+
+/// How a key (or section) is matched
+#[derive(Debug)]
+enum Matcher {
+    Section(String),
+    Literal(String, String),
+    Regex(String, String),
+}
+
+/// Parse `section "S"`, `"S" "K"` or `regex "S" "K"` from the start of the
+/// tokens
+fn parse_matcher(tokens: &mut std::vec::IntoIter<Token>) -> Result<Matcher, String> {
+    let first = tokens.next().ok_or("Missing section")?;
+    if first.is_keyword("section") {
+        let section = tokens.next().ok_or("Missing section")?;
+        return Ok(Matcher::Section(section.text));
+    }
+    let (regex, section) = if first.is_keyword("regex") {
+        (true, tokens.next().ok_or("Missing section")?)
+    } else {
+        (false, first)
+    };
+    let key = tokens.next().ok_or("Missing key")?;
+    Ok(if regex {
+        Matcher::Regex(section.text, key.text)
+    } else {
+        Matcher::Literal(section.text, key.text)
+    })
+}
+
+/// Parse `name=value` arguments
+fn parse_args(tokens: impl Iterator<Item = Token>) -> Result<TransformArgs<'static>, String> {
+    tokens
+        .map(|token| {
+            token
+                .text
+                .split_once('=')
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .ok_or_else(|| format!("Expected name=value argument, got {}", token.text))
+        })
+        .collect()
+}
+
+/// Map the transform names of `chezmoi_modify_manager` to the ones used by
+/// [`TransformerDispatch::from_name`]
+fn transform_name(name: &str) -> String {
+    match name {
+        "unsorted-list" => "unsorted_lists".into(),
+        _ => name.replace('-', "_"),
+    }
+}
+
+impl Directives {
+    /// Add a mutation for a matcher
+    fn add_mutation(&mut self, matcher: Matcher, action: Action) -> Result<(), String> {
+        match matcher {
+            Matcher::Section(section) => {
+                let action = match action {
+                    Action::Ignore => SectionAction::Ignore,
+                    Action::Delete => SectionAction::Delete,
+                    _ => return Err("Transforms can not apply to whole sections".into()),
+                };
+                self.mutations.add_section_action(section, action);
+            }
+            Matcher::Literal(section, key) => {
+                self.mutations.add_literal_action(section, key, action);
+            }
+            Matcher::Regex(section, key) => {
+                self.mutations.add_regex_action(section, key, action);
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a filter action for a matcher
+    fn add_filter(&mut self, matcher: Matcher, action: FilterAction) {
+        match matcher {
+            Matcher::Section(section) => {
+                self.filter.add_section_action(section, action);
+            }
+            Matcher::Literal(section, key) => {
+                self.filter.add_literal_action(section, key, action);
+            }
+            Matcher::Regex(section, key) => {
+                self.filter.add_regex_action(section, key, action);
+            }
+        }
+    }
+
+    /// Parse and apply a single (non-empty) line
+    fn parse_line(&mut self, line_no: usize, line: &str) -> Result<(), DirectiveError> {
+        let syntax = |message: String| DirectiveError::Syntax {
+            line: line_no,
+            message,
+        };
+        let tokens = tokenize(line).map_err(syntax)?;
+        let mut tokens = tokens.into_iter();
+        let Some(directive) = tokens.next() else {
+            return Ok(());
+        };
+        match directive.text.as_str() {
+            _ if directive.quoted => {
+                return Err(syntax(format!("Unknown directive \"{}\"", directive.text)));
+            }
+            "source" => {
+                let source = tokens
+                    .next()
+                    .ok_or_else(|| syntax("Missing source".into()))?;
+                self.source = Some(source.text);
+            }
+            "no-warn-multiple-key-matches" => {
+                self.mutations.warn_on_multiple_matches(false);
+                self.filter.warn_on_multiple_matches(false);
+            }
+            "ignore" | "remove" => {
+                let action = if directive.text == "ignore" {
+                    Action::Ignore
+                } else {
+                    Action::Delete
+                };
+                let matcher = parse_matcher(&mut tokens).map_err(syntax)?;
+                self.add_mutation(matcher, action).map_err(syntax)?;
+            }
+            "add:remove" | "add:hide" => {
+                let action = if directive.text == "add:remove" {
+                    FilterAction::Remove
+                } else {
+                    FilterAction::Replace(HIDDEN.into())
+                };
+                let matcher = parse_matcher(&mut tokens).map_err(syntax)?;
+                self.add_filter(matcher, action);
+            }
+            "set" => {
+                let section = tokens
+                    .next()
+                    .ok_or_else(|| syntax("Missing section".into()))?;
+                let key = tokens.next().ok_or_else(|| syntax("Missing key".into()))?;
+                let value = tokens
+                    .next()
+                    .ok_or_else(|| syntax("Missing value".into()))?;
+                let args = parse_args(&mut tokens).map_err(syntax)?;
+                let separator = args.get("separator").unwrap_or("=");
+                self.mutations
+                    .add_setter(section.text, key.text, value.text, separator);
+            }
+            "transform" => {
+                let matcher = parse_matcher(&mut tokens).map_err(syntax)?;
+                let name = tokens
+                    .next()
+                    .ok_or_else(|| syntax("Missing transform name".into()))?;
+                let args = parse_args(&mut tokens).map_err(syntax)?;
+                let transform = TransformerDispatch::from_name(&transform_name(&name.text), &args)
+                    .map_err(|source| DirectiveError::Transform {
+                        line: line_no,
+                        source,
+                    })?;
+                self.add_mutation(matcher, Action::Transform(transform))
+                    .map_err(syntax)?;
+            }
+            other => return Err(syntax(format!("Unknown directive {other}"))),
+        }
+        if let Some(extra) = tokens.next() {
+            return Err(syntax(format!("Unexpected argument {}", extra.text)));
+        }
+        Ok(())
+    }
+}
+
+/// Parse directives
+pub fn parse(input: &str) -> Result<Directives, DirectiveError> {
+    let mut directives = Directives::default();
+    for (idx, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        directives.parse_line(idx + 1, line)?;
+    }
+    Ok(directives)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+    use super::tokenize;
+    use super::DirectiveError;
+    use super::Token;
+    #[cfg(feature = "regex-rules")]
+    use crate::filter::filter_ini_from_str;
+    #[cfg(feature = "regex-rules")]
+    use crate::merge::merge_ini_from_str;
+    #[cfg(feature = "regex-rules")]
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn tokens() {
+        assert_eq!(
+            tokenize(r#"ignore section "a \"b\"" x="," "#).unwrap(),
+            vec![
+                Token {
+                    text: "ignore".into(),
+                    quoted: false
+                },
+                Token {
+                    text: "section".into(),
+                    quoted: false
+                },
+                Token {
+                    text: "a \"b\"".into(),
+                    quoted: true
+                },
+                Token {
+                    text: "x=,".into(),
+                    quoted: true
+                },
+            ]
+        );
+        assert!(tokenize(r#"ignore "a"#).is_err());
+    }
+
+    #[cfg(feature = "regex-rules")]
+    #[test]
+    fn directives() {
+        let directives = parse(indoc! {r#"
+            #!/usr/bin/env chezmoi_modify_manager
+            source auto
+
+            ignore section "a"
+            ignore "section" "k"
+            remove regex "b" "x.*"
+            set "b" "s" "1" separator=" = "
+            transform "b" "l" unsorted-list separator=","
+            add:hide "b" "l"
+        "#})
+        .unwrap();
+        assert_eq!(directives.source.as_deref(), Some("auto"));
+
+        let mutations = directives.mutations.build().unwrap();
+        let result = merge_ini_from_str(
+            "[a]\nk=t\n[section]\nk=t\n[b]\nx1=1\nl=b,a\n",
+            "[a]\nk=s\n[section]\nk=s\n[b]\nx1=2\nl=a,b\n",
+            &mutations,
+        )
+        .unwrap();
+        assert_eq!(
+            result,
+            vec!["[a]", "k=t", "[section]", "k=t", "[b]", "l=b,a", "s = 1"]
+        );
+
+        let filter = directives.filter.build().unwrap();
+        let result = filter_ini_from_str("[b]\nl=a,b\n", &filter).unwrap();
+        assert_eq!(result, vec!["[b]", "l=HIDDEN"]);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            parse("ignore \"a\"\n\nfrobnicate"),
+            Err(DirectiveError::Syntax { line: 1, .. })
+        ));
+        assert!(matches!(
+            parse("ignore section \"a\" extra"),
+            Err(DirectiveError::Syntax { line: 1, .. })
+        ));
+        assert!(matches!(
+            parse("# Comment\ntransform \"a\" \"b\" no-such-transform"),
+            Err(DirectiveError::Transform { line: 2, .. })
+        ));
+    }
+}
@@ -2,26 +2,82 @@
 
 use crate::actions::Actions;
 use crate::actions::ActionsBuilder;
-use crate::loader::Loader;
+use crate::actions::Rule;
+use crate::actions::RuleSet;
+use crate::cancel::CancelToken;
+use crate::limits::LimitError;
+use crate::loader::IniBuffer;
+use crate::loader::LoadError;
 use crate::loader::{self};
-use lending_iterator::prelude::*;
+use crate::output::TrailingNewline;
+use crate::output::{self};
+use crate::progress::Progress;
+use crate::progress::ProgressCounter;
+use crate::Property;
+use crate::SectionName;
 use log::error;
+use std::borrow::Cow;
+use std::fmt::Display;
 use std::io::Read;
 use thiserror::Error;
 
+mod paths;
+mod secrets;
+
+#[cfg(feature = "glob")]
+pub use self::paths::filter_glob;
+pub use self::paths::filter_paths;
+pub use self::paths::FilterPathsOptions;
+pub use self::paths::FilteredPath;
+pub use self::secrets::suggest_secret_rules;
+pub use self::secrets::SecretReason;
+pub use self::secrets::SuggestedRule;
+
 /// Operations that can be set for filtering
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum FilterAction {
     /// Remove a matching entry entirely
     Remove,
     /// Replace the *value* of an entry with the given string.
     /// Separator format (with or without spaces) is auto-detected.
-    Replace(&'static str),
+    Replace(Cow<'static, str>),
+    /// Keep the first and last few characters of the *value* and replace the
+    /// rest with `fill` (such as `ghp_…xyz`).
+    ///
+    /// Values that are not longer than the kept characters are replaced with
+    /// just `fill`.
+    Mask {
+        /// Number of characters to keep at the start
+        keep_prefix: usize,
+        /// Number of characters to keep at the end
+        keep_suffix: usize,
+        /// Replacement for the characters in between
+        fill: Cow<'static, str>,
+    },
+}
+
+impl Display for FilterAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Remove => f.write_str("remove"),
+            Self::Replace(replacement) => write!(f, "replace {replacement:?}"),
+            Self::Mask {
+                keep_prefix,
+                keep_suffix,
+                fill,
+            } => write!(
+                f,
+                "mask keep_prefix={keep_prefix} keep_suffix={keep_suffix} fill={fill:?}"
+            ),
+        }
+    }
 }
 
 impl From<&'_ Self> for FilterAction {
     fn from(value: &'_ Self) -> Self {
-        *value
+        value.clone()
     }
 }
 
@@ -29,6 +85,10 @@ impl From<&'_ Self> for FilterAction {
 pub type FilterActions = Actions<FilterAction, FilterAction>;
 /// Filter actions builder
 pub type FilterActionsBuilder = ActionsBuilder<FilterAction, FilterAction>;
+/// A single filter rule in data form
+pub type FilterRule = Rule<FilterAction, FilterAction>;
+/// A filter rule set in data form, see [`ActionsBuilder::from_serialized`]
+pub type FilterRuleSet = RuleSet<FilterAction, FilterAction>;
 
 /// Error type for INI merger
 #[derive(Debug, Error)]
@@ -36,7 +96,34 @@ pub type FilterActionsBuilder = ActionsBuilder<FilterAction, FilterAction>;
 pub enum FilterError {
     /// An error while loading the INI
     #[error("Failed to load input INI due to {0}")]
-    Load(#[source] Box<dyn std::error::Error + 'static + Send + Sync>),
+    Load(#[source] std::io::Error),
+    /// A resource limit was exceeded by the INI
+    #[error("Resource limit exceeded: {0}")]
+    Limit(#[source] LimitError),
+    /// The filtering was cancelled, see [`filter_ini_cancellable`]
+    #[error("Filtering was cancelled")]
+    Cancelled,
+    /// An error while writing the filtered file, see [`filter_paths`]
+    #[error("Failed to write filtered INI due to {0}")]
+    Write(#[source] std::io::Error),
+    /// An invalid glob pattern, see [`filter_glob`]
+    #[cfg(feature = "glob")]
+    #[error("Invalid glob pattern: {0}")]
+    Pattern(#[source] glob::PatternError),
+}
+
+impl FilterError {
+    fn from_load(err: LoadError) -> Self {
+        match err {
+            LoadError::Limit(inner) => Self::Limit(inner),
+            LoadError::Io(inner) => Self::Load(inner),
+        }
+    }
+}
+
+/// Load the input INI, subject to the limits in the actions
+fn load_input(input: &mut impl Read, actions: &FilterActions) -> Result<IniBuffer, FilterError> {
+    IniBuffer::read_with_limits(input, actions.limits()).map_err(FilterError::from_load)
 }
 
 /// State tracking for the merge algorithm
@@ -49,6 +136,14 @@ struct FilterState {
     pending_lines: Vec<String>,
     /// Name of the current section
     cur_section: String,
+    /// Start of the comment block at the end of the result, that belongs to
+    /// the next section header (only tracked when removing the comments of
+    /// removed sections)
+    comment_block: Option<usize>,
+    /// Comment block at the end of a removed section, that belongs to the
+    /// next section header (only when removing the comments of removed
+    /// sections)
+    removed_comments: Vec<String>,
 }
 
 impl FilterState {
@@ -57,6 +152,8 @@ impl FilterState {
             result: Default::default(),
             pending_lines: Default::default(),
             cur_section: crate::OUTSIDE_SECTION.to_string(),
+            comment_block: None,
+            removed_comments: Vec::new(),
         }
     }
 
@@ -90,57 +187,152 @@ impl FilterState {
     }
 }
 
-pub(crate) fn filter(input: &mut Loader, actions: &FilterActions) -> Vec<String> {
+/// Apply the filter actions to a single property.
+///
+/// Returns `None` if the property should be removed, otherwise the (possibly
+/// replaced) line and value.
+pub(crate) fn filter_property<'a>(
+    actions: &FilterActions,
+    section: &str,
+    key: &str,
+    val: Option<&'a str>,
+    raw: &'a str,
+) -> Option<(Cow<'a, str>, Option<Cow<'a, str>>)> {
+    let action = actions.find_line_action(section, key, val, raw);
+    let replacement = match (action.as_deref(), val) {
+        (None, _) => return Some((Cow::Borrowed(raw), val.map(Cow::Borrowed))),
+        (Some(FilterAction::Remove), _) => return None,
+        // There is no value, nothing to hide...
+        (Some(_), None) => return Some((Cow::Borrowed(raw), None)),
+        (Some(FilterAction::Replace(replacement)), Some(_)) => replacement.clone(),
+        (
+            Some(FilterAction::Mask {
+                keep_prefix,
+                keep_suffix,
+                fill,
+            }),
+            Some(val),
+        ) => Cow::Owned(mask_value(val, *keep_prefix, *keep_suffix, fill)),
+    };
+    let prop = Property {
+        section: SectionName::from_key(section),
+        key,
+        val,
+        raw,
+    };
+    Some((Cow::Owned(prop.with_value(&replacement)), Some(replacement)))
+}
+
+/// Mask all but the first and last few characters of a value
+fn mask_value(val: &str, keep_prefix: usize, keep_suffix: usize, fill: &str) -> String {
+    let len = val.chars().count();
+    if len <= keep_prefix.saturating_add(keep_suffix) {
+        return fill.into();
+    }
+    let prefix: String = val.chars().take(keep_prefix).collect();
+    let suffix: String = val.chars().skip(len - keep_suffix).collect();
+    format!("{prefix}{fill}{suffix}")
+}
+
+pub(crate) fn filter(input: &IniBuffer, actions: &FilterActions) -> Vec<String> {
+    filter_items(actions.dialect().parse(input.as_str()).items(), actions)
+}
+
+pub(crate) fn filter_items<'a>(
+    input: impl Iterator<Item = ini_roundtrip::Item<'a>>,
+    actions: &FilterActions,
+) -> Vec<String> {
+    // Nothing can abort the filtering
+    filter_items_observed(input, actions, |_| Ok(())).unwrap_or_default()
+}
+
+/// Filter the items, calling `observe` before each item (which can abort the
+/// filtering)
+fn filter_items_observed<'a>(
+    input: impl Iterator<Item = ini_roundtrip::Item<'a>>,
+    actions: &FilterActions,
+    mut observe: impl FnMut(&ini_roundtrip::Item<'a>) -> Result<(), FilterError>,
+) -> Result<Vec<String>, FilterError> {
     let mut state = FilterState::new();
 
-    while let Some(ref entry) = input.next() {
-        match *entry {
+    for entry in input {
+        observe(&entry)?;
+        match entry {
             ini_roundtrip::Item::Error(raw) => {
-                error!(target: "ini-merge", "Failed to parse line, copying verbatim: {raw}");
-                state.push_pending(raw.into());
+                state.comment_block = None;
+                state.removed_comments.clear();
+                match actions.find_parse_error_action(&state.cur_section, raw) {
+                    // There is no value to replace, so remove the whole line
+                    Some(_) => (),
+                    None => {
+                        error!(target: "ini-merge", "Failed to parse line, copying verbatim: {raw}");
+                        state.push_pending(raw.into());
+                    }
+                }
             }
             ini_roundtrip::Item::Comment { raw } | ini_roundtrip::Item::Blank { raw } => {
                 match actions.find_section_action(&state.cur_section) {
-                    None | Some(FilterAction::Replace(_)) => state.maybe_push(raw.into()),
-                    Some(FilterAction::Remove) => (),
+                    None | Some(FilterAction::Replace(_) | FilterAction::Mask { .. }) => {
+                        if actions.removes_section_comments()
+                            && matches!(entry, ini_roundtrip::Item::Comment { .. })
+                            && state.comment_block.is_none()
+                            && state.pending_lines.is_empty()
+                        {
+                            state.comment_block = Some(state.result.len());
+                        }
+                        state.maybe_push(raw.into());
+                    }
+                    Some(FilterAction::Remove) => {
+                        if actions.removes_section_comments()
+                            && (matches!(entry, ini_roundtrip::Item::Comment { .. })
+                                || !state.removed_comments.is_empty())
+                        {
+                            state.removed_comments.push(raw.into());
+                        }
+                    }
                 }
             }
             ini_roundtrip::Item::Section { name, raw } => {
                 state.cur_section.clear();
                 state.cur_section.push_str(name);
                 state.pending_lines.clear();
+                // The comments before the header belong to this section
+                let mut comments = match state.comment_block.take() {
+                    Some(start) => state.result.split_off(start),
+                    None => std::mem::take(&mut state.removed_comments),
+                };
 
                 match actions.find_section_action(name) {
                     Some(FilterAction::Remove) => (),
                     // For sections, replace all the values in the section, not the section itself.
-                    Some(FilterAction::Replace(_)) => state.push_pending(raw.into()),
-                    None => state.push_pending(raw.into()),
+                    Some(FilterAction::Replace(_) | FilterAction::Mask { .. }) | None => {
+                        state.pending_lines.append(&mut comments);
+                        state.push_pending(actions.dialect().section_header(raw).into());
+                    }
                 }
             }
             ini_roundtrip::Item::SectionEnd => (),
             ini_roundtrip::Item::Property { key, val, raw } => {
-                let action = actions.find_action(&state.cur_section, key);
-                match action.as_deref() {
-                    None => state.push(raw.into()),
-                    Some(FilterAction::Remove) => (),
-                    Some(FilterAction::Replace(replacement)) => {
-                        // Extract the separator
-                        match val {
-                            Some(value) => {
-                                let separator =
-                                    raw.get(key.len()..(raw.len() - value.len())).unwrap_or("=");
-                                state.push(format!("{key}{separator}{replacement}"));
-                            }
-                            // There is no value, nothing to hide...
-                            None => state.push(raw.into()),
-                        }
-                    }
+                state.comment_block = None;
+                state.removed_comments.clear();
+                if let Some((line, _)) = filter_property(actions, &state.cur_section, key, val, raw)
+                {
+                    state.push(line.into_owned());
                 }
             }
         }
     }
 
-    state.result
+    if actions.trims_trailing_whitespace() {
+        state
+            .result
+            .iter_mut()
+            .for_each(output::trim_trailing_whitespace);
+    }
+    if actions.squashes_blank_lines() {
+        output::squash_blank_lines(&mut state.result, &mut false);
+    }
+    Ok(state.result)
 }
 
 /// Filter an INI file
@@ -148,8 +340,106 @@ pub fn filter_ini(
     input: &mut impl Read,
     actions: &FilterActions,
 ) -> Result<Vec<String>, FilterError> {
-    let mut target = loader::load_ini(input).map_err(|inner| FilterError::Load(inner.into()))?;
-    Ok(filter(&mut target, actions))
+    let target = load_input(input, actions)?;
+    Ok(filter(&target, actions))
+}
+
+/// Filter an INI file read asynchronously
+///
+/// The input is read asynchronously, the filtering itself is done
+/// synchronously.
+#[cfg(feature = "tokio")]
+pub async fn filter_ini_async(
+    input: &mut (impl tokio::io::AsyncRead + Unpin),
+    actions: &FilterActions,
+) -> Result<Vec<String>, FilterError> {
+    let target = IniBuffer::read_async(input, actions.limits())
+        .await
+        .map_err(FilterError::from_load)?;
+    Ok(filter(&target, actions))
+}
+
+/// Filter an INI file by memory mapping it
+///
+/// This avoids copying the file into memory, which helps for very large
+/// files.
+///
+/// The file must not be modified while the filtering is in progress. Doing so
+/// is undefined behaviour.
+#[cfg(feature = "mmap")]
+pub fn filter_ini_mmap(
+    path: &std::path::Path,
+    actions: &FilterActions,
+) -> Result<Vec<String>, FilterError> {
+    let target = IniBuffer::mmap(path, actions.limits()).map_err(FilterError::from_load)?;
+    Ok(filter(&target, actions))
+}
+
+/// Filter an INI file that is already in memory
+///
+/// This avoids copying the input.
+pub fn filter_ini_from_str(
+    input: &str,
+    actions: &FilterActions,
+) -> Result<Vec<String>, FilterError> {
+    loader::check_limits(input, actions.limits()).map_err(FilterError::Limit)?;
+    Ok(filter_items(
+        actions.dialect().parse(input).items(),
+        actions,
+    ))
+}
+
+/// Filter an INI file that is already in memory, stopping with
+/// [`FilterError::Cancelled`] if the token is cancelled.
+///
+/// Useful to abort filtering of huge files from another thread.
+pub fn filter_ini_cancellable(
+    input: &str,
+    actions: &FilterActions,
+    cancel: &CancelToken,
+) -> Result<Vec<String>, FilterError> {
+    loader::check_limits(input, actions.limits()).map_err(FilterError::Limit)?;
+    filter_items_observed(actions.dialect().parse(input).items(), actions, |_| {
+        if cancel.is_cancelled() {
+            Err(FilterError::Cancelled)
+        } else {
+            Ok(())
+        }
+    })
+}
+
+/// Filter an INI file, calling `callback` with the progress every `every`
+/// lines and when done.
+///
+/// Useful to show progress for large files.
+pub fn filter_ini_with_progress(
+    input: &mut impl Read,
+    actions: &FilterActions,
+    every: usize,
+    callback: impl FnMut(Progress),
+) -> Result<Vec<String>, FilterError> {
+    let input = load_input(input, actions)?;
+    let mut progress = ProgressCounter::new(input.as_str().len(), every, callback);
+    let items = actions.dialect().parse(input.as_str());
+    let result = filter_items_observed(items.items(), actions, |item| {
+        progress.item(item);
+        Ok(())
+    })?;
+    progress.finish();
+    Ok(result)
+}
+
+/// Filter an INI file, giving the result as a string.
+///
+/// The final newline is handled according to `newline`.
+pub fn filter_ini_to_string(
+    input: &mut impl Read,
+    actions: &FilterActions,
+    newline: TrailingNewline,
+) -> Result<String, FilterError> {
+    let target = load_input(input, actions)?;
+    let lines = filter(&target, actions);
+    Ok(output::join_lines(lines, target.as_str(), newline))
 }
 
 #[cfg(test)]
@@ -158,6 +448,7 @@ mod tests {
     use super::FilterActionsBuilder;
     use indoc::indoc;
     use pretty_assertions::assert_eq;
+    #[cfg(feature = "regex-rules")]
     use std::collections::VecDeque;
 
     const INPUT: &str = indoc! {"
@@ -194,6 +485,7 @@ mod tests {
 
         "};
 
+    #[cfg(feature = "regex-rules")]
     const EXPECTED: &str = indoc! {"
         ; A comment
         a=1
@@ -214,6 +506,7 @@ mod tests {
 
         "};
 
+    #[cfg(feature = "regex-rules")]
     #[test]
     fn test_merge_ini() {
         let mut input: VecDeque<_> = INPUT.as_bytes().to_owned().into();
@@ -223,14 +516,215 @@ mod tests {
         actions.add_section_action("s4", FilterAction::Remove);
         actions.add_literal_action("s5", "b", FilterAction::Remove);
         // Note: priority is not guaranteed when there are overlapping matches
-        actions.add_regex_action(".*", ".*_replaced", FilterAction::Replace("HIDDEN"));
+        actions.add_regex_action(".*", ".*_replaced", FilterAction::Replace("HIDDEN".into()));
         actions.add_regex_action(".*", ".*_removed", FilterAction::Remove);
         actions.add_regex_action(".*_removed", ".*", FilterAction::Remove);
-        actions.add_regex_action(".*_replaced", ".*", FilterAction::Replace("HIDDEN"));
+        actions.add_regex_action(".*_replaced", ".*", FilterAction::Replace("HIDDEN".into()));
         let mutations = actions.build().unwrap();
 
         let result = super::filter_ini(&mut input, &mutations).unwrap();
 
         assert_eq!(EXPECTED, result.join("\n") + "\n");
     }
+
+    #[cfg(not(feature = "regex-rules"))]
+    #[test]
+    fn test_literal_only() {
+        use crate::actions::ActionsBuilderError;
+
+        let mut actions = FilterActionsBuilder::new();
+        actions.add_regex_action(".*", ".*_removed", FilterAction::Remove);
+        assert!(matches!(
+            actions.build(),
+            Err(ActionsBuilderError::RegexUnsupported)
+        ));
+
+        let mut actions = FilterActionsBuilder::new();
+        actions.add_section_action("s4", FilterAction::Remove);
+        actions.add_top_level_action("b_removed", FilterAction::Remove);
+        let actions = actions.build().unwrap();
+        let result = super::filter_ini(&mut INPUT.as_bytes(), &actions).unwrap();
+        assert_eq!(result[2], "c_replaced=3");
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_filter_ini_async() {
+        let mut actions = FilterActionsBuilder::new();
+        actions.add_section_action("s4", FilterAction::Remove);
+        let actions = actions.build().unwrap();
+
+        let mut input: VecDeque<_> = INPUT.as_bytes().to_owned().into();
+        let expected = super::filter_ini(&mut input, &actions).unwrap();
+        let result = super::filter_ini_async(&mut INPUT.as_bytes(), &actions)
+            .await
+            .unwrap();
+
+        assert_eq!(expected, result);
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_filter_ini_mmap() {
+        let path = std::env::temp_dir().join(format!("ini-merge-mmap-{}.ini", std::process::id()));
+        std::fs::write(&path, INPUT).unwrap();
+
+        let mut actions = FilterActionsBuilder::new();
+        actions.add_section_action("s4", FilterAction::Remove);
+        let actions = actions.build().unwrap();
+
+        let mut input: VecDeque<_> = INPUT.as_bytes().to_owned().into();
+        let expected = super::filter_ini(&mut input, &actions).unwrap();
+        let result = super::filter_ini_mmap(&path, &actions);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_filter_ini_serialized() {
+        let ruleset: super::FilterRuleSet = serde_json::from_str(
+            r#"{"rules": [
+                {"type": "section", "section": "s4", "action": "remove"},
+                {"type": "literal", "section": "s5", "key": "b", "action": "remove"},
+                {"type": "regex", "section": ".*", "key": ".*_replaced", "action": {"replace": "HIDDEN"}},
+                {"type": "regex", "section": ".*", "key": ".*_removed", "action": "remove"},
+                {"type": "regex", "section": ".*_removed", "key": ".*", "action": "remove"},
+                {"type": "regex", "section": ".*_replaced", "key": ".*", "action": {"replace": "HIDDEN"}}
+            ]}"#,
+        )
+        .unwrap();
+        let actions = FilterActionsBuilder::from_serialized(ruleset)
+            .build()
+            .unwrap();
+
+        let result = super::filter_ini_from_str(INPUT, &actions).unwrap();
+
+        assert_eq!(EXPECTED, result.join("\n") + "\n");
+    }
+
+    #[cfg(feature = "regex-rules")]
+    #[test]
+    fn test_mask() {
+        let mut builder = FilterActionsBuilder::new();
+        builder.add_regex_action(
+            "s",
+            ".*",
+            FilterAction::Mask {
+                keep_prefix: 4,
+                keep_suffix: 3,
+                fill: "…".into(),
+            },
+        );
+        let actions = builder.build().unwrap();
+        let result = super::filter_ini_from_str(
+            "[s]\ntoken = ghp_abcdefxyz\nshort=abcdefg\nempty\n[t]\nk=v\n",
+            &actions,
+        )
+        .unwrap();
+        assert_eq!(
+            result,
+            vec!["[s]", "token = ghp_…xyz", "short=…", "empty", "[t]", "k=v"]
+        );
+    }
+
+    #[test]
+    fn test_classifier() {
+        let mut builder = FilterActionsBuilder::new();
+        builder
+            .add_literal_action("s", "kept", FilterAction::Replace("HIDDEN".into()))
+            .classifier(|_section, key, val| match val {
+                Some(val) if val.starts_with("ghp_") => Some(FilterAction::Replace("TOKEN".into())),
+                _ if key.starts_with("tmp") => Some(FilterAction::Remove),
+                _ => None,
+            });
+        let actions = builder.build().unwrap();
+        let result = super::filter_ini_from_str(
+            "[s]\nkept=ghp_1\nt=ghp_2\nk=v\n[only_removed]\ntmp_a=1\n",
+            &actions,
+        )
+        .unwrap();
+        assert_eq!(result, vec!["[s]", "kept=HIDDEN", "t=TOKEN", "k=v"]);
+    }
+
+    #[test]
+    fn test_replace_keeps_separator() {
+        let mut dialect = crate::dialect::Dialect::new();
+        dialect.line_continuation(true);
+        let mut builder = FilterActionsBuilder::new();
+        for key in ["key : value", "key : a", "t", "a", "ключ", "n", "c"] {
+            builder.add_literal_action("s", key, FilterAction::Replace("HIDDEN".into()));
+        }
+        builder.dialect(dialect);
+        let actions = builder.build().unwrap();
+        let input = "[s]\nkey : value\nkey : a = b\nt\t=\tv\na = a\nключ = значение\nn = v\u{a0}\nc = 1 \\\n  2\n";
+        let result = super::filter_ini_from_str(input, &actions).unwrap();
+        assert_eq!(
+            result,
+            vec![
+                "[s]",
+                "key : value",
+                "key : a = HIDDEN",
+                "t\t=\tHIDDEN",
+                "a = HIDDEN",
+                "ключ = HIDDEN",
+                "n = HIDDEN",
+                "c = HIDDEN",
+            ]
+        );
+    }
+
+    #[cfg(feature = "regex-rules")]
+    #[test]
+    fn test_display() {
+        let mut builder = FilterActionsBuilder::new();
+        builder
+            .add_regex_action("s", "b.*", FilterAction::Remove)
+            .add_literal_action("z", "k", FilterAction::Replace("HIDDEN".into()))
+            .add_literal_action("a", "k", FilterAction::Remove)
+            .add_section_action("gone", FilterAction::Remove)
+            .add_regex_action("s", "a.*", FilterAction::Remove);
+        let actions = builder.build().unwrap();
+        assert_eq!(
+            actions.to_string(),
+            indoc! {r#"
+                section "gone": remove
+                "a" "k": remove
+                "z" "k": replace "HIDDEN"
+                regex "s" "b.*": remove
+                regex "s" "a.*": remove
+            "#}
+        );
+    }
+
+    #[test]
+    fn test_filter_ini_cancellable() {
+        use crate::cancel::CancelToken;
+
+        let actions = FilterActionsBuilder::new().build().unwrap();
+        let cancel = CancelToken::new();
+        let handle = cancel.clone();
+        assert_eq!(
+            super::filter_ini_cancellable("[s]\nk=v\n", &actions, &cancel).unwrap(),
+            vec!["[s]", "k=v"]
+        );
+        handle.cancel();
+        assert!(matches!(
+            super::filter_ini_cancellable("[s]\nk=v\n", &actions, &cancel),
+            Err(super::FilterError::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn test_filter_ini_with_progress() {
+        let actions = FilterActionsBuilder::new().build().unwrap();
+        let mut lines = Vec::new();
+        let result =
+            super::filter_ini_with_progress(&mut "a=1\nb=2\n".as_bytes(), &actions, 1, |p| {
+                lines.push(p.lines);
+            });
+        assert_eq!(result.unwrap(), vec!["a=1", "b=2"]);
+        assert_eq!(lines, vec![1, 2, 2]);
+    }
 }
@@ -0,0 +1,287 @@
+//! Filtering of many files in place
+
+use super::filter;
+use super::load_input;
+use super::FilterActions;
+use super::FilterError;
+use crate::output;
+use crate::output::TrailingNewline;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+/// Options for [`filter_paths`]
+#[derive(Debug, Clone, Default)]
+pub struct FilterPathsOptions {
+    /// Only report which files would change
+    dry_run: bool,
+    /// Policy for the newline at the end of the files
+    newline: TrailingNewline,
+}
+
+impl FilterPathsOptions {
+    /// Create the default options
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set if the files should only be checked, not written (default: false)
+    pub fn dry_run(&mut self, value: bool) -> &mut Self {
+        self.dry_run = value;
+        self
+    }
+
+    /// Set the policy for the newline at the end of the files (default:
+    /// [`TrailingNewline::Preserve`])
+    pub fn trailing_newline(&mut self, newline: TrailingNewline) -> &mut Self {
+        self.newline = newline;
+        self
+    }
+}
+
+/// Outcome of filtering one file, see [`filter_paths`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct FilteredPath {
+    /// The file
+    pub path: PathBuf,
+    /// If the file was changed by the filtering (or would be, for a dry run)
+    pub result: Result<bool, FilterError>,
+}
+
+/// Filter many files in place
+///
+/// Changed files are replaced atomically, by writing a temporary file next
+/// to the file and renaming it over the file (symlinks are followed).
+/// Unchanged files are not written. Lines are joined with the line ending of
+/// the file, and a file whose lines only differ in their line endings after
+/// filtering (one mixing `\n` and `\r\n`) counts as unchanged.
+///
+/// A failure for one file does not stop the others, the outcome for each file
+/// is reported in the order of `paths`.
+pub fn filter_paths(
+    paths: impl IntoIterator<Item = impl AsRef<Path>>,
+    actions: &FilterActions,
+    options: &FilterPathsOptions,
+) -> Vec<FilteredPath> {
+    paths
+        .into_iter()
+        .map(|path| {
+            let path = path.as_ref();
+            FilteredPath {
+                path: path.to_path_buf(),
+                result: filter_path(path, actions, options),
+            }
+        })
+        .collect()
+}
+
+/// Filter all files matching a glob pattern (such as `~/.config/**/*.ini`,
+/// without the `~` expansion) in place, see [`filter_paths`]
+///
+/// Directories matching the pattern are skipped. Errors if the pattern is
+/// invalid.
+#[cfg(feature = "glob")]
+pub fn filter_glob(
+    pattern: &str,
+    actions: &FilterActions,
+    options: &FilterPathsOptions,
+) -> Result<Vec<FilteredPath>, FilterError> {
+    let mut result = Vec::new();
+    for entry in glob::glob(pattern).map_err(FilterError::Pattern)? {
+        match entry {
+            Ok(path) if path.is_dir() => (),
+            Ok(path) => result.extend(filter_paths([path], actions, options)),
+            Err(err) => result.push(FilteredPath {
+                path: err.path().to_path_buf(),
+                result: Err(FilterError::Load(err.into())),
+            }),
+        }
+    }
+    Ok(result)
+}
+
+/// Filter a single file in place
+fn filter_path(
+    path: &Path,
+    actions: &FilterActions,
+    options: &FilterPathsOptions,
+) -> Result<bool, FilterError> {
+    let mut file = File::open(path).map_err(FilterError::Load)?;
+    let input = load_input(&mut file, actions)?;
+    drop(file);
+    let lines = filter(&input, actions);
+    let output = output::join_lines(lines, input.as_str(), options.newline);
+    if output == input.as_str() || same_lines(&output, input.as_str()) {
+        return Ok(false);
+    }
+    if !options.dry_run {
+        replace_file(path, &output).map_err(FilterError::Write)?;
+    }
+    Ok(true)
+}
+
+/// Check if two strings only differ in their line endings
+fn same_lines(a: &str, b: &str) -> bool {
+    a.ends_with('\n') == b.ends_with('\n') && a.lines().eq(b.lines())
+}
+
+/// Counter to give temporary files unique names within the process
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Atomically replace the contents of a file, keeping its permissions
+fn replace_file(path: &Path, contents: &str) -> std::io::Result<()> {
+    let path = std::fs::canonicalize(path)?;
+    let Some(file_name) = path.file_name() else {
+        return Err(ErrorKind::InvalidInput.into());
+    };
+    let (tmp_path, mut tmp) = loop {
+        let mut tmp_name = OsString::from(".");
+        tmp_name.push(file_name);
+        tmp_name.push(format!(
+            ".{}-{}.ini-merge-tmp",
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let tmp_path = path.with_file_name(tmp_name);
+        match File::options().write(true).create_new(true).open(&tmp_path) {
+            Ok(tmp) => break (tmp_path, tmp),
+            // Left over from an earlier run that crashed, try the next name
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    };
+    let result = tmp
+        .write_all(contents.as_bytes())
+        .and_then(|()| tmp.set_permissions(std::fs::metadata(&path)?.permissions()))
+        .and_then(|()| tmp.sync_all())
+        .and_then(|()| std::fs::rename(&tmp_path, &path));
+    if result.is_err() {
+        std::fs::remove_file(&tmp_path).ok();
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::filter_paths;
+    use super::FilterPathsOptions;
+    use crate::filter::FilterAction;
+    use crate::filter::FilterActionsBuilder;
+    use crate::filter::FilterError;
+    use pretty_assertions::assert_eq;
+
+    use std::path::PathBuf;
+
+    /// Temporary directory, removed when dropped (also if the test fails)
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    #[test]
+    fn filter_in_place() {
+        let tmp = TempDir::new("ini-merge-filter");
+        let dir = &tmp.0;
+        let changed = dir.join("changed.ini");
+        let unchanged = dir.join("unchanged.ini");
+        let missing = dir.join("missing.ini");
+        std::fs::write(&changed, "[s]\ntoken = abc\nk = v\n").unwrap();
+        std::fs::write(&unchanged, "[s]\nk = v\n").unwrap();
+
+        let mut builder = FilterActionsBuilder::new();
+        builder.add_literal_action("s", "token", FilterAction::Replace("HIDDEN".into()));
+        let actions = builder.build().unwrap();
+        let paths = [&changed, &unchanged, &missing];
+
+        let mut options = FilterPathsOptions::new();
+        options.dry_run(true);
+        let report = filter_paths(paths, &actions, &options);
+        let results: Vec<_> = report
+            .iter()
+            .map(|file| file.result.as_ref().ok())
+            .collect();
+        assert_eq!(results, [Some(&true), Some(&false), None]);
+        assert!(matches!(report[2].result, Err(FilterError::Load(_))));
+        assert_eq!(
+            std::fs::read_to_string(&changed).unwrap(),
+            "[s]\ntoken = abc\nk = v\n"
+        );
+
+        // A temporary file left over from a crash is not in the way
+        let stale = dir.join(format!(
+            ".changed.ini.{}-0.ini-merge-tmp",
+            std::process::id()
+        ));
+        std::fs::write(&stale, "").unwrap();
+        let report = filter_paths(paths, &actions, &FilterPathsOptions::new());
+        std::fs::remove_file(&stale).unwrap();
+        assert_eq!(report[0].path, changed);
+        assert!(matches!(report[0].result, Ok(true)));
+        assert_eq!(
+            std::fs::read_to_string(&changed).unwrap(),
+            "[s]\ntoken = HIDDEN\nk = v\n"
+        );
+        let mut entries: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        entries.sort_unstable();
+        assert_eq!(entries, ["changed.ini", "unchanged.ini"]);
+
+        #[cfg(feature = "glob")]
+        {
+            let pattern = dir.join("*.ini");
+            let report = super::filter_glob(pattern.to_str().unwrap(), &actions, &options).unwrap();
+            let paths: Vec<_> = report.iter().map(|file| &file.path).collect();
+            assert_eq!(paths, [&changed, &unchanged]);
+        }
+    }
+
+    #[test]
+    fn line_endings() {
+        let tmp = TempDir::new("ini-merge-filter-crlf");
+        let changed = tmp.0.join("changed.ini");
+        let unchanged = tmp.0.join("unchanged.ini");
+        let mixed = tmp.0.join("mixed.ini");
+        std::fs::write(&changed, "[s]\r\ntoken = abc\r\nk = v\r\n").unwrap();
+        std::fs::write(&unchanged, "[s]\r\nk = v\r\n").unwrap();
+        std::fs::write(&mixed, "[s]\r\nk = v\n").unwrap();
+
+        let mut builder = FilterActionsBuilder::new();
+        builder.add_literal_action("s", "token", FilterAction::Replace("HIDDEN".into()));
+        let actions = builder.build().unwrap();
+        let report = filter_paths(
+            [&changed, &unchanged, &mixed],
+            &actions,
+            &FilterPathsOptions::new(),
+        );
+        let results: Vec<_> = report
+            .iter()
+            .map(|file| file.result.as_ref().ok())
+            .collect();
+        assert_eq!(results, [Some(&true), Some(&false), Some(&false)]);
+        assert_eq!(
+            std::fs::read_to_string(&changed).unwrap(),
+            "[s]\r\ntoken = HIDDEN\r\nk = v\r\n"
+        );
+        assert_eq!(std::fs::read_to_string(&mixed).unwrap(), "[s]\r\nk = v\n");
+    }
+}
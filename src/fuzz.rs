@@ -0,0 +1,260 @@
+//! Generators for property based testing and fuzzing (requires the
+//! `arbitrary` feature)
+//!
+//! [`ArbitraryIni`] generates INI documents and [`ArbitraryRules`] generates
+//! rule sets. Both draw section and key names from small pools, so that
+//! generated documents and rules overlap. [`check_merge`] checks the
+//! invariants that should hold for any merge.
+//!
+//! ```
+//! use arbitrary::Arbitrary;
+//! use arbitrary::Unstructured;
+//! use ini_merge::fuzz::check_merge;
+//! use ini_merge::fuzz::ArbitraryIni;
+//! use ini_merge::fuzz::ArbitraryRules;
+//!
+//! let data = [7u8; 256];
+//! let mut u = Unstructured::new(&data);
+//! let target = ArbitraryIni::arbitrary(&mut u).unwrap();
+//! let source = ArbitraryIni::arbitrary(&mut u).unwrap();
+//! let rules = ArbitraryRules::arbitrary(&mut u).unwrap();
+//! check_merge(&target.0, &source.0, &rules.0).unwrap();
+//! ```
+
+use crate::merge::merge_ini_from_str;
+use crate::merge::mutations::Action;
+use crate::merge::mutations::MutationRule;
+use crate::merge::mutations::MutationsBuilder;
+use crate::merge::mutations::MutationsRuleSet;
+use crate::merge::mutations::SectionAction;
+use crate::merge::MergeError;
+use crate::OUTSIDE_SECTION;
+use arbitrary::Arbitrary;
+use arbitrary::Unstructured;
+use ini_roundtrip::Item;
+use ini_roundtrip::Parser;
+use std::collections::HashMap;
+use std::fmt::Write;
+use thiserror::Error;
+
+/// Section names used by the generators (including a `][` one)
+const SECTIONS: &[&str] = &["a", "b", "c d", "x][y", "Sec"];
+/// Key names used by the generators
+const KEYS: &[&str] = &["k", "key", "k2", "a.b", "Key"];
+/// Values used by the generators
+const VALUES: &[&str] = &["", "1", "2", "a,b", "b,a", "some value", "=", "x;y"];
+/// Whitespace used by the generators
+const SPACES: &[&str] = &["", "", " ", "  ", "\t"];
+
+/// A random INI document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArbitraryIni(pub String);
+
+impl<'a> Arbitrary<'a> for ArbitraryIni {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut doc = String::new();
+        for _ in 0..u.int_in_range(0..=40)? {
+            let ws1 = u.choose(SPACES)?;
+            let ws2 = u.choose(SPACES)?;
+            let ws3 = u.choose(SPACES)?;
+            // Writing to a String can not fail
+            let _ = match u.int_in_range(0..=6)? {
+                0 => writeln!(doc, "{ws1}[{}]", u.choose(SECTIONS)?),
+                1 => writeln!(doc, "{ws1}{}{ws2}comment", u.choose(&[";", "#"])?),
+                2 => writeln!(doc, "{ws1}"),
+                _ => writeln!(
+                    doc,
+                    "{ws1}{}{ws2}={ws3}{}",
+                    u.choose(KEYS)?,
+                    u.choose(VALUES)?
+                ),
+            };
+        }
+        if u.arbitrary()? {
+            doc.pop();
+        }
+        Ok(Self(doc))
+    }
+}
+
+/// A random rule set
+#[derive(Debug, Clone)]
+pub struct ArbitraryRules(pub MutationsRuleSet);
+
+/// Generate a random action for keys (transforms are not generated)
+fn arbitrary_action(u: &mut Unstructured<'_>) -> arbitrary::Result<Action> {
+    Ok(match u.int_in_range(0..=2)? {
+        0 => Action::Ignore,
+        1 => Action::IgnoreButRequire,
+        _ => Action::Delete,
+    })
+}
+
+impl<'a> Arbitrary<'a> for ArbitraryRules {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut rules = Vec::new();
+        for _ in 0..u.int_in_range(0..=8)? {
+            let section = u.choose(SECTIONS)?.to_string();
+            let key = u.choose(KEYS)?.to_string();
+            rules.push(match u.int_in_range(0..=3)? {
+                0 => MutationRule::Section {
+                    section,
+                    action: if u.arbitrary()? {
+                        SectionAction::Ignore
+                    } else {
+                        SectionAction::Delete
+                    },
+                },
+                1 => MutationRule::Literal {
+                    section,
+                    key,
+                    action: arbitrary_action(u)?,
+                },
+                2 => MutationRule::Regex {
+                    section: format!("^{}$", regex::escape(&section)),
+                    key: u.choose(&["^k.*$", "^.*$", "^key$"])?.to_string(),
+                    action: arbitrary_action(u)?,
+                },
+                _ => MutationRule::Set {
+                    section,
+                    key,
+                    value: u.choose(VALUES)?.to_string(),
+                    separator: u.choose(&["=", " = "])?.to_string(),
+                },
+            });
+        }
+        let mut ruleset = MutationsRuleSet::new(rules);
+        ruleset.warn_on_multiple_matches = false;
+        ruleset.setters_override_section_actions = u.arbitrary()?;
+        ruleset.strict_sync = u.arbitrary()?;
+        ruleset.delete_top_level_comments = u.arbitrary()?;
+        Ok(Self(ruleset))
+    }
+}
+
+/// A violated merge invariant, see [`check_merge`]
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum InvariantViolation {
+    /// The merge failed
+    #[error("Merge failed: {0}")]
+    Merge(#[from] MergeError),
+    /// The rule set failed to build
+    #[error("Failed to build rules: {0}")]
+    Rules(String),
+    /// The output has more lines that fail to parse than the target
+    #[error("Output does not re-parse")]
+    OutputParse,
+    /// An ignored key doesn't keep its target value
+    #[error("Ignored key {section}/{key} changed")]
+    IgnoredKeyChanged {
+        /// Section of the key
+        section: String,
+        /// The key
+        key: String,
+    },
+    /// A deleted key is present in the output
+    #[error("Deleted key {section}/{key} is present")]
+    DeletedKeyPresent {
+        /// Section of the key
+        section: String,
+        /// The key
+        key: String,
+    },
+}
+
+/// Raw property lines by section and key, and the number of error lines
+type Properties<'a> = (HashMap<(&'a str, &'a str), Vec<&'a str>>, usize);
+
+/// Collect the property lines of a document
+fn properties(data: &str) -> Properties<'_> {
+    let mut result: HashMap<_, Vec<_>> = HashMap::new();
+    let mut errors = 0;
+    let mut section = OUTSIDE_SECTION;
+    for item in Parser::new(data) {
+        match item {
+            Item::Section { name, .. } => section = name,
+            Item::Property { key, raw, .. } => result.entry((section, key)).or_default().push(raw),
+            Item::Error(_) => errors += 1,
+            Item::SectionEnd | Item::Comment { .. } | Item::Blank { .. } => (),
+        }
+    }
+    (result, errors)
+}
+
+/// Merge `source` into `target` and check the invariants that should hold
+/// for any merge:
+///
+/// * The output re-parses (with no more errors than the target has).
+/// * Keys whose action is [`Action::Ignore`] or [`Action::IgnoreButRequire`]
+///   keep all their target lines.
+/// * Keys whose action is [`Action::Delete`] are not present in the output.
+pub fn check_merge(
+    target: &str,
+    source: &str,
+    ruleset: &MutationsRuleSet,
+) -> Result<(), InvariantViolation> {
+    let mutations = MutationsBuilder::from_serialized(ruleset.clone())
+        .build()
+        .map_err(|err| InvariantViolation::Rules(err.to_string()))?;
+    let output = merge_ini_from_str(target, source, &mutations)?.join("\n");
+    let (target_props, target_errors) = properties(target);
+    let (output_props, output_errors) = properties(&output);
+    if output_errors > target_errors {
+        return Err(InvariantViolation::OutputParse);
+    }
+    for ((section, key), lines) in &target_props {
+        let output_lines = output_props.get(&(*section, *key));
+        match mutations.find_action(section, key).as_deref() {
+            Some(Action::Ignore | Action::IgnoreButRequire) if output_lines != Some(lines) => {
+                return Err(InvariantViolation::IgnoredKeyChanged {
+                    section: section.to_string(),
+                    key: key.to_string(),
+                });
+            }
+            Some(Action::Delete) if output_lines.is_some() => {
+                return Err(InvariantViolation::DeletedKeyPresent {
+                    section: section.to_string(),
+                    key: key.to_string(),
+                });
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_merge;
+    use super::ArbitraryIni;
+    use super::ArbitraryRules;
+    use arbitrary::Arbitrary;
+    use arbitrary::Unstructured;
+
+    #[test]
+    fn merge_invariants() {
+        // Simple deterministic pseudo random data (xorshift)
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let data: Vec<u8> = (0..200_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state.to_le_bytes()[0]
+            })
+            .collect();
+        let mut u = Unstructured::new(&data);
+        for _ in 0..300 {
+            let target = ArbitraryIni::arbitrary(&mut u).unwrap();
+            let source = ArbitraryIni::arbitrary(&mut u).unwrap();
+            let rules = ArbitraryRules::arbitrary(&mut u).unwrap();
+            if let Err(err) = check_merge(&target.0, &source.0, &rules.0) {
+                panic!(
+                    "{err}\ntarget:\n{}\nsource:\n{}\nrules: {:?}",
+                    target.0, source.0, rules.0
+                );
+            }
+        }
+    }
+}
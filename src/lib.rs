@@ -10,19 +10,66 @@
 //!   asymmetric: The values of the source are preferred unless specific rules
 //!   have been provided for those sections and/or keys. Formatting is
 //!   preserved. See [`merge::merge_ini`].
-//! * Filtering of an INI file based on a rule set
+//! * Filtering of an INI file based on a rule set (also of many files in
+//!   place, see [`filter::filter_paths`])
+//! * Deriving both from a single rule set, see [`combined::CombinedRules`]
+//! * Semantic comparison of INI files, see [`compare::diff_ini`]
+//! * Loading and parsing of INI files without copying, see
+//!   [`loader::IniBuffer`].
+//! * Streaming parsing with line numbers, see [`parse::events`].
+//! * Lossless processing of files that are not valid UTF-8 (with the `bytes`
+//!   feature), see `bytes` module.
+//! * Parsing of the directive language of `chezmoi_modify_manager`, see
+//!   [`directives::parse`].
+//!
+//! The commonly used types are available from [`prelude`].
+//!
+//! Optional Python bindings are available with the `python` feature, see
+//! `python` module.
+//!
+//! Regex rules need the (default) `regex-rules` feature. Without it only
+//! literal and prefix rules can be used, for a smaller build.
 
+/// Re-export `ini_roundtrip` (for the items given by [`loader::IniBuffer`])
+pub use ini_roundtrip;
 /// Re-export keyring
 #[cfg(feature = "keyring")]
 pub use keyring;
-// Re-export sub-module
-pub use merge::mutations;
+pub use output::TrailingNewline;
 
 pub mod actions;
+#[cfg(feature = "bytes")]
+pub mod bytes;
+pub mod cancel;
+pub mod combined;
+pub mod compare;
+pub mod dialect;
+pub mod directives;
 pub mod filter;
-mod loader;
+#[cfg(feature = "arbitrary")]
+pub mod fuzz;
+pub mod limits;
+pub mod lint;
+pub mod loader;
 pub mod merge;
+#[deprecated(
+    since = "0.6.2",
+    note = "use `ini_merge::merge::mutations` or `ini_merge::prelude` instead"
+)]
+pub mod mutations;
+#[cfg(not(feature = "regex-rules"))]
+mod no_regex;
+mod output;
+pub mod parse;
+pub mod prelude;
+pub mod progress;
+#[cfg(feature = "python")]
+pub mod python;
 mod source_loader;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+
+use std::ops::Range;
 
 /// Describes a property
 ///
@@ -31,7 +78,7 @@ mod source_loader;
 #[non_exhaustive]
 pub struct Property<'a> {
     /// Trimmed section
-    pub section: &'a str,
+    pub section: SectionName<'a>,
     /// Trimmed key
     pub key: &'a str,
     /// Trimmed value (if any)
@@ -41,9 +88,39 @@ pub struct Property<'a> {
 }
 
 impl<'a> Property<'a> {
+    /// Create a property from its parts (for example to test a
+    /// [`merge::mutations::transforms::Transformer`])
+    ///
+    /// The key and value should be trimmed and occur in the raw line, like
+    /// for properties from the parser. See also [`Self::parse`].
+    #[must_use]
+    pub fn new(
+        section: impl Into<SectionName<'a>>,
+        key: &'a str,
+        val: Option<&'a str>,
+        raw: &'a str,
+    ) -> Self {
+        Self {
+            section: section.into(),
+            key,
+            val,
+            raw,
+        }
+    }
+
+    /// Parse a single line (such as `key = value`) in the given section
+    ///
+    /// Returns `None` if the line is not a key (such as a comment or a
+    /// section header).
+    #[must_use]
+    pub fn parse(section: impl Into<SectionName<'a>>, raw: &'a str) -> Option<Self> {
+        let item = ini_roundtrip::Parser::new(raw).next()?;
+        Self::try_from_ini(section.into(), item)
+    }
+
     /// Convert from `SourceValue` to `Property`
     pub(crate) fn from_src(
-        section: &'a str,
+        section: SectionName<'a>,
         key: &'a str,
         value: &'a source_loader::SourceValue,
     ) -> Self {
@@ -57,7 +134,7 @@ impl<'a> Property<'a> {
 
     /// Convert from INI parser value to Property
     pub(crate) const fn try_from_ini(
-        section: &'a str,
+        section: SectionName<'a>,
         value: ini_roundtrip::Item<'a>,
     ) -> Option<Self> {
         if let ini_roundtrip::Item::Property { key, val, raw } = value {
@@ -71,6 +148,84 @@ impl<'a> Property<'a> {
             None
         }
     }
+
+    /// The byte range of the key in [`Self::raw`]
+    ///
+    /// Returns `None` if the key can't be found at the start of the line.
+    #[must_use]
+    pub fn key_span(&self) -> Option<Range<usize>> {
+        if let Some(span) = subslice_span(self.raw, self.key) {
+            return Some(span);
+        }
+        let start = self.raw.len() - trim_ascii_start(self.raw).len();
+        let end = start + self.key.len();
+        (self.raw.get(start..end) == Some(self.key)).then_some(start..end)
+    }
+
+    /// The byte range of the value in [`Self::raw`]
+    ///
+    /// Returns `None` if there is no value, or if it can't be found at the
+    /// end of the line.
+    #[must_use]
+    pub fn value_span(&self) -> Option<Range<usize>> {
+        let val = self.val?;
+        if let Some(span) = subslice_span(self.raw, val) {
+            return Some(span);
+        }
+        let end = trim_ascii_end(self.raw).len();
+        let start = end.checked_sub(val.len())?;
+        (self.raw.get(start..end) == Some(val)).then_some(start..end)
+    }
+
+    /// The raw line with the value replaced, keeping everything before the
+    /// value (such as the separator and any whitespace)
+    pub(crate) fn with_value(&self, value: &str) -> String {
+        if let Some(span) = self.value_span() {
+            return format!("{}{value}", &self.raw[..span.start]);
+        }
+        // The value is not in the line as is (such as for continued lines),
+        // so keep everything up to the `=` after the key and any whitespace
+        // after that
+        let key_end = self.key_span().map_or(0, |span| span.end);
+        match self.raw[key_end..].find('=') {
+            Some(pos) => {
+                let rest = &self.raw[key_end + pos + 1..];
+                let start = self.raw.len() - trim_ascii_start(rest).len();
+                format!("{}{value}", &self.raw[..start])
+            }
+            None => format!("{}={value}", self.key),
+        }
+    }
+
+    /// Everything between the key and the value, that is the separator
+    /// including any surrounding whitespace (such as `" = "`)
+    ///
+    /// Returns `None` if there is no value, or if the key or value can't be
+    /// found in the line.
+    #[must_use]
+    pub fn separator(&self) -> Option<&'a str> {
+        let key = self.key_span()?;
+        let val = self.value_span()?;
+        self.raw.get(key.end..val.start)
+    }
+}
+
+/// The byte range of `part` in `whole`, if `part` is a slice of it (as for
+/// the key and value from the parser)
+fn subslice_span(whole: &str, part: &str) -> Option<Range<usize>> {
+    let start = (part.as_ptr() as usize).checked_sub(whole.as_ptr() as usize)?;
+    let end = start + part.len();
+    (whole.get(start..end).map(str::as_ptr) == Some(part.as_ptr())).then_some(start..end)
+}
+
+/// Trim leading ASCII whitespace (like the parser)
+fn trim_ascii_start(s: &str) -> &str {
+    s.trim_start_matches(|chr: char| chr.is_ascii_whitespace())
+}
+
+/// Trim trailing ASCII whitespace (like the parser)
+fn trim_ascii_end(s: &str) -> &str {
+    s.trim_end_matches(|chr: char| chr.is_ascii_whitespace())
 }
 
 /// Input type to transformers
@@ -78,4 +233,168 @@ pub type InputData<'a> = Option<Property<'a>>;
 
 /// Identifier for things outside sections. We could use None, but that
 /// wouldn't allow easily ignoring by regex.
+///
+/// A section header with this name in the input is not confused with the top
+/// level, see [`SectionName`].
 pub const OUTSIDE_SECTION: &str = "<NO_SECTION>";
+
+/// Key of a section named [`OUTSIDE_SECTION`] in the rules and the internal
+/// maps. Section names can't contain newlines, so this can't be spoofed. It
+/// is only given out as the `String` for the builders, see [`SectionName`].
+const NAMED_OUTSIDE_SECTION: &str = "<NO_SECTION>\n";
+
+/// Name of a section, telling the keys before the first section header apart
+/// from a section that happens to be named [`OUTSIDE_SECTION`]
+///
+/// The builders take a section as a `String`, where [`OUTSIDE_SECTION`] is
+/// the top level. To add a rule for a section named [`OUTSIDE_SECTION`],
+/// pass a `SectionName` (the builders take one wherever they take a
+/// `String`).
+///
+/// Regex rules match the section with [`SectionName::as_str`], so both the
+/// top level and a section named [`OUTSIDE_SECTION`] are matched as
+/// `<NO_SECTION>` (see
+/// [`actions::ActionsBuilder::regexes_match_top_level`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SectionName<'a> {
+    /// Keys before the first section header
+    TopLevel,
+    /// A section with a header (the name may be empty, for `[]`)
+    Named(&'a str),
+}
+
+impl<'a> SectionName<'a> {
+    /// The name of the section, [`OUTSIDE_SECTION`] for the top level
+    #[must_use]
+    pub const fn as_str(&self) -> &'a str {
+        match *self {
+            Self::TopLevel => OUTSIDE_SECTION,
+            Self::Named(name) => name,
+        }
+    }
+
+    /// Check if this is the top level
+    #[must_use]
+    pub const fn is_top_level(&self) -> bool {
+        matches!(self, Self::TopLevel)
+    }
+
+    /// The name of the section, `None` for the top level
+    #[must_use]
+    pub const fn name(&self) -> Option<&'a str> {
+        match *self {
+            Self::TopLevel => None,
+            Self::Named(name) => Some(name),
+        }
+    }
+
+    /// Decode a key used in the rules and the internal maps
+    pub(crate) fn from_key(key: &'a str) -> Self {
+        match key {
+            OUTSIDE_SECTION => Self::TopLevel,
+            NAMED_OUTSIDE_SECTION => Self::Named(OUTSIDE_SECTION),
+            name => Self::Named(name),
+        }
+    }
+
+    /// The key used in the rules and the internal maps
+    pub(crate) fn key(&self) -> &'a str {
+        match *self {
+            Self::Named(OUTSIDE_SECTION) => NAMED_OUTSIDE_SECTION,
+            _ => self.as_str(),
+        }
+    }
+}
+
+/// A section as given to the builders, where [`OUTSIDE_SECTION`] is the top
+/// level
+impl<'a> From<&'a str> for SectionName<'a> {
+    fn from(section: &'a str) -> Self {
+        match section {
+            OUTSIDE_SECTION => Self::TopLevel,
+            name => Self::Named(name),
+        }
+    }
+}
+
+impl std::fmt::Display for SectionName<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The section for the rule builders
+impl From<SectionName<'_>> for String {
+    fn from(section: SectionName<'_>) -> Self {
+        section.key().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Property;
+    use super::SectionName;
+    use super::OUTSIDE_SECTION;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn property_spans() {
+        let prop = Property {
+            section: SectionName::Named("s"),
+            key: "key",
+            val: Some("a value"),
+            raw: "  key =\ta value ",
+        };
+        assert_eq!(prop.key_span(), Some(2..5));
+        assert_eq!(prop.value_span(), Some(8..15));
+        assert_eq!(prop.separator(), Some(" =\t"));
+
+        let prop = Property {
+            section: SectionName::Named("s"),
+            key: "key",
+            val: None,
+            raw: "key",
+        };
+        assert_eq!(prop.key_span(), Some(0..3));
+        assert_eq!(prop.value_span(), None);
+        assert_eq!(prop.separator(), None);
+    }
+
+    #[test]
+    fn property_new() {
+        let prop = Property::parse("s", "  key =\ta value ").unwrap();
+        assert_eq!(prop.section, SectionName::Named("s"));
+        assert_eq!(prop.key, "key");
+        assert_eq!(prop.val, Some("a value"));
+        assert_eq!(prop.separator(), Some(" =\t"));
+        let built = Property::new("s", "key", Some("a value"), "  key =\ta value ");
+        assert_eq!(built.value_span(), prop.value_span());
+        assert!(Property::parse("s", "; comment").is_none());
+        assert!(Property::parse("s", "[section]").is_none());
+    }
+
+    #[test]
+    fn section_name() {
+        for section in [
+            SectionName::TopLevel,
+            SectionName::Named(OUTSIDE_SECTION),
+            SectionName::Named(""),
+            SectionName::Named("s"),
+        ] {
+            assert_eq!(SectionName::from_key(section.key()), section);
+        }
+        assert_eq!(SectionName::TopLevel.as_str(), OUTSIDE_SECTION);
+        assert_eq!(
+            SectionName::Named(OUTSIDE_SECTION).as_str(),
+            OUTSIDE_SECTION
+        );
+        assert_ne!(
+            String::from(SectionName::Named(OUTSIDE_SECTION)),
+            OUTSIDE_SECTION
+        );
+        assert_eq!(SectionName::Named("").name(), Some(""));
+        assert!(SectionName::from(OUTSIDE_SECTION).is_top_level());
+        let prop = Property::new(OUTSIDE_SECTION, "k", None, "k");
+        assert!(prop.section.is_top_level());
+    }
+}
@@ -0,0 +1,178 @@
+//! Resource limits for processing untrusted input
+
+use thiserror::Error;
+
+/// Limits on the input and rules, to protect against untrusted input.
+///
+/// All limits are disabled by default. Set with
+/// [`crate::merge::mutations::MutationsBuilder::limits`] or
+/// [`crate::actions::ActionsBuilder::limits`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Limits {
+    max_input_size: Option<usize>,
+    max_line_length: Option<usize>,
+    max_sections: Option<usize>,
+    max_keys: Option<usize>,
+    regex_size_limit: Option<usize>,
+    regex_warn_size: Option<usize>,
+}
+
+impl Limits {
+    /// Create a new set of limits with all limits disabled
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            max_input_size: None,
+            max_line_length: None,
+            max_sections: None,
+            max_keys: None,
+            regex_size_limit: None,
+            regex_warn_size: None,
+        }
+    }
+
+    /// Maximum size of each input file in bytes
+    #[must_use]
+    pub const fn max_input_size(mut self, limit: usize) -> Self {
+        self.max_input_size = Some(limit);
+        self
+    }
+
+    /// Maximum length of a line in bytes
+    #[must_use]
+    pub const fn max_line_length(mut self, limit: usize) -> Self {
+        self.max_line_length = Some(limit);
+        self
+    }
+
+    /// Maximum number of sections in each input file
+    #[must_use]
+    pub const fn max_sections(mut self, limit: usize) -> Self {
+        self.max_sections = Some(limit);
+        self
+    }
+
+    /// Maximum number of keys in each input file
+    #[must_use]
+    pub const fn max_keys(mut self, limit: usize) -> Self {
+        self.max_keys = Some(limit);
+        self
+    }
+
+    /// Size limit for compiled regular expressions in bytes, see
+    /// `RegexSetBuilder::size_limit` in the `regex` crate
+    #[must_use]
+    pub const fn regex_size_limit(mut self, limit: usize) -> Self {
+        self.regex_size_limit = Some(limit);
+        self
+    }
+
+    /// Log a warning when a compiled regex set is larger than this size in
+    /// bytes (but still within [`Self::regex_size_limit`])
+    #[must_use]
+    pub const fn regex_warn_size(mut self, size: usize) -> Self {
+        self.regex_warn_size = Some(size);
+        self
+    }
+
+    pub(crate) const fn get_max_input_size(&self) -> Option<usize> {
+        self.max_input_size
+    }
+
+    #[cfg(feature = "regex-rules")]
+    pub(crate) const fn get_regex_size_limit(&self) -> Option<usize> {
+        self.regex_size_limit
+    }
+
+    #[cfg(feature = "regex-rules")]
+    pub(crate) const fn get_regex_warn_size(&self) -> Option<usize> {
+        self.regex_warn_size
+    }
+
+    /// Check the line length, section and key limits on loaded data
+    pub(crate) fn check(&self, data: &str) -> Result<(), LimitError> {
+        if let Some(limit) = self.max_line_length {
+            if let Some(line) = data.lines().position(|line| line.len() > limit) {
+                return Err(LimitError::LineLength {
+                    line: line + 1,
+                    limit,
+                });
+            }
+        }
+        if self.max_sections.is_none() && self.max_keys.is_none() {
+            return Ok(());
+        }
+        let mut sections = 0;
+        let mut keys = 0;
+        for item in ini_roundtrip::Parser::new(data) {
+            match item {
+                ini_roundtrip::Item::Section { .. } => {
+                    sections += 1;
+                    if self.max_sections.is_some_and(|limit| sections > limit) {
+                        return Err(LimitError::Sections(sections - 1));
+                    }
+                }
+                ini_roundtrip::Item::Property { .. } => {
+                    keys += 1;
+                    if self.max_keys.is_some_and(|limit| keys > limit) {
+                        return Err(LimitError::Keys(keys - 1));
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Error for when a limit in [`Limits`] is exceeded
+#[derive(Debug, Error, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LimitError {
+    /// The input is too large
+    #[error("Input is larger than the limit of {0} bytes")]
+    InputSize(usize),
+    /// A line is too long
+    #[error("Line {line} is longer than the limit of {limit} bytes")]
+    LineLength {
+        /// Line number (1-based)
+        line: usize,
+        /// The limit that was exceeded
+        limit: usize,
+    },
+    /// Too many sections
+    #[error("Input has more than the limit of {0} sections")]
+    Sections(usize),
+    /// Too many keys
+    #[error("Input has more than the limit of {0} keys")]
+    Keys(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LimitError;
+    use super::Limits;
+    use pretty_assertions::assert_eq;
+
+    const DATA: &str = "a=1\n[s1]\nb=2\nc=3\n[s2]\nlong line here\n";
+
+    #[test]
+    fn check_limits() {
+        assert_eq!(Limits::new().check(DATA), Ok(()));
+        assert_eq!(
+            Limits::new().max_line_length(10).check(DATA),
+            Err(LimitError::LineLength { line: 6, limit: 10 })
+        );
+        assert_eq!(Limits::new().max_sections(2).check(DATA), Ok(()));
+        assert_eq!(
+            Limits::new().max_sections(1).check(DATA),
+            Err(LimitError::Sections(1))
+        );
+        assert_eq!(
+            Limits::new().max_keys(3).check(DATA),
+            Err(LimitError::Keys(3))
+        );
+    }
+}
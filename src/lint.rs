@@ -0,0 +1,216 @@
+//! Findings from checking rules for common mistakes
+//!
+//! See [`crate::merge::mutations::Mutations::lint`] and
+//! [`crate::actions::Actions::lint`].
+
+use std::fmt::Display;
+
+/// Identifies a rule
+///
+/// Displayed in the directive syntax of [`crate::directives`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RuleLabel {
+    /// An action for a whole section
+    Section(String),
+    /// An action for an exact match of section and key
+    Literal { section: String, key: String },
+    /// An action for a regex match (`index` is the order it was added in
+    /// among the regex rules)
+    Regex {
+        index: usize,
+        section: String,
+        key: String,
+    },
+    /// A forced set
+    Setter { section: String, key: String },
+}
+
+impl Display for RuleLabel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Section(section) => write!(f, "section {section:?}"),
+            Self::Literal { section, key } => write!(f, "{section:?} {key:?}"),
+            Self::Regex {
+                index: _,
+                section,
+                key,
+            } => write!(f, "regex {section:?} {key:?}"),
+            Self::Setter { section, key } => write!(f, "set {section:?} {key:?}"),
+        }
+    }
+}
+
+/// The kind of mistake found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LintKind {
+    /// The regex can never match anything
+    RegexNeverMatches,
+    /// The rule never applies, as there is an action for the whole section
+    /// (the other rule)
+    ShadowedBySection,
+    /// The literal rule overlaps with a regex rule (the other rule) with a
+    /// different action. The literal rule takes precedence.
+    OverridesRegex,
+    /// The literal rule was replaced by a later rule for the same section and
+    /// key, with a different action
+    ConflictingDuplicate,
+    /// The setter collides with a rule that deletes the key or section (the
+    /// other rule)
+    SetterDeleted,
+}
+
+impl Display for LintKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::RegexNeverMatches => "regex can never match",
+            Self::ShadowedBySection => "shadowed by section rule",
+            Self::OverridesRegex => "overrides regex rule with different action",
+            Self::ConflictingDuplicate => "replaced by later rule with different action",
+            Self::SetterDeleted => "setter collides with delete rule",
+        })
+    }
+}
+
+/// A single lint finding
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LintFinding {
+    /// What is wrong
+    pub kind: LintKind,
+    /// The rule that has the problem
+    pub rule: RuleLabel,
+    /// The other rule involved (if any)
+    pub other: Option<RuleLabel>,
+}
+
+impl LintFinding {
+    pub(crate) const fn new(kind: LintKind, rule: RuleLabel, other: Option<RuleLabel>) -> Self {
+        Self { kind, rule, other }
+    }
+}
+
+impl Display for LintFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.rule, self.kind)?;
+        if let Some(ref other) = self.other {
+            write!(f, " ({other})")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "regex-rules"))]
+mod tests {
+    use super::LintFinding;
+    use super::LintKind;
+    use super::RuleLabel;
+    use crate::filter::FilterAction;
+    use crate::filter::FilterActionsBuilder;
+    use crate::merge::mutations::Action;
+    use crate::merge::mutations::MutationsBuilder;
+    use crate::merge::mutations::SectionAction;
+    use pretty_assertions::assert_eq;
+
+    fn literal(section: &str, key: &str) -> RuleLabel {
+        RuleLabel::Literal {
+            section: section.into(),
+            key: key.into(),
+        }
+    }
+
+    fn setter(section: &str, key: &str) -> RuleLabel {
+        RuleLabel::Setter {
+            section: section.into(),
+            key: key.into(),
+        }
+    }
+
+    #[test]
+    fn lint_mutations() {
+        let mut builder = MutationsBuilder::new();
+        builder
+            .add_section_action("a", SectionAction::Ignore)
+            .add_section_action("d", SectionAction::Delete);
+        builder.add_literal_action("a", "k", Action::Delete);
+        builder.add_regex_action("b", "[a&&b]", Action::Ignore);
+        builder.add_regex_action("c", "x.*", Action::Ignore);
+        builder.add_literal_action("c", "x1", Action::Delete);
+        builder.add_literal_action("c", "y", Action::Delete);
+        builder.add_literal_action("c", "y", Action::Ignore);
+        builder.add_setter("d", "s", "1", "=");
+        builder.add_setter("e", "s", "1", "=");
+        builder.add_literal_action("e", "s", Action::Delete);
+        let findings = builder.build().unwrap().lint();
+        assert_eq!(
+            findings,
+            vec![
+                LintFinding::new(
+                    LintKind::RegexNeverMatches,
+                    RuleLabel::Regex {
+                        index: 0,
+                        section: "b".into(),
+                        key: "[a&&b]".into()
+                    },
+                    None
+                ),
+                LintFinding::new(
+                    LintKind::ShadowedBySection,
+                    literal("a", "k"),
+                    Some(RuleLabel::Section("a".into()))
+                ),
+                LintFinding::new(
+                    LintKind::OverridesRegex,
+                    literal("c", "x1"),
+                    Some(RuleLabel::Regex {
+                        index: 1,
+                        section: "c".into(),
+                        key: "x.*".into()
+                    })
+                ),
+                LintFinding::new(
+                    LintKind::ConflictingDuplicate,
+                    literal("c", "y"),
+                    Some(literal("c", "y"))
+                ),
+                LintFinding::new(
+                    LintKind::SetterDeleted,
+                    setter("d", "s"),
+                    Some(RuleLabel::Section("d".into()))
+                ),
+                LintFinding::new(
+                    LintKind::SetterDeleted,
+                    setter("e", "s"),
+                    Some(literal("e", "s"))
+                ),
+            ]
+        );
+        assert_eq!(
+            findings[2].to_string(),
+            r#""c" "x1": overrides regex rule with different action (regex "c" "x.*")"#
+        );
+    }
+
+    #[test]
+    fn lint_filter() {
+        let mut builder = FilterActionsBuilder::new();
+        builder
+            .add_literal_action("a", "k", FilterAction::Remove)
+            .add_literal_action("a", "k", FilterAction::Remove)
+            .add_regex_action("a", ".*", FilterAction::Replace("X".into()));
+        let findings = builder.build().unwrap().lint();
+        assert_eq!(
+            findings,
+            vec![LintFinding::new(
+                LintKind::OverridesRegex,
+                literal("a", "k"),
+                Some(RuleLabel::Regex {
+                    index: 0,
+                    section: "a".into(),
+                    key: ".*".into()
+                })
+            )]
+        );
+    }
+}
@@ -1,37 +1,348 @@
-use ini_roundtrip::Parser;
-use lending_iterator::prelude::*;
-use ouroboros::self_referencing;
+//! Loading of INI files
+//!
+//! Loading is done in two phases: First the data is read into an
+//! [`IniBuffer`], then [`IniBuffer::parse`] gives the items, borrowing from
+//! the buffer. This avoids copies when parsing.
+
+use crate::dialect::Dialect;
+use crate::limits::LimitError;
+use crate::limits::Limits;
+use crate::parse::events_with_dialect;
+use crate::parse::Events;
+use ini_roundtrip::Item;
 use std::io::Read;
+use thiserror::Error;
+
+/// A buffer holding the contents of an INI file, ready for parsing.
+#[derive(Debug)]
+pub struct IniBuffer {
+    data: BufferData,
+}
+
+/// The backing storage of an [`IniBuffer`]
+#[derive(Debug)]
+enum BufferData {
+    /// Data read into memory
+    Owned(String),
+    /// A memory mapped file, that has been validated as UTF-8
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+}
+
+/// Error type for loading an INI file
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum LoadError {
+    /// An IO error (including the data not being valid UTF-8)
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    /// A resource limit was exceeded
+    #[error(transparent)]
+    Limit(#[from] LimitError),
+}
+
+impl IniBuffer {
+    /// Read all the data from a reader
+    pub fn read(reader: &mut impl Read) -> Result<Self, LoadError> {
+        Self::read_with_limits(reader, &Limits::new())
+    }
+
+    /// Read all the data from a reader, subject to limits
+    pub fn read_with_limits(reader: &mut impl Read, limits: &Limits) -> Result<Self, LoadError> {
+        let buf = match limits.get_max_input_size() {
+            Some(limit) => {
+                let mut buf = Vec::new();
+                reader.take(read_limit(limit)).read_to_end(&mut buf)?;
+                limited_string(buf, limit)?
+            }
+            None => {
+                let mut buf = String::new();
+                reader.read_to_string(&mut buf)?;
+                buf
+            }
+        };
+        Self::from_string(buf, limits)
+    }
+
+    /// Asynchronously read all the data from a reader, subject to limits
+    #[cfg(feature = "tokio")]
+    pub async fn read_async(
+        reader: &mut (impl tokio::io::AsyncRead + Unpin),
+        limits: &Limits,
+    ) -> Result<Self, LoadError> {
+        use tokio::io::AsyncReadExt;
+
+        let buf = match limits.get_max_input_size() {
+            Some(limit) => {
+                let mut buf = Vec::new();
+                reader.take(read_limit(limit)).read_to_end(&mut buf).await?;
+                limited_string(buf, limit)?
+            }
+            None => {
+                let mut buf = String::new();
+                reader.read_to_string(&mut buf).await?;
+                buf
+            }
+        };
+        Self::from_string(buf, limits)
+    }
+
+    /// Create a buffer from data that has already been read, subject to limits
+    pub fn from_string(data: String, limits: &Limits) -> Result<Self, LoadError> {
+        check_limits(&data, limits)?;
+        Ok(Self {
+            data: BufferData::Owned(data),
+        })
+    }
+
+    /// Memory map a file, to parse it in place
+    ///
+    /// The file must not be modified while it is mapped. Doing so is
+    /// undefined behaviour.
+    #[cfg(feature = "mmap")]
+    pub fn mmap(path: &std::path::Path, limits: &Limits) -> Result<Self, LoadError> {
+        let file = std::fs::File::open(path)?;
+        if let Some(limit) = limits.get_max_input_size() {
+            let len = file.metadata()?.len();
+            if u64::try_from(limit).is_ok_and(|limit| len > limit) {
+                return Err(LimitError::InputSize(limit).into());
+            }
+        }
+        // SAFETY: The file must not be modified while it is mapped. This
+        // cannot be guaranteed, which is why this is an opt-in feature with
+        // this caveat documented in the public API.
+        let data = unsafe { memmap2::Mmap::map(&file)? };
+        let text = std::str::from_utf8(&data)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        limits.check(text)?;
+        Ok(Self {
+            data: BufferData::Mapped(data),
+        })
+    }
+
+    /// The raw data
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        match self.data {
+            BufferData::Owned(ref data) => data,
+            #[cfg(feature = "mmap")]
+            BufferData::Mapped(ref data) => {
+                // SAFETY: The data was validated as UTF-8 when it was mapped
+                // (see IniBuffer::mmap).
+                unsafe { std::str::from_utf8_unchecked(data) }
+            }
+        }
+    }
+
+    /// Parse the data in the standard dialect, giving an iterator over the
+    /// items borrowing from the buffer
+    #[must_use]
+    pub fn parse(&self) -> Items<'_> {
+        self.parse_with_dialect(&Dialect::STANDARD)
+    }
+
+    /// Parse the data in the given dialect, giving an iterator over the items
+    /// borrowing from the buffer
+    ///
+    /// The lines are classified like [`crate::parse::events_with_dialect`]
+    /// does, values continued over several lines are not joined.
+    #[must_use]
+    pub fn parse_with_dialect<'a>(&'a self, dialect: &'a Dialect) -> Items<'a> {
+        Items {
+            events: events_with_dialect(self.as_str(), dialect),
+        }
+    }
+}
+
+/// Iterator over the items of an [`IniBuffer`], see [`IniBuffer::parse`]
+#[derive(Debug)]
+pub struct Items<'a> {
+    events: Events<'a>,
+}
+
+impl<'a> Iterator for Items<'a> {
+    type Item = Item<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.events.next().map(|event| event.item)
+    }
+}
 
-/// A loader for INI files. Handles the parser state internally.
+/// How much to read to detect if the input size limit is exceeded
+fn read_limit(limit: usize) -> u64 {
+    u64::try_from(limit).unwrap_or(u64::MAX).saturating_add(1)
+}
+
+/// Convert data read with [`read_limit`] to a string, checking the size
+/// first (the read may have cut a multi-byte character in half at the limit)
+fn limited_string(buf: Vec<u8>, limit: usize) -> Result<String, LoadError> {
+    if buf.len() > limit {
+        return Err(LimitError::InputSize(limit).into());
+    }
+    String::from_utf8(buf)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err).into())
+}
+
+/// Maximum nesting depth of includes
+pub(crate) const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// A function opening an included file by path
+type IncludeResolver<'a> = dyn FnMut(&str) -> std::io::Result<Box<dyn Read + 'a>> + 'a;
+
+/// Resolves includes when loading the source INI, so that the logical source
+/// can be assembled from multiple files. See
+/// [`crate::merge::merge_ini_with_includes`].
 ///
-/// Acts as a *lending* iterator over its contents to avoid copies when
-/// parsing.
-#[self_referencing]
-pub(crate) struct Loader {
-    data: String,
-    #[borrows(data)]
-    #[covariant]
-    parser: Parser<'this>,
+/// Included files are parsed on their own (keys before the first section
+/// header are top level keys) and their keys are added to the source,
+/// replacing earlier values for the same keys.
+pub struct Includes<'a> {
+    /// Key whose value is the path of a file to include
+    key: Option<String>,
+    /// Files to include after the main file
+    extra: Vec<String>,
+    /// Opens files by path
+    resolver: Box<IncludeResolver<'a>>,
+}
+
+impl std::fmt::Debug for Includes<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Includes")
+            .field("key", &self.key)
+            .field("extra", &self.extra)
+            .finish_non_exhaustive()
+    }
 }
 
-// For now, this is how lending iterators work. I hope it switches to proper
-// GATs some time soon.
-#[gat]
-impl LendingIterator for Loader {
-    type Item<'next> = <Parser<'next> as Iterator>::Item;
+impl<'a> Includes<'a> {
+    /// Create a new include resolver, opening files with `resolver`
+    pub fn new(resolver: impl FnMut(&str) -> std::io::Result<Box<dyn Read + 'a>> + 'a) -> Self {
+        Self {
+            key: None,
+            extra: Vec::new(),
+            resolver: Box::new(resolver),
+        }
+    }
+
+    /// Include the file named by the value of this key (in any section)
+    /// wherever it appears, for example `include`. Includes can be nested.
+    pub fn key(&mut self, key: impl Into<String>) -> &mut Self {
+        self.key = Some(key.into());
+        self
+    }
+
+    /// Include a file after the main file (for example the files in a
+    /// `file.d` directory, in the order they should apply)
+    pub fn extra(&mut self, path: impl Into<String>) -> &mut Self {
+        self.extra.push(path.into());
+        self
+    }
 
-    fn next(&mut self) -> Option<Self::Item<'_>> {
-        self.with_parser_mut(|parser| parser.next())
+    /// The key naming included files, if any
+    pub(crate) fn include_key(&self) -> Option<&str> {
+        self.key.as_deref()
     }
+
+    /// Take the files to include after the main file
+    pub(crate) fn take_extra(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.extra)
+    }
+
+    /// Open an included file
+    pub(crate) fn open(&mut self, path: &str) -> std::io::Result<Box<dyn Read + 'a>> {
+        (self.resolver)(path)
+    }
+}
+
+/// Check the limits on data that has already been read
+pub(crate) fn check_limits(data: &str, limits: &Limits) -> Result<(), LimitError> {
+    if let Some(limit) = limits.get_max_input_size() {
+        if data.len() > limit {
+            return Err(LimitError::InputSize(limit));
+        }
+    }
+    limits.check(data)
 }
 
-pub(crate) fn load_ini(data: &mut impl Read) -> Result<Loader, std::io::Error> {
-    let mut buf = String::new();
-    data.read_to_string(&mut buf)?;
-    Ok(LoaderBuilder {
-        data: buf,
-        parser_builder: |data: &String| Parser::new(data),
+#[cfg(test)]
+mod tests {
+    use super::IniBuffer;
+    use super::LoadError;
+    use crate::dialect::Dialect;
+    use crate::limits::LimitError;
+    use crate::limits::Limits;
+    use ini_roundtrip::Item;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn read_and_parse() {
+        let buffer = IniBuffer::read(&mut "a=1\n[s]\n".as_bytes()).unwrap();
+        assert_eq!(buffer.as_str(), "a=1\n[s]\n");
+        let items: Vec<_> = buffer.parse().collect();
+        assert_eq!(
+            items,
+            vec![
+                Item::Property {
+                    key: "a",
+                    val: Some("1"),
+                    raw: "a=1"
+                },
+                Item::SectionEnd,
+                Item::Section {
+                    name: "s",
+                    raw: "[s]"
+                },
+                Item::SectionEnd,
+            ]
+        );
+
+        let err =
+            IniBuffer::read_with_limits(&mut "a=1\n".as_bytes(), &Limits::new().max_input_size(2))
+                .unwrap_err();
+        assert!(matches!(err, LoadError::Limit(LimitError::InputSize(2))));
+    }
+
+    #[test]
+    fn parse_with_dialect() {
+        let buffer = IniBuffer::from_string("; a\n".to_string(), &Limits::new()).unwrap();
+        let mut dialect = Dialect::new();
+        dialect.comment_prefixes(["#"]);
+        let items: Vec<_> = buffer.parse_with_dialect(&dialect).collect();
+        assert_eq!(
+            items,
+            vec![
+                Item::Property {
+                    key: "; a",
+                    val: None,
+                    raw: "; a"
+                },
+                Item::SectionEnd,
+            ]
+        );
+    }
+
+    #[test]
+    fn limit_inside_character() {
+        // Reading one byte past the limit ends in the middle of the two
+        // byte "ä", this must still be reported as too large input
+        let limits = Limits::new().max_input_size(3);
+        let err = IniBuffer::read_with_limits(&mut "ab=ä".as_bytes(), &limits).unwrap_err();
+        assert!(matches!(err, LoadError::Limit(LimitError::InputSize(3))));
+
+        let limits = Limits::new().max_input_size(4);
+        let buffer = IniBuffer::read_with_limits(&mut "a=ä".as_bytes(), &limits).unwrap();
+        assert_eq!(buffer.as_str(), "a=ä");
+        let err = IniBuffer::read_with_limits(&mut b"a=\xff".as_slice(), &limits).unwrap_err();
+        assert!(matches!(err, LoadError::Io(_)));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(flavor = "current_thread")]
+    async fn limit_inside_character_async() {
+        let limits = Limits::new().max_input_size(3);
+        let err = IniBuffer::read_async(&mut "ab=ä".as_bytes(), &limits)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, LoadError::Limit(LimitError::InputSize(3))));
     }
-    .build())
 }
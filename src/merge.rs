@@ -1,23 +1,56 @@
 //! INI merger functionality
 
+use self::mutations::transforms::SectionInfo;
+use self::mutations::transforms::SectionView;
 use self::mutations::transforms::Transformer;
+use self::mutations::transforms::TransformerCallError;
+use self::mutations::transforms::TransformerDispatch;
 use self::mutations::Action;
+use self::mutations::KeyPlacement;
 use self::mutations::Mutations;
 use self::mutations::SectionAction;
-use crate::loader::Loader;
+use self::mutations::TargetOnlyPolicy;
+use self::mutations::TimeoutPolicy;
+use crate::cancel::CancelToken;
+use crate::dialect::ParsedInput;
+use crate::filter::FilterAction;
+use crate::filter::FilterActions;
+use crate::filter::{self};
+use crate::limits::LimitError;
+use crate::loader::Includes;
+use crate::loader::IniBuffer;
+use crate::loader::LoadError;
 use crate::loader::{self};
+use crate::output::TrailingNewline;
+use crate::output::{self};
+use crate::progress::Progress;
+use crate::progress::ProgressCounter;
 use crate::source_loader::SectionAndKey;
 use crate::source_loader::SourceIni;
 use crate::source_loader::SourceValue;
 use crate::source_loader::{self};
-use lending_iterator::prelude::*;
+use crate::SectionName;
+use log::debug;
 use log::error;
 use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::io::Read;
+use std::ops::Range;
+use std::time::Duration;
+use std::time::Instant;
 use thiserror::Error;
 
 pub mod mutations;
+mod report;
+
+pub use self::report::KeyDecision;
+pub use self::report::KeyOrigin;
+pub use self::report::MergeReport;
+pub use self::report::TransformStats;
+pub use crate::source_loader::SourceLoaderError;
 
 #[cfg(test)]
 mod tests;
@@ -28,10 +61,100 @@ mod tests;
 pub enum MergeError {
     /// An error while loading the target INI
     #[error("Failed to load target INI due to {0}")]
-    TargetLoad(#[source] Box<dyn std::error::Error + 'static + Send + Sync>),
+    TargetLoad(#[source] std::io::Error),
     /// An error while loading the source INI
     #[error("Failed to load source INI due to {0}")]
-    SourceLoad(#[source] Box<dyn std::error::Error + 'static + Send + Sync>),
+    SourceLoad(#[source] SourceLoaderError),
+    /// A resource limit was exceeded by the target or source INI
+    #[error("Resource limit exceeded: {0}")]
+    Limit(#[source] LimitError),
+    /// A transform failed on a key
+    #[error("Transform {transformer} failed on [{section}] {key} due to {source}")]
+    TransformerError {
+        /// Section of the key
+        section: String,
+        /// The key being transformed
+        key: String,
+        /// Name of the transform
+        transformer: &'static str,
+        /// The error from the transform
+        #[source]
+        source: TransformerCallError,
+    },
+    /// An internal invariant of the merge algorithm was violated (this is a
+    /// bug, please report it)
+    #[error("Internal invariant violated: {0}")]
+    InternalInvariant(&'static str),
+    /// The merge ran past its time limit, see
+    /// [`mutations::MutationsBuilder::time_limit`]
+    #[error("Merge exceeded its time limit of {0:?}")]
+    Timeout(Duration),
+    /// The merge was cancelled, see [`merge_ini_cancellable`]
+    #[error("Merge was cancelled")]
+    Cancelled,
+}
+
+impl MergeError {
+    fn from_target(err: LoadError) -> Self {
+        match err {
+            LoadError::Limit(inner) => Self::Limit(inner),
+            LoadError::Io(inner) => Self::TargetLoad(inner),
+        }
+    }
+
+    fn from_source(err: SourceLoaderError) -> Self {
+        match err {
+            SourceLoaderError::Limit(inner) => Self::Limit(inner),
+            inner => Self::SourceLoad(inner),
+        }
+    }
+}
+
+/// Load the target INI, subject to the limits in the mutations
+fn load_target(target: &mut impl Read, mutations: &Mutations) -> Result<IniBuffer, MergeError> {
+    IniBuffer::read_with_limits(target, mutations.limits()).map_err(MergeError::from_target)
+}
+
+/// Load the source INI, subject to the limits in the mutations
+fn load_source(source: &mut impl Read, mutations: &Mutations) -> Result<SourceIni, MergeError> {
+    source_loader::load_source_ini(source, mutations.limits(), mutations.dialect())
+        .map_err(MergeError::from_source)
+}
+
+/// Hasher for the bookkeeping of the merge algorithm (faster with the
+/// `ahash` feature)
+#[cfg(feature = "ahash")]
+type BuildHasher = ahash::RandomState;
+/// Hasher for the bookkeeping of the merge algorithm (faster with the
+/// `ahash` feature)
+#[cfg(not(feature = "ahash"))]
+type BuildHasher = std::collections::hash_map::RandomState;
+
+/// Set of section names or keys
+type NameSet = HashSet<Box<str>, BuildHasher>;
+
+/// Values of keys (if any) in a section
+pub(crate) type SectionValues = HashMap<Box<str>, Option<Box<str>>, BuildHasher>;
+
+/// Log a line changed by a transform (with the lines elided for secret
+/// transforms)
+fn log_transform(
+    section: SectionName<'_>,
+    key: &str,
+    transform: &TransformerDispatch,
+    before: &str,
+    after: Option<&str>,
+) {
+    if !log::log_enabled!(target: "ini-merge", log::Level::Debug) {
+        return;
+    }
+    let name = transform.name();
+    if transform.is_secret() {
+        debug!(target: "ini-merge", "Transform {name} changed [{section}] {key} (secret elided)");
+    } else {
+        let after = after.unwrap_or("<removed>");
+        debug!(target: "ini-merge", "Transform {name} changed [{section}] {key}: {before:?} -> {after:?}");
+    }
 }
 
 /// State tracking for the merge algorithm
@@ -43,12 +166,58 @@ struct MergeState {
     /// [`MergeState::result`] depending on what follows
     pending_lines: Vec<String>,
     /// All the section names we have seen so far
-    seen_sections: HashSet<String>,
+    seen_sections: NameSet,
     /// All the keys we have seen so far in the current section (cleared for
     /// each new section)
-    seen_keys: HashSet<String>,
+    seen_keys: NameSet,
+    /// Keys handled in earlier occurrences of the current section (for
+    /// targets where the same section header appears more than once)
+    earlier_keys: NameSet,
+    /// Keys handled per section, for sections that we have left
+    section_keys: HashMap<Box<str>, NameSet, BuildHasher>,
     /// Name of the current section
     cur_section: String,
+    /// Raw header of the current section in the target (empty for the top
+    /// level, `None` if the section is only in the source)
+    cur_header: Option<String>,
+    /// Keys per section in the target (only collected for
+    /// [`KeyPlacement::SourceOrder`])
+    target_keys: HashMap<Box<str>, NameSet, BuildHasher>,
+    /// Source only keys before this position in the source section have
+    /// been placed (for [`KeyPlacement::SourceOrder`])
+    placement_cursor: usize,
+    /// Values in the target of keys used in conditions (or of all keys if a
+    /// transform needs the rest of the section), per section
+    target_values: HashMap<Box<str>, SectionValues, BuildHasher>,
+    /// If the last normalized line of the result was blank (for
+    /// squashing blank lines)
+    last_blank: bool,
+    /// Start of the comment block (comments and blank lines) at the end of
+    /// the result, that belongs to the next section header (only tracked
+    /// when removing the comments of removed sections)
+    comment_block: Option<usize>,
+    /// Comment block at the end of a deleted section, that belongs to the
+    /// next section header (only when removing the comments of removed
+    /// sections)
+    deleted_comments: Vec<String>,
+    /// First error during the merge, reported once the merge is done
+    error: Option<MergeError>,
+    /// Lines of the target that failed to parse and were copied verbatim
+    parse_errors: Vec<String>,
+    /// Decisions taken for each key (only collected for reports)
+    decisions: Option<Vec<KeyDecision>>,
+    /// Counters per transform (only collected for reports)
+    transform_stats: Option<BTreeMap<&'static str, TransformStats>>,
+    /// Markers to annotate conflicts with, instead of choosing the source
+    conflict_markers: Option<ConflictMarkers>,
+    /// When the merge started (for the time limit)
+    started: Instant,
+}
+
+impl Default for MergeState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MergeState {
@@ -56,9 +225,204 @@ impl MergeState {
         Self {
             result: Vec::default(),
             pending_lines: Vec::default(),
-            seen_sections: HashSet::default(),
-            seen_keys: HashSet::default(),
+            seen_sections: NameSet::default(),
+            seen_keys: NameSet::default(),
+            earlier_keys: NameSet::default(),
+            section_keys: HashMap::default(),
             cur_section: crate::OUTSIDE_SECTION.to_string(),
+            cur_header: Some(String::new()),
+            target_keys: HashMap::default(),
+            placement_cursor: 0,
+            target_values: HashMap::default(),
+            last_blank: false,
+            comment_block: None,
+            deleted_comments: Vec::new(),
+            error: None,
+            parse_errors: Vec::new(),
+            decisions: None,
+            transform_stats: None,
+            conflict_markers: None,
+            started: Instant::now(),
+        }
+    }
+
+    /// Reset to the initial state, keeping allocated capacity
+    fn clear(&mut self) {
+        self.result.clear();
+        self.pending_lines.clear();
+        self.seen_sections.clear();
+        self.seen_keys.clear();
+        self.earlier_keys.clear();
+        self.section_keys.clear();
+        self.cur_section.clear();
+        self.cur_section.push_str(crate::OUTSIDE_SECTION);
+        self.cur_header = Some(String::new());
+        self.target_keys.clear();
+        self.placement_cursor = 0;
+        self.target_values.clear();
+        self.last_blank = false;
+        self.comment_block = None;
+        self.deleted_comments.clear();
+        self.error = None;
+        self.parse_errors.clear();
+        if let Some(decisions) = &mut self.decisions {
+            decisions.clear();
+        }
+        if let Some(stats) = &mut self.transform_stats {
+            stats.clear();
+        }
+        self.started = Instant::now();
+    }
+
+    /// Mark the keys of the current section as handled, so that no source
+    /// only or forced keys are emitted for it
+    fn skip_current_section(&mut self, source: &SourceIni, mutations: &Mutations) {
+        for (key, _) in source.section_entries(&self.cur_section) {
+            self.seen_keys.insert(key.as_ref().into());
+        }
+        if let Some(forced_keys) = mutations.forced_keys_in(&self.cur_section) {
+            for key in forced_keys {
+                self.seen_keys.insert(key.as_str().into());
+            }
+        }
+    }
+
+    /// Record the decision for a key in the current section (if collecting
+    /// decisions)
+    fn record(&mut self, key: &str, origin: Option<KeyOrigin>) {
+        if let (Some(decisions), Some(origin)) = (&mut self.decisions, origin) {
+            decisions.push(KeyDecision {
+                section: SectionName::from_key(&self.cur_section).to_string(),
+                key: key.into(),
+                origin,
+            });
+        }
+    }
+
+    /// Take the first error that happened during the merge (if any)
+    fn take_error(&mut self) -> Result<(), MergeError> {
+        self.error.take().map_or(Ok(()), Err)
+    }
+
+    /// Normalize the lines added to the result since the last call, as
+    /// enabled in the mutations
+    fn normalize_output(&mut self, mutations: &Mutations) {
+        if mutations.trims_trailing_whitespace() {
+            self.result
+                .iter_mut()
+                .for_each(output::trim_trailing_whitespace);
+        }
+        if mutations.squashes_blank_lines() {
+            // Keep the start of the comment block correct
+            let mut comments = match self.comment_block {
+                Some(start) => self.result.split_off(start),
+                None => Vec::new(),
+            };
+            output::squash_blank_lines(&mut self.result, &mut self.last_blank);
+            if self.comment_block.is_some() {
+                self.comment_block = Some(self.result.len());
+            }
+            output::squash_blank_lines(&mut comments, &mut self.last_blank);
+            self.result.append(&mut comments);
+        }
+    }
+
+    /// Collect the keys of each section in the target (for placing source
+    /// only keys) and the values of keys used in conditions
+    fn scan_target<'a>(
+        &mut self,
+        target: impl Iterator<Item = ini_roundtrip::Item<'a>>,
+        source: &SourceIni,
+        mutations: &Mutations,
+    ) {
+        let collect_keys = mutations.key_placement() == KeyPlacement::SourceOrder;
+        let mut section = crate::OUTSIDE_SECTION;
+        for item in target {
+            match item {
+                ini_roundtrip::Item::Section { name, .. } => {
+                    section = section_name(name, source, mutations);
+                }
+                ini_roundtrip::Item::Property { key, val, .. } => {
+                    if collect_keys {
+                        self.target_keys
+                            .entry(section.into())
+                            .or_default()
+                            .insert(key.into());
+                    }
+                    if mutations.needs_section_context() || mutations.is_condition_key(section, key)
+                    {
+                        self.target_values
+                            .entry(section.into())
+                            .or_default()
+                            .entry(key.into())
+                            .or_insert_with(|| val.map(Into::into));
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Lookup the action for a key, including conditional actions
+    fn find_action<'m>(
+        &self,
+        mutations: &'m Mutations,
+        source: &SourceIni,
+        section: &str,
+        key: &str,
+    ) -> Option<Cow<'m, Action>> {
+        if mutations.find_section_action(section).is_none() {
+            let value_of = |cond_key: &str| match self
+                .target_values
+                .get(section)
+                .and_then(|values| values.get(cond_key))
+            {
+                Some(value) => value.as_deref(),
+                None => source
+                    .property(&SectionAndKey::new(
+                        Cow::Owned(section.to_string()),
+                        Cow::Owned(cond_key.to_string()),
+                    ))
+                    .and_then(SourceValue::value),
+            };
+            if let Some(action) = mutations.find_conditional_action(section, key, value_of) {
+                return Some(Cow::Borrowed(action));
+            }
+        }
+        mutations.find_action(section, key)
+    }
+
+    /// Emit source only keys that come before `key` in the source (for
+    /// [`KeyPlacement::SourceOrder`])
+    fn emit_source_keys_before(&mut self, key: &str, source: &SourceIni, mutations: &Mutations) {
+        if mutations.find_section_action(&self.cur_section).is_some() {
+            return;
+        }
+        let Some(order) = source.section_order(&self.cur_section) else {
+            return;
+        };
+        let Some(pos) = order.position(key) else {
+            return;
+        };
+        if pos < self.placement_cursor {
+            return;
+        }
+        let target_keys = self.target_keys.get(self.cur_section.as_str());
+        let to_place: Vec<_> = order.keys()[self.placement_cursor..pos]
+            .iter()
+            .filter(|k| !target_keys.is_some_and(|keys| keys.contains(k.as_str())))
+            .filter(|k| !self.is_handled(k))
+            .collect();
+        self.placement_cursor = pos + 1;
+        for src_key in to_place {
+            let action = self.find_action(mutations, source, &self.cur_section, src_key);
+            let value = source.property(&SectionAndKey::new(
+                Cow::Owned(self.cur_section.clone()),
+                Cow::Borrowed(src_key.as_str()),
+            ));
+            self.seen_keys.insert(src_key.as_str().into());
+            self.emit_pending_lines();
+            self.emit_kv(action.as_deref(), src_key, value, None, source, mutations);
         }
     }
 
@@ -91,40 +455,71 @@ impl MergeState {
                 None => {
                     let mut unseen_entries: Vec<_> = source
                         .section_entries(&self.cur_section)
-                        .filter(|e| !self.seen_keys.contains(e.0.as_ref()))
+                        .filter(|e| !self.is_handled(e.0))
                         .collect();
-                    unseen_entries.sort_by_key(|e| e.0);
+                    match mutations.key_placement() {
+                        KeyPlacement::Sorted => unseen_entries.sort_by_key(|e| e.0),
+                        KeyPlacement::SourceOrder => {
+                            let order = source.section_order(&self.cur_section);
+                            unseen_entries.sort_by_key(|e| {
+                                order
+                                    .and_then(|order| order.position(e.0))
+                                    .unwrap_or(usize::MAX)
+                            });
+                        }
+                    }
                     for (key, value) in unseen_entries {
-                        let action = mutations.find_action(self.cur_section.as_str(), key);
-                        self.seen_keys.insert(key.to_string());
-                        self.emit_kv(action.as_deref(), key, Some(value), None);
+                        let action = self.find_action(mutations, source, &self.cur_section, key);
+                        self.seen_keys.insert(key.as_ref().into());
+                        self.emit_kv(action.as_deref(), key, Some(value), None, source, mutations);
                     }
                 }
                 Some(SectionAction::Ignore) => (),
                 Some(SectionAction::Delete) => (),
             }
         }
-        self.emit_force_keys(mutations);
+        self.emit_force_keys(source, mutations);
 
-        self.seen_keys.clear();
+        // Remember what we handled, in case the section appears again. Drain
+        // rather than take the seen keys, to reuse the allocation.
+        let mut handled = std::mem::take(&mut self.earlier_keys);
+        handled.extend(self.seen_keys.drain());
+        self.section_keys
+            .insert(self.cur_section.as_str().into(), handled);
+    }
+
+    /// Check if a key has been handled in the current section (including in
+    /// earlier occurrences of the same section)
+    fn is_handled(&self, key: &str) -> bool {
+        self.seen_keys.contains(key) || self.earlier_keys.contains(key)
     }
 
     /// Emit lines from forced keys in the current section
-    fn emit_force_keys(&mut self, mutations: &Mutations) {
-        if let Some(forced_keys) = mutations.forced_keys.get(&self.cur_section) {
+    fn emit_force_keys(&mut self, source: &SourceIni, mutations: &Mutations) {
+        if let Some(forced_keys) = mutations.forced_keys_in(&self.cur_section) {
             self.emit_pending_lines();
-            let mut forced_keys: Vec<_> = forced_keys
+            // Already sorted
+            let forced_keys: Vec<_> = forced_keys
                 .iter()
-                .filter(|&e| !self.seen_keys.contains(e))
+                .filter(|&e| !self.is_handled(e))
                 .collect();
-            forced_keys.sort();
             for key in forced_keys {
-                let action = mutations.find_action(self.cur_section.as_str(), key);
-                self.emit_kv(action.as_deref(), key, None, None);
+                // The setter decides the value of a forced key, conditional
+                // actions only apply to keys from the source or target
+                let action = mutations.find_action(&self.cur_section, key);
+                self.seen_keys.insert(key.as_str().into());
+                self.emit_kv(action.as_deref(), key, None, None, source, mutations);
             }
         }
     }
 
+    /// Record an error, keeping only the first one
+    fn record_error(&mut self, err: MergeError) {
+        if self.error.is_none() {
+            self.error = Some(err);
+        }
+    }
+
     /// Emit a key-value line, handling transforms. Ignores are NOT handled here
     /// fully.
     fn emit_kv(
@@ -133,170 +528,1183 @@ impl MergeState {
         key: &str,
         source: Option<&SourceValue>,
         target: Option<ini_roundtrip::Item<'_>>,
+        source_ini: &SourceIni,
+        mutations: &Mutations,
     ) {
+        let origin = match (target, source) {
+            (Some(_), _) => KeyOrigin::Source,
+            (None, Some(_)) => KeyOrigin::Added,
+            (None, None) => KeyOrigin::Forced,
+        };
+        self.record(key, KeyOrigin::of_action(action, origin));
         match action {
             None => {
                 match source {
                     Some(val) => self.result.push(val.raw().into()),
-                    // PANIC safety: In all cases were we are called with action pass, we should
-                    // have a source line. This invariant is upheld in MutationsBuilder when it
+                    // In all cases were we are called with action pass, we should have a
+                    // source line. This invariant is upheld in MutationsBuilder when it
                     // constructs forced_keys.
-                    None => panic!("This should never happen"),
+                    None => self.record_error(MergeError::InternalInvariant(
+                        "Key without action or source line",
+                    )),
                 }
             }
             Some(Action::Ignore) => (),
+            Some(Action::IgnoreButRequire | Action::IgnoreIfTargetMatches(_)) => {
+                if let Some(val) = source {
+                    self.result.push(val.raw().into());
+                }
+            }
             Some(Action::Delete) => (),
+            Some(Action::Redact(replacement)) => {
+                if let Some(val) = source {
+                    let prop = crate::Property::from_src(
+                        SectionName::from_key(&self.cur_section),
+                        key,
+                        val,
+                    );
+                    self.result.push(match prop.val {
+                        Some(_) => prop.with_value(replacement),
+                        // There is no value, nothing to hide
+                        None => prop.raw.into(),
+                    });
+                }
+            }
             Some(Action::Transform(transform)) => {
-                let src =
-                    source.map(|v| crate::Property::from_src(self.cur_section.as_str(), key, v));
-                let tgt = target
-                    .and_then(|v| crate::Property::try_from_ini(self.cur_section.as_str(), v));
-                let transform_result = transform.call(&src, &tgt);
+                let section = SectionName::from_key(self.cur_section.as_str());
+                let src = source.map(|v| crate::Property::from_src(section, key, v));
+                let tgt = target.and_then(|v| crate::Property::try_from_ini(section, v));
+                if let Some((limit, policy)) = mutations.time_limit() {
+                    if self.started.elapsed() >= limit {
+                        match policy {
+                            TimeoutPolicy::Fail => self.record_error(MergeError::Timeout(limit)),
+                            TimeoutPolicy::KeepTarget => {
+                                debug!(target: "ini-merge", "Time limit passed, keeping [{section}] {key}");
+                                if let Some(tgt) = tgt {
+                                    self.result.push(tgt.raw.into());
+                                }
+                            }
+                        }
+                        return;
+                    }
+                }
+                let start = self.transform_stats.is_some().then(Instant::now);
+                let transform_result = if transform.needs_section_context() {
+                    transform.call_with_section(
+                        &src,
+                        &tgt,
+                        mutations.context(),
+                        &SectionView::source(source_ini, &self.cur_section),
+                        &SectionView::target(
+                            self.target_values.get(self.cur_section.as_str()),
+                            self.cur_header
+                                .as_deref()
+                                .map(|raw| SectionInfo::new(&self.cur_section, raw)),
+                        ),
+                    )
+                } else {
+                    transform.call_with_context(&src, &tgt, mutations.context())
+                };
+                let elapsed = start.map(|start| start.elapsed());
+                let before = tgt.as_ref().map(|v| v.raw);
+                let changed = match &transform_result {
+                    Ok(mutations::transforms::TransformerAction::Nothing) => before.is_some(),
+                    Ok(mutations::transforms::TransformerAction::Line(raw_line)) => {
+                        before != Some(raw_line.as_ref())
+                    }
+                    Err(_) => false,
+                };
+                if let (Some(stats), Some(elapsed)) = (&mut self.transform_stats, elapsed) {
+                    let stats = stats.entry(transform.name()).or_default();
+                    stats.calls += 1;
+                    stats.changed += usize::from(changed);
+                    stats.errors += usize::from(transform_result.is_err());
+                    stats.time += elapsed;
+                }
                 match transform_result {
-                    Ok(mutations::transforms::TransformerAction::Nothing) => (),
+                    Ok(mutations::transforms::TransformerAction::Nothing) => {
+                        if let Some(before) = before {
+                            log_transform(section, key, transform, before, None);
+                        }
+                    }
                     Ok(mutations::transforms::TransformerAction::Line(raw_line)) => {
+                        if changed {
+                            let before = before.unwrap_or_default();
+                            log_transform(section, key, transform, before, Some(&raw_line));
+                        }
                         self.result.push(raw_line.into_owned());
                     }
                     Err(e) => {
-                        error!(target: "ini-merge", "Failed to transform key {key}: {e}");
+                        // Keep going, the first error is reported at the end
+                        self.record_error(MergeError::TransformerError {
+                            section: section.to_string(),
+                            key: key.into(),
+                            transformer: transform.name(),
+                            source: e,
+                        });
                     }
                 }
             }
         }
     }
-}
 
-/// Process the target file, merging the state of source and target files
-pub(crate) fn merge<'a>(
-    target: &'a mut Loader,
-    source: &'a SourceIni,
-    mutations: &Mutations,
-) -> Vec<String> {
-    let mut state = MergeState::new();
+    /// Emit both the target and source line of a key, between conflict
+    /// markers
+    fn emit_conflict(&mut self, target_raw: &str, source: &SourceValue) {
+        if let Some(markers) = &self.conflict_markers {
+            self.result.extend([
+                markers.start.clone(),
+                target_raw.into(),
+                markers.separator.clone(),
+                source.raw().into(),
+                markers.end.clone(),
+            ]);
+        }
+    }
+
+    /// Check if we are in a deleted section, where comments and blank lines
+    /// should be dropped.
+    ///
+    /// Such comments describe keys that are gone, and kept on their own they
+    /// would end up under the previous section. Lines that fail to parse are
+    /// dropped as well, as they belong to the deleted section too.
+    ///
+    /// Comments before the first section are kept (even if the top level is
+    /// deleted) unless configured otherwise, see
+    /// [`mutations::MutationsBuilder::delete_top_level_comments`].
+    fn in_deleted_section(&self, mutations: &Mutations) -> bool {
+        (self.cur_section != crate::OUTSIDE_SECTION || mutations.deletes_top_level_comments())
+            && matches!(
+                mutations.find_section_action(&self.cur_section),
+                Some(SectionAction::Delete)
+            )
+    }
 
-    while let Some(ref entry) = target.next() {
-        match *entry {
+    /// Process a single item from the target file
+    fn process_item(
+        &mut self,
+        entry: ini_roundtrip::Item<'_>,
+        source: &SourceIni,
+        mutations: &Mutations,
+    ) {
+        match entry {
             ini_roundtrip::Item::Error(raw) => {
-                error!(target: "ini-merge", "Failed to parse line, copying verbatim: {raw}");
-                state.push_raw(raw.into());
+                self.comment_block = None;
+                self.deleted_comments.clear();
+                if self.in_deleted_section(mutations) {
+                    return;
+                }
+                match mutations.find_parse_error_action(&self.cur_section, raw) {
+                    Some(Action::Delete) => (),
+                    // Known line, copy without complaining
+                    Some(_) => self.push_raw(raw.into()),
+                    None => {
+                        error!(target: "ini-merge", "Failed to parse line, copying verbatim: {raw}");
+                        self.parse_errors.push(raw.into());
+                        self.push_raw(raw.into());
+                    }
+                }
             }
-            ini_roundtrip::Item::Comment { raw } | ini_roundtrip::Item::Blank { raw } => {
-                state.push_raw(raw.into());
+            ini_roundtrip::Item::Comment { raw } => {
+                if self.in_deleted_section(mutations) {
+                    if mutations.removes_section_comments() {
+                        self.deleted_comments.push(raw.into());
+                    }
+                } else {
+                    if mutations.removes_section_comments()
+                        && self.comment_block.is_none()
+                        && self.pending_lines.is_empty()
+                    {
+                        self.comment_block = Some(self.result.len());
+                    }
+                    self.push_raw(raw.into());
+                }
+            }
+            ini_roundtrip::Item::Blank { raw } => {
+                if !self.in_deleted_section(mutations) {
+                    self.push_raw(raw.into());
+                } else if !self.deleted_comments.is_empty() {
+                    self.deleted_comments.push(raw.into());
+                }
             }
             ini_roundtrip::Item::Section { name, raw } => {
+                let name = section_name(name, source, mutations);
+                let header = mutations.dialect().section_header(raw);
+                // The comments before the header belong to this section
+                let mut comments = match self.comment_block.take() {
+                    Some(start) => self.result.split_off(start),
+                    None => std::mem::take(&mut self.deleted_comments),
+                };
                 // Emit any pending source only lines. Can't be done in SectionEnd,
                 // since there can be keys before the first section.
-                state.emit_non_target_lines(source, mutations);
+                self.emit_non_target_lines(source, mutations);
                 // Bookkeeping
-                state.cur_section.clear();
-                state.cur_section.push_str(name);
-                state.seen_sections.insert(name.into());
-                state.seen_keys.clear();
-                state.pending_lines.clear();
+                self.cur_section.clear();
+                self.cur_section.push_str(name);
+                self.cur_header = Some(raw.into());
+                self.seen_sections.insert(name.into());
+                self.seen_keys.clear();
+                self.earlier_keys = self.section_keys.remove(name).unwrap_or_default();
+                self.pending_lines.clear();
+                self.placement_cursor = 0;
 
                 match mutations.find_section_action(name) {
-                    Some(SectionAction::Ignore) => state.push_raw(raw.into()),
-                    None if source.has_section(name) => state.push_raw(raw.into()),
+                    Some(SectionAction::Ignore) => {
+                        self.result.append(&mut comments);
+                        self.push_raw(header.into());
+                    }
+                    None if source.has_section(name) => {
+                        self.result.append(&mut comments);
+                        self.push_raw(header.into());
+                    }
                     // We cannot yet be sure that this section shouldn't exist.
                     // It is possible that a key in this section is ignored, even
                     // though the whole section is not.
-                    None => state.pending_lines.push(raw.into()),
+                    None => {
+                        self.pending_lines.append(&mut comments);
+                        self.pending_lines.push(header.into());
+                    }
+                    // Only forced keys will be emitted, if any.
+                    Some(SectionAction::Delete) if mutations.forced_keys_in(name).is_some() => {
+                        self.pending_lines.append(&mut comments);
+                        self.pending_lines.push(header.into());
+                    }
                     // We will definitely skip the section in this case.
                     Some(SectionAction::Delete) => (),
                 }
             }
             ini_roundtrip::Item::SectionEnd => (),
-            target @ ini_roundtrip::Item::Property { key, val: _, raw } => {
+            target @ ini_roundtrip::Item::Property { key, val, raw } => {
+                self.comment_block = None;
+                self.deleted_comments.clear();
                 // Bookkeeping
-                let action = mutations.find_action(&state.cur_section, key);
+                let mut action = match mutations.find_raw_line_action(&self.cur_section, raw) {
+                    Some(action) => Some(Cow::Borrowed(action)),
+                    None => self.find_action(mutations, source, &self.cur_section, key),
+                };
+                if let Some(Action::IgnoreIfTargetMatches(pattern)) = action.as_deref() {
+                    let matches = val.is_some_and(|val| pattern.is_match(val));
+                    action = matches.then_some(Cow::Owned(Action::Ignore));
+                }
                 let src_property = source.property(&SectionAndKey::new(
-                    Cow::Owned(state.cur_section.clone()),
+                    Cow::Owned(self.cur_section.clone()),
                     Cow::Borrowed(key),
                 ));
+                // In a repeated section, keys are only emitted once. Ignored
+                // keys are still copied as is, as they belong to the target.
+                let handled_earlier = self.earlier_keys.contains(key);
+                if mutations.key_placement() == KeyPlacement::SourceOrder
+                    && src_property.is_some()
+                    && !handled_earlier
+                {
+                    self.emit_source_keys_before(key, source, mutations);
+                }
                 match action.as_deref() {
-                    None => {
+                    None
+                    | Some(
+                        Action::Transform(_) | Action::IgnoreIfTargetMatches(_) | Action::Redact(_),
+                    ) if handled_earlier => {}
+                    // Already resolved above, so this is a normal merge
+                    None | Some(Action::IgnoreIfTargetMatches(_) | Action::Redact(_)) => {
                         if let Some(src_val) = src_property {
-                            state.seen_keys.insert(key.into());
-                            state.emit_pending_lines();
-                            state.emit_kv(action.as_deref(), key, Some(src_val), Some(target));
+                            self.seen_keys.insert(key.into());
+                            self.emit_pending_lines();
+                            if action.is_none()
+                                && mutations.dialect().keeps_target(raw, val, src_val.value())
+                            {
+                                self.record(key, Some(KeyOrigin::Target));
+                                self.result.push(raw.into());
+                            } else if action.is_none()
+                                && self.conflict_markers.is_some()
+                                && val != src_val.value()
+                            {
+                                self.emit_conflict(raw, src_val);
+                            } else {
+                                self.emit_kv(
+                                    action.as_deref(),
+                                    key,
+                                    Some(src_val),
+                                    Some(target),
+                                    source,
+                                    mutations,
+                                );
+                            }
+                        } else if mutations.target_only_policy(&self.cur_section)
+                            == Some(TargetOnlyPolicy::KeepTargetOnly)
+                        {
+                            self.seen_keys.insert(key.into());
+                            self.emit_pending_lines();
+                            self.record(key, Some(KeyOrigin::TargetOnly));
+                            self.result.push(raw.into());
+                        } else {
+                            self.record(key, Some(KeyOrigin::Removed));
                         }
                     }
-                    Some(Action::Ignore) => {
-                        state.seen_keys.insert(key.into());
-                        state.emit_pending_lines();
-                        state.result.push(raw.into());
+                    Some(Action::Ignore | Action::IgnoreButRequire) => {
+                        self.seen_keys.insert(key.into());
+                        self.emit_pending_lines();
+                        self.record(key, Some(KeyOrigin::Ignored));
+                        self.result.push(raw.into());
                     }
                     Some(Action::Delete) => {
                         // Nothing to do, just don't emit anything
+                        self.record(key, Some(KeyOrigin::Removed));
+                    }
+                    Some(Action::Transform(_))
+                        if src_property.is_none()
+                            && mutations.target_only_policy(&self.cur_section)
+                                == Some(TargetOnlyPolicy::DropTargetOnly)
+                            && !mutations.is_forced(&self.cur_section, key) =>
+                    {
+                        // The source is authoritative for key existence
+                        self.record(key, Some(KeyOrigin::Removed));
                     }
                     Some(Action::Transform(_)) => {
-                        state.seen_keys.insert(key.into());
-                        state.emit_pending_lines();
-                        state.emit_kv(action.as_deref(), key, src_property, Some(target));
+                        self.seen_keys.insert(key.into());
+                        self.emit_pending_lines();
+                        self.emit_kv(
+                            action.as_deref(),
+                            key,
+                            src_property,
+                            Some(target),
+                            source,
+                            mutations,
+                        );
                     }
                 }
             }
         }
     }
 
-    // End of system file, emit source only keys for the last section.
-    state.emit_non_target_lines(source, mutations);
-
-    // Go through and emit any source only sections
-    let mut unseen_sections: HashSet<_> = source
-        .sections()
-        .filter(|x| !state.seen_sections.contains(x.0))
-        .map(|(section, raw)| (section, raw.to_owned()))
-        .collect();
-    // Also handle forced keys from `set` directives for sections that don't exist
-    // anywhere.
-    unseen_sections.extend(
-        mutations
-            .forced_keys
-            .keys()
-            .filter(|&x| !state.seen_sections.contains(x))
-            .map(|section| (section, format!("[{section}]"))),
-    );
-    let mut unseen_sections: Vec<_> = unseen_sections.into_iter().collect();
-    unseen_sections.sort_by_key(|e| e.0);
-    for (section, raw) in unseen_sections {
+    /// Handle the end of the target file, completing the merged result
+    fn finish(&mut self, source: &SourceIni, mutations: &Mutations) {
+        for (section, raw) in self.finish_target(source, mutations) {
+            self.emit_unseen_section(&section, &raw, source, mutations);
+        }
+    }
+
+    /// Handle the end of the target file, returning the source only sections
+    /// (and sections with only forced keys) to emit, in order.
+    fn finish_target(
+        &mut self,
+        source: &SourceIni,
+        mutations: &Mutations,
+    ) -> Vec<(String, String)> {
+        // End of system file, emit source only keys for the last section.
+        self.comment_block = None;
+        self.emit_non_target_lines(source, mutations);
+
+        // Go through and emit any source only sections. Keyed on the name, so
+        // that each section is only emitted once, using the header from the
+        // source if there is one.
+        let mut unseen_sections: BTreeMap<_, _> = source
+            .sections()
+            .filter(|x| !self.seen_sections.contains(x.0.as_str()))
+            .map(|(section, raw)| (section.clone(), raw.to_owned()))
+            .collect();
+        // Also handle forced keys from `set` directives for sections that don't exist
+        // anywhere.
+        for section in mutations.forced_keys.keys() {
+            if !self.seen_sections.contains(section.as_str()) {
+                unseen_sections
+                    .entry(section.clone())
+                    .or_insert_with(|| format!("[{}]", SectionName::from_key(section)));
+            }
+        }
+        let mut unseen_sections: Vec<_> = unseen_sections.into_iter().collect();
+        unseen_sections.sort_by(|a, b| {
+            let pos_a = mutations.section_position(&a.0).unwrap_or(usize::MAX);
+            let pos_b = mutations.section_position(&b.0).unwrap_or(usize::MAX);
+            pos_a.cmp(&pos_b).then_with(|| a.0.cmp(&b.0))
+        });
+        unseen_sections
+    }
+
+    /// Emit a section that doesn't exist in the target
+    fn emit_unseen_section(
+        &mut self,
+        section: &str,
+        raw: &str,
+        source: &SourceIni,
+        mutations: &Mutations,
+    ) {
         if section == crate::OUTSIDE_SECTION {
             // This case is handled above by the Section case for the first section.
-            continue;
+            return;
         }
-        match mutations.find_section_action(section) {
-            None => (),
-            Some(SectionAction::Ignore) => continue,
-            Some(SectionAction::Delete) => continue,
+        let forced_only = match mutations.find_section_action(section) {
+            None => false,
+            Some(_) if mutations.forced_keys_in(section).is_some() => true,
+            Some(SectionAction::Ignore) => return,
+            Some(SectionAction::Delete) => return,
+        };
+        self.cur_section.clear();
+        self.cur_section.push_str(section);
+        self.cur_header = None;
+        self.seen_keys.clear();
+        self.seen_sections.insert(section.into());
+        self.pending_lines.clear();
+
+        self.result
+            .push(mutations.dialect().section_header(raw).into());
+        if !forced_only {
+            for (key, value) in source.section_entries(section) {
+                let action = self.find_action(mutations, source, section, key);
+                self.seen_keys.insert(key.as_ref().into());
+                self.emit_kv(action.as_deref(), key, Some(value), None, source, mutations);
+            }
         }
-        state.cur_section.clear();
-        state.cur_section.push_str(section);
-        state.seen_keys.clear();
-        state.seen_sections.insert(section.into());
-        state.pending_lines.clear();
+        self.emit_force_keys(source, mutations);
+    }
+}
+
+/// The name of a section for bookkeeping: The spelling in the source if
+/// section names are case insensitive
+fn section_name<'a>(name: &'a str, source: &'a SourceIni, mutations: &Mutations) -> &'a str {
+    if mutations.dialect().folds_section_case() {
+        source.canonical_section(name)
+    } else {
+        name
+    }
+}
+
+/// Process the target file, merging the state of source and target files
+pub(crate) fn merge(
+    target: &IniBuffer,
+    source: &SourceIni,
+    mutations: &Mutations,
+) -> Result<Vec<String>, MergeError> {
+    let target = mutations.dialect().parse(target.as_str());
+    merge_items(target.items(), source, mutations)
+}
 
-        state.result.push(raw.clone());
-        for (key, value) in source.section_entries(section) {
-            let action = mutations.find_action(section, key);
-            state.seen_keys.insert(key.to_string());
-            state.emit_kv(action.as_deref(), key, Some(value), None);
+/// Process the items of the target file, merging the state of source and
+/// target files
+pub(crate) fn merge_items<'a>(
+    target: impl Iterator<Item = ini_roundtrip::Item<'a>> + Clone,
+    source: &SourceIni,
+    mutations: &Mutations,
+) -> Result<Vec<String>, MergeError> {
+    let mut state = MergeState::new();
+    merge_items_into(target, source, mutations, &mut state)?;
+    Ok(state.result)
+}
+
+/// Process the items of the target file into the (cleared) merge state
+fn merge_items_into<'a>(
+    target: impl Iterator<Item = ini_roundtrip::Item<'a>> + Clone,
+    source: &SourceIni,
+    mutations: &Mutations,
+    state: &mut MergeState,
+) -> Result<(), MergeError> {
+    merge_items_observed(target, source, mutations, state, |_| Ok(()))
+}
+
+/// Process the items of the target file into the (cleared) merge state,
+/// calling `observe` before each item (which can abort the merge)
+fn merge_items_observed<'a>(
+    target: impl Iterator<Item = ini_roundtrip::Item<'a>> + Clone,
+    source: &SourceIni,
+    mutations: &Mutations,
+    state: &mut MergeState,
+    mut observe: impl FnMut(&ini_roundtrip::Item<'a>) -> Result<(), MergeError>,
+) -> Result<(), MergeError> {
+    state.clear();
+    if mutations.needs_target_scan() {
+        state.scan_target(target.clone(), source, mutations);
+    }
+    for entry in target {
+        observe(&entry)?;
+        state.process_item(entry, source, mutations);
+    }
+    state.finish(source, mutations);
+    state.normalize_output(mutations);
+    state.take_error()
+}
+
+/// Process the target file with filtering applied inline, then merge it with
+/// the source.
+pub(crate) fn filter_merge(
+    target: &IniBuffer,
+    source: &SourceIni,
+    filter_actions: &FilterActions,
+    mutations: &Mutations,
+) -> Result<Vec<String>, MergeError> {
+    let target = mutations.dialect().parse(target.as_str());
+    filter_merge_items(target.items(), source, filter_actions, mutations)
+}
+
+/// Process the items of the target file with filtering applied inline, then
+/// merge it with the source.
+fn filter_merge_items<'a>(
+    target: impl Iterator<Item = ini_roundtrip::Item<'a>> + Clone,
+    source: &SourceIni,
+    filter_actions: &FilterActions,
+    mutations: &Mutations,
+) -> Result<Vec<String>, MergeError> {
+    let mut state = MergeState::new();
+    if mutations.needs_target_scan() {
+        state.scan_target(target.clone(), source, mutations);
+    }
+    // Tracked separately from the merge state, as the header of removed
+    // sections is never passed on.
+    let mut cur_section = crate::OUTSIDE_SECTION.to_string();
+
+    for entry in target {
+        match entry {
+            ini_roundtrip::Item::Section { name, raw: _ } => {
+                cur_section.clear();
+                cur_section.push_str(name);
+                match filter_actions.find_section_action(name) {
+                    Some(FilterAction::Remove) => (),
+                    None | Some(FilterAction::Replace(_) | FilterAction::Mask { .. }) => {
+                        state.process_item(entry, source, mutations);
+                    }
+                }
+            }
+            ini_roundtrip::Item::Error(_)
+            | ini_roundtrip::Item::Comment { .. }
+            | ini_roundtrip::Item::Blank { .. } => {
+                match filter_actions.find_section_action(&cur_section) {
+                    Some(FilterAction::Remove) => (),
+                    None | Some(FilterAction::Replace(_) | FilterAction::Mask { .. }) => {
+                        state.process_item(entry, source, mutations);
+                    }
+                }
+            }
+            ini_roundtrip::Item::SectionEnd => state.process_item(entry, source, mutations),
+            ini_roundtrip::Item::Property { key, val, raw } => {
+                if let Some((line, val)) =
+                    filter::filter_property(filter_actions, &cur_section, key, val, raw)
+                {
+                    let item = ini_roundtrip::Item::Property {
+                        key,
+                        val: val.as_deref(),
+                        raw: line.as_ref(),
+                    };
+                    state.process_item(item, source, mutations);
+                }
+            }
         }
-        state.emit_force_keys(mutations);
     }
 
-    state.result
+    state.finish(source, mutations);
+    state.normalize_output(mutations);
+    state.take_error()?;
+    Ok(state.result)
 }
 
 /// Merge two INI files, giving the merged file as a vector of strings, one per
 /// line.
+///
+/// Keys before the first section are treated as belonging to a section named
+/// [`crate::OUTSIDE_SECTION`] (also available as
+/// [`mutations::MutationsBuilder::TOP_LEVEL`]) that always exists in the
+/// source. Rules for that section apply to them like for any other section.
+/// Source only top level keys are placed just before the first section
+/// header.
+///
+/// Comments and blank lines in deleted sections are removed together with the
+/// section. Those before the first section are kept, unless
+/// [`mutations::MutationsBuilder::delete_top_level_comments`] is set.
+///
+/// If the target contains the same section more than once, keys that only
+/// exist in the source (or are forced by setters) are emitted at the end of
+/// the first occurrence. Keys that were already handled in an earlier
+/// occurrence are dropped from later ones, except for ignored keys which are
+/// always copied from the target as is.
 pub fn merge_ini(
     target: &mut impl Read,
     source: &mut impl Read,
     mutations: &Mutations,
 ) -> Result<Vec<String>, MergeError> {
-    let mut target =
-        loader::load_ini(target).map_err(|inner| MergeError::TargetLoad(inner.into()))?;
-    let source = source_loader::load_source_ini(source)
-        .map_err(|inner| MergeError::SourceLoad(inner.into()))?;
-    Ok(merge(&mut target, &source, mutations))
+    let target = load_target(target, mutations)?;
+    let source = load_source(source, mutations)?;
+    merge(&target, &source, mutations)
+}
+
+/// Merge two INI files, resolving includes in the source, giving the merged
+/// file as a vector of strings, one per line.
+///
+/// The source is assembled from the main source file and the files it
+/// includes before merging (resource limits apply to each file). The target
+/// is merged as is. See [`merge_ini`] for details.
+pub fn merge_ini_with_includes(
+    target: &mut impl Read,
+    source: &mut impl Read,
+    includes: &mut Includes<'_>,
+    mutations: &Mutations,
+) -> Result<Vec<String>, MergeError> {
+    let target = load_target(target, mutations)?;
+    let source = source_loader::load_source_ini_with_includes(
+        source,
+        mutations.limits(),
+        mutations.dialect(),
+        includes,
+    )
+    .map_err(MergeError::from_source)?;
+    merge(&target, &source, mutations)
+}
+
+/// Merge two INI files read asynchronously, giving the merged file as a vector
+/// of strings, one per line.
+///
+/// The input is read asynchronously, the merge itself is done synchronously.
+/// See [`merge_ini`] for details.
+#[cfg(feature = "tokio")]
+pub async fn merge_ini_async(
+    target: &mut (impl tokio::io::AsyncRead + Unpin),
+    source: &mut (impl tokio::io::AsyncRead + Unpin),
+    mutations: &Mutations,
+) -> Result<Vec<String>, MergeError> {
+    let target = IniBuffer::read_async(target, mutations.limits())
+        .await
+        .map_err(MergeError::from_target)?;
+    let source = IniBuffer::read_async(source, mutations.limits())
+        .await
+        .map_err(|err| MergeError::from_source(err.into()))?;
+    let source = source_loader::source_from_str(source.as_str(), mutations.dialect())
+        .map_err(MergeError::from_source)?;
+    merge(&target, &source, mutations)
+}
+
+/// Merge two INI files by memory mapping them, giving the merged file as a
+/// vector of strings, one per line.
+///
+/// This avoids copying the files into memory, which helps for very large
+/// files. See [`merge_ini`] for details.
+///
+/// The files must not be modified while the merge is in progress. Doing so
+/// is undefined behaviour.
+#[cfg(feature = "mmap")]
+pub fn merge_ini_mmap(
+    target: &std::path::Path,
+    source: &std::path::Path,
+    mutations: &Mutations,
+) -> Result<Vec<String>, MergeError> {
+    let target = IniBuffer::mmap(target, mutations.limits()).map_err(MergeError::from_target)?;
+    let source = IniBuffer::mmap(source, mutations.limits())
+        .map_err(|err| MergeError::from_source(err.into()))?;
+    let source = source_loader::source_from_str(source.as_str(), mutations.dialect())
+        .map_err(MergeError::from_source)?;
+    merge(&target, &source, mutations)
+}
+
+/// Merge two INI files that are already in memory, giving the merged file as
+/// a vector of strings, one per line.
+///
+/// This avoids copying the inputs. See [`merge_ini`] for details.
+pub fn merge_ini_from_str(
+    target: &str,
+    source: &str,
+    mutations: &Mutations,
+) -> Result<Vec<String>, MergeError> {
+    loader::check_limits(target, mutations.limits()).map_err(MergeError::Limit)?;
+    loader::check_limits(source, mutations.limits()).map_err(MergeError::Limit)?;
+    let source = source_loader::source_from_str(source, mutations.dialect())
+        .map_err(MergeError::from_source)?;
+    let target = mutations.dialect().parse(target);
+    merge_items(target.items(), &source, mutations)
+}
+
+/// Problem with the input that was worked around by [`merge_ini_lossy`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MergeWarning {
+    /// The target was not valid UTF-8, invalid sequences were replaced
+    InvalidUtf8Target,
+    /// The source was not valid UTF-8, invalid sequences were replaced
+    InvalidUtf8Source,
+    /// A line in the target failed to parse and was copied verbatim
+    TargetParseError(String),
+    /// A line in the source failed to parse and was skipped
+    SourceParseError(String),
+}
+
+/// Result of [`merge_ini_lossy`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LossyMerge {
+    /// The merged file, one string per line
+    pub lines: Vec<String>,
+    /// Problems with the input that were worked around
+    pub warnings: Vec<MergeWarning>,
+}
+
+/// Merge two INI files of arbitrary bytes, giving the merged file as a vector
+/// of strings, one per line, together with any warnings.
+///
+/// This is meant for files written by arbitrary programs, and does not panic
+/// on any input:
+/// * Invalid UTF-8 is replaced with U+FFFD.
+/// * Lines in the target that fail to parse are copied verbatim.
+/// * Lines in the source that fail to parse are skipped.
+///
+/// Resource limits still apply, and errors during the merge itself (such as
+/// a failing transform) are still returned as errors. See [`merge_ini`] for
+/// details.
+pub fn merge_ini_lossy(
+    target: &[u8],
+    source: &[u8],
+    mutations: &Mutations,
+) -> Result<LossyMerge, MergeError> {
+    let mut warnings = Vec::new();
+    let target = String::from_utf8_lossy(target);
+    if matches!(target, Cow::Owned(_)) {
+        warnings.push(MergeWarning::InvalidUtf8Target);
+    }
+    let source = String::from_utf8_lossy(source);
+    if matches!(source, Cow::Owned(_)) {
+        warnings.push(MergeWarning::InvalidUtf8Source);
+    }
+    loader::check_limits(&target, mutations.limits()).map_err(MergeError::Limit)?;
+    loader::check_limits(&source, mutations.limits()).map_err(MergeError::Limit)?;
+    let (source, skipped) = source_loader::source_from_str_lossy(&source, mutations.dialect());
+    warnings.extend(skipped.into_iter().map(MergeWarning::SourceParseError));
+    let target = mutations.dialect().parse(&target);
+    let mut state = MergeState::new();
+    merge_items_into(target.items(), &source, mutations, &mut state)?;
+    warnings.extend(
+        state
+            .parse_errors
+            .drain(..)
+            .map(MergeWarning::TargetParseError),
+    );
+    Ok(LossyMerge {
+        lines: state.result,
+        warnings,
+    })
+}
+
+/// Markers for annotating conflicts, see [`merge_ini_with_conflicts`]
+///
+/// The default is git style conflict markers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ConflictMarkers {
+    /// Line before the target line
+    pub start: String,
+    /// Line between the target and source lines
+    pub separator: String,
+    /// Line after the source line
+    pub end: String,
+}
+
+impl ConflictMarkers {
+    /// Create custom conflict markers
+    #[must_use]
+    pub fn new(
+        start: impl Into<String>,
+        separator: impl Into<String>,
+        end: impl Into<String>,
+    ) -> Self {
+        Self {
+            start: start.into(),
+            separator: separator.into(),
+            end: end.into(),
+        }
+    }
+}
+
+impl Default for ConflictMarkers {
+    fn default() -> Self {
+        Self::new("<<<<<<< target", "=======", ">>>>>>> source")
+    }
+}
+
+/// Merge two INI files that are already in memory, annotating conflicts
+/// instead of resolving them, giving the merged file as a vector of strings,
+/// one per line.
+///
+/// A conflict is a key that has different values in the source and target,
+/// where no rule decides the outcome. Instead of choosing the source line,
+/// both lines are emitted between the `markers`. This is meant for reviewing
+/// what a rule set does, the result is usually not a valid INI file. See
+/// [`merge_ini`] for details.
+pub fn merge_ini_with_conflicts(
+    target: &str,
+    source: &str,
+    mutations: &Mutations,
+    markers: &ConflictMarkers,
+) -> Result<Vec<String>, MergeError> {
+    loader::check_limits(target, mutations.limits()).map_err(MergeError::Limit)?;
+    loader::check_limits(source, mutations.limits()).map_err(MergeError::Limit)?;
+    let source = source_loader::source_from_str(source, mutations.dialect())
+        .map_err(MergeError::from_source)?;
+    let target = mutations.dialect().parse(target);
+    let mut state = MergeState::new();
+    state.conflict_markers = Some(markers.clone());
+    merge_items_into(target.items(), &source, mutations, &mut state)?;
+    Ok(state.result)
+}
+
+/// Merge two INI files that are already in memory, stopping with
+/// [`MergeError::Cancelled`] if the token is cancelled.
+///
+/// Useful to abort merging of huge files from another thread (such as the UI
+/// thread of a frontend). See [`merge_ini`] for details.
+pub fn merge_ini_cancellable(
+    target: &str,
+    source: &str,
+    mutations: &Mutations,
+    cancel: &CancelToken,
+) -> Result<Vec<String>, MergeError> {
+    loader::check_limits(target, mutations.limits()).map_err(MergeError::Limit)?;
+    loader::check_limits(source, mutations.limits()).map_err(MergeError::Limit)?;
+    let source = source_loader::source_from_str(source, mutations.dialect())
+        .map_err(MergeError::from_source)?;
+    let target = mutations.dialect().parse(target);
+    let mut state = MergeState::new();
+    merge_items_observed(target.items(), &source, mutations, &mut state, |_| {
+        if cancel.is_cancelled() {
+            Err(MergeError::Cancelled)
+        } else {
+            Ok(())
+        }
+    })?;
+    Ok(state.result)
+}
+
+/// Merge two INI files, calling `callback` with the progress through the
+/// target every `every` lines and when done.
+///
+/// Useful to show progress for large files. See [`merge_ini`] for details.
+pub fn merge_ini_with_progress(
+    target: &mut impl Read,
+    source: &mut impl Read,
+    mutations: &Mutations,
+    every: usize,
+    callback: impl FnMut(Progress),
+) -> Result<Vec<String>, MergeError> {
+    let target = load_target(target, mutations)?;
+    let source = load_source(source, mutations)?;
+    let mut progress = ProgressCounter::new(target.as_str().len(), every, callback);
+    let items = mutations.dialect().parse(target.as_str());
+    let mut state = MergeState::new();
+    merge_items_observed(items.items(), &source, mutations, &mut state, |item| {
+        progress.item(item);
+        Ok(())
+    })?;
+    progress.finish();
+    Ok(state.result)
+}
+
+/// Merge two INI files that are already in memory, giving the merged file
+/// together with the decision taken for each key and counters for each
+/// transform (such as the time spent in it).
+///
+/// Keys that are dropped because they were already handled in an earlier
+/// occurrence of the same section are not included. See [`merge_ini`] for
+/// details.
+pub fn merge_ini_report(
+    target: &str,
+    source: &str,
+    mutations: &Mutations,
+) -> Result<MergeReport, MergeError> {
+    loader::check_limits(target, mutations.limits()).map_err(MergeError::Limit)?;
+    loader::check_limits(source, mutations.limits()).map_err(MergeError::Limit)?;
+    let source = source_loader::source_from_str(source, mutations.dialect())
+        .map_err(MergeError::from_source)?;
+    let target = mutations.dialect().parse(target);
+    let mut state = MergeState::new();
+    state.decisions = Some(Vec::new());
+    state.transform_stats = Some(BTreeMap::new());
+    merge_items_into(target.items(), &source, mutations, &mut state)?;
+    Ok(MergeReport {
+        lines: state.result,
+        decisions: state.decisions.unwrap_or_default(),
+        transforms: state.transform_stats.unwrap_or_default(),
+    })
+}
+
+/// Merge two INI files, giving the merged file as a string.
+///
+/// See [`merge_ini`] for details. The trailing blank lines of the target are
+/// preserved, and the final newline is handled according to `newline`.
+pub fn merge_ini_to_string(
+    target: &mut impl Read,
+    source: &mut impl Read,
+    mutations: &Mutations,
+    newline: TrailingNewline,
+) -> Result<String, MergeError> {
+    let target = load_target(target, mutations)?;
+    let source = load_source(source, mutations)?;
+    let lines = merge(&target, &source, mutations)?;
+    Ok(output::join_lines(lines, target.as_str(), newline))
+}
+
+/// Reusable buffers for repeated merges, see [`merge_ini_with_buffers`]
+///
+/// Keeps the allocations for the result and the bookkeeping of the merge
+/// between calls, which helps when merging many files in a long-running
+/// process.
+#[derive(Debug, Default)]
+pub struct MergeBuffers {
+    state: MergeState,
+}
+
+impl MergeBuffers {
+    /// Create new (empty) buffers
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Clear the buffers, keeping the allocated capacity
+    pub fn clear(&mut self) {
+        self.state.clear();
+    }
+
+    /// The lines from the last merge
+    #[must_use]
+    pub fn lines(&self) -> &[String] {
+        &self.state.result
+    }
+}
+
+/// Merge two INI files into reusable buffers, giving the merged file as a
+/// slice of strings, one per line.
+///
+/// The buffers are cleared before merging. See [`merge_ini`] for details.
+pub fn merge_ini_with_buffers<'b>(
+    target: &mut impl Read,
+    source: &mut impl Read,
+    mutations: &Mutations,
+    buffers: &'b mut MergeBuffers,
+) -> Result<&'b [String], MergeError> {
+    let target = load_target(target, mutations)?;
+    let source = load_source(source, mutations)?;
+    let target = mutations.dialect().parse(target.as_str());
+    merge_items_into(target.items(), &source, mutations, &mut buffers.state)?;
+    Ok(buffers.lines())
+}
+
+/// Lazy iterator over the lines of a merge, see [`merge_ini_iter`]
+struct MergeIter<'a> {
+    target: ParsedInput<'a>,
+    parser: ini_roundtrip::Parser<'a>,
+    /// Position in the continued values of the target
+    next_continued: usize,
+    source: SourceIni,
+    mutations: &'a Mutations,
+    state: MergeState,
+    /// Lines that are done and can be yielded
+    ready: VecDeque<String>,
+    /// Sections left to emit after the end of the target (`None` until the
+    /// end of the target has been reached)
+    unseen_sections: Option<std::vec::IntoIter<(String, String)>>,
+    /// Set once an error has been yielded, ending the iteration
+    failed: bool,
+}
+
+impl Iterator for MergeIter<'_> {
+    type Item = Result<String, MergeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.failed {
+                return None;
+            }
+            if let Some(line) = self.ready.pop_front() {
+                return Some(Ok(line));
+            }
+            match self.unseen_sections {
+                None => match self
+                    .target
+                    .next_item(&mut self.parser, &mut self.next_continued)
+                {
+                    Some(item) => self.state.process_item(item, &self.source, self.mutations),
+                    None => {
+                        let sections = self.state.finish_target(&self.source, self.mutations);
+                        self.unseen_sections = Some(sections.into_iter());
+                    }
+                },
+                Some(ref mut sections) => {
+                    let (section, raw) = sections.next()?;
+                    self.state
+                        .emit_unseen_section(&section, &raw, &self.source, self.mutations);
+                }
+            }
+            // Lines in the result are never changed once added, so they can
+            // be passed on right away. Except for comments that may belong
+            // to a removed section, which are held back.
+            if let Err(err) = self.state.take_error() {
+                self.failed = true;
+                return Some(Err(err));
+            }
+            self.state.normalize_output(self.mutations);
+            let end = self.state.comment_block.unwrap_or(self.state.result.len());
+            self.ready.extend(self.state.result.drain(..end));
+            if self.state.comment_block.is_some() {
+                self.state.comment_block = Some(0);
+            }
+        }
+    }
+}
+
+/// Merge two INI files that are already in memory, giving the merged file as
+/// a lazy iterator over the lines.
+///
+/// The source is parsed up front, but the merged document is never held in
+/// memory as a whole. See [`merge_ini`] for details.
+///
+/// Errors during the merge (such as a failing transform) are yielded in
+/// place of the next line, after which the iteration ends.
+pub fn merge_ini_iter<'a>(
+    target: &'a str,
+    source: &str,
+    mutations: &'a Mutations,
+) -> Result<impl Iterator<Item = Result<Cow<'a, str>, MergeError>> + 'a, MergeError> {
+    loader::check_limits(target, mutations.limits()).map_err(MergeError::Limit)?;
+    loader::check_limits(source, mutations.limits()).map_err(MergeError::Limit)?;
+    let source = source_loader::source_from_str(source, mutations.dialect())
+        .map_err(MergeError::from_source)?;
+    let target = mutations.dialect().parse(target);
+    let mut state = MergeState::new();
+    if mutations.needs_target_scan() {
+        state.scan_target(target.items(), &source, mutations);
+    }
+    let iter = MergeIter {
+        parser: target.parser(),
+        next_continued: 0,
+        target,
+        source,
+        mutations,
+        state,
+        ready: VecDeque::new(),
+        unseen_sections: None,
+        failed: false,
+    };
+    Ok(iter.map(|line| line.map(Cow::Owned)))
+}
+
+/// Filter the target INI file and merge it with the source in a single pass.
+///
+/// This gives the same result as first filtering the target with
+/// [`crate::filter::filter_ini`] and then merging the filtered result using
+/// [`merge_ini`], but only parses the target once. The one exception is
+/// sections where the filter removes every entry: the header of such
+/// sections is still kept in place if the source has the section, rather
+/// than the section being appended at the end.
+pub fn process(
+    target: &mut impl Read,
+    source: &mut impl Read,
+    filter_actions: &FilterActions,
+    mutations: &Mutations,
+) -> Result<Vec<String>, MergeError> {
+    let target = load_target(target, mutations)?;
+    let source = load_source(source, mutations)?;
+    filter_merge(&target, &source, filter_actions, mutations)
+}
+
+/// A merge session, for merging the same source with different versions of
+/// the target file.
+///
+/// The parsed source and the compiled mutations are kept between merges, as
+/// are the results of regex matching on sections and keys. This is useful
+/// for example when re-merging on every change to the target file from a
+/// file watcher.
+#[derive(Debug)]
+pub struct MergeSession {
+    source: SourceIni,
+    mutations: Mutations,
+}
+
+impl MergeSession {
+    /// Create a new session, loading the source INI
+    pub fn new(source: &mut impl Read, mut mutations: Mutations) -> Result<Self, MergeError> {
+        let source = load_source(source, &mutations)?;
+        mutations.enable_regex_cache();
+        Ok(Self { source, mutations })
+    }
+
+    /// The mutations used by this session
+    #[must_use]
+    pub const fn mutations(&self) -> &Mutations {
+        &self.mutations
+    }
+
+    /// Merge a (new version of the) target file with the cached source
+    pub fn remerge(&self, target: &mut impl Read) -> Result<Vec<String>, MergeError> {
+        let target = load_target(target, &self.mutations)?;
+        merge(&target, &self.source, &self.mutations)
+    }
+
+    /// Merge only the sections of the target that overlap the `changed`
+    /// lines (0-based, end exclusive), for live previews in editors.
+    ///
+    /// Only the affected sections are merged. The result is the
+    /// same as for those sections in a full merge, except that:
+    /// * Sections that only exist in the source are not included.
+    /// * Other occurrences of the same section elsewhere in the target are
+    ///   not taken into account.
+    pub fn remerge_range(
+        &self,
+        target: &str,
+        changed: Range<usize>,
+    ) -> Result<RangeMerge, MergeError> {
+        loader::check_limits(target, self.mutations.limits()).map_err(MergeError::Limit)?;
+        let lines: Vec<_> = target.split_inclusive('\n').collect();
+        let headers = section_header_lines(&self.mutations.dialect().parse(target));
+        let start = changed.start.min(lines.len());
+        let start = match headers.binary_search(&start) {
+            Ok(idx) => headers[idx],
+            Err(idx) => idx.checked_sub(1).map_or(0, |idx| headers[idx]),
+        };
+        let end = changed.end.max(start + 1).min(lines.len());
+        let end = match headers.binary_search(&end) {
+            Ok(idx) | Err(idx) => headers.get(idx).copied().unwrap_or(lines.len()),
+        };
+        let offset = |line: usize| lines[..line].iter().map(|l| l.len()).sum::<usize>();
+        let part = &target[offset(start)..offset(end)];
+
+        let mut state = MergeState::new();
+        if start > 0 {
+            // Keys before the first section are not part of the range
+            state.skip_current_section(&self.source, &self.mutations);
+        }
+        let part = self.mutations.dialect().parse(part);
+        if self.mutations.needs_target_scan() {
+            state.scan_target(part.items(), &self.source, &self.mutations);
+        }
+        for entry in part.items() {
+            state.process_item(entry, &self.source, &self.mutations);
+        }
+        state.comment_block = None;
+        state.emit_non_target_lines(&self.source, &self.mutations);
+        state.normalize_output(&self.mutations);
+        state.take_error()?;
+        Ok(RangeMerge {
+            target_lines: start..end,
+            lines: state.result,
+        })
+    }
+}
+
+/// The lines (0-based) of the section headers in the input, in order
+fn section_header_lines(parsed: &ParsedInput<'_>) -> Vec<usize> {
+    let input = parsed.input();
+    let mut headers = Vec::new();
+    let mut line = 0;
+    let mut pos = 0;
+    for item in parsed.items() {
+        if let ini_roundtrip::Item::Section { raw, .. } = item {
+            let start = raw.as_ptr() as usize - input.as_ptr() as usize;
+            line += input[pos..start].matches('\n').count();
+            pos = start;
+            headers.push(line);
+        }
+    }
+    headers
+}
+
+/// Merged lines for part of the target, see [`MergeSession::remerge_range`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RangeMerge {
+    /// The lines of the target (0-based, end exclusive) that were merged.
+    /// Always covers whole sections.
+    pub target_lines: Range<usize>,
+    /// The merged lines for those sections
+    pub lines: Vec<String>,
 }
@@ -4,25 +4,85 @@ use self::transforms::TransformerDispatch;
 use crate::actions::Actions;
 use crate::actions::ActionsBuilder;
 use crate::actions::ActionsBuilderError;
-use crate::mutations::transforms::TransformSet;
+use crate::actions::ActionsStats;
+use crate::dialect::Dialect;
+use crate::limits::Limits;
+use crate::lint::LintFinding;
+use crate::lint::LintKind;
+use crate::lint::RuleLabel;
+use crate::merge::mutations::transforms::MergeContext;
+use crate::merge::mutations::transforms::TransformSet;
+use crate::merge::mutations::transforms::Transformer;
+use crate::merge::mutations::transforms::TransformerAction;
+use crate::merge::mutations::transforms::TransformerCallError;
+#[cfg(not(feature = "regex-rules"))]
+use crate::no_regex::Regex;
+use crate::InputData;
+use crate::Property;
+use crate::SectionName;
+#[cfg(feature = "regex-rules")]
+use regex::Regex;
 use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::fmt::Display;
+use std::time::Duration;
 
+mod suggest;
 pub mod transforms;
 
+pub use self::suggest::suggest_rules;
+pub use self::suggest::ChurnReason;
+pub use self::suggest::SuggestedMutation;
+
 /// Describes the action for mutating the input
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 #[non_exhaustive]
 pub enum Action {
     /// Ignore source value, always use target value
     Ignore,
+    /// Keep the target value, but seed it from the source if the target lacks
+    /// the key entirely (otherwise like [`Action::Ignore`])
+    ///
+    /// This is the building block for machine specific keys, and cheaper than
+    /// a transform doing the same.
+    IgnoreButRequire,
+    /// Like [`Action::Ignore`] if the value in the target matches the
+    /// pattern, otherwise merge normally
+    IgnoreIfTargetMatches(ValuePattern),
     /// Remove this entry
     Delete,
+    /// Use the source line, but with the value replaced by a placeholder (the
+    /// key is dropped if the source lacks it)
+    Redact(String),
     /// Custom transform
     Transform(TransformerDispatch),
 }
 
+/// Displayed as a short description for listing rules (such as
+/// `transform unsorted_lists separator=","`)
+///
+/// This is not the directive syntax of [`crate::directives`] and can't be
+/// parsed back, not all actions have a directive.
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ignore => f.write_str("ignore"),
+            Self::IgnoreButRequire => f.write_str("ignore but require"),
+            Self::IgnoreIfTargetMatches(pattern) => {
+                write!(f, "ignore if target matches {:?}", pattern.as_str())
+            }
+            Self::Delete => f.write_str("remove"),
+            Self::Redact(replacement) => write!(f, "redact {replacement:?}"),
+            Self::Transform(transform) => write!(f, "transform {transform}"),
+        }
+    }
+}
+
 impl From<SectionAction> for Action {
     fn from(value: SectionAction) -> Self {
         Self::from(&value)
@@ -38,8 +98,86 @@ impl From<&SectionAction> for Action {
     }
 }
 
+/// A regex matched against values, see [`Action::IgnoreIfTargetMatches`]
+///
+/// Compares equal if the patterns are the same.
+#[derive(Debug, Clone)]
+pub struct ValuePattern(Regex);
+
+impl ValuePattern {
+    /// Compile a pattern (not anchored, use `^` and `$` to match the whole
+    /// value)
+    ///
+    /// Fails without the `regex-rules` feature.
+    pub fn new(pattern: &str) -> Result<Self, ActionsBuilderError> {
+        #[cfg(feature = "regex-rules")]
+        let regex = Regex::new(pattern).map_err(|e| ActionsBuilderError::RegexCompile(Box::new(e)));
+        #[cfg(not(feature = "regex-rules"))]
+        let regex = Regex::new(pattern);
+        regex.map(Self)
+    }
+
+    /// The pattern as a string
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    /// Check if a value matches
+    pub(crate) fn is_match(&self, value: &str) -> bool {
+        self.0.is_match(value)
+    }
+}
+
+impl PartialEq for ValuePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for ValuePattern {}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ValuePattern {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ValuePattern {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern: String = serde::Deserialize::deserialize(deserializer)?;
+        Self::new(&pattern).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A condition on the value of another key in the same section, see
+/// [`MutationsBuilder::add_conditional_action`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Condition {
+    /// The key to check
+    pub key: String,
+    /// The pattern the value of the key must match
+    pub pattern: ValuePattern,
+}
+
+impl Condition {
+    /// Create a new condition
+    #[must_use]
+    pub fn new(key: impl Into<String>, pattern: ValuePattern) -> Self {
+        Self {
+            key: key.into(),
+            pattern,
+        }
+    }
+}
+
 /// Describes actions to apply to whole sections
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 #[non_exhaustive]
 pub enum SectionAction {
     /// Ignore source value, always use target value
@@ -48,13 +186,106 @@ pub enum SectionAction {
     Delete,
 }
 
+impl Display for SectionAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Ignore => "ignore",
+            Self::Delete => "remove",
+        })
+    }
+}
+
+/// What to do with keys that exist in the target but not in the source, see
+/// [`MutationsBuilder::target_only_policy`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum TargetOnlyPolicy {
+    /// Keep target only keys as they are
+    KeepTargetOnly,
+    /// Drop target only keys, even if they have a transform
+    DropTargetOnly,
+}
+
+/// Where keys that only exist in the source are placed in sections that exist
+/// in the target, see [`MutationsBuilder::key_placement`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum KeyPlacement {
+    /// At the end of the section, sorted by key
+    #[default]
+    Sorted,
+    /// Before the next key (in the source) that also exists in the target,
+    /// following the layout of the source
+    SourceOrder,
+}
+
+/// What happens to the remaining transforms when a merge runs past its time
+/// limit, see [`MutationsBuilder::time_limit`]
+///
+/// This policy does not apply to the timeouts of the individual transforms,
+/// which behave differently: The `keyring` transform keeps the target line
+/// when it times out, while a timeout in the `script` or `external`
+/// transforms is an error that fails the merge (see
+/// [`crate::merge::MergeError::TransformerError`]).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum TimeoutPolicy {
+    /// Fail the merge with [`crate::merge::MergeError::Timeout`]
+    #[default]
+    Fail,
+    /// Keep the target line instead of calling the transform
+    KeepTarget,
+}
+
 /// Collects all the ways we can ignore, transform etc (mutations)
 #[derive(Debug)]
 pub struct Mutations {
     /// Inner actions
     actions: Actions<Action, SectionAction>,
-    /// Section & keys that must exist (used to make "set" work)
-    pub(crate) forced_keys: HashMap<String, HashSet<String>>,
+    /// Section & keys that must exist (used to make "set" work), ordered to
+    /// make the output reproducible
+    pub(crate) forced_keys: BTreeMap<String, BTreeSet<String>>,
+    /// If setters take precedence over section actions
+    setters_override_section_actions: bool,
+    /// If keys absent from the source are dropped even with a transform
+    strict_sync: bool,
+    /// If deleting the top level also removes the comments before the first
+    /// section header
+    delete_top_level_comments: bool,
+    /// Per section policies for target only keys
+    target_only_policies: HashMap<String, TargetOnlyPolicy>,
+    /// Position of sections in the ordering hint
+    section_order: HashMap<String, usize>,
+    /// Placement of source only keys
+    key_placement: KeyPlacement,
+    /// Conditional actions by section and key
+    conditional_actions: HashMap<String, HashMap<String, Vec<(Condition, Action)>>>,
+    /// Keys used in conditions, by section
+    condition_keys: HashMap<String, HashSet<String>>,
+    /// The rules in data form, indexed by [`RuleId`] (`None` if removed)
+    rules: Vec<Option<MutationRule>>,
+    /// Context for transforms
+    context: MergeContext,
+    /// If any transform needs the rest of the section
+    needs_section_context: bool,
+    /// Time limit for transforms in each merge
+    time_limit: Option<(Duration, TimeoutPolicy)>,
+}
+
+/// Lists the rules one per line, in the order they were added
+impl Display for Mutations {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for rule in self.rules.iter().flatten() {
+            writeln!(f, "{rule}")?;
+        }
+        Ok(())
+    }
 }
 
 impl Mutations {
@@ -64,19 +295,370 @@ impl Mutations {
         MutationsBuilder::new()
     }
 
+    /// Resource limits for input processed with these mutations
+    pub(crate) const fn limits(&self) -> &Limits {
+        self.actions.limits()
+    }
+
+    /// Dialect of input processed with these mutations
+    pub(crate) const fn dialect(&self) -> &Dialect {
+        self.actions.dialect()
+    }
+
+    /// The context for transforms
+    #[must_use]
+    pub const fn context(&self) -> &MergeContext {
+        &self.context
+    }
+
+    /// If runs of blank lines in the output should be collapsed
+    pub(crate) const fn squashes_blank_lines(&self) -> bool {
+        self.actions.squashes_blank_lines()
+    }
+
+    /// If the comments directly before removed sections should be removed
+    pub(crate) const fn removes_section_comments(&self) -> bool {
+        self.actions.removes_section_comments()
+    }
+
+    /// If trailing whitespace should be removed from output lines
+    pub(crate) const fn trims_trailing_whitespace(&self) -> bool {
+        self.actions.trims_trailing_whitespace()
+    }
+
+    /// Remember regex match results between lookups
+    pub(crate) fn enable_regex_cache(&mut self) {
+        self.actions.enable_regex_cache();
+    }
+
     #[inline]
     pub(crate) fn find_section_action(&self, section: &str) -> Option<&SectionAction> {
         self.actions.find_section_action(section)
     }
 
+    #[inline]
+    pub(crate) fn find_raw_line_action(&self, section: &str, raw: &str) -> Option<&Action> {
+        self.actions.find_raw_line_action(section, raw)
+    }
+
+    #[inline]
+    pub(crate) fn find_parse_error_action(&self, section: &str, raw: &str) -> Option<&Action> {
+        self.actions.find_parse_error_action(section, raw)
+    }
+
     #[inline]
     pub(crate) fn find_action<'this>(
         &'this self,
         section: &str,
         key: &str,
     ) -> Option<Cow<'this, Action>> {
+        if self.is_forced(section, key) && self.setters_apply_in(section) {
+            return self.actions.find_key_action(section, key);
+        }
         self.actions.find_action(section, key)
     }
+
+    /// Convert back to the data form of the rules and settings, for example
+    /// to cache the rule set (with the `serde` feature, [`Mutations`] can also
+    /// be serialized directly)
+    ///
+    /// Removed rules are left out.
+    #[must_use]
+    pub fn to_serialized(&self) -> MutationsRuleSet {
+        let mut section_order: Vec<_> = self.section_order.iter().collect();
+        section_order.sort_unstable_by_key(|(_, idx)| **idx);
+        MutationsRuleSet {
+            rules: self.rules.iter().flatten().cloned().collect(),
+            warn_on_multiple_matches: self.actions.warns_on_multiple_matches(),
+            setters_override_section_actions: self.setters_override_section_actions,
+            strict_sync: self.strict_sync,
+            delete_top_level_comments: self.delete_top_level_comments,
+            section_order: section_order
+                .into_iter()
+                .map(|(section, _)| section.clone())
+                .collect(),
+            key_placement: self.key_placement,
+            regexes_match_top_level: self.actions.regexes_match_top_level(),
+            dialect: self.dialect().clone(),
+            limits: *self.limits(),
+            trim_trailing_whitespace: self.trims_trailing_whitespace(),
+            squash_blank_lines: self.squashes_blank_lines(),
+            remove_section_comments: self.removes_section_comments(),
+            context: self.context.clone(),
+            time_limit: self.time_limit,
+        }
+    }
+
+    /// Counts and approximate memory usage of the rules, see
+    /// [`Actions::stats`]
+    #[must_use]
+    pub fn stats(&self) -> ActionsStats {
+        self.actions.stats()
+    }
+
+    /// Check the rules for common mistakes
+    #[must_use]
+    pub fn lint(&self) -> Vec<LintFinding> {
+        let is_forced = |label: &RuleLabel| match label {
+            RuleLabel::Literal { section, key } => self
+                .forced_keys
+                .get(section)
+                .is_some_and(|keys| keys.contains(key)),
+            _ => false,
+        };
+        // Findings on setters are replaced by the checks below
+        let mut findings: Vec<_> = self
+            .actions
+            .lint()
+            .into_iter()
+            .filter(|finding| !is_forced(&finding.rule))
+            .collect();
+        let forced = self
+            .forced_keys
+            .iter()
+            .flat_map(|(section, keys)| keys.iter().map(move |key| (section, key)));
+        for (section, key) in forced {
+            let name = SectionName::from_key(section).to_string();
+            let label = RuleLabel::Setter {
+                section: name.clone(),
+                key: key.clone(),
+            };
+            let literal = RuleLabel::Literal {
+                section: name.clone(),
+                key: key.clone(),
+            };
+            if !self.setters_apply_in(section) {
+                findings.push(LintFinding::new(
+                    LintKind::SetterDeleted,
+                    label,
+                    Some(RuleLabel::Section(name)),
+                ));
+            } else if self.actions.literal_action(section, key) == Some(&Action::Delete)
+                || self
+                    .actions
+                    .replaced_literal_actions(section, key)
+                    .any(|action| *action == Action::Delete)
+            {
+                findings.push(LintFinding::new(
+                    LintKind::SetterDeleted,
+                    label,
+                    Some(literal),
+                ));
+            }
+        }
+        findings
+    }
+
+    /// Dry-run a single rule on sample values for a key, as if it was the
+    /// only rule
+    ///
+    /// The samples are the values of the key in the source and in the target
+    /// (`None` if the key is missing). Returns `Ok(None)` if the rule doesn't
+    /// apply to the key, otherwise the line the rule would produce. Conditions
+    /// of conditional actions are not checked, and target only policies and
+    /// rules for parse errors never apply.
+    pub fn test_rule(
+        &self,
+        rule: RuleId,
+        section: &str,
+        key: &str,
+        source: Option<&str>,
+        target: Option<&str>,
+    ) -> Result<Option<TransformerAction<'static>>, TransformerCallError> {
+        let Some(Some(rule)) = self.rules.get(rule.0) else {
+            return Ok(None);
+        };
+        let src_raw = source.map(|val| format!("{key}={val}"));
+        let tgt_raw = target.map(|val| format!("{key}={val}"));
+        let src = src_raw.as_deref().map(|raw| Property {
+            section: SectionName::from_key(section),
+            key,
+            val: source,
+            raw,
+        });
+        let tgt = tgt_raw.as_deref().map(|raw| Property {
+            section: SectionName::from_key(section),
+            key,
+            val: target,
+            raw,
+        });
+        let action = match rule {
+            MutationRule::Conditional {
+                section: rule_section,
+                key: rule_key,
+                action,
+                ..
+            } => (rule_section == section && rule_key == key).then_some(Cow::Borrowed(action)),
+            MutationRule::ParseError { .. } | MutationRule::TargetOnly { .. } => None,
+            _ => {
+                let mut builder = MutationsBuilder::new();
+                builder.add_rule(rule.clone());
+                let Ok(single) = builder.build() else {
+                    return Ok(None);
+                };
+                let raw_action = tgt_raw
+                    .as_deref()
+                    .and_then(|raw| single.find_raw_line_action(section, raw));
+                match raw_action {
+                    Some(action) => Some(Cow::Owned(action.clone())),
+                    None => single
+                        .find_action(section, key)
+                        .map(|action| Cow::Owned(action.into_owned())),
+                }
+            }
+        };
+        let Some(action) = action else {
+            return Ok(None);
+        };
+        let line = |prop: &InputData<'_>| match prop {
+            Some(prop) => TransformerAction::Line(Cow::Owned(prop.raw.to_string())),
+            None => TransformerAction::Nothing,
+        };
+        Ok(Some(match action.as_ref() {
+            Action::Ignore => line(&tgt),
+            Action::IgnoreButRequire if tgt.is_some() => line(&tgt),
+            Action::IgnoreButRequire => line(&src),
+            Action::IgnoreIfTargetMatches(pattern)
+                if target.is_some_and(|val| pattern.is_match(val)) =>
+            {
+                line(&tgt)
+            }
+            Action::IgnoreIfTargetMatches(_) => line(&src),
+            Action::Delete => TransformerAction::Nothing,
+            Action::Redact(replacement) => match src {
+                Some(prop) if prop.val.is_some() => {
+                    TransformerAction::Line(Cow::Owned(prop.with_value(replacement)))
+                }
+                _ => line(&src),
+            },
+            Action::Transform(_) if src.is_none() && tgt.is_none() => TransformerAction::Nothing,
+            Action::Transform(transform) => {
+                match transform.call_with_context(&src, &tgt, &self.context)? {
+                    TransformerAction::Nothing => TransformerAction::Nothing,
+                    TransformerAction::Line(line) => {
+                        TransformerAction::Line(Cow::Owned(line.into_owned()))
+                    }
+                }
+            }
+        }))
+    }
+
+    /// Check if a key is forced by a setter
+    pub(crate) fn is_forced(&self, section: &str, key: &str) -> bool {
+        self.forced_keys
+            .get(section)
+            .is_some_and(|keys| keys.contains(key))
+    }
+
+    /// The policy for keys in a section that only exist in the target, if
+    /// any
+    pub(crate) fn target_only_policy(&self, section: &str) -> Option<TargetOnlyPolicy> {
+        match self.target_only_policies.get(section) {
+            Some(policy) => Some(*policy),
+            None if self.strict_sync => Some(TargetOnlyPolicy::DropTargetOnly),
+            None => None,
+        }
+    }
+
+    /// If deleting the top level also removes the comments and blank lines
+    /// before the first section header
+    pub(crate) const fn deletes_top_level_comments(&self) -> bool {
+        self.delete_top_level_comments
+    }
+
+    /// Placement of source only keys
+    pub(crate) const fn key_placement(&self) -> KeyPlacement {
+        self.key_placement
+    }
+
+    /// If the merge needs to look through the target before merging
+    pub(crate) fn needs_target_scan(&self) -> bool {
+        self.key_placement == KeyPlacement::SourceOrder
+            || !self.condition_keys.is_empty()
+            || self.needs_section_context
+    }
+
+    /// Time limit for transforms in each merge and what happens past it
+    pub(crate) const fn time_limit(&self) -> Option<(Duration, TimeoutPolicy)> {
+        self.time_limit
+    }
+
+    /// If any transform needs the rest of the section (which requires
+    /// collecting all values in the target)
+    pub(crate) const fn needs_section_context(&self) -> bool {
+        self.needs_section_context
+    }
+
+    /// Check if a key is used in a condition
+    pub(crate) fn is_condition_key(&self, section: &str, key: &str) -> bool {
+        self.condition_keys
+            .get(section)
+            .is_some_and(|keys| keys.contains(key))
+    }
+
+    /// Find the first conditional action for a key whose condition holds,
+    /// given a lookup of the values of other keys in the section
+    pub(crate) fn find_conditional_action<'v>(
+        &self,
+        section: &str,
+        key: &str,
+        value_of: impl Fn(&str) -> Option<&'v str>,
+    ) -> Option<&Action> {
+        self.conditional_actions
+            .get(section)?
+            .get(key)?
+            .iter()
+            .find(|(condition, _)| {
+                value_of(&condition.key).is_some_and(|value| condition.pattern.is_match(value))
+            })
+            .map(|(_, action)| action)
+    }
+
+    /// Position of a section in the ordering hint, if it is in it
+    pub(crate) fn section_position(&self, section: &str) -> Option<usize> {
+        self.section_order.get(section).copied()
+    }
+
+    /// Get the forced keys (from setters) that should be emitted for a section.
+    pub(crate) fn forced_keys_in(&self, section: &str) -> Option<&BTreeSet<String>> {
+        if !self.setters_apply_in(section) {
+            return None;
+        }
+        self.forced_keys.get(section)
+    }
+
+    /// If setters apply in a section
+    ///
+    /// Unless [`MutationsBuilder::setters_override_section_actions`] is set,
+    /// deleting a section takes precedence over setters. Ignoring a section
+    /// does not.
+    fn setters_apply_in(&self, section: &str) -> bool {
+        self.setters_override_section_actions
+            || self.find_section_action(section) != Some(&SectionAction::Delete)
+    }
+}
+
+/// Handle to a rule added to a [`MutationsBuilder`], see
+/// [`MutationsBuilder::last_rule`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RuleId(usize);
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Mutations {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_serialized().serialize(serializer)
+    }
+}
+
+/// Deserializing recompiles the rules, see [`MutationsBuilder::build`]
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Mutations {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let ruleset = MutationsRuleSet::deserialize(deserializer)?;
+        MutationsBuilder::from_serialized(ruleset)
+            .build()
+            .map_err(serde::de::Error::custom)
+    }
 }
 
 /// Builder for [Mutations].
@@ -85,10 +667,37 @@ pub struct MutationsBuilder {
     /// Inner builder
     action_builder: ActionsBuilder<Action, SectionAction>,
     /// Note! Only add entries that also exist as a transform here
-    forced_keys: HashMap<String, HashSet<String>>,
+    forced_keys: BTreeMap<String, BTreeSet<String>>,
+    /// If setters take precedence over section actions
+    setters_override_section_actions: bool,
+    /// If keys absent from the source are dropped even with a transform
+    strict_sync: bool,
+    /// If deleting the top level also removes the comments before the first
+    /// section header
+    delete_top_level_comments: bool,
+    /// Per section policies for target only keys
+    target_only_policies: HashMap<String, TargetOnlyPolicy>,
+    /// Ordering hint for sections
+    section_order: Vec<String>,
+    /// Placement of source only keys
+    key_placement: KeyPlacement,
+    /// Conditional actions by section and key
+    conditional_actions: HashMap<String, HashMap<String, Vec<(Condition, Action)>>>,
+    /// All added rules in data form, applied in [`Self::build`] (`None` if
+    /// removed, to keep the handles stable)
+    rules: Vec<Option<MutationRule>>,
+    /// Context for transforms
+    context: MergeContext,
+    /// Time limit for transforms in each merge
+    time_limit: Option<(Duration, TimeoutPolicy)>,
 }
 
 impl MutationsBuilder {
+    /// Section name to use for keys before the first section header.
+    ///
+    /// Same as [`crate::OUTSIDE_SECTION`].
+    pub const TOP_LEVEL: &'static str = crate::OUTSIDE_SECTION;
+
     /// Create a new builder
     #[must_use]
     pub fn new() -> Self {
@@ -101,68 +710,339 @@ impl MutationsBuilder {
         section: impl Into<String>,
         action: SectionAction,
     ) -> &mut Self {
-        self.action_builder.add_section_action(section, action);
+        self.add_rule(MutationRule::Section {
+            section: section.into(),
+            action,
+        });
         self
     }
 
+    /// Add an action for all sections starting with a prefix, see
+    /// [`ActionsBuilder::add_section_prefix_action`]
+    pub fn add_section_prefix_action(
+        &mut self,
+        prefix: impl Into<String>,
+        action: SectionAction,
+    ) -> &mut Self {
+        self.add_rule(MutationRule::SectionPrefix {
+            prefix: prefix.into(),
+            action,
+        });
+        self
+    }
+
+    /// Add an action for all sections matching a regex (exact section
+    /// actions take precedence)
+    pub fn add_section_regex_action(
+        &mut self,
+        section: impl Into<String>,
+        action: SectionAction,
+    ) -> &mut Self {
+        self.add_rule(MutationRule::SectionRegex {
+            section: section.into(),
+            action,
+        });
+        self
+    }
+
+    /// Set the action for all sections without any other section action
+    /// (including the top level)
+    pub fn default_section_action(&mut self, action: SectionAction) -> &mut Self {
+        self.add_rule(MutationRule::DefaultSection { action });
+        self
+    }
+
+    /// Keep the target as is, except for forced keys from setters.
+    ///
+    /// Sections with their own section action are still handled by that.
+    pub fn ignore_all(&mut self) -> &mut Self {
+        self.default_section_action(SectionAction::Ignore)
+    }
+
+    /// Ignore a whole section (exact match), keeping it as it is in the
+    /// target
+    pub fn ignore_section(&mut self, section: impl Into<String>) -> &mut Self {
+        self.add_section_action(section, SectionAction::Ignore)
+    }
+
+    /// Delete all sections matching a regex
+    pub fn delete_matching_sections(&mut self, section: impl Into<String>) -> &mut Self {
+        self.add_section_regex_action(section, SectionAction::Delete)
+    }
+
     /// Add an action for an exact match of section and key
+    ///
+    /// Returns a handle to the new rule.
     pub fn add_literal_action(
         &mut self,
         section: impl Into<String>,
         key: impl AsRef<str>,
         action: Action,
-    ) -> &mut Self {
-        self.action_builder.add_literal_action(section, key, action);
-        self
+    ) -> RuleId {
+        self.add_rule(MutationRule::Literal {
+            section: section.into(),
+            key: key.as_ref().into(),
+            action,
+        })
     }
 
     /// Add an action for a regex match of a section and key
+    ///
+    /// Returns a handle to the new rule.
     pub fn add_regex_action(
         &mut self,
         section: impl AsRef<str>,
         key: impl AsRef<str>,
         action: Action,
+    ) -> RuleId {
+        self.add_rule(MutationRule::Regex {
+            section: section.as_ref().into(),
+            key: key.as_ref().into(),
+            action,
+        })
+    }
+
+    /// Ignore a key (exact match), keeping the value from the target
+    ///
+    /// Same as [`Self::add_literal_action`] with [`Action::Ignore`].
+    pub fn ignore_key(&mut self, section: impl Into<String>, key: impl AsRef<str>) -> RuleId {
+        self.add_literal_action(section, key, Action::Ignore)
+    }
+
+    /// Add an action for a key before the first section header (exact
+    /// match)
+    ///
+    /// Same as [`Self::add_literal_action`] with [`Self::TOP_LEVEL`] as the
+    /// section. Returns a handle to the new rule.
+    pub fn add_top_level_action(&mut self, key: impl AsRef<str>, action: Action) -> RuleId {
+        self.add_literal_action(Self::TOP_LEVEL, key, action)
+    }
+
+    /// Ignore a key before the first section header (exact match), keeping
+    /// the value from the target
+    ///
+    /// Same as [`Self::add_top_level_action`] with [`Action::Ignore`].
+    pub fn ignore_top_level_key(&mut self, key: impl AsRef<str>) -> RuleId {
+        self.add_top_level_action(key, Action::Ignore)
+    }
+
+    /// Ignore all keys matching a section and key regex
+    ///
+    /// Same as [`Self::add_regex_action`] with [`Action::Ignore`].
+    pub fn ignore_regex(&mut self, section: impl AsRef<str>, key: impl AsRef<str>) -> RuleId {
+        self.add_regex_action(section, key, Action::Ignore)
+    }
+
+    /// Delete a key (exact match) from the output
+    ///
+    /// Same as [`Self::add_literal_action`] with [`Action::Delete`].
+    pub fn delete_key(&mut self, section: impl Into<String>, key: impl AsRef<str>) -> RuleId {
+        self.add_literal_action(section, key, Action::Delete)
+    }
+
+    /// Add an action for a regex match of a section and the whole raw line of
+    /// the target, see [`ActionsBuilder::add_raw_line_action`]
+    ///
+    /// Lines that fail to parse are kept unless the action is
+    /// [`Action::Delete`] (without logging an error).
+    pub fn add_raw_line_action(
+        &mut self,
+        section: impl AsRef<str>,
+        line: impl AsRef<str>,
+        action: Action,
+    ) -> &mut Self {
+        self.add_rule(MutationRule::RawLine {
+            section: section.as_ref().into(),
+            line: line.as_ref().into(),
+            action,
+        });
+        self
+    }
+
+    /// Set the action for target lines that fail to parse, see
+    /// [`ActionsBuilder::on_parse_error`]
+    ///
+    /// The lines are kept unless the action is [`Action::Delete`] (without
+    /// logging an error).
+    pub fn on_parse_error(&mut self, action: Action) -> &mut Self {
+        self.add_rule(MutationRule::ParseError { action });
+        self
+    }
+
+    /// Add an action for an exact match of section and key, that only
+    /// applies when the value of another key in the same section matches a
+    /// pattern.
+    ///
+    /// The value is taken from the target, or from the source if the target
+    /// lacks the key. Conditional actions take precedence over other actions
+    /// for the key (but not over section actions), and the first one added
+    /// whose condition holds is used.
+    pub fn add_conditional_action(
+        &mut self,
+        section: impl Into<String>,
+        key: impl Into<String>,
+        condition: Condition,
+        action: Action,
     ) -> &mut Self {
-        self.action_builder.add_regex_action(section, key, action);
+        self.add_rule(MutationRule::Conditional {
+            section: section.into(),
+            key: key.into(),
+            condition,
+            action,
+        });
         self
     }
 
     /// Add a forced set.
+    ///
+    /// Returns a handle to the new rule.
     pub fn add_setter(
         &mut self,
         section: impl Into<String>,
         key: impl Into<String>,
         value: impl AsRef<str>,
         separator: impl AsRef<str>,
+    ) -> RuleId {
+        self.add_rule(MutationRule::Set {
+            section: section.into(),
+            key: key.into(),
+            value: value.as_ref().into(),
+            separator: separator.as_ref().into(),
+        })
+    }
+
+    /// Set if setters should take precedence over section actions (default:
+    /// false).
+    ///
+    /// By default a deleted section stays deleted, even if there are setters
+    /// for keys in it. With this set, the forced keys are emitted anyway
+    /// (recreating the section with just those keys). Setters always apply in
+    /// ignored sections.
+    pub fn setters_override_section_actions(&mut self, value: bool) -> &mut Self {
+        self.setters_override_section_actions = value;
+        self
+    }
+
+    /// Set the context for transforms (default: empty), such as the host name
+    /// used by the `per_machine` transform
+    pub fn context(&mut self, context: MergeContext) -> &mut Self {
+        self.context = context;
+        self
+    }
+
+    /// Set a time limit for each merge (default: none), to not hang
+    /// indefinitely on slow transforms (such as a keyring prompt that is
+    /// never answered)
+    ///
+    /// Transforms are not called once the limit has passed, instead the
+    /// policy decides what happens. A transform that is already running is
+    /// not interrupted, use the timeouts of the slow transforms for that.
+    pub fn time_limit(&mut self, limit: Duration, policy: TimeoutPolicy) -> &mut Self {
+        self.time_limit = Some((limit, policy));
+        self
+    }
+
+    /// Set if the source is authoritative for which keys exist (default:
+    /// false).
+    ///
+    /// Keys without any rule are always dropped when they are absent from the
+    /// source. With this set, keys absent from the source are also dropped
+    /// when they have a transform, without calling the transform. Only
+    /// ignored keys and keys forced by setters are kept.
+    pub fn strict_sync(&mut self, value: bool) -> &mut Self {
+        self.strict_sync = value;
+        self
+    }
+
+    /// Set if deleting the top level (with a section action for
+    /// [`Self::TOP_LEVEL`]) also removes the comments and blank lines before
+    /// the first section header (default: false).
+    ///
+    /// By default they are kept, as they usually form a header for the whole
+    /// file.
+    pub fn delete_top_level_comments(&mut self, value: bool) -> &mut Self {
+        self.delete_top_level_comments = value;
+        self
+    }
+
+    /// Set the policy for keys in a section that exist in the target but not
+    /// in the source.
+    ///
+    /// Without a policy, keys without any rule are dropped and transforms
+    /// decide for themselves (unless [`Self::strict_sync`] is set, which acts
+    /// as [`TargetOnlyPolicy::DropTargetOnly`] for all sections without a
+    /// policy). Ignored keys are always kept.
+    pub fn target_only_policy(
+        &mut self,
+        section: impl Into<String>,
+        policy: TargetOnlyPolicy,
     ) -> &mut Self {
-        fn inner(
-            this: &mut MutationsBuilder,
-            section: String,
-            key: String,
-            value: &str,
-            separator: &str,
-        ) {
-            this.action_builder.add_literal_action(
-                &section,
-                &key,
-                Action::Transform(
-                    TransformSet::new((key.clone() + separator + value).into()).into(),
-                ),
-            );
-            this.forced_keys
-                .entry(section)
-                .and_modify(|v| {
-                    v.insert(key.clone());
-                })
-                .or_insert_with(|| HashSet::from_iter([key]));
-        }
-        inner(
-            self,
-            section.into(),
-            key.into(),
-            value.as_ref(),
-            separator.as_ref(),
-        );
+        self.add_rule(MutationRule::TargetOnly {
+            section: section.into(),
+            policy,
+        });
+        self
+    }
+
+    /// Set the order to use for sections that are missing from the target
+    /// and appended at the end (for example the canonical order of the
+    /// program that writes the file).
+    ///
+    /// Sections in the list are appended in the given order, followed by any
+    /// other sections sorted by name (the default for all sections).
+    pub fn section_order(
+        &mut self,
+        sections: impl IntoIterator<Item = impl Into<String>>,
+    ) -> &mut Self {
+        self.section_order = sections.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the dialect of the input (both target and source)
+    pub fn dialect(&mut self, dialect: Dialect) -> &mut Self {
+        self.action_builder.dialect(dialect);
+        self
+    }
+
+    /// Set if trailing whitespace should be removed from output lines, see
+    /// [`ActionsBuilder::trim_trailing_whitespace`]
+    pub fn trim_trailing_whitespace(&mut self, value: bool) -> &mut Self {
+        self.action_builder.trim_trailing_whitespace(value);
+        self
+    }
+
+    /// Set if runs of blank lines in the output should be collapsed, see
+    /// [`ActionsBuilder::squash_blank_lines`]
+    pub fn squash_blank_lines(&mut self, value: bool) -> &mut Self {
+        self.action_builder.squash_blank_lines(value);
+        self
+    }
+
+    /// Set if the comments directly before removed sections should be
+    /// removed, see [`ActionsBuilder::remove_section_comments`]
+    ///
+    /// When enabled, the comments before a section header are kept together
+    /// with the header, after any keys added to the previous section.
+    pub fn remove_section_comments(&mut self, value: bool) -> &mut Self {
+        self.action_builder.remove_section_comments(value);
+        self
+    }
+
+    /// Set where keys that only exist in the source are placed in sections
+    /// that exist in the target (default: [`KeyPlacement::Sorted`]).
+    ///
+    /// With [`KeyPlacement::SourceOrder`], each such key is placed just
+    /// before the next key in the source that the target also has, so the
+    /// merged file follows the layout of the source.
+    pub fn key_placement(&mut self, placement: KeyPlacement) -> &mut Self {
+        self.key_placement = placement;
+        self
+    }
+
+    /// Set resource limits, both for the rules and for the input processed
+    /// with them.
+    pub fn limits(&mut self, limits: Limits) -> &mut Self {
+        self.action_builder.limits(limits);
         self
     }
 
@@ -171,16 +1051,648 @@ impl MutationsBuilder {
         self
     }
 
+    /// Set if regex rules can match the keys before the first section header,
+    /// see [`ActionsBuilder::regexes_match_top_level`] (default: true)
+    pub fn regexes_match_top_level(&mut self, value: bool) -> &mut Self {
+        self.action_builder.regexes_match_top_level(value);
+        self
+    }
+
+    /// Add a rule in its data form
+    ///
+    /// Returns a handle to the new rule.
+    pub fn add_rule(&mut self, rule: MutationRule) -> RuleId {
+        self.rules.push(Some(rule));
+        RuleId(self.rules.len() - 1)
+    }
+
+    /// Remove a rule added earlier, returning it
+    ///
+    /// Returns `None` if the rule was already removed. The handles of other
+    /// rules stay valid.
+    pub fn remove(&mut self, rule: RuleId) -> Option<MutationRule> {
+        self.rules.get_mut(rule.0)?.take()
+    }
+
+    /// Replace a rule added earlier (keeping its position among the other
+    /// rules), returning the old rule
+    ///
+    /// Does nothing and returns `None` if the rule was removed.
+    pub fn replace(&mut self, rule: RuleId, new_rule: MutationRule) -> Option<MutationRule> {
+        self.rules
+            .get_mut(rule.0)?
+            .as_mut()
+            .map(|old| std::mem::replace(old, new_rule))
+    }
+
+    /// Add all rules from another builder after the rules in this one
+    ///
+    /// Only the rules are taken from `other`, the settings (such as
+    /// [`Self::strict_sync`] or [`Self::dialect`]) of this builder are kept.
+    /// Handles from `other` are not valid for this builder.
+    pub fn extend(&mut self, other: Self) -> &mut Self {
+        self.rules.extend(other.rules);
+        self
+    }
+
+    /// The handle of the most recently added rule, see [`Mutations::test_rule`]
+    ///
+    /// Handles are assigned in the order rules are added, starting from the
+    /// first rule added to this builder. Settings (such as
+    /// [`Self::strict_sync`]) are not rules and don't get handles.
+    #[must_use]
+    pub fn last_rule(&self) -> Option<RuleId> {
+        self.rules.len().checked_sub(1).map(RuleId)
+    }
+
+    /// Apply a rule to the inner state
+    fn apply_rule(&mut self, rule: MutationRule) {
+        match rule {
+            MutationRule::Section { section, action } => {
+                self.action_builder.add_section_action(section, action);
+            }
+            MutationRule::SectionPrefix { prefix, action } => {
+                self.action_builder
+                    .add_section_prefix_action(prefix, action);
+            }
+            MutationRule::SectionRegex { section, action } => {
+                self.action_builder
+                    .add_section_regex_action(section, action);
+            }
+            MutationRule::DefaultSection { action } => {
+                self.action_builder.default_section_action(action);
+            }
+            MutationRule::Literal {
+                section,
+                key,
+                action,
+            } => {
+                self.action_builder.add_literal_action(section, key, action);
+            }
+            MutationRule::Regex {
+                section,
+                key,
+                action,
+            } => {
+                self.action_builder.add_regex_action(section, key, action);
+            }
+            MutationRule::RawLine {
+                section,
+                line,
+                action,
+            } => {
+                self.action_builder
+                    .add_raw_line_action(section, line, action);
+            }
+            MutationRule::ParseError { action } => {
+                self.action_builder.on_parse_error(action);
+            }
+            MutationRule::Set {
+                section,
+                key,
+                value,
+                separator,
+            } => {
+                self.action_builder.add_literal_action(
+                    &section,
+                    &key,
+                    Action::Transform(
+                        TransformSet::new(format!("{key}{separator}{value}").into()).into(),
+                    ),
+                );
+                self.forced_keys.entry(section).or_default().insert(key);
+            }
+            MutationRule::Conditional {
+                section,
+                key,
+                condition,
+                action,
+            } => {
+                self.conditional_actions
+                    .entry(section)
+                    .or_default()
+                    .entry(key)
+                    .or_default()
+                    .push((condition, action));
+            }
+            MutationRule::TargetOnly { section, policy } => {
+                self.target_only_policies.insert(section, policy);
+            }
+        }
+    }
+
+    /// Create a builder from a rule set in its data form (for example
+    /// deserialized with the `serde` feature)
+    #[must_use]
+    pub fn from_serialized(ruleset: MutationsRuleSet) -> Self {
+        let mut builder = Self::new();
+        builder
+            .warn_on_multiple_matches(ruleset.warn_on_multiple_matches)
+            .setters_override_section_actions(ruleset.setters_override_section_actions)
+            .strict_sync(ruleset.strict_sync)
+            .delete_top_level_comments(ruleset.delete_top_level_comments)
+            .section_order(ruleset.section_order)
+            .key_placement(ruleset.key_placement)
+            .regexes_match_top_level(ruleset.regexes_match_top_level)
+            .dialect(ruleset.dialect)
+            .limits(ruleset.limits)
+            .trim_trailing_whitespace(ruleset.trim_trailing_whitespace)
+            .squash_blank_lines(ruleset.squash_blank_lines)
+            .remove_section_comments(ruleset.remove_section_comments)
+            .context(ruleset.context);
+        builder.time_limit = ruleset.time_limit;
+        for rule in ruleset.rules {
+            builder.add_rule(rule);
+        }
+        builder
+    }
+
     /// Build the Mutations struct
     ///
     /// Errors if a regex fails to compile.
-    pub fn build(self) -> Result<Mutations, ActionsBuilderError> {
+    pub fn build(mut self) -> Result<Mutations, ActionsBuilderError> {
+        let rules = std::mem::take(&mut self.rules);
+        for rule in rules.iter().flatten() {
+            self.apply_rule(rule.clone());
+        }
+        let needs_section_context = rules.iter().flatten().any(|rule| match rule {
+            MutationRule::Literal { action, .. }
+            | MutationRule::Regex { action, .. }
+            | MutationRule::RawLine { action, .. }
+            | MutationRule::Conditional { action, .. } => {
+                matches!(action, Action::Transform(t) if t.needs_section_context())
+            }
+            _ => false,
+        });
+        let mut section_order = HashMap::new();
+        for (idx, section) in self.section_order.into_iter().enumerate() {
+            section_order.entry(section).or_insert(idx);
+        }
         Ok(Mutations {
             actions: self.action_builder.build()?,
             forced_keys: self.forced_keys,
+            setters_override_section_actions: self.setters_override_section_actions,
+            strict_sync: self.strict_sync,
+            delete_top_level_comments: self.delete_top_level_comments,
+            target_only_policies: self.target_only_policies,
+            section_order,
+            key_placement: self.key_placement,
+            condition_keys: self
+                .conditional_actions
+                .iter()
+                .map(|(section, keys)| {
+                    let condition_keys = keys
+                        .values()
+                        .flatten()
+                        .map(|(condition, _)| condition.key.clone())
+                        .collect();
+                    (section.clone(), condition_keys)
+                })
+                .collect(),
+            conditional_actions: self.conditional_actions,
+            rules,
+            context: self.context,
+            needs_section_context,
+            time_limit: self.time_limit,
         })
     }
 }
 
+/// A single mutation rule in data form, see [`MutationsBuilder::add_rule`]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum MutationRule {
+    /// See [`MutationsBuilder::add_section_action`]
+    Section {
+        section: String,
+        action: SectionAction,
+    },
+    /// See [`MutationsBuilder::add_section_prefix_action`]
+    SectionPrefix {
+        prefix: String,
+        action: SectionAction,
+    },
+    /// See [`MutationsBuilder::add_section_regex_action`]
+    SectionRegex {
+        section: String,
+        action: SectionAction,
+    },
+    /// See [`MutationsBuilder::default_section_action`]
+    DefaultSection { action: SectionAction },
+    /// See [`MutationsBuilder::add_literal_action`]
+    Literal {
+        section: String,
+        key: String,
+        action: Action,
+    },
+    /// See [`MutationsBuilder::add_regex_action`]
+    Regex {
+        section: String,
+        key: String,
+        action: Action,
+    },
+    /// See [`MutationsBuilder::add_raw_line_action`]
+    RawLine {
+        section: String,
+        line: String,
+        action: Action,
+    },
+    /// See [`MutationsBuilder::on_parse_error`]
+    ParseError { action: Action },
+    /// See [`MutationsBuilder::add_setter`]
+    Set {
+        section: String,
+        key: String,
+        value: String,
+        separator: String,
+    },
+    /// See [`MutationsBuilder::add_conditional_action`]
+    Conditional {
+        section: String,
+        key: String,
+        condition: Condition,
+        action: Action,
+    },
+    /// See [`MutationsBuilder::target_only_policy`]
+    TargetOnly {
+        section: String,
+        policy: TargetOnlyPolicy,
+    },
+}
+
+impl Display for MutationRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Section { section, action } => write!(f, "section {section:?}: {action}"),
+            Self::SectionPrefix { prefix, action } => {
+                write!(f, "section prefix {prefix:?}: {action}")
+            }
+            Self::SectionRegex { section, action } => {
+                write!(f, "section regex {section:?}: {action}")
+            }
+            Self::DefaultSection { action } => write!(f, "default section: {action}"),
+            Self::Literal {
+                section,
+                key,
+                action,
+            } => write!(f, "{section:?} {key:?}: {action}"),
+            Self::Regex {
+                section,
+                key,
+                action,
+            } => write!(f, "regex {section:?} {key:?}: {action}"),
+            Self::RawLine {
+                section,
+                line,
+                action,
+            } => write!(f, "raw line {section:?} {line:?}: {action}"),
+            Self::ParseError { action } => write!(f, "parse error: {action}"),
+            Self::Set {
+                section,
+                key,
+                value,
+                separator,
+            } => write!(
+                f,
+                "set {section:?} {key:?} {value:?} separator={separator:?}"
+            ),
+            Self::Conditional {
+                section,
+                key,
+                condition,
+                action,
+            } => write!(
+                f,
+                "{section:?} {key:?} if {:?} matches {:?}: {action}",
+                condition.key,
+                condition.pattern.as_str()
+            ),
+            Self::TargetOnly { section, policy } => {
+                let policy = match policy {
+                    TargetOnlyPolicy::KeepTargetOnly => "keep",
+                    TargetOnlyPolicy::DropTargetOnly => "drop",
+                };
+                write!(f, "target only {section:?}: {policy}")
+            }
+        }
+    }
+}
+
+/// A mutation rule set in data form, see [`MutationsBuilder::from_serialized`]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct MutationsRuleSet {
+    /// The rules, in the order they are added
+    pub rules: Vec<MutationRule>,
+    /// Warn on multiple matches (default: true)
+    #[cfg_attr(feature = "serde", serde(default = "crate::actions::default_true"))]
+    pub warn_on_multiple_matches: bool,
+    /// See [`MutationsBuilder::setters_override_section_actions`] (default:
+    /// false)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub setters_override_section_actions: bool,
+    /// See [`MutationsBuilder::strict_sync`] (default: false)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub strict_sync: bool,
+    /// See [`MutationsBuilder::delete_top_level_comments`] (default: false)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub delete_top_level_comments: bool,
+    /// See [`MutationsBuilder::section_order`] (default: empty)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub section_order: Vec<String>,
+    /// See [`MutationsBuilder::key_placement`] (default: sorted)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub key_placement: KeyPlacement,
+    /// See [`MutationsBuilder::regexes_match_top_level`] (default: true)
+    #[cfg_attr(feature = "serde", serde(default = "crate::actions::default_true"))]
+    pub regexes_match_top_level: bool,
+    /// See [`MutationsBuilder::dialect`] (default: standard)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub dialect: Dialect,
+    /// See [`MutationsBuilder::limits`] (default: no limits)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub limits: Limits,
+    /// See [`MutationsBuilder::trim_trailing_whitespace`] (default: false)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub trim_trailing_whitespace: bool,
+    /// See [`MutationsBuilder::squash_blank_lines`] (default: false)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub squash_blank_lines: bool,
+    /// See [`MutationsBuilder::remove_section_comments`] (default: false)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub remove_section_comments: bool,
+    /// See [`MutationsBuilder::context`] (default: empty)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub context: MergeContext,
+    /// See [`MutationsBuilder::time_limit`] (default: none)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub time_limit: Option<(Duration, TimeoutPolicy)>,
+}
+
+impl MutationsRuleSet {
+    /// Create a rule set from a list of rules
+    #[must_use]
+    pub const fn new(rules: Vec<MutationRule>) -> Self {
+        Self {
+            rules,
+            warn_on_multiple_matches: true,
+            setters_override_section_actions: false,
+            strict_sync: false,
+            delete_top_level_comments: false,
+            section_order: Vec::new(),
+            key_placement: KeyPlacement::Sorted,
+            regexes_match_top_level: true,
+            dialect: Dialect::new(),
+            limits: Limits::new(),
+            trim_trailing_whitespace: false,
+            squash_blank_lines: false,
+            remove_section_comments: false,
+            context: MergeContext::new(),
+            time_limit: None,
+        }
+    }
+}
+
 #[cfg(test)]
-mod tests {}
+mod tests {
+    #[cfg(feature = "regex-rules")]
+    use super::transforms::TransformUnsortedLists;
+    #[cfg(feature = "regex-rules")]
+    use super::transforms::TransformerAction;
+    use super::Action;
+    use super::MutationsBuilder;
+    use super::SectionAction;
+    use pretty_assertions::assert_eq;
+
+    #[cfg(feature = "regex-rules")]
+    #[test]
+    fn test_rule() {
+        let line = |line: &str| Some(TransformerAction::Line(line.to_string().into()));
+        let mut builder = MutationsBuilder::new();
+        let ignore = builder.add_regex_action("s.*", "k[0-9]", Action::Ignore);
+        let setter = builder.add_setter("s", "k1", "forced", "=");
+        let list = builder.add_literal_action(
+            "s",
+            "list",
+            Action::Transform(TransformUnsortedLists::new(',').into()),
+        );
+        builder.add_section_action("gone", SectionAction::Delete);
+        let gone = builder.last_rule().unwrap();
+        let mutations = builder.build().unwrap();
+
+        let test = |rule, section, key, src, tgt| {
+            mutations.test_rule(rule, section, key, src, tgt).unwrap()
+        };
+        assert_eq!(test(ignore, "sa", "k2", Some("s"), Some("t")), line("k2=t"));
+        assert_eq!(test(ignore, "sa", "key", Some("s"), Some("t")), None);
+        assert_eq!(test(ignore, "a", "k2", Some("s"), Some("t")), None);
+        // Rules are tested in isolation
+        assert_eq!(test(setter, "s", "k1", None, Some("t")), line("k1=forced"));
+        assert_eq!(test(setter, "s", "k2", None, Some("t")), None);
+        assert_eq!(
+            test(list, "s", "list", Some("a,b"), Some("b,a")),
+            line("list=b,a")
+        );
+        assert_eq!(
+            test(list, "s", "list", Some("a,c"), Some("b,a")),
+            line("list=a,c")
+        );
+        assert_eq!(
+            test(gone, "gone", "k", Some("s"), Some("t")),
+            Some(TransformerAction::Nothing)
+        );
+        assert_eq!(test(gone, "kept", "k", Some("s"), Some("t")), None);
+    }
+
+    #[test]
+    #[cfg(feature = "regex-rules")]
+    fn display() {
+        use super::Condition;
+        use super::ValuePattern;
+
+        let mut builder = MutationsBuilder::new();
+        builder.ignore_section("a");
+        builder.add_regex_action(
+            "s",
+            "l.*",
+            Action::Transform(TransformUnsortedLists::new(',').into()),
+        );
+        builder.delete_key("s", "d");
+        builder.add_setter("s", "k", "1", "=");
+        builder.add_conditional_action(
+            "s",
+            "c",
+            Condition::new("mode", ValuePattern::new("^on$").unwrap()),
+            Action::Ignore,
+        );
+        let mutations = builder.build().unwrap();
+        assert_eq!(
+            mutations.to_string(),
+            indoc::indoc! {r#"
+                section "a": ignore
+                regex "s" "l.*": transform unsorted_lists separator=","
+                "s" "d": remove
+                set "s" "k" "1" separator="="
+                "s" "c" if "mode" matches "^on$": ignore
+            "#}
+        );
+    }
+
+    #[test]
+    fn edit_rules() {
+        use super::MutationRule;
+        use crate::merge::merge_ini_from_str;
+
+        let mut base = MutationsBuilder::new();
+        let ignore = base.add_literal_action("a", "i", Action::Ignore);
+        let delete = base.add_literal_action("a", "d", Action::Delete);
+        base.add_setter("a", "s", "1", "=");
+        let mut user = MutationsBuilder::new();
+        user.add_literal_action("a", "u", Action::Ignore);
+
+        assert!(base.remove(ignore).is_some());
+        assert!(base.remove(ignore).is_none());
+        assert!(base
+            .replace(
+                ignore,
+                MutationRule::Section {
+                    section: "a".into(),
+                    action: SectionAction::Delete
+                }
+            )
+            .is_none());
+        let old = base.replace(
+            delete,
+            MutationRule::Literal {
+                section: "a".into(),
+                key: "d".into(),
+                action: Action::Ignore,
+            },
+        );
+        assert!(matches!(old, Some(MutationRule::Literal { key, .. }) if key == "d"));
+        base.extend(user);
+
+        let mutations = base.build().unwrap();
+        let result =
+            merge_ini_from_str("[a]\ni=t\nd=t\nu=t\n", "[a]\ni=s\nd=s\nu=s\n", &mutations).unwrap();
+        assert_eq!(result, vec!["[a]", "i=s", "d=t", "u=t", "s=1"]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialized_ruleset() {
+        use super::MutationsBuilder;
+        use super::MutationsRuleSet;
+        use crate::merge::merge_ini_from_str;
+        use pretty_assertions::assert_eq;
+
+        let json = r#"{
+            "rules": [
+                {"type": "section", "section": "a", "action": "ignore"},
+                {"type": "literal", "section": "b", "key": "x", "action": "delete"},
+                {"type": "regex", "section": "b", "key": "l.*", "action":
+                    {"transform": {"name": "unsorted_lists", "args": {"separator": ","}}}},
+                {"type": "set", "section": "b", "key": "s", "value": "1", "separator": "="}
+            ]
+        }"#;
+        let ruleset: MutationsRuleSet = serde_json::from_str(json).unwrap();
+        assert!(ruleset.warn_on_multiple_matches);
+        assert_eq!(
+            serde_json::to_value(&ruleset.rules[2]).unwrap(),
+            serde_json::json!({
+                "type": "regex",
+                "section": "b",
+                "key": "l.*",
+                "action": {"transform": {"name": "unsorted_lists", "args": {"separator": ","}}}
+            })
+        );
+        let mutations = MutationsBuilder::from_serialized(ruleset).build().unwrap();
+        let result = merge_ini_from_str(
+            "[a]\nk=t\n[b]\nx=1\nl=b,a\n",
+            "[a]\nk=s\n[b]\nx=2\nl=a,b\n",
+            &mutations,
+        )
+        .unwrap();
+        assert_eq!(result, vec!["[a]", "k=t", "[b]", "l=b,a", "s=1"]);
+
+        let json = serde_json::to_value(&mutations).unwrap();
+        assert_eq!(json["rules"][3]["type"], "set");
+        let cached: super::Mutations = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&cached).unwrap(), json);
+        let result = merge_ini_from_str(
+            "[a]\nk=t\n[b]\nx=1\nl=b,a\n",
+            "[a]\nk=s\n[b]\nx=2\nl=a,b\n",
+            &cached,
+        )
+        .unwrap();
+        assert_eq!(result, vec!["[a]", "k=t", "[b]", "l=b,a", "s=1"]);
+
+        let err = serde_json::from_str::<MutationsRuleSet>(
+            r#"{"rules": [{"type": "literal", "section": "a", "key": "b",
+                "action": {"transform": {"name": "nope"}}}]}"#,
+        );
+        assert!(err.is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialized_settings() {
+        use super::MutationsBuilder;
+        use super::TimeoutPolicy;
+        use crate::dialect::Dialect;
+        use crate::limits::Limits;
+        use crate::merge::merge_ini_from_str;
+        use crate::merge::mutations::transforms::MergeContext;
+        use pretty_assertions::assert_eq;
+        use std::time::Duration;
+
+        let mut dialect = Dialect::new();
+        dialect
+            .comment_prefixes(["//"])
+            .case_insensitive_sections(true);
+        let mut context = MergeContext::new();
+        context.set("host", "a");
+        let mut builder = MutationsBuilder::new();
+        builder
+            .dialect(dialect.clone())
+            .limits(Limits::new().max_keys(10))
+            .trim_trailing_whitespace(true)
+            .squash_blank_lines(true)
+            .remove_section_comments(true)
+            .delete_top_level_comments(true)
+            .regexes_match_top_level(false)
+            .context(context.clone())
+            .time_limit(Duration::from_secs(5), TimeoutPolicy::KeepTarget);
+        builder.add_section_action("gone", SectionAction::Delete);
+        let mutations = builder.build().unwrap();
+
+        let json = serde_json::to_value(&mutations).unwrap();
+        let cached: super::Mutations = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&cached).unwrap(), json);
+        let ruleset = cached.to_serialized();
+        assert_eq!(ruleset.dialect, dialect);
+        assert_eq!(ruleset.limits, Limits::new().max_keys(10));
+        assert!(ruleset.trim_trailing_whitespace);
+        assert!(ruleset.squash_blank_lines);
+        assert!(ruleset.remove_section_comments);
+        assert!(ruleset.delete_top_level_comments);
+        assert!(!ruleset.regexes_match_top_level);
+        assert_eq!(cached.context(), &context);
+        assert_eq!(
+            ruleset.time_limit,
+            Some((Duration::from_secs(5), TimeoutPolicy::KeepTarget))
+        );
+
+        let target = "// c\n[S]\nk=t  \n\n\n// c\n[GONE]\nx=1\n";
+        let source = "[s]\nk=s  \n[gone]\nx=2\n";
+        let expected = merge_ini_from_str(target, source, &mutations).unwrap();
+        assert_eq!(expected, vec!["// c", "[S]", "k=s", ""]);
+        assert_eq!(
+            merge_ini_from_str(target, source, &cached).unwrap(),
+            expected
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! Heuristics for finding keys that change without user action
+
+use super::transforms::TransformUnsortedLists;
+use super::Action;
+use super::MutationRule;
+use super::SectionAction;
+use crate::compare::diff_snapshots;
+use crate::compare::snapshot;
+use crate::compare::IniChange;
+use crate::compare::Snapshot;
+use crate::dialect::Dialect;
+use std::collections::HashSet;
+
+/// Separators tried when checking if a value is a reordered list
+const LIST_SEPARATORS: &[char] = &[',', ';'];
+
+/// Why a rule was suggested, see [`SuggestedMutation`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ChurnReason {
+    /// The value changed
+    ValueChanged,
+    /// The value is a list that was reordered
+    Reordered {
+        /// Separator of the list
+        separator: char,
+    },
+    /// The key was added or removed
+    KeyChurn,
+    /// The section was added or removed, or every key in it changed
+    SectionChurn,
+}
+
+/// A rule suggested from observed changes, see [`suggest_rules`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SuggestedMutation {
+    /// Section the rule applies to
+    pub section: String,
+    /// The key the rule applies to (`None` for rules on the whole section)
+    pub key: Option<String>,
+    /// Why the rule was suggested
+    pub reason: ChurnReason,
+}
+
+impl SuggestedMutation {
+    /// The suggested rule
+    #[must_use]
+    pub fn rule(&self) -> MutationRule {
+        let section = self.section.clone();
+        match (&self.key, self.reason) {
+            (None, _) => MutationRule::Section {
+                section,
+                action: SectionAction::Ignore,
+            },
+            (Some(key), ChurnReason::Reordered { separator }) => MutationRule::Literal {
+                section,
+                key: key.clone(),
+                action: Action::Transform(TransformUnsortedLists::new(separator).into()),
+            },
+            (Some(key), _) => MutationRule::Literal {
+                section,
+                key: key.clone(),
+                action: Action::Ignore,
+            },
+        }
+    }
+}
+
+/// Check if two values are the same list in a different order
+fn reordered(old: &str, new: &str) -> Option<char> {
+    LIST_SEPARATORS.iter().copied().find(|&separator| {
+        old.contains(separator)
+            && old.split(separator).collect::<HashSet<_>>()
+                == new.split(separator).collect::<HashSet<_>>()
+    })
+}
+
+/// Compare two snapshots of a target file, taken without user action in
+/// between, suggesting rules for the keys and sections that changed
+///
+/// Values that are the same list in a different order get an
+/// `unsorted_lists` transform, other changed or added/removed keys are
+/// ignored. Sections that were added or removed, or where every key changed,
+/// are ignored as a whole. Suggestions are sorted by section and key.
+#[must_use]
+pub fn suggest_rules(old_target: &str, new_target: &str) -> Vec<SuggestedMutation> {
+    let dialect = Dialect::new();
+    let old_parsed = dialect.parse(old_target);
+    let new_parsed = dialect.parse(new_target);
+    let old = snapshot(&old_parsed, &dialect);
+    let new = snapshot(&new_parsed, &dialect);
+    let mut suggestions = Vec::new();
+    let changes = diff_snapshots(&old, &new);
+    let mut rest = changes.as_slice();
+    while let Some(first) = rest.first() {
+        // Changes are sorted by section
+        let section = first.section();
+        let len = rest.iter().take_while(|c| c.section() == section).count();
+        let (changes, tail) = rest.split_at(len);
+        rest = tail;
+        let mut changed = Vec::new();
+        for change in changes {
+            let reason = match change {
+                IniChange::SectionAdded { .. } | IniChange::SectionRemoved { .. } => {
+                    ChurnReason::SectionChurn
+                }
+                IniChange::ValueChanged {
+                    old: Some(old_val),
+                    new: Some(new_val),
+                    ..
+                } => match reordered(old_val, new_val) {
+                    Some(separator) => ChurnReason::Reordered { separator },
+                    None => ChurnReason::ValueChanged,
+                },
+                IniChange::ValueChanged { .. } => ChurnReason::ValueChanged,
+                _ => ChurnReason::KeyChurn,
+            };
+            changed.push(SuggestedMutation {
+                section: section.into(),
+                key: change.key().map(Into::into),
+                reason,
+            });
+        }
+        // Every key in the section changed
+        let key_count = |snapshot: &Snapshot<'_>| snapshot.get(section).map_or(0, |s| s.keys.len());
+        let all_changed = changed.len() > 1
+            && changed.len() >= key_count(&old).max(key_count(&new))
+            && section != crate::OUTSIDE_SECTION;
+        if all_changed || changed[0].reason == ChurnReason::SectionChurn {
+            changed.truncate(1);
+            changed[0].key = None;
+            changed[0].reason = ChurnReason::SectionChurn;
+        }
+        suggestions.append(&mut changed);
+    }
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::suggest_rules;
+    use super::ChurnReason;
+    use crate::merge::mutations::MutationRule;
+    use crate::merge::mutations::SectionAction;
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn suggestions() {
+        let old = indoc! {"
+            [General]
+            fixed=1
+            geometry=100x200
+            recent=a,b,c
+            [State]
+            a=1
+            b=2
+            [Gone]
+            x=1
+        "};
+        let new = indoc! {"
+            [General]
+            fixed=1
+            geometry=120x200
+            recent=c,a,b
+            session=42
+            [State]
+            a=2
+            b=3
+        "};
+        let result: Vec<_> = suggest_rules(old, new)
+            .into_iter()
+            .map(|s| (s.section.clone(), s.key.clone(), s.reason, s.rule()))
+            .collect();
+        let reasons: Vec<_> = result
+            .iter()
+            .map(|(section, key, reason, _)| (section.as_str(), key.as_deref(), *reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                ("General", Some("geometry"), ChurnReason::ValueChanged),
+                (
+                    "General",
+                    Some("recent"),
+                    ChurnReason::Reordered { separator: ',' }
+                ),
+                ("General", Some("session"), ChurnReason::KeyChurn),
+                ("Gone", None, ChurnReason::SectionChurn),
+                ("State", None, ChurnReason::SectionChurn),
+            ]
+        );
+        assert!(matches!(
+            &result[4].3,
+            MutationRule::Section {
+                section,
+                action: SectionAction::Ignore
+            } if section == "State"
+        ));
+        assert_eq!(
+            result[1].3.to_string(),
+            r#""General" "recent": transform unsorted_lists separator=",""#
+        );
+        assert!(suggest_rules(old, old).is_empty());
+    }
+}
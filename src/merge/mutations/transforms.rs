@@ -1,16 +1,31 @@
 //! Define transfomers that can be applied as mutations
 
+pub use self::args::ArgSpec;
+pub use self::args::ArgType;
+pub use self::args::CheckedArgs;
+pub use self::args::TransformArgs;
+use crate::dialect::untrimmed_section_name;
+use crate::merge::SectionValues;
+use crate::source_loader::SectionAndKey;
+use crate::source_loader::SourceIni;
+use crate::source_loader::SourceValue;
 use crate::InputData;
-use itertools::Itertools;
+use crate::SectionName;
+#[cfg(feature = "external")]
+pub use external_transform::TransformExternal;
 #[cfg(feature = "keyring")]
 pub use keyring_transform::TransformKeyring;
-use std::borrow::Borrow;
+#[cfg(feature = "rhai")]
+pub use script_transform::TransformScript;
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::collections::HashSet;
-use std::hash::Hash;
+use std::fmt::Display;
+use std::time::Duration;
 use thiserror::Error;
 
+mod args;
+
 /// The action that a transform decides should happen for a line it processes.
 #[derive(Debug, PartialEq, Eq)]
 #[non_exhaustive]
@@ -27,6 +42,27 @@ pub enum TransformerAction<'a> {
 pub enum TransformerConstructionError {
     #[error("Failed to construct transformer due to {0}")]
     Construct(&'static str),
+    #[error("Invalid value for argument {argument}{}, expected {expected}", of_transform(.transform))]
+    InvalidArgument {
+        transform: Option<&'static str>,
+        argument: String,
+        expected: &'static str,
+    },
+    #[error("Missing argument {argument} of transform {transform}")]
+    MissingArgument {
+        transform: &'static str,
+        argument: String,
+    },
+    #[error("Unknown argument {argument} of transform {transform}")]
+    UnknownArgument {
+        transform: &'static str,
+        argument: String,
+    },
+}
+
+/// The transform an argument belongs to, for error messages
+fn of_transform(transform: &Option<&'static str>) -> String {
+    transform.map_or_else(String::new, |name| format!(" of transform {name}"))
 }
 
 /// Error type for loading the source.
@@ -35,6 +71,10 @@ pub enum TransformerConstructionError {
 pub enum TransformerCallError {
     #[error("Invalid data for specific transform: {0}")]
     InvalidData(&'static str),
+    #[error("Timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("Key is missing in both source and target")]
+    MissingInput,
 }
 
 /// Trait for transformers operating on the input.
@@ -47,12 +87,235 @@ pub trait Transformer: std::fmt::Debug {
         tgt: &InputData<'a>,
     ) -> Result<TransformerAction<'a>, TransformerCallError>;
 
+    /// Apply transformer to a property, with access to the context supplied
+    /// by the caller. By default the context is not used.
+    fn call_with_context<'a>(
+        &self,
+        src: &InputData<'a>,
+        tgt: &InputData<'a>,
+        _context: &MergeContext,
+    ) -> Result<TransformerAction<'a>, TransformerCallError> {
+        self.call(src, tgt)
+    }
+
+    /// If the transform needs the rest of the section in both files, see
+    /// [`Self::call_with_section`]. This makes the merge look through the
+    /// whole target first, so it should only be set when needed.
+    fn needs_section_context(&self) -> bool {
+        false
+    }
+
+    /// Apply transformer to a property, with access to the rest of the
+    /// section in the source and target. Only called by the merge when
+    /// [`Self::needs_section_context`] is set. By default the sections are
+    /// not used.
+    fn call_with_section<'a>(
+        &self,
+        src: &InputData<'a>,
+        tgt: &InputData<'a>,
+        context: &MergeContext,
+        _src_section: &SectionView<'_>,
+        _tgt_section: &SectionView<'_>,
+    ) -> Result<TransformerAction<'a>, TransformerCallError> {
+        self.call_with_context(src, tgt, context)
+    }
+}
+
+/// Construction of a transformer from user provided arguments
+///
+/// Kept separate from [`Transformer`], so that it stays object safe (such as
+/// for `Arc<dyn Transformer>`).
+pub trait TransformerFactory: Transformer + Sized {
+    /// Name of the transform, for error messages
+    const NAME: &'static str;
+
+    /// The arguments the transform accepts
+    const ARGS: &'static [ArgSpec] = &[];
+
+    /// Construct from arguments that were checked against [`Self::ARGS`]
+    fn from_checked_args(args: &CheckedArgs<'_>) -> Result<Self, TransformerConstructionError>;
+
     /// Construct from a mapping of user provided arguments
-    fn from_user_input(
-        args: &HashMap<impl Borrow<str> + Eq + Hash, impl AsRef<str>>,
-    ) -> Result<Self, TransformerConstructionError>
-    where
-        Self: Sized;
+    fn from_user_input(args: &TransformArgs<'_>) -> Result<Self, TransformerConstructionError> {
+        Self::from_checked_args(&args.check(Self::NAME, Self::ARGS)?)
+    }
+}
+
+/// Context supplied by the caller for transforms, such as the host name, the
+/// operating system and user defined variables
+///
+/// Set with [`super::MutationsBuilder::context`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct MergeContext {
+    vars: BTreeMap<String, String>,
+}
+
+impl MergeContext {
+    /// Key for the host name of the machine
+    pub const HOSTNAME: &'static str = "hostname";
+    /// Key for the operating system (as in [`std::env::consts::OS`])
+    pub const OS: &'static str = "os";
+
+    /// Create an empty context
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            vars: BTreeMap::new(),
+        }
+    }
+
+    /// Create a context describing the current machine (the host name, if it
+    /// can be found, and the operating system)
+    #[must_use]
+    pub fn from_environment() -> Self {
+        let mut context = Self::new();
+        if let Some(name) = host_name() {
+            context.set(Self::HOSTNAME, name);
+        }
+        context.set(Self::OS, std::env::consts::OS);
+        context
+    }
+
+    /// Set a variable
+    pub fn set(&mut self, key: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.vars.insert(key.into(), value.into());
+        self
+    }
+
+    /// Get a variable
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.vars.get(key).map(String::as_str)
+    }
+
+    /// Iterate over the variables, sorted by key
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.vars.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+/// Read access to the keys of a section in the source or target, for
+/// transforms that need neighbouring keys, see
+/// [`Transformer::call_with_section`]
+#[derive(Debug, Clone, Copy)]
+pub struct SectionView<'v> {
+    inner: SectionViewInner<'v>,
+    /// The section header (`None` if the section doesn't exist in the file)
+    info: Option<SectionInfo<'v>>,
+}
+
+/// Where the keys of a [`SectionView`] come from
+#[derive(Debug, Clone, Copy)]
+enum SectionViewInner<'v> {
+    Empty,
+    Source {
+        source: &'v SourceIni,
+        section: &'v str,
+    },
+    Target(&'v SectionValues),
+}
+
+impl<'v> SectionView<'v> {
+    /// A view of a section without any keys
+    #[must_use]
+    pub const fn empty() -> Self {
+        Self {
+            inner: SectionViewInner::Empty,
+            info: None,
+        }
+    }
+
+    /// A view of a section in the source
+    pub(crate) fn source(source: &'v SourceIni, section: &'v str) -> Self {
+        Self {
+            inner: SectionViewInner::Source { source, section },
+            info: source
+                .section_header(section)
+                .map(|raw| SectionInfo::new(section, raw)),
+        }
+    }
+
+    /// A view of a section in the target (without keys if the values are
+    /// missing)
+    pub(crate) const fn target(
+        values: Option<&'v SectionValues>,
+        info: Option<SectionInfo<'v>>,
+    ) -> Self {
+        let inner = match values {
+            Some(values) => SectionViewInner::Target(values),
+            None => SectionViewInner::Empty,
+        };
+        Self { inner, info }
+    }
+
+    /// The section header, `None` if the section doesn't exist in the file
+    #[must_use]
+    pub const fn info(&self) -> Option<SectionInfo<'v>> {
+        self.info
+    }
+
+    /// The value of a key (`None` if the key is missing or has no value)
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&'v str> {
+        match self.inner {
+            SectionViewInner::Empty => None,
+            SectionViewInner::Source { source, section } => source
+                .property(&SectionAndKey::new(
+                    Cow::Borrowed(section),
+                    Cow::Owned(key.to_string()),
+                ))
+                .and_then(SourceValue::value),
+            SectionViewInner::Target(values) => values.get(key).and_then(Option::as_deref),
+        }
+    }
+
+    /// Iterate over the keys and their values, in no particular order
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&'v str, Option<&'v str>)> + 'v> {
+        match self.inner {
+            SectionViewInner::Empty => Box::new(std::iter::empty()),
+            SectionViewInner::Source { source, section } => Box::new(
+                source
+                    .section_entries(section)
+                    .map(|(key, value)| (key.as_ref(), value.value())),
+            ),
+            SectionViewInner::Target(values) => Box::new(
+                values
+                    .iter()
+                    .map(|(key, value)| (key.as_ref(), value.as_deref())),
+            ),
+        }
+    }
+}
+
+/// A section header in the source or target, see [`SectionView::info`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SectionInfo<'v> {
+    /// The section
+    pub name: SectionName<'v>,
+    /// The header line as written in the file (`None` for the top level)
+    pub raw: Option<&'v str>,
+}
+
+impl<'v> SectionInfo<'v> {
+    /// Information about a section, given the key of the section (see
+    /// [`SectionName::key`]) and its header line
+    pub(crate) fn new(section: &'v str, raw: &'v str) -> Self {
+        let name = SectionName::from_key(section);
+        Self {
+            name,
+            raw: (!name.is_top_level()).then_some(raw),
+        }
+    }
+
+    /// The text between the brackets of the header line, without any
+    /// trimming (`None` for the top level)
+    #[must_use]
+    pub fn untrimmed_name(&self) -> Option<&'v str> {
+        self.raw.and_then(untrimmed_section_name)
+    }
 }
 
 /// Enum to avoid dynamic dispatch
@@ -61,9 +324,21 @@ pub trait Transformer: std::fmt::Debug {
 #[allow(variant_size_differences)]
 pub enum TransformerDispatch {
     UnsortedLists(TransformUnsortedLists),
+    IdLists(TransformIdLists),
+    AppendOnlyList(TransformAppendOnlyList),
     KdeShortcut(TransformKdeShortcut),
+    FloatFormat(TransformFloatFormat),
+    Geometry(TransformGeometry),
+    NumericLocale(TransformNumericLocale),
+    QtStateBlob(TransformQtStateBlob),
+    SetValue(TransformSetValue),
+    PerMachine(TransformPerMachine),
     #[cfg(feature = "keyring")]
     Keyring(TransformKeyring),
+    #[cfg(feature = "rhai")]
+    Script(TransformScript),
+    #[cfg(feature = "external")]
+    External(TransformExternal),
     #[doc(hidden)]
     Set(TransformSet),
 }
@@ -76,20 +351,205 @@ impl Transformer for TransformerDispatch {
     ) -> Result<TransformerAction<'a>, TransformerCallError> {
         match self {
             Self::UnsortedLists(v) => v.call(src, tgt),
+            Self::IdLists(v) => v.call(src, tgt),
+            Self::AppendOnlyList(v) => v.call(src, tgt),
             Self::KdeShortcut(v) => v.call(src, tgt),
+            Self::FloatFormat(v) => v.call(src, tgt),
+            Self::Geometry(v) => v.call(src, tgt),
+            Self::NumericLocale(v) => v.call(src, tgt),
+            Self::QtStateBlob(v) => v.call(src, tgt),
+            Self::SetValue(v) => v.call(src, tgt),
+            Self::PerMachine(v) => v.call(src, tgt),
             Self::Set(v) => v.call(src, tgt),
             #[cfg(feature = "keyring")]
             Self::Keyring(v) => v.call(src, tgt),
+            #[cfg(feature = "rhai")]
+            Self::Script(v) => v.call(src, tgt),
+            #[cfg(feature = "external")]
+            Self::External(v) => v.call(src, tgt),
         }
     }
 
-    fn from_user_input(
-        _args: &HashMap<impl Borrow<str> + Eq + Hash, impl AsRef<str>>,
-    ) -> Result<Self, TransformerConstructionError>
-    where
-        Self: Sized,
-    {
-        panic!("Can not construct dispatcher from user input. Invalid API usage!");
+    fn call_with_context<'a>(
+        &self,
+        src: &InputData<'a>,
+        tgt: &InputData<'a>,
+        context: &MergeContext,
+    ) -> Result<TransformerAction<'a>, TransformerCallError> {
+        match self {
+            Self::PerMachine(v) => v.call_with_context(src, tgt, context),
+            _ => self.call(src, tgt),
+        }
+    }
+
+    fn needs_section_context(&self) -> bool {
+        match self {
+            #[cfg(feature = "rhai")]
+            Self::Script(v) => v.needs_section_context(),
+            _ => false,
+        }
+    }
+
+    #[cfg_attr(not(feature = "rhai"), allow(unused_variables))]
+    fn call_with_section<'a>(
+        &self,
+        src: &InputData<'a>,
+        tgt: &InputData<'a>,
+        context: &MergeContext,
+        src_section: &SectionView<'_>,
+        tgt_section: &SectionView<'_>,
+    ) -> Result<TransformerAction<'a>, TransformerCallError> {
+        match self {
+            #[cfg(feature = "rhai")]
+            Self::Script(v) => v.call_with_section(src, tgt, context, src_section, tgt_section),
+            _ => self.call_with_context(src, tgt, context),
+        }
+    }
+}
+
+impl TransformerDispatch {
+    /// Construct a transform from its name and a mapping of user provided
+    /// arguments.
+    ///
+    /// The names are `unsorted_lists`, `id_lists`, `append_only_list`,
+    /// `kde_shortcut`, `float_format`, `geometry`, `numeric_locale`,
+    /// `qt_state_blob`, `set_value`, `per_machine`, `keyring`, `script` and
+    /// `external` (the last three depend on features being enabled).
+    pub fn from_name(
+        name: &str,
+        args: &TransformArgs<'_>,
+    ) -> Result<Self, TransformerConstructionError> {
+        match name {
+            "unsorted_lists" => Ok(TransformUnsortedLists::from_user_input(args)?.into()),
+            "id_lists" => Ok(TransformIdLists::from_user_input(args)?.into()),
+            "append_only_list" => Ok(TransformAppendOnlyList::from_user_input(args)?.into()),
+            "kde_shortcut" => Ok(TransformKdeShortcut::from_user_input(args)?.into()),
+            "float_format" => Ok(TransformFloatFormat::from_user_input(args)?.into()),
+            "geometry" => Ok(TransformGeometry::from_user_input(args)?.into()),
+            "numeric_locale" => Ok(TransformNumericLocale::from_user_input(args)?.into()),
+            "qt_state_blob" => Ok(TransformQtStateBlob::from_user_input(args)?.into()),
+            "set_value" => Ok(TransformSetValue::from_user_input(args)?.into()),
+            "per_machine" => Ok(TransformPerMachine::from_user_input(args)?.into()),
+            "set" => Ok(TransformSet::from_user_input(args)?.into()),
+            #[cfg(feature = "keyring")]
+            "keyring" => Ok(TransformKeyring::from_user_input(args)?.into()),
+            #[cfg(feature = "rhai")]
+            "script" => Ok(TransformScript::from_user_input(args)?.into()),
+            #[cfg(feature = "external")]
+            "external" => Ok(TransformExternal::from_user_input(args)?.into()),
+            _ => Err(TransformerConstructionError::Construct("Unknown transform")),
+        }
+    }
+
+    /// The name of the transform, as accepted by [`Self::from_name`]
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::UnsortedLists(_) => "unsorted_lists",
+            Self::IdLists(_) => "id_lists",
+            Self::AppendOnlyList(_) => "append_only_list",
+            Self::KdeShortcut(_) => "kde_shortcut",
+            Self::FloatFormat(_) => "float_format",
+            Self::Geometry(_) => "geometry",
+            Self::NumericLocale(_) => "numeric_locale",
+            Self::QtStateBlob(_) => "qt_state_blob",
+            Self::SetValue(_) => "set_value",
+            Self::PerMachine(_) => "per_machine",
+            Self::Set(_) => "set",
+            #[cfg(feature = "keyring")]
+            Self::Keyring(_) => "keyring",
+            #[cfg(feature = "rhai")]
+            Self::Script(_) => "script",
+            #[cfg(feature = "external")]
+            Self::External(_) => "external",
+        }
+    }
+
+    /// If the transform produces secret values (such as passwords from the
+    /// keyring), that should not be stored
+    #[must_use]
+    pub const fn is_secret(&self) -> bool {
+        match self {
+            #[cfg(feature = "keyring")]
+            Self::Keyring(_) => true,
+            _ => false,
+        }
+    }
+
+    /// The user arguments that construct this transform with
+    /// [`Self::from_name`]
+    #[must_use]
+    pub fn user_input(&self) -> TransformArgs<'static> {
+        match self {
+            Self::UnsortedLists(v) => TransformArgs::from([("separator", v.separator.to_string())]),
+            Self::IdLists(v) => TransformArgs::from([("separator", v.separator.to_string())]),
+            Self::AppendOnlyList(v) => {
+                TransformArgs::from([("separator", v.separator.to_string())])
+            }
+            Self::KdeShortcut(_) => TransformArgs::new(),
+            Self::FloatFormat(_) | Self::Geometry(_) | Self::NumericLocale(_) => {
+                TransformArgs::new()
+            }
+            Self::QtStateBlob(v) => v.user_input(),
+            Self::SetValue(v) => TransformArgs::from([("value", v.value.to_string())]),
+            Self::PerMachine(v) => v.user_input(),
+            Self::Set(v) => TransformArgs::from([("raw", v.raw.to_string())]),
+            #[cfg(feature = "keyring")]
+            Self::Keyring(v) => v.user_input(),
+            #[cfg(feature = "rhai")]
+            Self::Script(v) => v.user_input(),
+            #[cfg(feature = "external")]
+            Self::External(v) => v.user_input(),
+        }
+    }
+}
+
+/// The name followed by the user arguments (sorted), such as
+/// `unsorted_lists separator=","`
+impl Display for TransformerDispatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())?;
+        for (name, value) in self.user_input().iter() {
+            write!(f, " {name}={value:?}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Transforms are compared by name and user arguments
+impl PartialEq for TransformerDispatch {
+    fn eq(&self, other: &Self) -> bool {
+        self.name() == other.name() && self.user_input() == other.user_input()
+    }
+}
+
+/// Transforms are serialized by name and user arguments, see
+/// [`TransformerDispatch::from_name`]
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct NamedTransform<Args> {
+    name: String,
+    #[serde(default)]
+    args: Args,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for TransformerDispatch {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        NamedTransform {
+            name: self.name().to_string(),
+            args: self.user_input(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for TransformerDispatch {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let named = NamedTransform::<TransformArgs<'_>>::deserialize(deserializer)?;
+        Self::from_name(&named.name, &named.args)
+            .map_err(|err| serde::de::Error::custom(format!("{}: {err}", named.name)))
     }
 }
 
@@ -104,10 +564,22 @@ macro_rules! dispatch_from {
 }
 
 dispatch_from!(TransformUnsortedLists, UnsortedLists);
+dispatch_from!(TransformIdLists, IdLists);
+dispatch_from!(TransformAppendOnlyList, AppendOnlyList);
 dispatch_from!(TransformKdeShortcut, KdeShortcut);
+dispatch_from!(TransformFloatFormat, FloatFormat);
+dispatch_from!(TransformGeometry, Geometry);
+dispatch_from!(TransformNumericLocale, NumericLocale);
+dispatch_from!(TransformQtStateBlob, QtStateBlob);
+dispatch_from!(TransformSetValue, SetValue);
+dispatch_from!(TransformPerMachine, PerMachine);
 dispatch_from!(TransformSet, Set);
 #[cfg(feature = "keyring")]
 dispatch_from!(TransformKeyring, Keyring);
+#[cfg(feature = "rhai")]
+dispatch_from!(TransformScript, Script);
+#[cfg(feature = "external")]
+dispatch_from!(TransformExternal, External);
 
 /// Compare the value as an unsorted list.
 ///
@@ -134,9 +606,9 @@ impl Transformer for TransformUnsortedLists {
         tgt: &InputData<'a>,
     ) -> Result<TransformerAction<'a>, TransformerCallError> {
         // Deal with case of line in just target or source.
-        // At least one of them will exist (or we wouldn't be here).
+        // At least one of them should exist (otherwise report an error).
         match (src, tgt) {
-            (None, None) => unreachable!(),
+            (None, None) => Err(TransformerCallError::MissingInput),
             (None, Some(_)) => Ok(TransformerAction::Nothing),
             (Some(val), None) => Ok(TransformerAction::Line(val.raw.into())),
             (Some(sval), Some(tval)) => {
@@ -163,76 +635,64 @@ impl Transformer for TransformUnsortedLists {
             }
         }
     }
+}
 
-    fn from_user_input(
-        args: &HashMap<impl Borrow<str> + Eq + Hash, impl AsRef<str>>,
-    ) -> Result<Self, TransformerConstructionError>
-    where
-        Self: Sized,
-    {
-        Ok(Self::new(
-            args.get("separator")
-                .map(AsRef::as_ref)
-                .ok_or(TransformerConstructionError::Construct(
-                    "Failed to get separator",
-                ))?
-                .chars()
-                .exactly_one()
-                .map_err(|_| {
-                    TransformerConstructionError::Construct(
-                        "Failed to get character from separator",
-                    )
-                })?,
-        ))
+impl TransformerFactory for TransformUnsortedLists {
+    const NAME: &'static str = "unsorted_lists";
+    const ARGS: &'static [ArgSpec] = &[ArgSpec::required("separator", ArgType::Char)];
+
+    fn from_checked_args(args: &CheckedArgs<'_>) -> Result<Self, TransformerConstructionError> {
+        Ok(Self::new(args.char("separator")?))
     }
 }
 
-/// Specialised transform to handle KDE changing certain global shortcuts back
-/// and forth between formats like:
+/// Keep the target line if the list in the target contains all entries of the
+/// list in the source.
 ///
-/// ```ini
-/// playmedia=none,,Play media playback
-/// playmedia=none,none,Play media playback
-/// ```
+/// Useful for lists that grow on the target machine (such as recently used
+/// files or enabled plugins), that should not be truncated back to the
+/// source.
 ///
-/// No arguments
+/// Arguments:
+/// * `separator`: Separating character in the list
 #[derive(Debug, Clone)]
-pub struct TransformKdeShortcut;
+pub struct TransformAppendOnlyList {
+    separator: char,
+}
 
-impl Transformer for TransformKdeShortcut {
+impl TransformAppendOnlyList {
+    #[must_use]
+    pub const fn new(separator: char) -> Self {
+        Self { separator }
+    }
+}
+
+impl Transformer for TransformAppendOnlyList {
     fn call<'a>(
         &self,
         src: &InputData<'a>,
         tgt: &InputData<'a>,
     ) -> Result<TransformerAction<'a>, TransformerCallError> {
-        // Deal with case of line in just target or source.
-        // At least one of them will exist (or we wouldn't be here).
         match (src, tgt) {
-            (None, None) => unreachable!(),
+            (None, None) => Err(TransformerCallError::MissingInput),
             (None, Some(_)) => Ok(TransformerAction::Nothing),
             (Some(val), None) => Ok(TransformerAction::Line(val.raw.into())),
             (Some(sval), Some(tval)) => {
-                let src_split: Vec<_> = sval
+                let ss: HashSet<_> = sval
                     .val
                     .ok_or(TransformerCallError::InvalidData(
                         "Key is missing value in source",
                     ))?
-                    .split(',')
+                    .split(self.separator)
                     .collect();
-                let tgt_split: Vec<_> = tval
+                let ts: HashSet<_> = tval
                     .val
                     .ok_or(TransformerCallError::InvalidData(
                         "Key is missing value in target",
                     ))?
-                    .split(',')
+                    .split(self.separator)
                     .collect();
-                if src_split.len() == tgt_split.len()
-                    && src_split.len() == 3
-                    && src_split[0] == tgt_split[0]
-                    && src_split[2] == tgt_split[2]
-                    && ["", "none"].contains(&src_split[1])
-                    && ["", "none"].contains(&tgt_split[1])
-                {
+                if ts.is_superset(&ss) {
                     Ok(TransformerAction::Line(tval.raw.into()))
                 } else {
                     Ok(TransformerAction::Line(sval.raw.into()))
@@ -240,184 +700,1526 @@ impl Transformer for TransformKdeShortcut {
             }
         }
     }
+}
 
-    fn from_user_input(
-        args: &HashMap<impl Borrow<str> + Eq + Hash, impl AsRef<str>>,
-    ) -> Result<Self, TransformerConstructionError>
-    where
-        Self: Sized,
-    {
-        if args.is_empty() {
-            Ok(Self)
-        } else {
-            Err(TransformerConstructionError::Construct(
-                "Unexpected arguments",
-            ))
-        }
+impl TransformerFactory for TransformAppendOnlyList {
+    const NAME: &'static str = "append_only_list";
+    const ARGS: &'static [ArgSpec] = &[ArgSpec::required("separator", ArgType::Char)];
+
+    fn from_checked_args(args: &CheckedArgs<'_>) -> Result<Self, TransformerConstructionError> {
+        Ok(Self::new(args.char("separator")?))
     }
 }
 
-/// Transform to set to a fixed value.
+/// Compare the value as an unsorted list of entries containing ids that are
+/// assigned per machine, keeping the target line if the lists have the same
+/// shape.
 ///
-/// This is meant to be used together with templating, to override an entry
-/// only on some systems.
-///
-/// *NOTE*: This is not meant to be used directly, as special support is
-/// needed elsewhere. Instead, use [`super::MutationsBuilder::add_setter`]
+/// Useful because Konversation numbers identities and servers (such as
+/// `ServerList=Server 3,Server 7`) separately on each machine. Entries are
+/// compared with runs of digits ignored, so only the number of entries and
+/// what they are apart from the ids has to match. The target numbering is
+/// kept.
 ///
 /// Arguments:
-/// * `raw`: Raw line to set
-#[doc(hidden)]
+/// * `separator`: Separating character in the list
 #[derive(Debug, Clone)]
-pub struct TransformSet {
-    raw: Box<str>,
+pub struct TransformIdLists {
+    separator: char,
 }
 
-impl TransformSet {
+impl TransformIdLists {
     #[must_use]
-    pub const fn new(raw: Box<str>) -> Self {
-        Self { raw }
+    pub const fn new(separator: char) -> Self {
+        Self { separator }
+    }
+
+    /// The entries of the list with ids removed, sorted
+    fn shape(&self, value: &str) -> Vec<String> {
+        let mut shape: Vec<_> = value
+            .split(self.separator)
+            .map(|entry| {
+                let mut result = String::with_capacity(entry.len());
+                for c in entry.chars() {
+                    if !c.is_ascii_digit() {
+                        result.push(c);
+                    } else if !result.ends_with('#') {
+                        result.push('#');
+                    }
+                }
+                result
+            })
+            .collect();
+        shape.sort_unstable();
+        shape
     }
 }
 
-impl Transformer for TransformSet {
+impl Transformer for TransformIdLists {
     fn call<'a>(
         &self,
-        _src: &InputData<'a>,
-        _tgt: &InputData<'a>,
+        src: &InputData<'a>,
+        tgt: &InputData<'a>,
     ) -> Result<TransformerAction<'a>, TransformerCallError> {
-        Ok(TransformerAction::Line(Cow::Owned(self.raw.to_string())))
+        match (src, tgt) {
+            (None, None) => Err(TransformerCallError::MissingInput),
+            (None, Some(_)) => Ok(TransformerAction::Nothing),
+            (Some(val), None) => Ok(TransformerAction::Line(val.raw.into())),
+            (Some(sval), Some(tval)) => {
+                let ss = self.shape(sval.val.ok_or(TransformerCallError::InvalidData(
+                    "Key is missing value in source",
+                ))?);
+                let ts = self.shape(tval.val.ok_or(TransformerCallError::InvalidData(
+                    "Key is missing value in target",
+                ))?);
+                if ss == ts {
+                    Ok(TransformerAction::Line(tval.raw.into()))
+                } else {
+                    Ok(TransformerAction::Line(sval.raw.into()))
+                }
+            }
+        }
     }
+}
 
-    fn from_user_input(
-        args: &HashMap<impl Borrow<str> + Eq + Hash, impl AsRef<str>>,
-    ) -> Result<Self, TransformerConstructionError>
-    where
-        Self: Sized,
-    {
-        Ok(Self::new(
-            args.get("raw")
-                .map(AsRef::as_ref)
-                .ok_or(TransformerConstructionError::Construct(
-                    "Failed to get raw entry",
-                ))?
-                .into(),
-        ))
+impl TransformerFactory for TransformIdLists {
+    const NAME: &'static str = "id_lists";
+    const ARGS: &'static [ArgSpec] = &[ArgSpec::required("separator", ArgType::Char)];
+
+    fn from_checked_args(args: &CheckedArgs<'_>) -> Result<Self, TransformerConstructionError> {
+        Ok(Self::new(args.char("separator")?))
     }
 }
 
-#[cfg(feature = "keyring")]
-mod keyring_transform {
-    use super::Transformer;
-    use super::TransformerAction;
-    use super::TransformerConstructionError;
-    use crate::InputData;
-    use log::error;
-    use std::borrow::Borrow;
-    use std::hash::Hash;
+/// Compare the value as a floating point number, keeping the target line if
+/// the numbers are equal.
+///
+/// Useful because KDE and games change how floats are written between
+/// releases (such as `1`, `1.0` and `1.000000`) without the value changing.
+/// Values that are not numbers are compared as text.
+///
+/// No arguments
+#[derive(Debug, Clone)]
+pub struct TransformFloatFormat;
 
-    /// Get value from system keyring (secrets service). Useful for passwords
-    /// etc that you do not want in your dotfiles repo, but sync via some more
-    /// secure manner.
-    ///
-    /// Arguments:
-    /// * `service`: Which service name to look under
-    /// * `user`: The username identifying the entry
-    /// * `separator`: The separator to use between key and value (optional,
-    ///   default is `=`)
-    ///
-    /// Example args:
-    /// * service: "my-service"
-    /// * user: "my-user"
-    ///
-    /// To add a key compatible with the above service and user run a command
-    /// like the following:
-    ///
-    /// ```console
-    /// $ chezmoi_modify_manager --keyring-set my-service my-user
+impl Transformer for TransformFloatFormat {
+    fn call<'a>(
+        &self,
+        src: &InputData<'a>,
+        tgt: &InputData<'a>,
+    ) -> Result<TransformerAction<'a>, TransformerCallError> {
+        match (src, tgt) {
+            (None, None) => Err(TransformerCallError::MissingInput),
+            (None, Some(_)) => Ok(TransformerAction::Nothing),
+            (Some(val), None) => Ok(TransformerAction::Line(val.raw.into())),
+            (Some(sval), Some(tval)) => {
+                let equal = match (sval.val, tval.val) {
+                    (Some(s), Some(t)) => {
+                        match (s.trim().parse::<f64>(), t.trim().parse::<f64>()) {
+                            (Ok(s), Ok(t)) => s == t,
+                            _ => s == t,
+                        }
+                    }
+                    (s, t) => s == t,
+                };
+                if equal {
+                    Ok(TransformerAction::Line(tval.raw.into()))
+                } else {
+                    Ok(TransformerAction::Line(sval.raw.into()))
+                }
+            }
+        }
+    }
+}
+
+impl TransformerFactory for TransformFloatFormat {
+    const NAME: &'static str = "float_format";
+
+    fn from_checked_args(_args: &CheckedArgs<'_>) -> Result<Self, TransformerConstructionError> {
+        Ok(Self)
+    }
+}
+
+/// Keep the target line for sizes (such as `1920x1080` or `800,600`), as
+/// these depend on the screen of the machine.
+///
+/// If either value is not a size (two integers separated by `x` or `,`), the
+/// source line is used.
+/// To keep the target value regardless of the format, use
+/// [`super::Action::IgnoreButRequire`] instead.
+///
+/// No arguments
+#[derive(Debug, Clone)]
+pub struct TransformGeometry;
+
+impl Transformer for TransformGeometry {
+    fn call<'a>(
+        &self,
+        src: &InputData<'a>,
+        tgt: &InputData<'a>,
+    ) -> Result<TransformerAction<'a>, TransformerCallError> {
+        match (src, tgt) {
+            (None, None) => Err(TransformerCallError::MissingInput),
+            (None, Some(_)) => Ok(TransformerAction::Nothing),
+            (Some(val), None) => Ok(TransformerAction::Line(val.raw.into())),
+            (Some(sval), Some(tval)) => {
+                let is_size = |val: Option<&str>| val.and_then(parse_size).is_some();
+                if is_size(sval.val) && is_size(tval.val) {
+                    Ok(TransformerAction::Line(tval.raw.into()))
+                } else {
+                    Ok(TransformerAction::Line(sval.raw.into()))
+                }
+            }
+        }
+    }
+}
+
+impl TransformerFactory for TransformGeometry {
+    const NAME: &'static str = "geometry";
+
+    fn from_checked_args(_args: &CheckedArgs<'_>) -> Result<Self, TransformerConstructionError> {
+        Ok(Self)
+    }
+}
+
+/// Parse a size like `1920x1080` or `800,600`, see [`TransformGeometry`]
+fn parse_size(value: &str) -> Option<(u32, u32)> {
+    let (width, height) = value.split_once(['x', 'X', ','])?;
+    Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+}
+
+/// Compare the value as a number written according to the locale, keeping
+/// the target line if the numbers are equal.
+///
+/// Applications that honour `LC_NUMERIC` write values differently depending
+/// on the locale of the machine, such as `1.5` and `1,5` or `1 000` and
+/// `1000`. Spaces (including no-break spaces) and apostrophes are taken as
+/// digit grouping. If both `.` and `,` occur, the last one is the decimal
+/// separator; if only one of them occurs it is the decimal separator unless
+/// it occurs more than once. Values that are not numbers are compared as
+/// text.
+///
+/// No arguments
+#[derive(Debug, Clone)]
+pub struct TransformNumericLocale;
+
+impl Transformer for TransformNumericLocale {
+    fn call<'a>(
+        &self,
+        src: &InputData<'a>,
+        tgt: &InputData<'a>,
+    ) -> Result<TransformerAction<'a>, TransformerCallError> {
+        match (src, tgt) {
+            (None, None) => Err(TransformerCallError::MissingInput),
+            (None, Some(_)) => Ok(TransformerAction::Nothing),
+            (Some(val), None) => Ok(TransformerAction::Line(val.raw.into())),
+            (Some(sval), Some(tval)) => {
+                let equal = match (sval.val, tval.val) {
+                    (Some(s), Some(t)) => match (normalize_number(s), normalize_number(t)) {
+                        (Some(s), Some(t)) => s == t,
+                        _ => s == t,
+                    },
+                    (s, t) => s == t,
+                };
+                if equal {
+                    Ok(TransformerAction::Line(tval.raw.into()))
+                } else {
+                    Ok(TransformerAction::Line(sval.raw.into()))
+                }
+            }
+        }
+    }
+}
+
+impl TransformerFactory for TransformNumericLocale {
+    const NAME: &'static str = "numeric_locale";
+
+    fn from_checked_args(_args: &CheckedArgs<'_>) -> Result<Self, TransformerConstructionError> {
+        Ok(Self)
+    }
+}
+
+/// Normalize a number written according to some locale to the form
+/// `-123.45` (without redundant zeros), see [`TransformNumericLocale`]
+fn normalize_number(value: &str) -> Option<String> {
+    let value: String = value
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '\u{a0}' | '\u{202f}' | '\''))
+        .collect();
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value.strip_prefix('+').unwrap_or(&value)),
+    };
+    let decimal = match (digits.rfind('.'), digits.rfind(',')) {
+        (Some(dot), Some(comma)) => Some(dot.max(comma)),
+        (Some(pos), None) | (None, Some(pos)) => {
+            let separator = &digits[pos..=pos];
+            (digits.matches(separator).count() == 1).then_some(pos)
+        }
+        (None, None) => None,
+    };
+    let (int, frac) = match decimal {
+        Some(pos) => (&digits[..pos], &digits[pos + 1..]),
+        None => (digits, ""),
+    };
+    // Only the other separator may be used for grouping
+    let grouping = match decimal.map(|pos| &digits[pos..=pos]) {
+        Some(".") => ",",
+        Some(_) => ".",
+        None => ".,",
+    };
+    let int: String = int.chars().filter(|&c| !grouping.contains(c)).collect();
+    if int.is_empty() && frac.is_empty()
+        || !int.chars().all(|c| c.is_ascii_digit())
+        || !frac.chars().all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    let int = int.trim_start_matches('0');
+    let frac = frac.trim_end_matches('0');
+    let int = if int.is_empty() { "0" } else { int };
+    let sign = if negative && (int != "0" || !frac.is_empty()) {
+        "-"
+    } else {
+        ""
+    };
+    Some(if frac.is_empty() {
+        format!("{sign}{int}")
+    } else {
+        format!("{sign}{int}.{frac}")
+    })
+}
+
+/// Specialised transform to handle KDE changing certain global shortcuts back
+/// and forth between formats like:
+///
+/// ```ini
+/// playmedia=none,,Play media playback
+/// playmedia=none,none,Play media playback
+/// ```
+///
+/// No arguments
+#[derive(Debug, Clone)]
+pub struct TransformKdeShortcut;
+
+impl Transformer for TransformKdeShortcut {
+    fn call<'a>(
+        &self,
+        src: &InputData<'a>,
+        tgt: &InputData<'a>,
+    ) -> Result<TransformerAction<'a>, TransformerCallError> {
+        // Deal with case of line in just target or source.
+        // At least one of them should exist (otherwise report an error).
+        match (src, tgt) {
+            (None, None) => Err(TransformerCallError::MissingInput),
+            (None, Some(_)) => Ok(TransformerAction::Nothing),
+            (Some(val), None) => Ok(TransformerAction::Line(val.raw.into())),
+            (Some(sval), Some(tval)) => {
+                let src_split: Vec<_> = sval
+                    .val
+                    .ok_or(TransformerCallError::InvalidData(
+                        "Key is missing value in source",
+                    ))?
+                    .split(',')
+                    .collect();
+                let tgt_split: Vec<_> = tval
+                    .val
+                    .ok_or(TransformerCallError::InvalidData(
+                        "Key is missing value in target",
+                    ))?
+                    .split(',')
+                    .collect();
+                if src_split.len() == tgt_split.len()
+                    && src_split.len() == 3
+                    && src_split[0] == tgt_split[0]
+                    && src_split[2] == tgt_split[2]
+                    && ["", "none"].contains(&src_split[1])
+                    && ["", "none"].contains(&tgt_split[1])
+                {
+                    Ok(TransformerAction::Line(tval.raw.into()))
+                } else {
+                    Ok(TransformerAction::Line(sval.raw.into()))
+                }
+            }
+        }
+    }
+}
+
+impl TransformerFactory for TransformKdeShortcut {
+    const NAME: &'static str = "kde_shortcut";
+
+    fn from_checked_args(_args: &CheckedArgs<'_>) -> Result<Self, TransformerConstructionError> {
+        Ok(Self)
+    }
+}
+
+/// Compare Qt state blobs (such as `State=` in the `[MainWindow]` section)
+/// by their decoded bytes, keeping the target line if they are equal.
+///
+/// Many Qt applications rewrite these on every exit. Values can be either
+/// `@ByteArray(...)` (with `QSettings` escapes) or base64, optionally quoted.
+/// Values that can't be decoded are compared as text.
+///
+/// Arguments:
+/// * `keep_target`: If `true`, always keep the target line when the key
+///   exists in both files (optional)
+#[derive(Debug, Clone)]
+pub struct TransformQtStateBlob {
+    keep_target: bool,
+}
+
+impl TransformQtStateBlob {
+    #[must_use]
+    pub const fn new(keep_target: bool) -> Self {
+        Self { keep_target }
+    }
+
+    fn user_input(&self) -> TransformArgs<'static> {
+        if self.keep_target {
+            TransformArgs::from([("keep_target", "true")])
+        } else {
+            TransformArgs::new()
+        }
+    }
+}
+
+impl Transformer for TransformQtStateBlob {
+    fn call<'a>(
+        &self,
+        src: &InputData<'a>,
+        tgt: &InputData<'a>,
+    ) -> Result<TransformerAction<'a>, TransformerCallError> {
+        match (src, tgt) {
+            (None, None) => Err(TransformerCallError::MissingInput),
+            (None, Some(_)) => Ok(TransformerAction::Nothing),
+            (Some(val), None) => Ok(TransformerAction::Line(val.raw.into())),
+            (Some(sval), Some(tval)) => {
+                let equal = self.keep_target
+                    || match (sval.val, tval.val) {
+                        (Some(s), Some(t)) => match (decode_qt_blob(s), decode_qt_blob(t)) {
+                            (Some(s), Some(t)) => s == t,
+                            _ => s == t,
+                        },
+                        (s, t) => s == t,
+                    };
+                if equal {
+                    Ok(TransformerAction::Line(tval.raw.into()))
+                } else {
+                    Ok(TransformerAction::Line(sval.raw.into()))
+                }
+            }
+        }
+    }
+}
+
+impl TransformerFactory for TransformQtStateBlob {
+    const NAME: &'static str = "qt_state_blob";
+    const ARGS: &'static [ArgSpec] = &[ArgSpec::optional("keep_target", ArgType::Bool)];
+
+    fn from_checked_args(args: &CheckedArgs<'_>) -> Result<Self, TransformerConstructionError> {
+        Ok(Self::new(args.bool("keep_target")))
+    }
+}
+
+/// Decode a Qt state blob, see [`TransformQtStateBlob`]
+fn decode_qt_blob(value: &str) -> Option<Vec<u8>> {
+    let value = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value);
+    match value
+        .strip_prefix("@ByteArray(")
+        .and_then(|v| v.strip_suffix(')'))
+    {
+        Some(escaped) => unescape_qt_bytes(escaped),
+        None => decode_base64(value),
+    }
+}
+
+/// Undo the escaping `QSettings` applies to byte arrays in INI files
+fn unescape_qt_bytes(escaped: &str) -> Option<Vec<u8>> {
+    let mut result = Vec::with_capacity(escaped.len());
+    let mut bytes = escaped.bytes().peekable();
+    while let Some(byte) = bytes.next() {
+        if byte != b'\\' {
+            result.push(byte);
+            continue;
+        }
+        let escape = bytes.next()?;
+        let (radix, first) = match escape {
+            b'x' => (16, 0),
+            b'0'..=b'7' => (8, u32::from(escape - b'0')),
+            b'a' => (0, 0x07),
+            b'b' => (0, 0x08),
+            b'f' => (0, 0x0c),
+            b'n' => (0, b'\n'.into()),
+            b'r' => (0, b'\r'.into()),
+            b't' => (0, b'\t'.into()),
+            b'v' => (0, 0x0b),
+            other => (0, other.into()),
+        };
+        let mut code = first;
+        if radix != 0 {
+            // Numeric escapes continue as long as there are digits
+            while let Some(digit) = bytes.peek().and_then(|&b| char::from(b).to_digit(radix)) {
+                code = code.checked_mul(radix)?.checked_add(digit)?;
+                bytes.next();
+            }
+        }
+        result.push(u8::try_from(code).ok()?);
+    }
+    Some(result)
+}
+
+/// Decode standard base64 (with optional padding)
+fn decode_base64(encoded: &str) -> Option<Vec<u8>> {
+    let encoded = encoded.trim_end_matches('=');
+    let mut result = Vec::with_capacity(encoded.len() * 3 / 4);
+    let mut buffer = 0_u32;
+    let mut bits = 0;
+    for byte in encoded.bytes() {
+        let sextet = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | u32::from(sextet);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(result)
+}
+
+/// Transform to set the value to a fixed value, keeping the formatting of
+/// the line.
+///
+/// Unlike [`super::MutationsBuilder::add_setter`] only the value is replaced.
+/// The key spelling and separator (with its whitespace) are taken from the
+/// target line, falling back to the source line and then to `=`. This
+/// transform does not create the key if it is missing in both files.
+///
+/// Arguments:
+/// * `value`: The value to set
+#[derive(Debug, Clone)]
+pub struct TransformSetValue {
+    value: Box<str>,
+}
+
+impl TransformSetValue {
+    #[must_use]
+    pub const fn new(value: Box<str>) -> Self {
+        Self { value }
+    }
+}
+
+impl Transformer for TransformSetValue {
+    fn call<'a>(
+        &self,
+        src: &InputData<'a>,
+        tgt: &InputData<'a>,
+    ) -> Result<TransformerAction<'a>, TransformerCallError> {
+        Ok(TransformerAction::Line(
+            set_value_line(&self.value, src, tgt)?.into(),
+        ))
+    }
+}
+
+impl TransformerFactory for TransformSetValue {
+    const NAME: &'static str = "set_value";
+    const ARGS: &'static [ArgSpec] = &[ArgSpec::required("value", ArgType::Text)];
+
+    fn from_checked_args(args: &CheckedArgs<'_>) -> Result<Self, TransformerConstructionError> {
+        Ok(Self::new(args.text("value")?.into()))
+    }
+}
+
+/// A line with the value replaced, keeping the formatting of the target (or
+/// source) line, see [`TransformSetValue`]
+fn set_value_line(
+    value: &str,
+    src: &InputData<'_>,
+    tgt: &InputData<'_>,
+) -> Result<String, TransformerCallError> {
+    let prefix = [tgt, src]
+        .into_iter()
+        .flatten()
+        .find_map(|prop| Some(&prop.raw[..prop.value_span()?.start]));
+    match (prefix, tgt, src) {
+        (Some(prefix), _, _) => Ok(format!("{prefix}{value}")),
+        (None, Some(prop), _) | (None, None, Some(prop)) => Ok(format!("{}={value}", prop.key)),
+        (None, None, None) => Err(TransformerCallError::MissingInput),
+    }
+}
+
+/// Transform to set the value depending on the machine.
+///
+/// The value for the current machine is set like with [`TransformSetValue`].
+/// Without a value for the machine (and no default) the target line is kept,
+/// and the key is not created if it is missing in the target.
+///
+/// The machine is identified by the host name, unless given explicitly. The
+/// host name in the [`MergeContext`] takes precedence over the host name of
+/// the current machine.
+///
+/// Arguments:
+/// * `values`: Lines of `machine=value`
+/// * `default`: Value for other machines (optional)
+/// * `machine`: Identifier of the current machine (optional)
+#[derive(Debug, Clone)]
+pub struct TransformPerMachine {
+    values: BTreeMap<String, String>,
+    default: Option<Box<str>>,
+    machine: Option<Box<str>>,
+}
+
+impl TransformPerMachine {
+    /// Create the transform, identifying the machine by the host name
+    #[must_use]
+    pub fn new(values: BTreeMap<String, String>, default: Option<Box<str>>) -> Self {
+        Self {
+            values,
+            default,
+            machine: None,
+        }
+    }
+
+    /// Identify the current machine by `machine` instead of the host name
+    #[must_use]
+    pub fn with_machine(mut self, machine: impl Into<Box<str>>) -> Self {
+        self.machine = Some(machine.into());
+        self
+    }
+
+    /// The value for the current machine (if any)
+    fn value(&self, context: &MergeContext) -> Option<&str> {
+        let value = match (&self.machine, context.get(MergeContext::HOSTNAME)) {
+            (Some(machine), _) => self.values.get(machine.as_ref()),
+            (None, Some(name)) => self.values.get(name),
+            (None, None) => host_name().and_then(|name| self.values.get(&name)),
+        };
+        value.map(String::as_str).or(self.default.as_deref())
+    }
+
+    fn user_input(&self) -> TransformArgs<'static> {
+        let values = self
+            .values
+            .iter()
+            .map(|(machine, value)| format!("{machine}={value}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut result = TransformArgs::from([("values", values)]);
+        if let Some(default) = &self.default {
+            result.insert("default", default.to_string());
+        }
+        if let Some(machine) = &self.machine {
+            result.insert("machine", machine.to_string());
+        }
+        result
+    }
+}
+
+/// The host name of the current machine (if it can be found)
+fn host_name() -> Option<String> {
+    let from_env = ["HOSTNAME", "COMPUTERNAME"]
+        .into_iter()
+        .find_map(|var| std::env::var(var).ok());
+    let name = from_env.or_else(|| {
+        ["/proc/sys/kernel/hostname", "/etc/hostname"]
+            .into_iter()
+            .find_map(|path| std::fs::read_to_string(path).ok())
+    })?;
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+impl Transformer for TransformPerMachine {
+    fn call<'a>(
+        &self,
+        src: &InputData<'a>,
+        tgt: &InputData<'a>,
+    ) -> Result<TransformerAction<'a>, TransformerCallError> {
+        self.call_with_context(src, tgt, &MergeContext::new())
+    }
+
+    fn call_with_context<'a>(
+        &self,
+        src: &InputData<'a>,
+        tgt: &InputData<'a>,
+        context: &MergeContext,
+    ) -> Result<TransformerAction<'a>, TransformerCallError> {
+        match (self.value(context), tgt) {
+            (Some(value), _) => Ok(TransformerAction::Line(
+                set_value_line(value, src, tgt)?.into(),
+            )),
+            (None, Some(tgt)) => Ok(TransformerAction::Line(tgt.raw.into())),
+            (None, None) => Ok(TransformerAction::Nothing),
+        }
+    }
+}
+
+impl TransformerFactory for TransformPerMachine {
+    const NAME: &'static str = "per_machine";
+    const ARGS: &'static [ArgSpec] = &[
+        ArgSpec::required("values", ArgType::Text),
+        ArgSpec::optional("default", ArgType::Text),
+        ArgSpec::optional("machine", ArgType::Text),
+    ];
+
+    fn from_checked_args(args: &CheckedArgs<'_>) -> Result<Self, TransformerConstructionError> {
+        let values = args
+            .text("values")?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                line.split_once('=')
+                    .map(|(machine, value)| (machine.trim().to_string(), value.trim().to_string()))
+                    .ok_or(TransformerConstructionError::Construct(
+                        "Values must be lines of machine=value",
+                    ))
+            })
+            .collect::<Result<_, _>>()?;
+        let default = args.get("default").map(Into::into);
+        let result = Self::new(values, default);
+        Ok(match args.get("machine") {
+            Some(machine) => result.with_machine(machine),
+            None => result,
+        })
+    }
+}
+
+/// Transform to set to a fixed value.
+///
+/// This is meant to be used together with templating, to override an entry
+/// only on some systems.
+///
+/// *NOTE*: This is not meant to be used directly, as special support is
+/// needed elsewhere. Instead, use [`super::MutationsBuilder::add_setter`]
+///
+/// Arguments:
+/// * `raw`: Raw line to set
+#[doc(hidden)]
+#[derive(Debug, Clone)]
+pub struct TransformSet {
+    raw: Box<str>,
+}
+
+impl TransformSet {
+    #[must_use]
+    pub const fn new(raw: Box<str>) -> Self {
+        Self { raw }
+    }
+}
+
+impl Transformer for TransformSet {
+    fn call<'a>(
+        &self,
+        _src: &InputData<'a>,
+        _tgt: &InputData<'a>,
+    ) -> Result<TransformerAction<'a>, TransformerCallError> {
+        Ok(TransformerAction::Line(Cow::Owned(self.raw.to_string())))
+    }
+}
+
+impl TransformerFactory for TransformSet {
+    const NAME: &'static str = "set";
+    const ARGS: &'static [ArgSpec] = &[ArgSpec::required("raw", ArgType::Text)];
+
+    fn from_checked_args(args: &CheckedArgs<'_>) -> Result<Self, TransformerConstructionError> {
+        Ok(Self::new(args.text("raw")?.into()))
+    }
+}
+
+#[cfg(feature = "keyring")]
+mod keyring_transform {
+    use super::ArgSpec;
+    use super::ArgType;
+    use super::CheckedArgs;
+    use super::TransformArgs;
+    use super::Transformer;
+    use super::TransformerAction;
+    use super::TransformerConstructionError;
+    use super::TransformerFactory;
+    use crate::InputData;
+    use log::error;
+    use log::warn;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    /// Get value from system keyring (secrets service). Useful for passwords
+    /// etc that you do not want in your dotfiles repo, but sync via some more
+    /// secure manner.
+    ///
+    /// Arguments:
+    /// * `service`: Which service name to look under
+    /// * `user`: The username identifying the entry
+    /// * `separator`: The separator to use between key and value (optional,
+    ///   default is `=`)
+    /// * `timeout`: Seconds to wait for the keyring, after which the target
+    ///   line is kept (optional). Useful when the keyring prompts to be
+    ///   unlocked and nobody is there to answer, such as over SSH.
+    /// * `retries`: How many times to retry after a transient failure, such
+    ///   as a locked keyring or a D-Bus timeout (optional, default is `0`).
+    ///   Each attempt may prompt to unlock the keyring, if the backend
+    ///   supports that.
+    /// * `retry_delay`: Seconds to wait before the first retry, doubled for
+    ///   each following retry (optional, default is `0.1`)
+    ///
+    /// Example args:
+    /// * service: "my-service"
+    /// * user: "my-user"
+    ///
+    /// To add a key compatible with the above service and user run a command
+    /// like the following:
+    ///
+    /// ```console
+    /// $ chezmoi_modify_manager --keyring-set my-service my-user
+    /// ```
+    /// and enter the password when prompted
+    #[derive(Debug, Clone)]
+    pub struct TransformKeyring {
+        service: Box<str>,
+        user: Box<str>,
+        separator: Box<str>,
+        timeout: Option<Duration>,
+        retries: u32,
+        retry_delay: Duration,
+    }
+
+    /// Default delay before the first retry
+    const DEFAULT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+    impl TransformKeyring {
+        #[must_use]
+        pub const fn new(service: Box<str>, user: Box<str>, separator: Box<str>) -> Self {
+            Self {
+                service,
+                user,
+                separator,
+                timeout: None,
+                retries: 0,
+                retry_delay: DEFAULT_RETRY_DELAY,
+            }
+        }
+
+        /// Give up on the keyring after the timeout, keeping the target line
+        #[must_use]
+        pub const fn with_timeout(mut self, timeout: Duration) -> Self {
+            self.timeout = Some(timeout);
+            self
+        }
+
+        /// Retry transient failures, waiting `delay` before the first retry
+        /// and doubling it for each following one
+        #[must_use]
+        pub const fn with_retries(mut self, retries: u32, delay: Duration) -> Self {
+            self.retries = retries;
+            self.retry_delay = delay;
+            self
+        }
+
+        /// Look up the password in the keyring, retrying transient failures
+        fn lookup(&self) -> Option<String> {
+            let mut delay = self.retry_delay;
+            let mut attempt = 0;
+            loop {
+                let result = keyring::Entry::new(&self.service, &self.user)
+                    .and_then(|entry| entry.get_password());
+                match result {
+                    Ok(v) => return Some(v),
+                    Err(err) if attempt < self.retries && is_transient(&err) => {
+                        warn!("Keyring lookup error: {err}, retrying in {delay:?}");
+                        std::thread::sleep(delay);
+                        delay = delay.saturating_mul(2);
+                        attempt += 1;
+                    }
+                    Err(err) => {
+                        error!("Keyring lookup error: {err}");
+                        error!("Keyring query: service={} user={}", self.service, self.user);
+                        return None;
+                    }
+                }
+            }
+        }
+
+        /// Look up the password, giving up after the timeout (if any)
+        fn lookup_with_timeout(&self) -> Option<String> {
+            let Some(timeout) = self.timeout else {
+                return self.lookup();
+            };
+            // The lookup can't be cancelled, so it is left to finish in the
+            // background if it takes too long
+            let (sender, receiver) = mpsc::channel();
+            let this = self.clone();
+            std::thread::spawn(move || sender.send(this.lookup()).ok());
+            receiver.recv_timeout(timeout).unwrap_or_else(|_| {
+                error!(
+                    "Keyring lookup timed out after {timeout:?}: service={} user={}",
+                    self.service, self.user
+                );
+                None
+            })
+        }
+    }
+
+    impl Transformer for TransformKeyring {
+        fn call<'a>(
+            &self,
+            src: &InputData<'a>,
+            tgt: &InputData<'a>,
+        ) -> Result<TransformerAction<'a>, super::TransformerCallError> {
+            let password = self.lookup_with_timeout();
+            let key = {
+                if let Some(prop) = src {
+                    prop.key
+                } else if let Some(prop) = tgt {
+                    prop.key
+                } else {
+                    return Err(super::TransformerCallError::MissingInput);
+                }
+            };
+            match password {
+                Some(value) => Ok(TransformerAction::Line(
+                    format!("{key}{}{value}", self.separator).into(),
+                )),
+                None => {
+                    // Try to copy from target state, useful if updating
+                    // remotely over SSH with keyring not unlocked.
+                    if let Some(prop) = tgt {
+                        Ok(TransformerAction::Line(prop.raw.into()))
+                    } else {
+                        Ok(TransformerAction::Line(
+                            format!("{key}{}<KEYRING ERROR>", self.separator).into(),
+                        ))
+                    }
+                }
+            }
+        }
+    }
+
+    impl TransformerFactory for TransformKeyring {
+        const NAME: &'static str = "keyring";
+        const ARGS: &'static [ArgSpec] = &[
+            ArgSpec::required("service", ArgType::Text),
+            ArgSpec::required("user", ArgType::Text),
+            ArgSpec::optional("separator", ArgType::Text).with_default("="),
+            ArgSpec::optional("timeout", ArgType::Seconds),
+            ArgSpec::optional("retries", ArgType::Count).with_default("0"),
+            ArgSpec::optional("retry_delay", ArgType::Seconds).with_default("0.1"),
+        ];
+
+        fn from_checked_args(args: &CheckedArgs<'_>) -> Result<Self, TransformerConstructionError> {
+            let transform = Self::new(
+                args.text("service")?.into(),
+                args.text("user")?.into(),
+                args.text("separator")?.into(),
+            )
+            .with_retries(
+                args.count("retries").unwrap_or_default(),
+                args.seconds("retry_delay").unwrap_or(DEFAULT_RETRY_DELAY),
+            );
+            Ok(match args.seconds("timeout") {
+                Some(timeout) => transform.with_timeout(timeout),
+                None => transform,
+            })
+        }
+    }
+
+    impl TransformKeyring {
+        pub(super) fn user_input(&self) -> TransformArgs<'static> {
+            let mut args = TransformArgs::from([
+                ("service", self.service.to_string()),
+                ("user", self.user.to_string()),
+                ("separator", self.separator.to_string()),
+            ]);
+            if let Some(timeout) = self.timeout {
+                args.insert("timeout", timeout.as_secs_f64().to_string());
+            }
+            if self.retries > 0 {
+                args.insert("retries", self.retries.to_string());
+                args.insert("retry_delay", self.retry_delay.as_secs_f64().to_string());
+            }
+            args
+        }
+    }
+
+    /// Check if a keyring error may go away when trying again (such as a
+    /// locked keyring or a D-Bus timeout)
+    const fn is_transient(err: &keyring::Error) -> bool {
+        matches!(
+            err,
+            keyring::Error::PlatformFailure(_) | keyring::Error::NoStorageAccess(_)
+        )
+    }
+}
+
+#[cfg(feature = "rhai")]
+mod script_transform {
+    use super::ArgSpec;
+    use super::ArgType;
+    use super::CheckedArgs;
+    use super::MergeContext;
+    use super::SectionView;
+    use super::TransformArgs;
+    use super::Transformer;
+    use super::TransformerAction;
+    use super::TransformerCallError;
+    use super::TransformerConstructionError;
+    use super::TransformerFactory;
+    use crate::InputData;
+    use crate::Property;
+    use log::error;
+    use rhai::Dynamic;
+    use rhai::Engine;
+    use rhai::EvalAltResult;
+    use rhai::Map;
+    use rhai::Scope;
+    use rhai::AST;
+    use std::cell::Cell;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    thread_local! {
+        /// When the script being evaluated on this thread must stop (if it
+        /// has a timeout)
+        static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+    }
+
+    /// Limit on the number of operations a script may perform per call, to
+    /// guard against runaway scripts.
+    const MAX_OPERATIONS: u64 = 1_000_000;
+
+    /// Run a user provided [Rhai](https://rhai.rs) script to decide the output.
+    ///
+    /// This is meant for one-off application quirks that do not justify a
+    /// built-in transform.
+    ///
+    /// The script gets the variables `src` and `tgt`. Each is either `()` (if
+    /// the key doesn't exist in that file) or a map with the fields `section`,
+    /// `key`, `val` (`()` if the line has no value) and `raw`. `section` is
+    /// `<NO_SECTION>` for keys before the first section header.
+    ///
+    /// The script should evaluate to the line to output (a string) or to `()`
+    /// to output nothing.
+    ///
+    /// With `section_context` the script also gets `src_section` and
+    /// `tgt_section`: maps from the keys of the section in each file to their
+    /// values (`()` for keys without a value). This makes the merge look
+    /// through the whole target first, so only use it when needed.
+    ///
+    /// Arguments:
+    /// * `script`: The source code of the script
+    /// * `section_context`: If `true`, provide the rest of the section
+    ///   (optional)
+    /// * `timeout`: Seconds the script may run per call, after which the
+    ///   merge fails (optional)
+    ///
+    /// Example script that ignores case differences in values:
+    ///
+    /// ```rhai
+    /// if src == () || tgt == () {
+    ///     if src == () { () } else { src.raw }
+    /// } else if src.val.to_lower() == tgt.val.to_lower() {
+    ///     tgt.raw
+    /// } else {
+    ///     src.raw
+    /// }
+    /// ```
+    #[derive(Debug, Clone)]
+    pub struct TransformScript {
+        script: Arc<str>,
+        engine: Arc<Engine>,
+        ast: Arc<AST>,
+        section_context: bool,
+        timeout: Option<Duration>,
+    }
+
+    /// Create the engine for running scripts
+    fn engine(timeout: Option<Duration>) -> Engine {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        if timeout.is_some() {
+            engine.on_progress(|_| {
+                DEADLINE
+                    .get()
+                    .filter(|deadline| Instant::now() >= *deadline)
+                    .map(|_| Dynamic::UNIT)
+            });
+        }
+        engine
+    }
+
+    impl TransformScript {
+        /// Compile a script
+        pub fn new(script: &str) -> Result<Self, TransformerConstructionError> {
+            let engine = engine(None);
+            let ast = engine.compile(script).map_err(|err| {
+                error!(target: "ini-merge", "Failed to compile script: {err}");
+                TransformerConstructionError::Construct("Failed to compile script")
+            })?;
+            Ok(Self {
+                script: script.into(),
+                engine: Arc::new(engine),
+                ast: Arc::new(ast),
+                section_context: false,
+                timeout: None,
+            })
+        }
+
+        /// Stop the script if a call runs for longer than the timeout
+        #[must_use]
+        pub fn with_timeout(mut self, timeout: Duration) -> Self {
+            self.engine = Arc::new(engine(Some(timeout)));
+            self.timeout = Some(timeout);
+            self
+        }
+
+        /// Provide the rest of the section to the script
+        #[must_use]
+        pub const fn with_section_context(mut self) -> Self {
+            self.section_context = true;
+            self
+        }
+
+        pub(super) fn user_input(&self) -> TransformArgs<'static> {
+            let mut args = TransformArgs::from([("script", self.script.to_string())]);
+            if self.section_context {
+                args.insert("section_context", "true");
+            }
+            if let Some(timeout) = self.timeout {
+                args.insert("timeout", timeout.as_secs_f64().to_string());
+            }
+            args
+        }
+
+        /// Evaluate the script with the given variables
+        fn eval<'a>(
+            &self,
+            mut scope: Scope<'_>,
+        ) -> Result<TransformerAction<'a>, TransformerCallError> {
+            DEADLINE.set(self.timeout.map(|timeout| Instant::now() + timeout));
+            let result = self.engine.eval_ast_with_scope(&mut scope, &self.ast);
+            DEADLINE.set(None);
+            let result: Dynamic = result.map_err(|err| match (*err, self.timeout) {
+                (EvalAltResult::ErrorTerminated(..), Some(timeout)) => {
+                    error!(target: "ini-merge", "Script timed out after {timeout:?}");
+                    TransformerCallError::Timeout(timeout)
+                }
+                (err, _) => {
+                    error!(target: "ini-merge", "Script evaluation failed: {err}");
+                    TransformerCallError::InvalidData("Script evaluation failed")
+                }
+            })?;
+            if result.is_unit() {
+                Ok(TransformerAction::Nothing)
+            } else {
+                let line = result.into_string().map_err(|_| {
+                    TransformerCallError::InvalidData("Script must return a string or ()")
+                })?;
+                Ok(TransformerAction::Line(line.into()))
+            }
+        }
+    }
+
+    /// Convert a section to a value usable from the script
+    fn section_to_dynamic(section: &SectionView<'_>) -> Dynamic {
+        section
+            .iter()
+            .map(|(key, val)| (key.into(), val.map_or(Dynamic::UNIT, Into::into)))
+            .collect::<Map>()
+            .into()
+    }
+
+    /// Convert a property to a value usable from the script
+    fn to_dynamic(prop: &InputData<'_>) -> Dynamic {
+        match prop {
+            None => Dynamic::UNIT,
+            Some(Property {
+                section,
+                key,
+                val,
+                raw,
+            }) => {
+                let mut map = Map::new();
+                map.insert("section".into(), section.as_str().into());
+                map.insert("key".into(), (*key).into());
+                map.insert("val".into(), val.map_or(Dynamic::UNIT, Into::into));
+                map.insert("raw".into(), (*raw).into());
+                map.into()
+            }
+        }
+    }
+
+    impl Transformer for TransformScript {
+        fn call<'a>(
+            &self,
+            src: &InputData<'a>,
+            tgt: &InputData<'a>,
+        ) -> Result<TransformerAction<'a>, TransformerCallError> {
+            self.call_with_section(
+                src,
+                tgt,
+                &MergeContext::new(),
+                &SectionView::empty(),
+                &SectionView::empty(),
+            )
+        }
+
+        fn needs_section_context(&self) -> bool {
+            self.section_context
+        }
+
+        fn call_with_section<'a>(
+            &self,
+            src: &InputData<'a>,
+            tgt: &InputData<'a>,
+            _context: &MergeContext,
+            src_section: &SectionView<'_>,
+            tgt_section: &SectionView<'_>,
+        ) -> Result<TransformerAction<'a>, TransformerCallError> {
+            let mut scope = Scope::new();
+            scope.push_constant_dynamic("src", to_dynamic(src));
+            scope.push_constant_dynamic("tgt", to_dynamic(tgt));
+            if self.section_context {
+                scope.push_constant_dynamic("src_section", section_to_dynamic(src_section));
+                scope.push_constant_dynamic("tgt_section", section_to_dynamic(tgt_section));
+            }
+            self.eval(scope)
+        }
+    }
+
+    impl TransformerFactory for TransformScript {
+        const NAME: &'static str = "script";
+        const ARGS: &'static [ArgSpec] = &[
+            ArgSpec::required("script", ArgType::Text),
+            ArgSpec::optional("section_context", ArgType::Bool),
+            ArgSpec::optional("timeout", ArgType::Seconds),
+        ];
+
+        fn from_checked_args(args: &CheckedArgs<'_>) -> Result<Self, TransformerConstructionError> {
+            let script = args.text("script")?;
+            let transform = if args.bool("section_context") {
+                Self::new(script)?.with_section_context()
+            } else {
+                Self::new(script)?
+            };
+            Ok(match args.seconds("timeout") {
+                Some(timeout) => transform.with_timeout(timeout),
+                None => transform,
+            })
+        }
+    }
+}
+
+#[cfg(feature = "external")]
+mod external_transform {
+    use super::ArgSpec;
+    use super::ArgType;
+    use super::CheckedArgs;
+    use super::TransformArgs;
+    use super::Transformer;
+    use super::TransformerAction;
+    use super::TransformerCallError;
+    use super::TransformerConstructionError;
+    use super::TransformerFactory;
+    use crate::InputData;
+    use log::error;
+    use serde_json::json;
+    use serde_json::Value;
+    use std::io::BufRead;
+    use std::io::BufReader;
+    use std::io::Write;
+    use std::process::Child;
+    use std::process::Command;
+    use std::process::Stdio;
+    use std::sync::mpsc;
+    use std::sync::mpsc::Receiver;
+    use std::sync::mpsc::RecvTimeoutError;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    /// How long a helper without a timeout gets to exit after its input is
+    /// closed, before it is killed
+    const EXIT_GRACE_PERIOD: Duration = Duration::from_secs(1);
+
+    /// How often to check if a helper has exited
+    const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    /// Delegate the decision to an external helper program.
+    ///
+    /// The program is started on first use and kept alive for as long as the
+    /// transform (and any clones of it) exist, so the startup cost is only
+    /// paid once per merge.
+    ///
+    /// The protocol is JSON-lines over stdin/stdout. For each property one
+    /// request line is written:
+    ///
+    /// ```json
+    /// {"section": "s", "key": "k", "src": {"val": "v", "raw": "k=v"}, "tgt": null}
     /// ```
-    /// and enter the password when prompted
+    ///
+    /// `section` is `<NO_SECTION>` for keys before the first section header.
+    /// `src`/`tgt` are `null` if the key doesn't exist in that file, and `val`
+    /// is `null` if the line has no value. The program must answer with
+    /// exactly one line, either `{"line": "k=v"}` to output a line or
+    /// `{"line": null}` to output nothing.
+    ///
+    /// Arguments:
+    /// * `program`: The program to run (not passed through a shell)
+    /// * `args`: Whitespace separated arguments to the program (optional)
+    /// * `timeout`: Seconds to wait for each answer, after which the program
+    ///   is killed and the merge fails (optional)
+    ///
+    /// When the transform is dropped, the input of the program is closed. The
+    /// program is killed if it doesn't exit within the timeout (one second
+    /// without a timeout). A program that gave an invalid answer is killed
+    /// right away.
     #[derive(Debug, Clone)]
-    pub struct TransformKeyring {
-        service: Box<str>,
-        user: Box<str>,
-        separator: Box<str>,
+    pub struct TransformExternal {
+        program: Box<str>,
+        args: Box<[Box<str>]>,
+        timeout: Option<Duration>,
+        process: Arc<Mutex<Option<ExternalProcess>>>,
     }
 
-    impl TransformKeyring {
+    /// A running helper process
+    #[derive(Debug)]
+    struct ExternalProcess {
+        child: Child,
+        /// Lines read from stdout (by a background thread, so that reading
+        /// can time out)
+        responses: Receiver<std::io::Result<String>>,
+        /// How long to wait for the helper to exit before killing it
+        exit_timeout: Duration,
+    }
+
+    impl Drop for ExternalProcess {
+        fn drop(&mut self) {
+            // Closing stdin signals the helper to exit. Kill it if it doesn't,
+            // so that a helper ignoring the end of its input can't hang the
+            // merge.
+            drop(self.child.stdin.take());
+            let deadline = Instant::now() + self.exit_timeout;
+            loop {
+                match self.child.try_wait() {
+                    Ok(Some(_)) => return,
+                    Ok(None) if Instant::now() < deadline => std::thread::sleep(EXIT_POLL_INTERVAL),
+                    Ok(None) => break,
+                    Err(err) => {
+                        error!(target: "ini-merge", "Failed to wait for external transform: {err}");
+                        break;
+                    }
+                }
+            }
+            if let Err(err) = self.child.kill() {
+                error!(target: "ini-merge", "Failed to kill external transform: {err}");
+            }
+            if let Err(err) = self.child.wait() {
+                error!(target: "ini-merge", "Failed to wait for external transform: {err}");
+            }
+        }
+    }
+
+    impl TransformExternal {
         #[must_use]
-        pub const fn new(service: Box<str>, user: Box<str>, separator: Box<str>) -> Self {
+        pub fn new(program: Box<str>, args: Box<[Box<str>]>) -> Self {
             Self {
-                service,
-                user,
-                separator,
+                program,
+                args,
+                timeout: None,
+                process: Arc::default(),
+            }
+        }
+
+        /// Kill the program if it doesn't answer within the timeout
+        #[must_use]
+        pub fn with_timeout(mut self, timeout: Duration) -> Self {
+            self.timeout = Some(timeout);
+            self
+        }
+
+        pub(super) fn user_input(&self) -> TransformArgs<'static> {
+            let mut args = TransformArgs::from([
+                ("program", self.program.to_string()),
+                ("args", self.args.join(" ")),
+            ]);
+            if let Some(timeout) = self.timeout {
+                args.insert("timeout", timeout.as_secs_f64().to_string());
+            }
+            args
+        }
+
+        /// Start the helper process
+        fn spawn(&self) -> Result<ExternalProcess, TransformerCallError> {
+            let mut child = Command::new(self.program.as_ref())
+                .args(self.args.iter().map(AsRef::as_ref))
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .map_err(|err| {
+                    error!(target: "ini-merge", "Failed to start {}: {err}", self.program);
+                    TransformerCallError::InvalidData("Failed to start external transform")
+                })?;
+            let stdout = child
+                .stdout
+                .take()
+                .ok_or(TransformerCallError::InvalidData(
+                    "Failed to get stdout of external transform",
+                ))?;
+            let (sender, responses) = mpsc::channel();
+            std::thread::spawn(move || {
+                let mut stdout = BufReader::new(stdout);
+                loop {
+                    let mut line = String::new();
+                    let result = stdout.read_line(&mut line).map(|_| line);
+                    let done = !matches!(&result, Ok(line) if !line.is_empty());
+                    if sender.send(result).is_err() || done {
+                        break;
+                    }
+                }
+            });
+            Ok(ExternalProcess {
+                child,
+                responses,
+                exit_timeout: self.timeout.unwrap_or(EXIT_GRACE_PERIOD),
+            })
+        }
+
+        /// Send one request and read back the response
+        fn roundtrip(
+            &self,
+            process: &mut ExternalProcess,
+            request: &Value,
+        ) -> Result<Value, TransformerCallError> {
+            let stdin = process
+                .child
+                .stdin
+                .as_mut()
+                .ok_or(TransformerCallError::InvalidData(
+                    "Failed to get stdin of external transform",
+                ))?;
+            writeln!(stdin, "{request}")
+                .and_then(|()| stdin.flush())
+                .map_err(|err| {
+                    error!(target: "ini-merge", "Failed to write to external transform: {err}");
+                    TransformerCallError::InvalidData("Failed to write to external transform")
+                })?;
+            let response = match self.timeout {
+                None => process.responses.recv().ok(),
+                Some(timeout) => match process.responses.recv_timeout(timeout) {
+                    Ok(response) => Some(response),
+                    Err(RecvTimeoutError::Timeout) => {
+                        error!(target: "ini-merge", "External transform timed out after {timeout:?}");
+                        if let Err(err) = process.child.kill() {
+                            error!(target: "ini-merge", "Failed to kill external transform: {err}");
+                        }
+                        return Err(TransformerCallError::Timeout(timeout));
+                    }
+                    Err(RecvTimeoutError::Disconnected) => None,
+                },
+            };
+            // An empty line means the end of the output
+            match response.filter(|r| !matches!(r, Ok(line) if line.is_empty())) {
+                None => Err(TransformerCallError::InvalidData(
+                    "External transform exited unexpectedly",
+                )),
+                Some(Ok(response)) => serde_json::from_str(&response).map_err(|err| {
+                    error!(target: "ini-merge", "Invalid response from external transform: {err}");
+                    TransformerCallError::InvalidData("Invalid response from external transform")
+                }),
+                Some(Err(err)) => {
+                    error!(target: "ini-merge", "Failed to read from external transform: {err}");
+                    Err(TransformerCallError::InvalidData(
+                        "Failed to read from external transform",
+                    ))
+                }
             }
         }
     }
 
-    impl Transformer for TransformKeyring {
+    /// Convert a property to the JSON form used in requests
+    fn to_json(prop: &InputData<'_>) -> Value {
+        match prop {
+            None => Value::Null,
+            Some(prop) => json!({ "val": prop.val, "raw": prop.raw }),
+        }
+    }
+
+    impl Transformer for TransformExternal {
         fn call<'a>(
             &self,
             src: &InputData<'a>,
             tgt: &InputData<'a>,
-        ) -> Result<TransformerAction<'a>, super::TransformerCallError> {
-            let password: Option<_> = {
-                match keyring::Entry::new(&self.service, &self.user) {
-                    Ok(entry) => match entry.get_password() {
-                        Ok(v) => Some(v),
-                        Err(err) => {
-                            error!("Keyring lookup error: {err}");
-                            error!("Keyring query: service={} user={}", self.service, self.user);
-                            None
-                        }
-                    },
-                    Err(err) => {
-                        error!("Keyring error: {err}");
-                        None
-                    }
-                }
+        ) -> Result<TransformerAction<'a>, TransformerCallError> {
+            let (section, key) = match (src, tgt) {
+                (Some(prop), _) | (None, Some(prop)) => (prop.section, prop.key),
+                (None, None) => return Err(TransformerCallError::MissingInput),
             };
-            let key = {
-                if let Some(prop) = src {
-                    prop.key
-                } else if let Some(prop) = tgt {
-                    prop.key
-                } else {
-                    unreachable!()
+            let request = json!({
+                "section": section.as_str(),
+                "key": key,
+                "src": to_json(src),
+                "tgt": to_json(tgt),
+            });
+            let mut guard = self.process.lock().map_err(|_| {
+                TransformerCallError::InvalidData("External transform lock poisoned")
+            })?;
+            if guard.is_none() {
+                *guard = Some(self.spawn()?);
+            }
+            let process = guard.as_mut().ok_or(TransformerCallError::InvalidData(
+                "External transform not running",
+            ))?;
+            let response = match self.roundtrip(process, &request) {
+                Ok(response) => response,
+                Err(err) => {
+                    // Kill the misbehaving process, and restart it on the next
+                    // call
+                    process.exit_timeout = Duration::ZERO;
+                    *guard = None;
+                    return Err(err);
                 }
             };
-            match password {
-                Some(value) => Ok(TransformerAction::Line(
-                    format!("{key}{}{value}", self.separator).into(),
+            match response.get("line") {
+                Some(Value::Null) => Ok(TransformerAction::Nothing),
+                Some(Value::String(line)) => Ok(TransformerAction::Line(line.clone().into())),
+                _ => Err(TransformerCallError::InvalidData(
+                    "Response from external transform lacks a valid line field",
                 )),
-                None => {
-                    // Try to copy from target state, useful if updating
-                    // remotely over SSH with keyring not unlocked.
-                    if let Some(prop) = tgt {
-                        Ok(TransformerAction::Line(prop.raw.into()))
-                    } else {
-                        Ok(TransformerAction::Line(
-                            format!("{key}{}<KEYRING ERROR>", self.separator).into(),
-                        ))
-                    }
-                }
             }
         }
+    }
+
+    impl TransformerFactory for TransformExternal {
+        const NAME: &'static str = "external";
+        const ARGS: &'static [ArgSpec] = &[
+            ArgSpec::required("program", ArgType::Text),
+            ArgSpec::optional("args", ArgType::Text),
+            ArgSpec::optional("timeout", ArgType::Seconds),
+        ];
 
-        fn from_user_input(
-            args: &std::collections::HashMap<impl Borrow<str> + Eq + Hash, impl AsRef<str>>,
-        ) -> Result<Self, TransformerConstructionError>
-        where
-            Self: Sized,
-        {
-            let service = args.get("service").map(AsRef::as_ref).ok_or(
-                TransformerConstructionError::Construct("Failed to get service"),
-            )?;
-            let user = args.get("user").map(AsRef::as_ref).ok_or(
-                TransformerConstructionError::Construct("Failed to get user"),
-            )?;
-            let separator = args.get("separator").map_or("=", AsRef::as_ref);
-            Ok(Self::new(service.into(), user.into(), separator.into()))
+        fn from_checked_args(args: &CheckedArgs<'_>) -> Result<Self, TransformerConstructionError> {
+            let program_args = args
+                .get("args")
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(Into::into)
+                .collect();
+            let transform = Self::new(args.text("program")?.into(), program_args);
+            Ok(match args.seconds("timeout") {
+                Some(timeout) => transform.with_timeout(timeout),
+                None => transform,
+            })
         }
     }
 }
@@ -428,18 +2230,23 @@ mod tests {
     use crate::Property;
     use pretty_assertions::assert_eq;
 
+    /// Parse a line as a property in a section
+    fn prop(section: &'static str, raw: &'static str) -> InputData<'static> {
+        Property::parse(SectionName::Named(section), raw)
+    }
+
     #[test]
     fn unsorted_lists() {
         let t = TransformUnsortedLists::new(',');
         let action = t.call(
             &Some(Property {
-                section: "a",
+                section: SectionName::Named("a"),
                 key: "b",
                 val: Some("a,b,c"),
                 raw: "b=a,b,c",
             }),
             &Some(Property {
-                section: "a",
+                section: SectionName::Named("a"),
                 key: "b",
                 val: Some("c,a,b"),
                 raw: "b=c,a,b",
@@ -453,13 +2260,13 @@ mod tests {
         let t = TransformUnsortedLists::new(',');
         let action = t.call(
             &Some(Property {
-                section: "a",
+                section: SectionName::Named("a"),
                 key: "b",
                 val: Some(""),
                 raw: "b=",
             }),
             &Some(Property {
-                section: "a",
+                section: SectionName::Named("a"),
                 key: "b",
                 val: Some(""),
                 raw: "b=",
@@ -469,13 +2276,13 @@ mod tests {
 
         let action = t.call(
             &Some(Property {
-                section: "a",
+                section: SectionName::Named("a"),
                 key: "b",
                 val: None,
                 raw: "b",
             }),
             &Some(Property {
-                section: "a",
+                section: SectionName::Named("a"),
                 key: "b",
                 val: None,
                 raw: "b",
@@ -487,6 +2294,50 @@ mod tests {
                 "Key is missing value in source"
             ))
         );
+
+        assert_eq!(
+            t.call(&None, &None),
+            Err(TransformerCallError::MissingInput)
+        );
+    }
+
+    #[test]
+    fn append_only_list() {
+        let t = TransformAppendOnlyList::new(',');
+        assert_eq!(
+            t.call(&prop("a", "b=x,y"), &prop("a", "b=z,y,x")),
+            Ok(TransformerAction::Line(Cow::Borrowed("b=z,y,x")))
+        );
+        assert_eq!(
+            t.call(&prop("a", "b=x,y"), &prop("a", "b=x,z")),
+            Ok(TransformerAction::Line(Cow::Borrowed("b=x,y")))
+        );
+    }
+
+    #[test]
+    fn id_lists() {
+        let t = TransformIdLists::new(',');
+        let action = t.call(
+            &prop("ServerGroup 0", "ServerList=Server 1,Server 2,Channel 10"),
+            &prop("ServerGroup 0", "ServerList=Channel 3,Server 7,Server 12"),
+        );
+        assert_eq!(
+            action,
+            Ok(TransformerAction::Line(Cow::Borrowed(
+                "ServerList=Channel 3,Server 7,Server 12"
+            )))
+        );
+        // A server was added
+        let action = t.call(
+            &prop("ServerGroup 0", "ServerList=Server 1,Server 2"),
+            &prop("ServerGroup 0", "ServerList=Server 7"),
+        );
+        assert_eq!(
+            action,
+            Ok(TransformerAction::Line(Cow::Borrowed(
+                "ServerList=Server 1,Server 2"
+            )))
+        );
     }
 
     #[test]
@@ -494,13 +2345,13 @@ mod tests {
         let t = TransformKdeShortcut;
         let action = t.call(
             &Some(Property {
-                section: "a",
+                section: SectionName::Named("a"),
                 key: "b",
                 val: Some("none,,Media volume down"),
                 raw: "b=none,,Media volume down",
             }),
             &Some(Property {
-                section: "a",
+                section: SectionName::Named("a"),
                 key: "b",
                 val: Some("none,none,Media volume down"),
                 raw: "b=none,none,Media volume down",
@@ -514,18 +2365,137 @@ mod tests {
         );
     }
 
+    #[test]
+    fn float_format() {
+        let t = TransformFloatFormat;
+        for (src, tgt) in [("b=1", "b=1.000000"), ("b=0.5", "b=5e-1"), ("b=a", "b=a")] {
+            assert_eq!(
+                t.call(&prop("a", src), &prop("a", tgt)),
+                Ok(TransformerAction::Line(Cow::Borrowed(tgt))),
+                "{src} {tgt}"
+            );
+        }
+        for (src, tgt) in [("b=1", "b=1.01"), ("b=1", "b=a"), ("b=nan", "b=NaN")] {
+            assert_eq!(
+                t.call(&prop("a", src), &prop("a", tgt)),
+                Ok(TransformerAction::Line(Cow::Borrowed(src))),
+                "{src} {tgt}"
+            );
+        }
+    }
+
+    #[test]
+    fn geometry() {
+        let t = TransformGeometry;
+        assert_eq!(
+            t.call(&prop("a", "b=1920x1080"), &prop("a", "b=800, 600")),
+            Ok(TransformerAction::Line(Cow::Borrowed("b=800, 600")))
+        );
+        assert_eq!(
+            t.call(&prop("a", "b=1920x1080"), &prop("a", "b=maximized")),
+            Ok(TransformerAction::Line(Cow::Borrowed("b=1920x1080")))
+        );
+        assert_eq!(
+            t.call(&prop("a", "b=1920x1080"), &None),
+            Ok(TransformerAction::Line(Cow::Borrowed("b=1920x1080")))
+        );
+        assert_eq!(parse_size("1920X1080"), Some((1920, 1080)));
+        assert_eq!(parse_size("1920x"), None);
+    }
+
+    #[test]
+    fn numeric_locale() {
+        let t = TransformNumericLocale;
+        for (src, tgt) in [
+            ("b=1.5", "b=1,5"),
+            ("b=1000", "b=1 000"),
+            ("b=1234567.5", "b=1.234.567,50"),
+            ("b=-0.25", "b=-,25"),
+            ("b=3", "b=003.0"),
+        ] {
+            assert_eq!(
+                t.call(&prop("a", src), &prop("a", tgt)),
+                Ok(TransformerAction::Line(Cow::Borrowed(tgt))),
+                "{src} {tgt}"
+            );
+        }
+        for (src, tgt) in [("b=1.5", "b=1.6"), ("b=1,000", "b=1000"), ("b=a", "b=b")] {
+            assert_eq!(
+                t.call(&prop("a", src), &prop("a", tgt)),
+                Ok(TransformerAction::Line(Cow::Borrowed(src))),
+                "{src} {tgt}"
+            );
+        }
+        assert_eq!(normalize_number("-0,0"), Some("0".into()));
+        assert_eq!(normalize_number("1.2.3,4,5"), None);
+        assert_eq!(normalize_number(","), None);
+    }
+
+    #[test]
+    fn qt_state_blob() {
+        let t = TransformQtStateBlob::new(false);
+        // Same bytes, different encoding
+        let action = t.call(
+            &prop("MainWindow", r"State=@ByteArray(\0\0\0\xff\x1\tA)"),
+            &prop("MainWindow", "State=AAAA/wEJQQ=="),
+        );
+        assert_eq!(
+            action,
+            Ok(TransformerAction::Line(Cow::Borrowed("State=AAAA/wEJQQ==")))
+        );
+        let action = t.call(
+            &prop("MainWindow", r#"State="@ByteArray(\0\x2)""#),
+            &prop("MainWindow", r"State=@ByteArray(\0\x1)"),
+        );
+        assert_eq!(
+            action,
+            Ok(TransformerAction::Line(Cow::Borrowed(
+                r#"State="@ByteArray(\0\x2)""#
+            )))
+        );
+        // Not valid base64, compared as text
+        let action = t.call(
+            &prop("MainWindow", "State=a!"),
+            &prop("MainWindow", "State=b!"),
+        );
+        assert_eq!(
+            action,
+            Ok(TransformerAction::Line(Cow::Borrowed("State=a!")))
+        );
+
+        let t = TransformQtStateBlob::new(true);
+        let action = t.call(
+            &prop("MainWindow", "State=a!"),
+            &prop("MainWindow", "State=b!"),
+        );
+        assert_eq!(
+            action,
+            Ok(TransformerAction::Line(Cow::Borrowed("State=b!")))
+        );
+        assert_eq!(
+            t.call(&None, &prop("MainWindow", "State=b!")),
+            Ok(TransformerAction::Nothing)
+        );
+
+        assert_eq!(
+            decode_base64("AAAA/wEJQQ"),
+            Some(vec![0, 0, 0, 255, 1, 9, 65])
+        );
+        assert_eq!(unescape_qt_bytes(r"\x100"), None);
+    }
+
     #[test]
     fn set() {
         let t = TransformSet::new("a = q".into());
         let action = t.call(
             &Some(Property {
-                section: "a",
+                section: SectionName::Named("a"),
                 key: "b",
                 val: Some("c"),
                 raw: "b=c",
             }),
             &Some(Property {
-                section: "a",
+                section: SectionName::Named("a"),
                 key: "b",
                 val: Some("d"),
                 raw: "b=d",
@@ -536,4 +2506,304 @@ mod tests {
             Ok(TransformerAction::Line(Cow::Owned("a = q".to_owned())))
         );
     }
+
+    #[test]
+    fn set_value() {
+        let t = TransformSetValue::new("q".into());
+        let src = Some(Property {
+            section: SectionName::Named("a"),
+            key: "b",
+            val: Some("c"),
+            raw: "b=c",
+        });
+        let tgt = Some(Property {
+            section: SectionName::Named("a"),
+            key: "b",
+            val: Some("d"),
+            raw: "  b  =\td",
+        });
+        assert_eq!(
+            t.call(&src, &tgt),
+            Ok(TransformerAction::Line(Cow::Owned("  b  =\tq".to_owned())))
+        );
+        assert_eq!(
+            t.call(&src, &None),
+            Ok(TransformerAction::Line(Cow::Owned("b=q".to_owned())))
+        );
+        let tgt = Some(Property {
+            section: SectionName::Named("a"),
+            key: "b",
+            val: None,
+            raw: "b",
+        });
+        assert_eq!(
+            t.call(&None, &tgt),
+            Ok(TransformerAction::Line(Cow::Owned("b=q".to_owned())))
+        );
+    }
+
+    #[test]
+    fn per_machine() {
+        let values = BTreeMap::from([("laptop".to_string(), "1".to_string())]);
+        let src = Some(Property {
+            section: SectionName::Named("a"),
+            key: "b",
+            val: Some("c"),
+            raw: "b=c",
+        });
+        let tgt = Some(Property {
+            section: SectionName::Named("a"),
+            key: "b",
+            val: Some("d"),
+            raw: "b = d",
+        });
+        let t = TransformPerMachine::new(values.clone(), None).with_machine("laptop");
+        assert_eq!(
+            t.call(&src, &tgt),
+            Ok(TransformerAction::Line(Cow::Owned("b = 1".to_owned())))
+        );
+        let t = TransformPerMachine::new(values.clone(), None).with_machine("desktop");
+        assert_eq!(
+            t.call(&src, &tgt),
+            Ok(TransformerAction::Line(Cow::Borrowed("b = d")))
+        );
+        assert_eq!(t.call(&src, &None), Ok(TransformerAction::Nothing));
+        let t = TransformPerMachine::new(values, Some("2".into())).with_machine("desktop");
+        assert_eq!(
+            t.call(&src, &None),
+            Ok(TransformerAction::Line(Cow::Owned("b=2".to_owned())))
+        );
+
+        let args = TransformArgs::from([
+            ("values", "laptop=1\ndesktop = x=y\n"),
+            ("machine", "desktop"),
+        ]);
+        let t = TransformerDispatch::from_name("per_machine", &args).unwrap();
+        assert_eq!(
+            t.call(&src, &tgt),
+            Ok(TransformerAction::Line(Cow::Owned("b = x=y".to_owned())))
+        );
+        let roundtrip = TransformerDispatch::from_name("per_machine", &t.user_input()).unwrap();
+        assert_eq!(roundtrip, t);
+
+        // The host name from the context
+        let args = TransformArgs::from([("values", "laptop=1")]);
+        let t = TransformerDispatch::from_name("per_machine", &args).unwrap();
+        let mut context = MergeContext::new();
+        context.set(MergeContext::HOSTNAME, "laptop");
+        assert_eq!(
+            t.call_with_context(&src, &tgt, &context),
+            Ok(TransformerAction::Line(Cow::Owned("b = 1".to_owned())))
+        );
+        context.set(MergeContext::HOSTNAME, "other");
+        assert_eq!(
+            t.call_with_context(&src, &tgt, &context),
+            Ok(TransformerAction::Line(Cow::Borrowed("b = d")))
+        );
+    }
+
+    #[test]
+    fn section_view() {
+        let source = crate::source_loader::source_from_str(
+            "[a]\nb=1\nc\n[ d ]\ne=2\n",
+            &crate::dialect::Dialect::new(),
+        )
+        .unwrap();
+        let view = SectionView::source(&source, "a");
+        assert_eq!(view.info().and_then(|info| info.raw), Some("[a]"));
+        assert_eq!(view.get("b"), Some("1"));
+        assert_eq!(view.get("c"), None);
+        assert_eq!(view.get("e"), None);
+        let mut entries: Vec<_> = view.iter().collect();
+        entries.sort_unstable();
+        assert_eq!(entries, vec![("b", Some("1")), ("c", None)]);
+        let info = SectionView::source(&source, "d").info().unwrap();
+        assert_eq!(info.name, SectionName::Named("d"));
+        assert_eq!(info.untrimmed_name(), Some(" d "));
+        let top = SectionView::source(&source, crate::OUTSIDE_SECTION).info();
+        assert_eq!(top.map(|info| info.name), Some(SectionName::TopLevel));
+        assert_eq!(top.and_then(|info| info.raw), None);
+        assert_eq!(SectionView::target(None, None).get("b"), None);
+        assert_eq!(SectionView::empty().iter().count(), 0);
+        assert_eq!(SectionView::empty().info(), None);
+    }
+
+    #[cfg(feature = "rhai")]
+    #[test]
+    fn script() {
+        let t = TransformScript::new(indoc::indoc! {r#"
+            if src == () || tgt == () {
+                if src == () { () } else { src.raw }
+            } else if src.val.to_lower() == tgt.val.to_lower() {
+                tgt.raw
+            } else {
+                src.raw
+            }
+        "#})
+        .unwrap();
+        let action = t.call(
+            &Some(Property {
+                section: SectionName::Named("a"),
+                key: "b",
+                val: Some("Value"),
+                raw: "b=Value",
+            }),
+            &Some(Property {
+                section: SectionName::Named("a"),
+                key: "b",
+                val: Some("value"),
+                raw: "b=value",
+            }),
+        );
+        assert_eq!(
+            action,
+            Ok(TransformerAction::Line(Cow::Borrowed("b=value")))
+        );
+
+        let action = t.call(
+            &None,
+            &Some(Property {
+                section: SectionName::Named("a"),
+                key: "b",
+                val: Some("value"),
+                raw: "b=value",
+            }),
+        );
+        assert_eq!(action, Ok(TransformerAction::Nothing));
+
+        assert_eq!(
+            TransformScript::new("let x = ").unwrap_err(),
+            TransformerConstructionError::Construct("Failed to compile script")
+        );
+    }
+
+    #[cfg(feature = "rhai")]
+    #[test]
+    fn script_timeout() {
+        let timeout = Duration::from_millis(1);
+        let t = TransformScript::new("loop {}")
+            .unwrap()
+            .with_timeout(timeout);
+        let src = Some(Property {
+            section: SectionName::Named("a"),
+            key: "b",
+            val: Some("c"),
+            raw: "b=c",
+        });
+        assert_eq!(
+            t.call(&src, &None),
+            Err(TransformerCallError::Timeout(timeout))
+        );
+        let args = TransformArgs::from([("script", "src.raw"), ("timeout", "0.5")]);
+        let t = TransformerDispatch::from_name("script", &args).unwrap();
+        assert_eq!(t.user_input().get("timeout"), Some("0.5"));
+        assert_eq!(
+            t.call(&src, &None),
+            Ok(TransformerAction::Line(Cow::Owned("b=c".into())))
+        );
+    }
+
+    #[cfg(all(feature = "external", unix))]
+    #[test]
+    fn external() {
+        let t = TransformExternal::new(
+            "sh".into(),
+            [
+                "-c".into(),
+                r#"while read -r line; do echo '{"line": "b=ext"}'; done"#.into(),
+            ]
+            .into(),
+        );
+        let src = Some(Property {
+            section: SectionName::Named("a"),
+            key: "b",
+            val: Some("c"),
+            raw: "b=c",
+        });
+        // Call twice to make sure the process is reused
+        for _ in 0..2 {
+            let action = t.call(&src, &None);
+            assert_eq!(
+                action,
+                Ok(TransformerAction::Line(Cow::Owned("b=ext".into())))
+            );
+        }
+    }
+
+    #[cfg(all(feature = "external", unix))]
+    #[test]
+    fn external_timeout() {
+        let timeout = Duration::from_millis(50);
+        let t = TransformExternal::new("sleep".into(), ["10".into()].into()).with_timeout(timeout);
+        let src = Some(Property {
+            section: SectionName::Named("a"),
+            key: "b",
+            val: Some("c"),
+            raw: "b=c",
+        });
+        assert_eq!(
+            t.call(&src, &None),
+            Err(TransformerCallError::Timeout(timeout))
+        );
+    }
+
+    #[cfg(all(feature = "external", unix))]
+    #[test]
+    fn external_ignoring_eof() {
+        use std::time::Instant;
+
+        let helper = |answer: &str| {
+            TransformExternal::new(
+                "sh".into(),
+                [
+                    "-c".into(),
+                    format!("read -r line; echo '{answer}'; exec sleep 10").into(),
+                ]
+                .into(),
+            )
+            .with_timeout(Duration::from_millis(50))
+        };
+        let src = Some(Property::parse(SectionName::Named("a"), "b=c").unwrap());
+        let start = Instant::now();
+        // Killed after the timeout when dropped
+        let t = helper(r#"{"line": "b=ext"}"#);
+        assert!(t.call(&src, &None).is_ok());
+        drop(t);
+        // Killed right away after an invalid answer
+        let t = helper("garbage");
+        assert!(matches!(
+            t.call(&src, &None),
+            Err(TransformerCallError::InvalidData(_))
+        ));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn dyn_transformer() {
+        use std::sync::Arc;
+
+        let args = TransformArgs::from([("separator", ";")]);
+        let registry: Vec<Arc<dyn Transformer + Send + Sync>> = vec![
+            Arc::new(TransformUnsortedLists::from_user_input(&args).unwrap()),
+            Arc::new(TransformIdLists::from_user_input(&args).unwrap()),
+            Arc::new(TransformGeometry),
+        ];
+        let src = Some(Property::new("s", "k", Some("a;b"), "k=a;b"));
+        let tgt = Some(Property::new("s", "k", Some("b;a"), "k=b;a"));
+        for transform in &registry {
+            assert!(transform.call(&src, &tgt).is_ok());
+        }
+        assert_eq!(
+            registry[0].call(&src, &tgt),
+            Ok(TransformerAction::Line(Cow::Borrowed("k=b;a")))
+        );
+    }
+
+    #[test]
+    fn display() {
+        let t = TransformerDispatch::from(TransformUnsortedLists::new(','));
+        assert_eq!(t.to_string(), r#"unsorted_lists separator=",""#);
+        let t = TransformerDispatch::from(TransformGeometry);
+        assert_eq!(t.to_string(), "geometry");
+    }
 }
@@ -0,0 +1,492 @@
+//! Arguments for constructing transforms
+
+use super::TransformerConstructionError;
+use log::warn;
+use std::borrow::Cow;
+use std::collections::btree_map;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+/// User provided arguments for constructing a transform, see
+/// [`super::TransformerFactory`]
+///
+/// Names are case insensitive (they are stored in lower case) and iterate in
+/// sorted order. Names and values may be borrowed or owned.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransformArgs<'a> {
+    args: BTreeMap<Cow<'a, str>, Cow<'a, str>>,
+}
+
+/// Lower case a name, only allocating if needed
+fn normalize<'a>(name: impl Into<Cow<'a, str>>) -> Cow<'a, str> {
+    let name = name.into();
+    if name.chars().any(char::is_uppercase) {
+        Cow::Owned(name.to_lowercase())
+    } else {
+        name
+    }
+}
+
+impl<'a> TransformArgs<'a> {
+    /// Create an empty set of arguments
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            args: BTreeMap::new(),
+        }
+    }
+
+    /// Set an argument, returning the previous value (if any)
+    pub fn insert(
+        &mut self,
+        name: impl Into<Cow<'a, str>>,
+        value: impl Into<Cow<'a, str>>,
+    ) -> Option<Cow<'a, str>> {
+        self.args.insert(normalize(name), value.into())
+    }
+
+    /// The value of an argument
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.args.get(normalize(name).as_ref()).map(AsRef::as_ref)
+    }
+
+    /// If the argument is set
+    #[must_use]
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// The value of an argument that must be a single character
+    pub fn get_char(&self, name: &str) -> Result<Option<char>, TransformerConstructionError> {
+        self.get(name)
+            .map(|value| {
+                let mut chars = value.chars();
+                chars
+                    .next()
+                    .filter(|_| chars.next().is_none())
+                    .ok_or_else(|| invalid(name, "a single character"))
+            })
+            .transpose()
+    }
+
+    /// The value of an argument that must be `true` or `false`
+    pub fn get_bool(&self, name: &str) -> Result<Option<bool>, TransformerConstructionError> {
+        self.get(name)
+            .map(|value| match value {
+                "true" => Ok(true),
+                "false" => Ok(false),
+                _ => Err(invalid(name, "true or false")),
+            })
+            .transpose()
+    }
+
+    /// The value of an argument as a path
+    #[must_use]
+    pub fn get_path(&self, name: &str) -> Option<&Path> {
+        self.get(name).map(Path::new)
+    }
+
+    /// Number of arguments
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.args.len()
+    }
+
+    /// If there are no arguments
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty()
+    }
+
+    /// The arguments (name and value), sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.args
+            .iter()
+            .map(|(name, value)| (name.as_ref(), value.as_ref()))
+    }
+
+    /// Copy borrowed names and values, to get arguments that live on their
+    /// own
+    #[must_use]
+    pub fn into_owned(self) -> TransformArgs<'static> {
+        TransformArgs {
+            args: self
+                .args
+                .into_iter()
+                .map(|(name, value)| (name.into_owned().into(), value.into_owned().into()))
+                .collect(),
+        }
+    }
+}
+
+/// Error for an argument with an invalid value
+fn invalid(name: &str, expected: &'static str) -> TransformerConstructionError {
+    TransformerConstructionError::InvalidArgument {
+        transform: None,
+        argument: name.to_string(),
+        expected,
+    }
+}
+
+/// Type of a transform argument, see [`ArgSpec`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ArgType {
+    /// Any string
+    Text,
+    /// A single character
+    Char,
+    /// `true` or `false`
+    Bool,
+    /// A (non-negative) number of seconds
+    Seconds,
+    /// A non-negative integer
+    Count,
+}
+
+impl ArgType {
+    /// Description of valid values, for error messages
+    const fn expected(self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::Char => "a single character",
+            Self::Bool => "true or false",
+            Self::Seconds => "a number of seconds",
+            Self::Count => "a non-negative integer",
+        }
+    }
+
+    /// Check if a value is valid
+    fn accepts(self, value: &str) -> bool {
+        match self {
+            Self::Text => true,
+            Self::Char => value.chars().count() == 1,
+            Self::Bool => matches!(value, "true" | "false"),
+            Self::Seconds => parse_seconds(value).is_some(),
+            Self::Count => value.parse::<u32>().is_ok(),
+        }
+    }
+}
+
+/// Parse a number of seconds
+fn parse_seconds(value: &str) -> Option<Duration> {
+    value
+        .parse()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+}
+
+/// Declaration of an argument of a transform, see
+/// [`super::TransformerFactory::ARGS`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ArgSpec {
+    /// Name of the argument (lower case)
+    pub name: &'static str,
+    /// Type of the value
+    pub ty: ArgType,
+    /// If the argument must be given
+    pub required: bool,
+    /// Value used when the argument is not given
+    pub default: Option<&'static str>,
+}
+
+impl ArgSpec {
+    /// An argument that must be given
+    #[must_use]
+    pub const fn required(name: &'static str, ty: ArgType) -> Self {
+        Self {
+            name,
+            ty,
+            required: true,
+            default: None,
+        }
+    }
+
+    /// An argument that may be left out
+    #[must_use]
+    pub const fn optional(name: &'static str, ty: ArgType) -> Self {
+        Self {
+            name,
+            ty,
+            required: false,
+            default: None,
+        }
+    }
+
+    /// Use a default value when the argument is not given
+    #[must_use]
+    pub const fn with_default(mut self, default: &'static str) -> Self {
+        self.default = Some(default);
+        self
+    }
+}
+
+impl<'a> TransformArgs<'a> {
+    /// Check the arguments against the declared arguments of a transform
+    ///
+    /// Fails (naming the transform and the argument) on missing arguments
+    /// and values of the wrong type. Unknown arguments are an error for
+    /// transforms without arguments, other transforms ignore them with a
+    /// warning (for compatibility, they used to be silently ignored).
+    pub fn check(
+        &self,
+        transform: &'static str,
+        specs: &'static [ArgSpec],
+    ) -> Result<CheckedArgs<'_>, TransformerConstructionError> {
+        for (name, _) in self
+            .iter()
+            .filter(|(name, _)| !specs.iter().any(|spec| spec.name == *name))
+        {
+            if specs.is_empty() {
+                return Err(TransformerConstructionError::UnknownArgument {
+                    transform,
+                    argument: name.to_string(),
+                });
+            }
+            warn!(target: "ini-merge",
+                  "Unknown argument {name} of transform {transform} ignored");
+        }
+        for spec in specs {
+            match self.get(spec.name) {
+                None if spec.required => {
+                    return Err(TransformerConstructionError::MissingArgument {
+                        transform,
+                        argument: spec.name.to_string(),
+                    })
+                }
+                Some(value) if !spec.ty.accepts(value) => {
+                    return Err(TransformerConstructionError::InvalidArgument {
+                        transform: Some(transform),
+                        argument: spec.name.to_string(),
+                        expected: spec.ty.expected(),
+                    })
+                }
+                _ => (),
+            }
+        }
+        Ok(CheckedArgs {
+            transform,
+            specs,
+            args: self,
+        })
+    }
+}
+
+/// Arguments that were checked against the declared arguments of a
+/// transform, see [`TransformArgs::check`]
+///
+/// Defaults are filled in. Getting a required argument only fails if it was
+/// not declared.
+#[derive(Debug, Clone, Copy)]
+pub struct CheckedArgs<'s> {
+    transform: &'static str,
+    specs: &'static [ArgSpec],
+    args: &'s TransformArgs<'s>,
+}
+
+impl<'s> CheckedArgs<'s> {
+    /// The value of an argument, or its default
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&'s str> {
+        self.args.get(name).or_else(|| {
+            self.specs
+                .iter()
+                .find(|spec| spec.name == name)
+                .and_then(|spec| spec.default)
+        })
+    }
+
+    /// The value of a required argument (or one with a default)
+    pub fn text(&self, name: &str) -> Result<&'s str, TransformerConstructionError> {
+        self.get(name)
+            .ok_or_else(|| TransformerConstructionError::MissingArgument {
+                transform: self.transform,
+                argument: name.to_string(),
+            })
+    }
+
+    /// The value of a required character argument
+    ///
+    /// This relies on [`TransformArgs::check`] having checked that the value
+    /// is a single character (the argument is declared as [`ArgType::Char`]),
+    /// otherwise only the first character is used.
+    pub fn char(&self, name: &str) -> Result<char, TransformerConstructionError> {
+        let value = self.text(name)?;
+        value
+            .chars()
+            .next()
+            .ok_or_else(|| TransformerConstructionError::InvalidArgument {
+                transform: Some(self.transform),
+                argument: name.to_string(),
+                expected: ArgType::Char.expected(),
+            })
+    }
+
+    /// The value of a boolean argument (`false` if not given)
+    #[must_use]
+    pub fn bool(&self, name: &str) -> bool {
+        self.get(name) == Some("true")
+    }
+
+    /// The value of an optional argument in seconds
+    #[must_use]
+    pub fn seconds(&self, name: &str) -> Option<Duration> {
+        self.get(name).and_then(parse_seconds)
+    }
+
+    /// The value of an optional integer argument
+    #[must_use]
+    pub fn count(&self, name: &str) -> Option<u32> {
+        self.get(name).and_then(|value| value.parse().ok())
+    }
+}
+
+impl<'a, K, V> FromIterator<(K, V)> for TransformArgs<'a>
+where
+    K: Into<Cow<'a, str>>,
+    V: Into<Cow<'a, str>>,
+{
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let mut args = Self::new();
+        args.extend(iter);
+        args
+    }
+}
+
+impl<'a, K, V> Extend<(K, V)> for TransformArgs<'a>
+where
+    K: Into<Cow<'a, str>>,
+    V: Into<Cow<'a, str>>,
+{
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        for (name, value) in iter {
+            self.insert(name, value);
+        }
+    }
+}
+
+impl<'a, K, V, const N: usize> From<[(K, V); N]> for TransformArgs<'a>
+where
+    K: Into<Cow<'a, str>>,
+    V: Into<Cow<'a, str>>,
+{
+    fn from(args: [(K, V); N]) -> Self {
+        args.into_iter().collect()
+    }
+}
+
+impl<'a> IntoIterator for TransformArgs<'a> {
+    type Item = (Cow<'a, str>, Cow<'a, str>);
+    type IntoIter = btree_map::IntoIter<Cow<'a, str>, Cow<'a, str>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.args.into_iter()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for TransformArgs<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for TransformArgs<'_> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let args = BTreeMap::<String, String>::deserialize(deserializer)?;
+        Ok(args.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ArgSpec;
+    use super::ArgType;
+    use super::TransformArgs;
+    use crate::merge::mutations::transforms::TransformerConstructionError;
+    use pretty_assertions::assert_eq;
+    use std::path::Path;
+    use std::time::Duration;
+
+    #[test]
+    fn typed_getters() {
+        let owned = String::from("/bin/true");
+        let mut args = TransformArgs::from([("Separator", ";"), ("flag", "true")]);
+        args.insert("program", &owned);
+        args.insert("bad", "yes");
+        assert_eq!(args.get("separator"), Some(";"));
+        assert_eq!(args.get("SEPARATOR"), Some(";"));
+        assert_eq!(args.get_char("separator"), Ok(Some(';')));
+        assert_eq!(args.get_char("missing"), Ok(None));
+        assert_eq!(args.get_bool("flag"), Ok(Some(true)));
+        assert_eq!(
+            args.get_bool("bad"),
+            Err(TransformerConstructionError::InvalidArgument {
+                transform: None,
+                argument: "bad".into(),
+                expected: "true or false"
+            })
+        );
+        assert!(args.get_char("program").is_err());
+        assert_eq!(args.get_path("program"), Some(Path::new("/bin/true")));
+        let names: Vec<_> = args.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["bad", "flag", "program", "separator"]);
+        assert_eq!(args.clone().into_owned(), args);
+    }
+
+    #[test]
+    fn check() {
+        const ARGS: &[ArgSpec] = &[
+            ArgSpec::required("separator", ArgType::Char),
+            ArgSpec::optional("mode", ArgType::Text).with_default("fast"),
+            ArgSpec::optional("timeout", ArgType::Seconds),
+            ArgSpec::optional("retries", ArgType::Count).with_default("0"),
+        ];
+        let args = TransformArgs::from([("separator", ","), ("timeout", "1.5")]);
+        let checked = args.check("test", ARGS).unwrap();
+        assert_eq!(checked.char("separator"), Ok(','));
+        assert_eq!(checked.text("mode"), Ok("fast"));
+        assert_eq!(
+            checked.seconds("timeout"),
+            Some(Duration::from_millis(1500))
+        );
+        assert!(!checked.bool("flag"));
+        assert_eq!(checked.count("retries"), Some(0));
+
+        let check = |args: TransformArgs<'_>| args.check("test", ARGS).map(|_| ()).unwrap_err();
+        assert_eq!(
+            check(TransformArgs::new()),
+            TransformerConstructionError::MissingArgument {
+                transform: "test",
+                argument: "separator".into()
+            }
+        );
+        // Unknown arguments are only ignored by transforms with arguments
+        let args = TransformArgs::from([("separator", ","), ("other", "1")]);
+        assert_eq!(args.check("test", ARGS).unwrap().get("other"), Some("1"));
+        assert_eq!(
+            TransformArgs::from([("other", "1")])
+                .check("test", &[])
+                .map(|_| ())
+                .unwrap_err(),
+            TransformerConstructionError::UnknownArgument {
+                transform: "test",
+                argument: "other".into()
+            }
+        );
+        let err = check(TransformArgs::from([("separator", ",;")]));
+        assert_eq!(
+            err.to_string(),
+            "Invalid value for argument separator of transform test, expected a single character"
+        );
+        let err = check(TransformArgs::from([("separator", ","), ("retries", "-1")]));
+        assert_eq!(
+            err.to_string(),
+            "Invalid value for argument retries of transform test, expected a non-negative integer"
+        );
+    }
+}
@@ -0,0 +1,104 @@
+//! Report of the decisions taken for each key during a merge
+
+use super::mutations::transforms::TransformerDispatch;
+use super::mutations::Action;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Where the line for a key in the merged result came from (if anywhere)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(tag = "origin", rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum KeyOrigin {
+    /// Taken from the source
+    Source,
+    /// Kept from the target, as the dialect considers it equal to the source
+    Target,
+    /// Kept from the target due to an ignore rule
+    Ignored,
+    /// Kept from the target due to the target only policy
+    TargetOnly,
+    /// Only in the source, added to the result
+    Added,
+    /// Forced by a setter
+    Forced,
+    /// Produced by a transform
+    Transformed {
+        /// Name of the transform
+        transform: &'static str,
+    },
+    /// Taken from the source with the value redacted
+    Redacted,
+    /// Not in the result
+    Removed,
+}
+
+impl KeyOrigin {
+    /// The origin of a line emitted with the given action, where `default`
+    /// is the origin when there is no action. `None` if no line is emitted.
+    pub(super) fn of_action(action: Option<&Action>, default: Self) -> Option<Self> {
+        match action {
+            None | Some(Action::IgnoreButRequire | Action::IgnoreIfTargetMatches(_)) => {
+                Some(default)
+            }
+            Some(Action::Ignore) => None,
+            Some(Action::Delete) => Some(Self::Removed),
+            Some(Action::Redact(_)) => Some(Self::Redacted),
+            Some(Action::Transform(TransformerDispatch::Set(_))) => Some(Self::Forced),
+            Some(Action::Transform(transform)) => Some(Self::Transformed {
+                transform: transform.name(),
+            }),
+        }
+    }
+}
+
+/// The decision taken for a key during a merge
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct KeyDecision {
+    /// Section of the key
+    pub section: String,
+    /// The key
+    pub key: String,
+    /// Where the line for the key came from
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub origin: KeyOrigin,
+}
+
+/// Counters for a transform during a merge, see [`MergeReport::transforms`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct TransformStats {
+    /// Number of times the transform was called
+    pub calls: usize,
+    /// Number of calls that gave a different line than the target (including
+    /// adding or removing the line)
+    pub changed: usize,
+    /// Number of calls that failed
+    pub errors: usize,
+    /// Total time spent in the transform
+    pub time: Duration,
+}
+
+/// The result of a merge together with the decisions taken, see
+/// [`super::merge_ini_report`]
+///
+/// With the `serde` feature this can be serialized (for example as JSON),
+/// for use by external tools.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct MergeReport {
+    /// The merged file, one string per line
+    pub lines: Vec<String>,
+    /// The decisions for each key, in the order they were handled
+    pub decisions: Vec<KeyDecision>,
+    /// Counters for each transform used, by name
+    pub transforms: BTreeMap<&'static str, TransformStats>,
+}
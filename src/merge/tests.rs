@@ -1,8 +1,22 @@
-use crate::mutations::transforms::TransformKdeShortcut;
-use crate::mutations::transforms::TransformUnsortedLists;
-use crate::mutations::Action;
-use crate::mutations::MutationsBuilder;
-use crate::mutations::SectionAction;
+use crate::dialect::Dialect;
+use crate::filter::FilterAction;
+use crate::filter::FilterActionsBuilder;
+#[cfg(feature = "regex-rules")]
+use crate::merge::mutations::transforms::TransformKdeShortcut;
+use crate::merge::mutations::transforms::TransformUnsortedLists;
+use crate::merge::mutations::Action;
+#[cfg(feature = "regex-rules")]
+use crate::merge::mutations::Condition;
+use crate::merge::mutations::KeyPlacement;
+use crate::merge::mutations::MutationsBuilder;
+use crate::merge::mutations::SectionAction;
+#[cfg(feature = "regex-rules")]
+use crate::merge::mutations::TargetOnlyPolicy;
+#[cfg(feature = "regex-rules")]
+use crate::merge::mutations::ValuePattern;
+use crate::merge::SourceLoaderError;
+use crate::SectionName;
+use crate::OUTSIDE_SECTION;
 use indoc::indoc;
 use pretty_assertions::assert_eq;
 use std::collections::VecDeque;
@@ -52,6 +66,7 @@ const TARGET: &str = indoc! {"
     aaa = 2
     "};
 
+#[cfg(feature = "regex-rules")]
 const EXPECTED: &str = indoc! {"
     ; Comments are copied from target
     src_first=1
@@ -77,6 +92,7 @@ const EXPECTED: &str = indoc! {"
     source_only = 42
     "};
 
+#[cfg(feature = "regex-rules")]
 #[test]
 fn test_merge_ini() {
     let mut src: VecDeque<_> = SOURCE.as_bytes().to_owned().into();
@@ -104,3 +120,1761 @@ fn test_merge_ini() {
 
     assert_eq!(EXPECTED, result.join("\n") + "\n");
 }
+
+#[cfg(feature = "regex-rules")]
+#[test]
+fn test_process_matches_filter_then_merge() {
+    let mut filter_actions = FilterActionsBuilder::new();
+    filter_actions.add_literal_action("s1", "b", FilterAction::Remove);
+    filter_actions.add_section_action("s3", FilterAction::Remove);
+    filter_actions.add_regex_action("s5", ".*_ign", FilterAction::Replace("HIDDEN".into()));
+    let filter_actions = filter_actions.build().unwrap();
+
+    let mut mutations = MutationsBuilder::new();
+    mutations.add_literal_action("s1", "c", Action::Ignore);
+    mutations.add_regex_action("s5", ".*_ign", Action::Ignore);
+    let mutations = mutations.build().unwrap();
+
+    let mut tgt: VecDeque<_> = TARGET.as_bytes().to_owned().into();
+    let filtered = crate::filter::filter_ini(&mut tgt, &filter_actions).unwrap();
+    let mut filtered: VecDeque<_> = (filtered.join("\n") + "\n").into_bytes().into();
+    let mut src: VecDeque<_> = SOURCE.as_bytes().to_owned().into();
+    let expected = super::merge_ini(&mut filtered, &mut src, &mutations).unwrap();
+
+    let mut tgt: VecDeque<_> = TARGET.as_bytes().to_owned().into();
+    let mut src: VecDeque<_> = SOURCE.as_bytes().to_owned().into();
+    let result = super::process(&mut tgt, &mut src, &filter_actions, &mutations).unwrap();
+
+    assert_eq!(expected, result);
+    assert!(result.contains(&"b_ign = HIDDEN".to_string()));
+}
+
+#[cfg(feature = "regex-rules")]
+#[test]
+fn test_merge_session() {
+    let mut mutations = MutationsBuilder::new();
+    mutations.add_literal_action("s1", "c", Action::Ignore);
+    mutations.add_regex_action("s5", ".*_ign", Action::Ignore);
+    mutations.add_regex_action(
+        "s1",
+        "unsorted_.*",
+        Action::Transform(TransformUnsortedLists::new(',').into()),
+    );
+    let mutations = mutations.build().unwrap();
+    let mut src: VecDeque<_> = SOURCE.as_bytes().to_owned().into();
+    let session = super::MergeSession::new(&mut src, mutations).unwrap();
+
+    let mut src: VecDeque<_> = SOURCE.as_bytes().to_owned().into();
+    let mut tgt: VecDeque<_> = TARGET.as_bytes().to_owned().into();
+    let expected = super::merge_ini(&mut tgt, &mut src, session.mutations()).unwrap();
+
+    // Merging repeatedly (with a warm cache) should give the same result
+    for _ in 0..2 {
+        let mut tgt: VecDeque<_> = TARGET.as_bytes().to_owned().into();
+        assert_eq!(expected, session.remerge(&mut tgt).unwrap());
+    }
+}
+
+#[test]
+fn test_remerge_range() {
+    let mut mutations = MutationsBuilder::new();
+    mutations.add_setter("c", "forced", "1", "=");
+    let mutations = mutations.build().unwrap();
+    let source = indoc! {"
+        top=2
+        [a]
+        k=2
+        [b]
+        k=3
+        new=1
+        [c]
+        k=1
+        [d]
+        q=1
+    "};
+    let target = indoc! {"
+        top=1
+        [a]
+        k=1
+        [b]
+        k=1
+
+        [c]
+        k=1
+    "};
+    let mut src: VecDeque<_> = source.as_bytes().to_owned().into();
+    let session = super::MergeSession::new(&mut src, mutations).unwrap();
+
+    let result = session.remerge_range(target, 4..5).unwrap();
+    assert_eq!(result.target_lines, 3..6);
+    assert_eq!(result.lines, vec!["[b]", "k=3", "", "new=1"]);
+
+    let result = session.remerge_range(target, 0..1).unwrap();
+    assert_eq!(result.target_lines, 0..1);
+    assert_eq!(result.lines, vec!["top=2"]);
+
+    // Source only sections are not included
+    let result = session.remerge_range(target, 7..8).unwrap();
+    assert_eq!(result.target_lines, 6..8);
+    assert_eq!(result.lines, vec!["[c]", "k=1", "forced=1"]);
+
+    // The whole document gives the same result as a full merge, except for
+    // the source only sections
+    let result = session.remerge_range(target, 0..100).unwrap();
+    assert_eq!(result.target_lines, 0..8);
+    let full = super::merge_ini_from_str(target, source, session.mutations()).unwrap();
+    assert_eq!(result.lines, full[..result.lines.len()]);
+    assert_eq!(full[result.lines.len()..], ["[d]", "q=1"]);
+}
+
+#[test]
+fn test_remerge_range_dialect() {
+    let mut mutations = MutationsBuilder::new();
+    mutations.dialect(Dialect::GIT_CONFIG);
+    let mutations = mutations.build().unwrap();
+    let source = indoc! {"
+        [a]
+        k = 2 \\
+          [x]
+        [b]
+        k = 2
+    "};
+    // The continued line that looks like a section header belongs to `k`
+    let target = indoc! {"
+        [a]
+        k = 1 \\
+          [x]
+        [b]
+        k = 1
+    "};
+    let mut src: VecDeque<_> = source.as_bytes().to_owned().into();
+    let session = super::MergeSession::new(&mut src, mutations).unwrap();
+
+    let result = session.remerge_range(target, 2..3).unwrap();
+    assert_eq!(result.target_lines, 0..3);
+    assert_eq!(result.lines, vec!["[a]", "k = 2 \\\n  [x]"]);
+
+    let result = session.remerge_range(target, 3..4).unwrap();
+    assert_eq!(result.target_lines, 3..5);
+    assert_eq!(result.lines, vec!["[b]", "k = 2"]);
+}
+
+/// Merge with the given mutations, returning the result as a string
+fn merge_str(target: &str, source: &str, mutations: &crate::merge::mutations::Mutations) -> String {
+    let mut src: VecDeque<_> = source.as_bytes().to_owned().into();
+    let mut tgt: VecDeque<_> = target.as_bytes().to_owned().into();
+    super::merge_ini(&mut tgt, &mut src, mutations)
+        .unwrap()
+        .join("\n")
+        + "\n"
+}
+
+#[test]
+fn test_duplicate_section_source_only_keys_emitted_once() {
+    let source = indoc! {"
+        [a]
+        x = 1
+        y = 2
+        "};
+    let target = indoc! {"
+        [a]
+        x = 0
+        [b]
+        [a]
+        "};
+    // [b] doesn't exist in the source, so it is removed
+    let expected = indoc! {"
+        [a]
+        x = 1
+        y = 2
+        [a]
+        "};
+    let mutations = MutationsBuilder::new().build().unwrap();
+    assert_eq!(expected, merge_str(target, source, &mutations));
+}
+
+#[test]
+fn test_duplicate_section_key_in_both_occurrences() {
+    let source = indoc! {"
+        [a]
+        x = 1
+        y = 2
+        "};
+    let target = indoc! {"
+        [a]
+        x = 0
+        [a]
+        x = 0
+        y = 0
+        "};
+    // y is emitted at the end of the first occurrence, so both x and y are
+    // dropped from the second.
+    let expected = indoc! {"
+        [a]
+        x = 1
+        y = 2
+        [a]
+        "};
+    let mutations = MutationsBuilder::new().build().unwrap();
+    assert_eq!(expected, merge_str(target, source, &mutations));
+}
+
+#[cfg(feature = "regex-rules")]
+#[test]
+fn test_duplicate_section_ignored_and_forced_keys() {
+    let source = indoc! {"
+        [a]
+        x = 1
+        "};
+    let target = indoc! {"
+        [a]
+        ign = first
+        [a]
+        ign = second
+        forced = old
+        new_ign = 3
+        "};
+    let expected = indoc! {"
+        [a]
+        ign = first
+        x = 1
+        forced=new
+        [a]
+        ign = second
+        new_ign = 3
+        "};
+    let mutations = {
+        let mut builder = MutationsBuilder::new();
+        builder.add_regex_action("a", ".*ign", Action::Ignore);
+        builder.add_setter("a", "forced", "new", "=");
+        builder.build().unwrap()
+    };
+    assert_eq!(expected, merge_str(target, source, &mutations));
+}
+
+#[test]
+fn test_deleted_section_comments() {
+    let source = indoc! {"
+        [a]
+        k = v
+        [del]
+        k = v
+        "};
+    let target = indoc! {"
+        [a]
+        k = v
+        [del]
+        ; Explains k, which is gone
+        k = v
+
+        [broken
+        [a]
+        ; Comment in a later part of a kept section
+        "};
+    let expected = indoc! {"
+        [a]
+        k = v
+        [a]
+        ; Comment in a later part of a kept section
+        "};
+    let mutations = {
+        let mut builder = MutationsBuilder::new();
+        builder.add_section_action("del", SectionAction::Delete);
+        builder.build().unwrap()
+    };
+    assert_eq!(expected, merge_str(target, source, &mutations));
+}
+
+#[test]
+fn test_top_level_with_deleted_first_section() {
+    let source = indoc! {"
+        top = 1
+        new_top = 2
+        [del]
+        k = v
+        [keep]
+        k = v
+        "};
+    let target = indoc! {"
+        ; File header
+        top = 0
+
+        [del]
+        ; Comment in deleted section
+        k = v
+
+        [keep]
+        k = v
+        "};
+    let expected = indoc! {"
+        ; File header
+        top = 1
+
+        new_top = 2
+        [keep]
+        k = v
+        "};
+    let mutations = {
+        let mut builder = MutationsBuilder::new();
+        builder.add_section_action("del", SectionAction::Delete);
+        builder.build().unwrap()
+    };
+    assert_eq!(expected, merge_str(target, source, &mutations));
+}
+
+#[test]
+fn test_top_level_section_action() {
+    let source = indoc! {"
+        top = 1
+        [s]
+        k = v
+        "};
+    let target = indoc! {"
+        ; File header
+        top = 0
+        [s]
+        k = v
+        "};
+    let expected = indoc! {"
+        ; File header
+        top = 0
+        [s]
+        k = v
+        "};
+    let mutations = {
+        let mut builder = MutationsBuilder::new();
+        builder.add_section_action(MutationsBuilder::TOP_LEVEL, SectionAction::Ignore);
+        builder.build().unwrap()
+    };
+    assert_eq!(expected, merge_str(target, source, &mutations));
+
+    // Deleting the top level keeps the file header comment
+    let expected = indoc! {"
+        ; File header
+        [s]
+        k = v
+        "};
+    let mutations = {
+        let mut builder = MutationsBuilder::new();
+        builder.add_section_action(MutationsBuilder::TOP_LEVEL, SectionAction::Delete);
+        builder.build().unwrap()
+    };
+    assert_eq!(expected, merge_str(target, source, &mutations));
+
+    // Unless configured to remove it as well
+    let expected = indoc! {"
+        [s]
+        k = v
+        "};
+    let mutations = {
+        let mut builder = MutationsBuilder::new();
+        builder
+            .add_section_action(MutationsBuilder::TOP_LEVEL, SectionAction::Delete)
+            .delete_top_level_comments(true);
+        builder.build().unwrap()
+    };
+    assert_eq!(expected, merge_str(target, source, &mutations));
+}
+
+#[test]
+fn test_section_named_like_top_level() {
+    let source = indoc! {"
+        k = 1
+        [<NO_SECTION>]
+        k = 1
+        j = 1
+        "};
+    let target = indoc! {"
+        k = 0
+        [<NO_SECTION>]
+        k = 0
+        j = 0
+        "};
+    let mutations = {
+        let mut builder = MutationsBuilder::new();
+        builder.ignore_top_level_key("k");
+        builder.ignore_key(SectionName::Named(OUTSIDE_SECTION), "j");
+        builder.build().unwrap()
+    };
+    let expected = indoc! {"
+        k = 0
+        [<NO_SECTION>]
+        k = 1
+        j = 0
+        "};
+    assert_eq!(expected, merge_str(target, source, &mutations));
+}
+
+#[cfg(feature = "regex-rules")]
+#[test]
+fn test_section_named_like_top_level_regex() {
+    let source = indoc! {"
+        k = 1
+        [<NO_SECTION>]
+        k = 1
+        "};
+    let target = indoc! {"
+        k = 0
+        [<NO_SECTION>]
+        k = 0
+        "};
+    let mutations = {
+        let mut builder = MutationsBuilder::new();
+        builder.regexes_match_top_level(false);
+        builder.add_regex_action(".*", "k", Action::Ignore);
+        builder.build().unwrap()
+    };
+    let expected = indoc! {"
+        k = 1
+        [<NO_SECTION>]
+        k = 0
+        "};
+    assert_eq!(expected, merge_str(target, source, &mutations));
+}
+
+#[cfg(feature = "rhai")]
+#[test]
+fn test_section_named_like_top_level_transform() {
+    use crate::merge::mutations::transforms::TransformScript;
+
+    let script = TransformScript::new(r#"src.key + "=" + src.section"#).unwrap();
+    let mut builder = MutationsBuilder::new();
+    builder.add_literal_action(
+        SectionName::Named(OUTSIDE_SECTION),
+        "k",
+        Action::Transform(script.into()),
+    );
+    let mutations = builder.build().unwrap();
+    let result =
+        super::merge_ini_from_str("[<NO_SECTION>]\nk=0\n", "[<NO_SECTION>]\nk=1\n", &mutations);
+    assert_eq!(result.unwrap(), vec!["[<NO_SECTION>]", "k=<NO_SECTION>"]);
+}
+
+#[test]
+fn test_untrimmed_section_names() {
+    let source = indoc! {"
+        [ a ]
+        k = 1
+        "};
+    let target = indoc! {"
+        [ a ]
+        k = 0
+        [a]
+        k = 0
+        "};
+    let mut dialect = Dialect::new();
+    dialect
+        .trim_section_names(false)
+        .normalize_section_headers(true);
+    let mutations = {
+        let mut builder = MutationsBuilder::new();
+        builder.ignore_key("a", "k");
+        builder.dialect(dialect);
+        builder.build().unwrap()
+    };
+    // The sections are kept apart, and only the rule for `[a]` applies
+    let expected = indoc! {"
+        [a]
+        k = 1
+        [a]
+        k = 0
+        "};
+    assert_eq!(expected, merge_str(target, source, &mutations));
+}
+
+#[cfg(feature = "regex-rules")]
+#[test]
+fn test_top_level_keys() {
+    let source = indoc! {"
+        top = 1
+        other = 1
+        [s]
+        k = 1
+        "};
+    let target = indoc! {"
+        top = 0
+        other = 0
+        [s]
+        k = 0
+        "};
+    let mutations = {
+        let mut builder = MutationsBuilder::new();
+        builder.ignore_top_level_key("top");
+        builder.ignore_regex(".*", "k|other");
+        builder.build().unwrap()
+    };
+    let expected = indoc! {"
+        top = 0
+        other = 0
+        [s]
+        k = 0
+        "};
+    assert_eq!(expected, merge_str(target, source, &mutations));
+
+    // Opt the top level out of the regex rules
+    let mutations = {
+        let mut builder = MutationsBuilder::new();
+        builder.ignore_top_level_key("top");
+        builder.ignore_regex(".*", "k|other");
+        builder.regexes_match_top_level(false);
+        builder.build().unwrap()
+    };
+    let expected = indoc! {"
+        top = 0
+        other = 1
+        [s]
+        k = 0
+        "};
+    assert_eq!(expected, merge_str(target, source, &mutations));
+}
+
+#[test]
+fn test_setters_and_section_actions() {
+    let source = indoc! {"
+        [del]
+        k = v
+        [ign]
+        k = v
+        "};
+    let target = indoc! {"
+        [del]
+        k = v
+        forced = old
+        [ign]
+        k = target
+        forced = old
+        "};
+    let make_mutations = |override_sections| {
+        let mut builder = MutationsBuilder::new();
+        builder.add_section_action("del", SectionAction::Delete);
+        builder.add_section_action("ign", SectionAction::Ignore);
+        builder.add_section_action("del_missing", SectionAction::Delete);
+        builder.add_setter("del", "forced", "new", "=");
+        builder.add_setter("ign", "forced", "new", "=");
+        builder.add_setter("ign", "forced2", "new", "=");
+        builder.add_setter("del_missing", "forced", "new", "=");
+        builder.setters_override_section_actions(override_sections);
+        builder.build().unwrap()
+    };
+
+    // By default deleting wins, while setters still apply in ignored sections
+    let expected = indoc! {"
+        [ign]
+        k = target
+        forced=new
+        forced2=new
+        "};
+    assert_eq!(expected, merge_str(target, source, &make_mutations(false)));
+
+    // With the override, setters win
+    let expected = indoc! {"
+        [del]
+        forced=new
+        [ign]
+        k = target
+        forced=new
+        forced2=new
+        [del_missing]
+        forced=new
+        "};
+    assert_eq!(expected, merge_str(target, source, &make_mutations(true)));
+}
+
+#[test]
+fn test_setter_in_ignored_section() {
+    let mut builder = MutationsBuilder::new();
+    builder.ignore_section("s");
+    builder.add_setter("s", "forced", "new", "=");
+    let mutations = builder.build().unwrap();
+
+    let target = indoc! {"
+        [s]
+        k = target
+        forced = old
+        "};
+    let expected = indoc! {"
+        [s]
+        k = target
+        forced=new
+        "};
+    assert_eq!(expected, merge_str(target, "[s]\nk = source\n", &mutations));
+    let expected = indoc! {"
+        [s]
+        k = target
+        forced=new
+        "};
+    assert_eq!(expected, merge_str("[s]\nk = target\n", "", &mutations));
+}
+
+#[test]
+fn test_limits() {
+    let limits = crate::limits::Limits::new().max_sections(3);
+    let mut mutations = MutationsBuilder::new();
+    mutations.limits(limits);
+    let mutations = mutations.build().unwrap();
+
+    let mut src: VecDeque<_> = SOURCE.as_bytes().to_owned().into();
+    let mut tgt: VecDeque<_> = TARGET.as_bytes().to_owned().into();
+    let result = super::merge_ini(&mut tgt, &mut src, &mutations);
+    assert!(matches!(
+        result,
+        Err(super::MergeError::Limit(
+            crate::limits::LimitError::Sections(3)
+        ))
+    ));
+
+    let mut mutations = MutationsBuilder::new();
+    mutations.limits(crate::limits::Limits::new().regex_size_limit(10));
+    mutations.add_regex_action("s1", "a[0-9]{1,100}", Action::Ignore);
+    assert!(mutations.build().is_err());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test(flavor = "current_thread")]
+async fn test_merge_ini_async() {
+    let mutations = MutationsBuilder::new().build().unwrap();
+    let mut src: VecDeque<_> = SOURCE.as_bytes().to_owned().into();
+    let mut tgt: VecDeque<_> = TARGET.as_bytes().to_owned().into();
+    let expected = super::merge_ini(&mut tgt, &mut src, &mutations).unwrap();
+
+    let result = super::merge_ini_async(&mut TARGET.as_bytes(), &mut SOURCE.as_bytes(), &mutations)
+        .await
+        .unwrap();
+    assert_eq!(expected, result);
+}
+
+#[test]
+fn test_merge_ini_from_str() {
+    let mutations = MutationsBuilder::new().build().unwrap();
+    let mut src: VecDeque<_> = SOURCE.as_bytes().to_owned().into();
+    let mut tgt: VecDeque<_> = TARGET.as_bytes().to_owned().into();
+    let expected = super::merge_ini(&mut tgt, &mut src, &mutations).unwrap();
+
+    let result = super::merge_ini_from_str(TARGET, SOURCE, &mutations).unwrap();
+    assert_eq!(expected, result);
+}
+
+#[cfg(feature = "regex-rules")]
+#[test]
+fn test_strict_sync() {
+    let source = indoc! {"
+        [s]
+        k = 1
+        "};
+    let target = indoc! {"
+        [s]
+        k = 2
+        stale = 1
+        kept = 1
+        "};
+    let make_mutations = |strict| {
+        let mut builder = MutationsBuilder::new();
+        builder.add_regex_action(
+            "s",
+            ".*",
+            Action::Transform(
+                crate::merge::mutations::transforms::TransformSetValue::new("x".into()).into(),
+            ),
+        );
+        builder.add_literal_action("s", "kept", Action::Ignore);
+        builder.strict_sync(strict);
+        builder.build().unwrap()
+    };
+
+    let expected = indoc! {"
+        [s]
+        k = x
+        stale = x
+        kept = 1
+        "};
+    assert_eq!(expected, merge_str(target, source, &make_mutations(false)));
+
+    let expected = indoc! {"
+        [s]
+        k = x
+        kept = 1
+        "};
+    assert_eq!(expected, merge_str(target, source, &make_mutations(true)));
+}
+
+#[cfg(feature = "regex-rules")]
+#[test]
+fn test_target_only_policy() {
+    let source = indoc! {"
+        [General]
+        k = 1
+        [Shortcuts]
+        a = 1
+        "};
+    let target = indoc! {"
+        [General]
+        k = 2
+        extra = 1
+        [Shortcuts]
+        a = 1
+        b = 1
+        "};
+    let mut builder = MutationsBuilder::new();
+    builder.add_regex_action(
+        "Shortcuts",
+        ".*",
+        Action::Transform(TransformKdeShortcut.into()),
+    );
+    builder.target_only_policy("General", TargetOnlyPolicy::KeepTargetOnly);
+    builder.target_only_policy("Shortcuts", TargetOnlyPolicy::DropTargetOnly);
+    let mutations = builder.build().unwrap();
+
+    let expected = indoc! {"
+        [General]
+        k = 1
+        extra = 1
+        [Shortcuts]
+        a = 1
+        "};
+    assert_eq!(expected, merge_str(target, source, &mutations));
+}
+
+#[cfg(feature = "regex-rules")]
+#[test]
+fn test_ignore_but_require() {
+    let source = indoc! {"
+        [General]
+        a = 1
+        b = 1
+        [New]
+        c = 1
+        "};
+    let target = indoc! {"
+        [General]
+        a = 2
+        "};
+    let mut builder = MutationsBuilder::new();
+    builder.add_regex_action(".*", ".*", Action::IgnoreButRequire);
+    let mutations = builder.build().unwrap();
+
+    let expected = indoc! {"
+        [General]
+        a = 2
+        b = 1
+        [New]
+        c = 1
+        "};
+    assert_eq!(expected, merge_str(target, source, &mutations));
+}
+
+#[cfg(feature = "regex-rules")]
+#[test]
+fn test_batch_section_actions() {
+    let source = indoc! {"
+        top = 1
+        [a]
+        k = 1
+        [Cache 1]
+        k = 1
+        [new]
+        k = 1
+        "};
+    let target = indoc! {"
+        top = 2
+        [a]
+        k = 2
+        [Cache 1]
+        k = 2
+        [Cache 2]
+        k = 2
+        "};
+    let mut builder = MutationsBuilder::new();
+    builder.ignore_all().delete_matching_sections("^Cache ");
+    let mutations = builder.build().unwrap();
+
+    let expected = indoc! {"
+        top = 2
+        [a]
+        k = 2
+        "};
+    assert_eq!(expected, merge_str(target, source, &mutations));
+}
+
+#[test]
+fn test_merge_buffers() {
+    let mutations = MutationsBuilder::new().build().unwrap();
+    let mut buffers = super::MergeBuffers::new();
+    for (target, source) in [("[a]\nk=1\n[b]\nk=1", "[a]\nk=2"), ("x=1", "[c]\nk=3")] {
+        let expected = super::merge_ini_from_str(target, source, &mutations).unwrap();
+        let result = super::merge_ini_with_buffers(
+            &mut target.as_bytes(),
+            &mut source.as_bytes(),
+            &mutations,
+            &mut buffers,
+        )
+        .unwrap();
+        assert_eq!(expected, result);
+    }
+    buffers.clear();
+    assert!(buffers.lines().is_empty());
+}
+
+#[test]
+fn test_merge_ini_iter() {
+    let mut builder = MutationsBuilder::new();
+    builder.add_literal_action("s1", "c", Action::Ignore);
+    builder.add_setter("forced", "k", "1", "=");
+    let mutations = builder.build().unwrap();
+    let expected = super::merge_ini_from_str(TARGET, SOURCE, &mutations).unwrap();
+    let result: Vec<_> = super::merge_ini_iter(TARGET, SOURCE, &mutations)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(expected, result);
+}
+
+#[test]
+fn test_section_order() {
+    let source = indoc! {"
+        [a]
+        k = 1
+        [b]
+        k = 1
+        [ZBuffer]
+        k = 1
+        [c]
+        k = 1
+        "};
+    let target = indoc! {"
+        [a]
+        k = 1
+        "};
+    let mut builder = MutationsBuilder::new();
+    builder.section_order(["ZBuffer", "c", "a"]);
+    let mutations = builder.build().unwrap();
+
+    let expected = indoc! {"
+        [a]
+        k = 1
+        [ZBuffer]
+        k = 1
+        [c]
+        k = 1
+        [b]
+        k = 1
+        "};
+    assert_eq!(expected, merge_str(target, source, &mutations));
+}
+
+#[test]
+fn test_key_placement() {
+    let source = indoc! {"
+        [a]
+        first = 1
+        x = 1
+        new1 = 1
+        y = 1
+        new2 = 1
+        z = 1
+        new3 = 1
+        "};
+    let target = indoc! {"
+        [a]
+        x = 2
+        y = 2
+        z = 2
+        "};
+    let mut builder = MutationsBuilder::new();
+    builder.key_placement(KeyPlacement::SourceOrder);
+    let mutations = builder.build().unwrap();
+
+    let expected = indoc! {"
+        [a]
+        first = 1
+        x = 1
+        new1 = 1
+        y = 1
+        new2 = 1
+        z = 1
+        new3 = 1
+        "};
+    assert_eq!(expected, merge_str(target, source, &mutations));
+    let result: Vec<_> = super::merge_ini_iter(target, source, &mutations)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(expected.lines().collect::<Vec<_>>(), result);
+}
+
+#[test]
+#[cfg(feature = "regex-rules")]
+fn test_ignore_if_target_matches() {
+    let source = indoc! {"
+        [a]
+        k1 = source
+        k2 = source
+        k3 = source
+        "};
+    let target = indoc! {"
+        [a]
+        k1 = custom:local
+        k2 = other
+        "};
+    let mut builder = MutationsBuilder::new();
+    builder.add_regex_action(
+        "a",
+        "k.*",
+        Action::IgnoreIfTargetMatches(ValuePattern::new("^custom:").unwrap()),
+    );
+    let mutations = builder.build().unwrap();
+
+    let expected = indoc! {"
+        [a]
+        k1 = custom:local
+        k2 = source
+        k3 = source
+        "};
+    assert_eq!(expected, merge_str(target, source, &mutations));
+}
+
+#[test]
+#[cfg(feature = "regex-rules")]
+fn test_conditional_action() {
+    let source = indoc! {"
+        [Account1]
+        type = oauth
+        password = source
+        [Account2]
+        type = plain
+        password = source
+        [Account3]
+        password = source
+        type = oauth
+        "};
+    let target = indoc! {"
+        [Account1]
+        password = target
+        type = oauth
+        [Account2]
+        password = target
+        type = plain
+        [Account3]
+        password = target
+        type = plain
+        "};
+    let mut builder = MutationsBuilder::new();
+    for section in ["Account1", "Account2", "Account3"] {
+        builder.add_conditional_action(
+            section,
+            "password",
+            Condition::new("type", ValuePattern::new("^oauth$").unwrap()),
+            Action::Ignore,
+        );
+    }
+    let mutations = builder.build().unwrap();
+
+    // The condition is checked against the target (even for later keys)
+    let expected = indoc! {"
+        [Account1]
+        password = target
+        type = oauth
+        [Account2]
+        password = source
+        type = plain
+        [Account3]
+        password = source
+        type = oauth
+        "};
+    assert_eq!(expected, merge_str(target, source, &mutations));
+}
+
+#[test]
+#[cfg(feature = "regex-rules")]
+fn test_conditional_action_and_setter() {
+    let mut builder = MutationsBuilder::new();
+    builder.add_setter("s", "k", "1", "=");
+    builder.add_conditional_action(
+        "s",
+        "k",
+        Condition::new("mode", ValuePattern::new("^on$").unwrap()),
+        Action::Transform(TransformUnsortedLists::new(',').into()),
+    );
+    let mutations = builder.build().unwrap();
+
+    // The key is in neither file, so the setter is used
+    let expected = indoc! {"
+        [s]
+        mode=on
+        k=1
+        "};
+    assert_eq!(
+        expected,
+        merge_str("[s]\nmode=on\n", "[s]\nmode=on\n", &mutations)
+    );
+}
+
+#[test]
+fn test_merge_ini_with_includes() {
+    let files = [
+        ("common.ini", "[a]\nk2 = common\ninclude = nested.ini\n"),
+        ("nested.ini", "[b]\nk = nested\n"),
+        ("file.d/10.ini", "[a]\nk3 = extra\n"),
+    ];
+    let mut includes = super::Includes::new(|path| -> std::io::Result<Box<dyn std::io::Read>> {
+        let (_, data) = files
+            .iter()
+            .find(|(name, _)| *name == path)
+            .ok_or(std::io::ErrorKind::NotFound)?;
+        Ok(Box::new(data.as_bytes()))
+    });
+    includes.key("include").extra("file.d/10.ini");
+    let mutations = MutationsBuilder::new().build().unwrap();
+    let result = super::merge_ini_with_includes(
+        &mut "[a]\nk1 = 2\n".as_bytes(),
+        &mut "include = common.ini\n[a]\nk1 = 1\n".as_bytes(),
+        &mut includes,
+        &mutations,
+    )
+    .unwrap();
+    assert_eq!(
+        result,
+        vec![
+            "include = common.ini",
+            "[a]",
+            "k1 = 1",
+            "include = nested.ini",
+            "k2 = common",
+            "k3 = extra",
+            "[b]",
+            "k = nested"
+        ]
+    );
+
+    let mut includes = super::Includes::new(|_| Err(std::io::ErrorKind::NotFound.into()));
+    includes.key("include");
+    let result = super::merge_ini_with_includes(
+        &mut "".as_bytes(),
+        &mut "include = missing.ini\n".as_bytes(),
+        &mut includes,
+        &mutations,
+    );
+    assert!(matches!(
+        result,
+        Err(super::MergeError::SourceLoad(SourceLoaderError::Include(ref path, ref err)))
+            if path == "missing.ini" && err.kind() == std::io::ErrorKind::NotFound
+    ));
+
+    let result = super::merge_ini(&mut [0xff_u8].as_slice(), &mut "".as_bytes(), &mutations);
+    assert!(matches!(
+        result,
+        Err(super::MergeError::TargetLoad(ref err)) if err.kind() == std::io::ErrorKind::InvalidData
+    ));
+}
+
+#[test]
+fn test_dialect() {
+    let mut dialect = Dialect::new();
+    dialect.comment_prefixes(["//"]).indented_comments(true);
+    let mut builder = MutationsBuilder::new();
+    builder.dialect(dialect);
+    let mutations = builder.build().unwrap();
+    let result = super::merge_ini_from_str(
+        "[a]\n// note\n  // indented\nk = 2\n;x = 1\n",
+        "[a]\n// source comment\nk = 1\n",
+        &mutations,
+    )
+    .unwrap();
+    assert_eq!(result, vec!["[a]", "// note", "  // indented", "k = 1"]);
+}
+
+#[test]
+fn test_line_continuation() {
+    let mut dialect = Dialect::new();
+    dialect.line_continuation(true);
+    let mut builder = MutationsBuilder::new();
+    builder.dialect(dialect);
+    builder.add_literal_action("Unit", "Ignored", Action::Ignore);
+    let mutations = builder.build().unwrap();
+    let result = super::merge_ini_from_str(
+        "[Unit]\nExec=/bin/a \\\n  --flag\nIgnored=a \\\n  b\nOther=1\n",
+        "[Unit]\nExec=/bin/a --flag\nOther=2 \\\n  3\n",
+        &mutations,
+    )
+    .unwrap();
+    assert_eq!(
+        result,
+        vec![
+            "[Unit]",
+            "Exec=/bin/a \\\n  --flag",
+            "Ignored=a \\\n  b",
+            "Other=2 \\\n  3"
+        ]
+    );
+}
+
+#[test]
+fn test_escapes() {
+    let mut builder = MutationsBuilder::new();
+    builder.dialect(Dialect::PROPERTIES);
+    let mutations = builder.build().unwrap();
+    let result = super::merge_ini_from_str(
+        "a = tab\\there\nb = \\u00e5\nc = x\n",
+        "a = tab\there\nb = å\nc = y\\n\n",
+        &mutations,
+    )
+    .unwrap();
+    assert_eq!(result, vec!["a = tab\\there", "b = \\u00e5", "c = y\\n"]);
+}
+
+#[cfg(feature = "regex-rules")]
+#[test]
+fn test_raw_line_action() {
+    let mut builder = MutationsBuilder::new();
+    builder
+        .add_raw_line_action("a", r"^\[broken", Action::Delete)
+        .add_raw_line_action("a", r"^junk", Action::Ignore);
+    let mutations = builder.build().unwrap();
+    let result = super::merge_ini_from_str(
+        "[a]\n[broken\njunk~~ = 1\n[other\nk = 1\n",
+        "[a]\nk = 2\n",
+        &mutations,
+    )
+    .unwrap();
+    assert_eq!(result, vec!["[a]", "junk~~ = 1", "[other", "k = 2"]);
+
+    let mut builder = FilterActionsBuilder::new();
+    builder
+        .add_raw_line_action("a", r"^\[broken", FilterAction::Remove)
+        .add_raw_line_action("a", r"secret", FilterAction::Replace("****".into()));
+    let actions = builder.build().unwrap();
+    let result =
+        crate::filter::filter_ini_from_str("[a]\n[broken secret\nmy_secret = 1\nk = 1\n", &actions)
+            .unwrap();
+    assert_eq!(result, vec!["[a]", "my_secret = ****", "k = 1"]);
+}
+
+#[cfg(feature = "regex-rules")]
+#[test]
+fn test_on_parse_error() {
+    let mut builder = MutationsBuilder::new();
+    builder
+        .on_parse_error(Action::Delete)
+        .add_raw_line_action("a", r"^\[keep", Action::Ignore);
+    let mutations = builder.build().unwrap();
+    let result =
+        super::merge_ini_from_str("[a]\n[broken\n[keep\nk = 1\n", "[a]\nk = 2\n", &mutations)
+            .unwrap();
+    assert_eq!(result, vec!["[a]", "[keep", "k = 2"]);
+
+    let mut builder = FilterActionsBuilder::new();
+    builder.on_parse_error(FilterAction::Remove);
+    let actions = builder.build().unwrap();
+    let result = crate::filter::filter_ini_from_str("[a]\n[broken\nk = 1\n", &actions).unwrap();
+    assert_eq!(result, vec!["[a]", "k = 1"]);
+}
+
+#[test]
+fn test_case_insensitive_sections() {
+    let mut dialect = Dialect::new();
+    dialect.case_insensitive_sections(true);
+    let mut builder = MutationsBuilder::new();
+    builder
+        .dialect(dialect)
+        .add_literal_action("General", "ignored", Action::Ignore);
+    let mutations = builder.build().unwrap();
+    let result = super::merge_ini_from_str(
+        "[general]\na = 1\nignored = 1\n[other]\nb = 1\n",
+        "[General]\na = 2\n[OTHER]\nb = 2\n[New]\nc = 1\n",
+        &mutations,
+    )
+    .unwrap();
+    assert_eq!(
+        result,
+        vec![
+            "[general]",
+            "a = 2",
+            "ignored = 1",
+            "[other]",
+            "b = 2",
+            "[New]",
+            "c = 1"
+        ]
+    );
+}
+
+#[test]
+fn test_trim_trailing_whitespace() {
+    let mut builder = MutationsBuilder::new();
+    builder
+        .trim_trailing_whitespace(true)
+        .add_literal_action("a", "kept", Action::Ignore);
+    let mutations = builder.build().unwrap();
+    let result = super::merge_ini_from_str(
+        "; comment \n[a]\nkept = 1  \nk = 1\n",
+        "[a]\nk = 2 \t\n",
+        &mutations,
+    )
+    .unwrap();
+    assert_eq!(result, vec!["; comment", "[a]", "kept = 1", "k = 2"]);
+
+    let mut builder = FilterActionsBuilder::new();
+    builder.trim_trailing_whitespace(true);
+    let actions = builder.build().unwrap();
+    let result = crate::filter::filter_ini_from_str("[a]\nk = 1 \n", &actions).unwrap();
+    assert_eq!(result, vec!["[a]", "k = 1"]);
+
+    let mut builder = FilterActionsBuilder::new();
+    builder
+        .trim_trailing_whitespace(true)
+        .dialect(Dialect::PROPERTIES);
+    let actions = builder.build().unwrap();
+    let result = crate::filter::filter_ini_from_str("k = 1 \n", &actions).unwrap();
+    assert_eq!(result, vec!["k = 1 "]);
+}
+
+#[test]
+fn test_squash_blank_lines() {
+    let mut builder = MutationsBuilder::new();
+    builder.squash_blank_lines(true);
+    let mutations = builder.build().unwrap();
+    let target = "[a]\nk = 1\n\n\n\n[b]\n\n\nk = 1\n";
+    let source = "[a]\nk = 2\n[b]\nk = 2\n";
+    let result = super::merge_ini_from_str(target, source, &mutations).unwrap();
+    assert_eq!(result, vec!["[a]", "k = 2", "", "[b]", "", "k = 2"]);
+    let lines: Vec<_> = super::merge_ini_iter(target, source, &mutations)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(lines, result);
+
+    let mut builder = FilterActionsBuilder::new();
+    builder.squash_blank_lines(true);
+    let actions = builder.build().unwrap();
+    let result = crate::filter::filter_ini_from_str(target, &actions).unwrap();
+    assert_eq!(result, vec!["[a]", "k = 1", "", "[b]", "", "k = 1"]);
+}
+
+#[test]
+fn test_remove_section_comments() {
+    let mut builder = MutationsBuilder::new();
+    builder
+        .remove_section_comments(true)
+        .add_section_action("deleted", SectionAction::Delete);
+    let mutations = builder.build().unwrap();
+    let target = indoc! {"
+        [a]
+        k = 1
+
+        ; About the deleted section
+
+        [deleted]
+        k = 1
+
+        ; About b
+        [b]
+        k = 1
+    "};
+    let source = "[a]\nk = 1\nnew = 1\n[b]\nk = 1\n";
+    let result = super::merge_ini_from_str(target, source, &mutations).unwrap();
+    let expected = vec!["[a]", "k = 1", "", "new = 1", "; About b", "[b]", "k = 1"];
+    assert_eq!(result, expected);
+    let lines: Vec<_> = super::merge_ini_iter(target, source, &mutations)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(lines, expected);
+
+    let mut builder = FilterActionsBuilder::new();
+    builder
+        .remove_section_comments(true)
+        .add_section_action("deleted", FilterAction::Remove);
+    let actions = builder.build().unwrap();
+    let result = crate::filter::filter_ini_from_str(target, &actions).unwrap();
+    assert_eq!(
+        result,
+        vec!["[a]", "k = 1", "", "; About b", "[b]", "k = 1"]
+    );
+}
+
+#[cfg(feature = "regex-rules")]
+#[test]
+fn test_stats() {
+    let mut builder = MutationsBuilder::new();
+    builder
+        .limits(crate::limits::Limits::new().regex_warn_size(1))
+        .add_section_action("a", SectionAction::Ignore)
+        .add_section_regex_action("b.*", SectionAction::Delete);
+    builder.add_literal_action("c", "k1", Action::Ignore);
+    builder.add_setter("c", "k2", "1", "=");
+    builder.add_regex_action("d", "k.*", Action::Ignore);
+    let stats = builder.build().unwrap().stats();
+    assert_eq!(stats.section_literals, 1);
+    assert_eq!(stats.section_regexes, 1);
+    assert_eq!(stats.literals, 2);
+    assert_eq!(stats.regexes, 1);
+    assert_eq!(stats.raw_lines, 0);
+    assert!(stats.literal_bytes > 0);
+    assert_eq!(
+        stats.regex_pattern_bytes,
+        "b.*".len() + "(?:d)\0(?:k.*)".len()
+    );
+}
+
+#[cfg(feature = "regex-rules")]
+#[test]
+fn test_section_prefix_action() {
+    let mut builder = MutationsBuilder::new();
+    builder
+        .add_section_prefix_action("Applet-", SectionAction::Delete)
+        .add_section_prefix_action("Applet-Kept", SectionAction::Ignore)
+        .add_section_action("Applet-3", SectionAction::Ignore)
+        .add_section_regex_action("Applet.*", SectionAction::Ignore);
+    let mutations = builder.build().unwrap();
+    let result = super::merge_ini_from_str(
+        "[Applet-1]\na=t\n[Applet-3]\na=t\n[Applet-KeptX]\na=t\n[Other]\na=t\n",
+        "[Applet-1]\na=s\n[Applet-3]\na=s\n[Applet-KeptX]\na=s\n[Other]\na=s\n",
+        &mutations,
+    )
+    .unwrap();
+    assert_eq!(
+        result,
+        vec![
+            "[Applet-3]",
+            "a=t",
+            "[Applet-KeptX]",
+            "a=t",
+            "[Other]",
+            "a=s"
+        ]
+    );
+    assert_eq!(mutations.stats().section_prefixes, 2);
+}
+
+#[cfg(feature = "regex-rules")]
+#[test]
+fn test_ignore_helpers() {
+    let mut builder = MutationsBuilder::new();
+    builder.ignore_section("ign");
+    builder.ignore_key("s", "k");
+    builder.ignore_regex("s", "r.*");
+    builder.delete_key("s", "d");
+    let mutations = builder.build().unwrap();
+    let result = super::merge_ini_from_str(
+        "[ign]\na=t\n[s]\nk=t\nr1=t\nd=t\no=t\n",
+        "[ign]\na=s\n[s]\nk=s\nr1=s\nd=s\no=s\n",
+        &mutations,
+    )
+    .unwrap();
+    assert_eq!(result, vec!["[ign]", "a=t", "[s]", "k=t", "r1=t", "o=s"]);
+}
+
+#[cfg(feature = "regex-rules")]
+#[test]
+fn test_redact() {
+    let mut builder = MutationsBuilder::new();
+    builder.add_regex_action("s", "pw.*", Action::Redact("<redacted>".into()));
+    let mutations = builder.build().unwrap();
+    let result = super::merge_ini_from_str(
+        "[s]\npw1 = old\npw_target=old\nk=1\n",
+        "[s]\npw1 = secret\nk=1\npw2=secret\npw_none\n",
+        &mutations,
+    )
+    .unwrap();
+    assert_eq!(
+        result,
+        vec![
+            "[s]",
+            "pw1 = <redacted>",
+            "k=1",
+            "pw2=<redacted>",
+            "pw_none"
+        ]
+    );
+}
+
+#[test]
+fn test_reproducible_output() {
+    let mut builder = MutationsBuilder::new();
+    for section in ["z", "new", "m", "a", "b"] {
+        for key in ["k3", "k1", "k2"] {
+            builder.add_setter(section, key, "1", "=");
+        }
+    }
+    let mutations = builder.build().unwrap();
+    let target = "[b]\nx=1\n";
+    let source = "[b]\nx=1\n[ new ]\nk0=0\n[c]\nk=1\n";
+    let expected = super::merge_ini_from_str(target, source, &mutations).unwrap();
+    // The section from the source and the forced keys are only emitted once
+    assert_eq!(
+        expected.iter().filter(|line| line.contains("new")).count(),
+        1
+    );
+    assert_eq!(
+        &expected[..6],
+        ["[b]", "x=1", "k1=1", "k2=1", "k3=1", "[a]"]
+    );
+    for _ in 0..20 {
+        let result = super::merge_ini_from_str(target, source, &mutations).unwrap();
+        assert_eq!(result, expected);
+    }
+}
+
+#[test]
+fn test_transform_error() {
+    let mut builder = MutationsBuilder::new();
+    builder.add_literal_action(
+        "s",
+        "list",
+        Action::Transform(TransformUnsortedLists::new(',').into()),
+    );
+    let mutations = builder.build().unwrap();
+    let source = "[s]\nlist=a,b\n";
+    // The key is missing a value in the target
+    let target = "[s]\nlist\n";
+    let err = super::merge_ini_from_str(target, source, &mutations).unwrap_err();
+    match err {
+        super::MergeError::TransformerError {
+            section,
+            key,
+            transformer,
+            ..
+        } => {
+            assert_eq!(section, "s");
+            assert_eq!(key, "list");
+            assert_eq!(transformer, "unsorted_lists");
+        }
+        _ => panic!("Unexpected error: {err}"),
+    }
+    let lines: Result<Vec<_>, _> = super::merge_ini_iter(target, source, &mutations)
+        .unwrap()
+        .collect();
+    assert!(matches!(
+        lines,
+        Err(super::MergeError::TransformerError { .. })
+    ));
+    // Not an error when the values are fine
+    let result = super::merge_ini_from_str("[s]\nlist=b,a\n", source, &mutations).unwrap();
+    assert_eq!(result, vec!["[s]", "list=b,a"]);
+}
+
+#[cfg(feature = "rhai")]
+#[test]
+fn test_transform_error_source_only() {
+    use crate::merge::mutations::transforms::TransformScript;
+
+    let failing = || Action::Transform(TransformScript::new("42").unwrap().into());
+    let target = "[s]\nk=1\n";
+    let check = |mutations, source| {
+        let err = super::merge_ini_from_str(target, source, mutations).unwrap_err();
+        assert!(matches!(err, super::MergeError::TransformerError { .. }));
+        let mut lines = super::merge_ini_iter(target, source, mutations).unwrap();
+        assert!(lines.any(|line| line.is_err()));
+        assert!(lines.next().is_none());
+    };
+
+    // Source only key in a section of the target
+    let mut builder = MutationsBuilder::new();
+    builder.add_literal_action("s", "new", failing());
+    let mutations = builder.build().unwrap();
+    check(&mutations, "[s]\nk=1\nnew=1\n");
+
+    // Key in a trailing source only section
+    let mut builder = MutationsBuilder::new();
+    builder.add_literal_action("t", "new", failing());
+    let mutations = builder.build().unwrap();
+    check(&mutations, "[s]\nk=1\n[t]\nnew=1\n");
+
+    // Forced key
+    let mut builder = MutationsBuilder::new();
+    builder.add_setter("s", "forced", "1", "=");
+    builder.add_literal_action("s", "forced", failing());
+    let mutations = builder.build().unwrap();
+    check(&mutations, "[s]\nk=1\n");
+    let mut builder = MutationsBuilder::new();
+    builder.add_setter("t", "forced", "1", "=");
+    builder.add_literal_action("t", "forced", failing());
+    let mutations = builder.build().unwrap();
+    check(&mutations, "[s]\nk=1\n");
+}
+
+#[test]
+fn test_internal_invariant() {
+    // A key with neither an action nor a source line is reported as an error
+    // rather than a panic
+    let mutations = MutationsBuilder::new().build().unwrap();
+    let source = crate::source_loader::source_from_str("", &Dialect::new()).unwrap();
+    let mut state = super::MergeState::new();
+    state.emit_kv(None, "k", None, None, &source, &mutations);
+    assert!(state.result.is_empty());
+    assert!(matches!(
+        state.take_error(),
+        Err(super::MergeError::InternalInvariant(_))
+    ));
+    assert!(state.take_error().is_ok());
+}
+
+#[test]
+fn test_merge_ini_lossy() {
+    use super::MergeWarning;
+
+    let mutations = MutationsBuilder::new().build().unwrap();
+    let target = b"[a]\nk=\xff\n[broken\n";
+    let source = b"[a]\nk=1\n[broken\nx=\xfe\n";
+    let result = super::merge_ini_lossy(target, source, &mutations).unwrap();
+    // The broken header in the source is skipped, so x ends up in [a]
+    assert_eq!(result.lines, vec!["[a]", "k=1", "[broken", "x=\u{fffd}"]);
+    assert_eq!(
+        result.warnings,
+        vec![
+            MergeWarning::InvalidUtf8Target,
+            MergeWarning::InvalidUtf8Source,
+            MergeWarning::SourceParseError("[broken".into()),
+            MergeWarning::TargetParseError("[broken".into()),
+        ]
+    );
+
+    // Arbitrary bytes never panic
+    let alphabet = b"[]=;# \n\r\tab\xff\xc3";
+    let mut seed = 0x2545_f491_u32;
+    for _ in 0..200 {
+        let data: Vec<u8> = (0..64)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                alphabet[seed as usize % alphabet.len()]
+            })
+            .collect();
+        let (target, source) = data.split_at(32);
+        super::merge_ini_lossy(target, source, &mutations).unwrap();
+    }
+}
+
+#[test]
+fn test_merge_report() {
+    use super::KeyOrigin;
+
+    let mut builder = MutationsBuilder::new();
+    builder.ignore_key("a", "ignored");
+    builder.delete_key("a", "deleted");
+    builder.add_literal_action(
+        "a",
+        "list",
+        Action::Transform(TransformUnsortedLists::new(',').into()),
+    );
+    builder.add_setter("a", "forced", "1", "=");
+    let mutations = builder.build().unwrap();
+    let target = indoc! {"
+        [a]
+        same=1
+        changed=1
+        ignored=1
+        deleted=1
+        list=b,a
+        target_only=1
+    "};
+    let source = indoc! {"
+        [a]
+        same=1
+        changed=2
+        ignored=2
+        list=a,b
+        new=1
+    "};
+    let report = super::merge_ini_report(target, source, &mutations).unwrap();
+    assert_eq!(
+        report.lines,
+        super::merge_ini_from_str(target, source, &mutations).unwrap()
+    );
+    let decisions: Vec<_> = report
+        .decisions
+        .iter()
+        .map(|d| {
+            assert_eq!(d.section, "a");
+            (d.key.as_str(), d.origin)
+        })
+        .collect();
+    assert_eq!(
+        decisions,
+        vec![
+            ("same", KeyOrigin::Source),
+            ("changed", KeyOrigin::Source),
+            ("ignored", KeyOrigin::Ignored),
+            ("deleted", KeyOrigin::Removed),
+            (
+                "list",
+                KeyOrigin::Transformed {
+                    transform: "unsorted_lists"
+                }
+            ),
+            ("target_only", KeyOrigin::Removed),
+            ("new", KeyOrigin::Added),
+            ("forced", KeyOrigin::Forced),
+        ]
+    );
+    let stats: Vec<_> = report
+        .transforms
+        .iter()
+        .map(|(name, stats)| (*name, stats.calls, stats.changed, stats.errors))
+        .collect();
+    assert_eq!(stats, vec![("set", 1, 1, 0), ("unsorted_lists", 1, 0, 0)]);
+
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_value(&report.decisions[4]).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "section": "a",
+                "key": "list",
+                "origin": "transformed",
+                "transform": "unsorted_lists",
+            })
+        );
+        let json = serde_json::to_value(&report.decisions[0]).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"section": "a", "key": "same", "origin": "source"})
+        );
+    }
+}
+
+#[test]
+fn test_merge_ini_with_conflicts() {
+    let mut builder = MutationsBuilder::new();
+    builder.ignore_key("s", "ignored");
+    let mutations = builder.build().unwrap();
+    let target = indoc! {"
+        [s]
+        same = 1
+        differs=1
+        ignored=1
+    "};
+    let source = indoc! {"
+        [s]
+        same=1
+        differs=2
+        ignored=2
+    "};
+    let markers = super::ConflictMarkers::default();
+    let result = super::merge_ini_with_conflicts(target, source, &mutations, &markers).unwrap();
+    assert_eq!(
+        result,
+        vec![
+            "[s]",
+            "same=1",
+            "<<<<<<< target",
+            "differs=1",
+            "=======",
+            "differs=2",
+            ">>>>>>> source",
+            "ignored=1",
+        ]
+    );
+
+    let markers = super::ConflictMarkers::new("; target:", "; source:", "; end");
+    let result = super::merge_ini_with_conflicts(target, source, &mutations, &markers).unwrap();
+    assert_eq!(
+        result[2..7],
+        ["; target:", "differs=1", "; source:", "differs=2", "; end"]
+    );
+}
+
+#[test]
+fn test_merge_context() {
+    use crate::merge::mutations::transforms::MergeContext;
+    use crate::merge::mutations::transforms::TransformPerMachine;
+    use std::collections::BTreeMap;
+
+    let values = BTreeMap::from([("laptop".to_string(), "1".to_string())]);
+    let mut context = MergeContext::new();
+    context.set(MergeContext::HOSTNAME, "laptop");
+    let mut builder = MutationsBuilder::new();
+    builder.context(context);
+    builder.add_literal_action(
+        "s",
+        "k",
+        Action::Transform(TransformPerMachine::new(values, None).into()),
+    );
+    let mutations = builder.build().unwrap();
+    let result = super::merge_ini_from_str("[s]\nk=0\n", "[s]\nk=2\n", &mutations).unwrap();
+    assert_eq!(result, vec!["[s]", "k=1"]);
+}
+
+#[cfg(feature = "rhai")]
+#[test]
+fn test_section_context() {
+    use crate::merge::mutations::transforms::TransformScript;
+
+    // Keep the target size only when the target uses a custom mode, which
+    // comes after the size in the target
+    let script = TransformScript::new(indoc! {r#"
+        if tgt_section.mode == "custom" && tgt != () { tgt.raw } else { src.raw }
+    "#})
+    .unwrap()
+    .with_section_context();
+    let mut builder = MutationsBuilder::new();
+    builder.add_literal_action("s", "size", Action::Transform(script.into()));
+    let mutations = builder.build().unwrap();
+    let source = "[s]\nsize=1\nmode=custom\n";
+    let result = super::merge_ini_from_str("[s]\nsize=2\nmode=custom\n", source, &mutations);
+    assert_eq!(result.unwrap(), vec!["[s]", "size=2", "mode=custom"]);
+    let result = super::merge_ini_from_str("[s]\nsize=2\nmode=auto\n", source, &mutations);
+    assert_eq!(result.unwrap(), vec!["[s]", "size=1", "mode=custom"]);
+}
+
+#[test]
+fn test_time_limit() {
+    use crate::merge::mutations::TimeoutPolicy;
+    use std::time::Duration;
+
+    let merge = |policy| {
+        let mut builder = MutationsBuilder::new();
+        builder.time_limit(Duration::ZERO, policy);
+        builder.add_literal_action(
+            "s",
+            "list",
+            Action::Transform(TransformUnsortedLists::new(',').into()),
+        );
+        let mutations = builder.build().unwrap();
+        super::merge_ini_from_str("[s]\nlist=a\nk=1\n", "[s]\nlist=b\nk=2\n", &mutations)
+    };
+    assert_eq!(
+        merge(TimeoutPolicy::KeepTarget).unwrap(),
+        vec!["[s]", "list=a", "k=2"]
+    );
+    assert!(matches!(
+        merge(TimeoutPolicy::Fail),
+        Err(super::MergeError::Timeout(Duration::ZERO))
+    ));
+}
+
+#[test]
+fn test_merge_ini_cancellable() {
+    use crate::cancel::CancelToken;
+
+    let mutations = MutationsBuilder::new().build().unwrap();
+    let cancel = CancelToken::new();
+    let handle = cancel.clone();
+    let result = super::merge_ini_cancellable("[s]\nk=1\n", "[s]\nk=2\n", &mutations, &cancel);
+    assert_eq!(result.unwrap(), vec!["[s]", "k=2"]);
+    handle.cancel();
+    assert!(cancel.is_cancelled());
+    assert!(matches!(
+        super::merge_ini_cancellable("[s]\nk=1\n", "[s]\nk=2\n", &mutations, &cancel),
+        Err(super::MergeError::Cancelled)
+    ));
+}
+
+#[test]
+fn test_merge_ini_with_progress() {
+    use crate::progress::Progress;
+
+    let mutations = MutationsBuilder::new().build().unwrap();
+    let target = "[s]\nk=1\nl=2\n";
+    let mut progress = Vec::new();
+    let result = super::merge_ini_with_progress(
+        &mut target.as_bytes(),
+        &mut "[s]\nk=2\n".as_bytes(),
+        &mutations,
+        2,
+        |p: Progress| progress.push((p.lines, p.bytes, p.total_bytes)),
+    );
+    assert_eq!(result.unwrap(), vec!["[s]", "k=2"]);
+    assert_eq!(progress, vec![(2, 8, 12), (3, 12, 12)]);
+}
@@ -0,0 +1,49 @@
+//! Old path of [`merge::mutations`](crate::merge::mutations), kept for
+//! compatibility
+//!
+//! This re-exports the same items (there is only one `Action`), but mixing
+//! both paths makes error messages confusing. Use
+//! [`merge::mutations`](crate::merge::mutations) or the
+//! [`prelude`](crate::prelude) instead.
+//!
+//! Deprecating this module only warns when the module itself is named, and
+//! re-exports can not be deprecated. The structs are therefore deprecated
+//! type aliases, which warn on use. Enums, functions and modules are plain
+//! re-exports (without a warning), since a type alias of an enum can not be
+//! used to import its variants.
+
+use crate::merge::mutations as current;
+
+pub use crate::merge::mutations::suggest_rules;
+pub use crate::merge::mutations::transforms;
+pub use crate::merge::mutations::Action;
+pub use crate::merge::mutations::ChurnReason;
+pub use crate::merge::mutations::KeyPlacement;
+pub use crate::merge::mutations::MutationRule;
+pub use crate::merge::mutations::SectionAction;
+pub use crate::merge::mutations::TargetOnlyPolicy;
+pub use crate::merge::mutations::TimeoutPolicy;
+
+/// Define deprecated aliases of the structs in the new path
+macro_rules! deprecated_alias {
+    ($($name:ident),* $(,)?) => {
+        $(
+            #[doc = concat!("See [`crate::merge::mutations::", stringify!($name), "`]")]
+            #[deprecated(
+                since = "0.6.2",
+                note = "use `ini_merge::merge::mutations` or `ini_merge::prelude` instead"
+            )]
+            pub type $name = current::$name;
+        )*
+    };
+}
+
+deprecated_alias!(
+    Condition,
+    Mutations,
+    MutationsBuilder,
+    MutationsRuleSet,
+    RuleId,
+    SuggestedMutation,
+    ValuePattern,
+);
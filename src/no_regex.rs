@@ -0,0 +1,76 @@
+//! Stand-ins for the regex types when the `regex-rules` feature is disabled
+//!
+//! Only empty regex sets can be built, so only literal rules (and section
+//! prefixes) match. Adding a regex rule fails when building the rules.
+
+use crate::actions::ActionsBuilderError;
+use std::iter::Empty;
+
+/// A regex set without any patterns
+#[derive(Debug, Default)]
+pub(crate) struct RegexSet {
+    patterns: Vec<String>,
+}
+
+impl RegexSet {
+    /// Build a set, failing if there are any patterns
+    pub(crate) fn new(patterns: &[String]) -> Result<Self, ActionsBuilderError> {
+        if patterns.is_empty() {
+            Ok(Self::default())
+        } else {
+            Err(ActionsBuilderError::RegexUnsupported)
+        }
+    }
+
+    /// The patterns of the set (always empty)
+    pub(crate) fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// Match against the set (never matches)
+    pub(crate) const fn matches(&self, _haystack: &str) -> SetMatches {
+        SetMatches
+    }
+}
+
+/// Result of matching against a [`RegexSet`]
+#[derive(Debug)]
+pub(crate) struct SetMatches;
+
+impl SetMatches {
+    /// Whether any pattern matched
+    pub(crate) const fn matched_any(&self) -> bool {
+        false
+    }
+
+    /// Whether the pattern with the given index matched
+    pub(crate) const fn matched(&self, _index: usize) -> bool {
+        false
+    }
+
+    /// Indices of the patterns that matched
+    pub(crate) fn iter(&self) -> Empty<usize> {
+        std::iter::empty()
+    }
+}
+
+/// A regex, which can not be constructed
+#[derive(Debug, Clone)]
+pub(crate) enum Regex {}
+
+impl Regex {
+    /// Always fails
+    pub(crate) const fn new(_pattern: &str) -> Result<Self, ActionsBuilderError> {
+        Err(ActionsBuilderError::RegexUnsupported)
+    }
+
+    /// The pattern as a string
+    pub(crate) const fn as_str(&self) -> &str {
+        match *self {}
+    }
+
+    /// Check if a value matches
+    pub(crate) const fn is_match(&self, _value: &str) -> bool {
+        match *self {}
+    }
+}
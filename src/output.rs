@@ -0,0 +1,151 @@
+//! Helpers for turning the processed lines into the final output
+
+/// Policy for the newline at the end of the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum TrailingNewline {
+    /// End with a newline if the input (the target when merging) did
+    #[default]
+    Preserve,
+    /// Always end with a newline
+    Always,
+    /// Never end with a newline
+    Never,
+}
+
+/// Remove trailing whitespace from a line, without reallocating
+pub(crate) fn trim_trailing_whitespace(line: &mut String) {
+    let len = line
+        .trim_end_matches(|chr: char| chr.is_ascii_whitespace())
+        .len();
+    line.truncate(len);
+}
+
+/// Collapse runs of blank lines into a single blank line.
+///
+/// `prev_blank` is if the line before `lines` was blank, and is updated to
+/// be if the last line is blank (for processing the output in chunks).
+pub(crate) fn squash_blank_lines(lines: &mut Vec<String>, prev_blank: &mut bool) {
+    lines.retain(|line| {
+        let blank = line.trim().is_empty();
+        let keep = !(blank && *prev_blank);
+        *prev_blank = blank;
+        keep
+    });
+}
+
+/// The line ending used by the input: `\r\n` if its first line ends with
+/// that, otherwise `\n`
+fn line_ending(input: &str) -> &'static str {
+    match input.find('\n') {
+        Some(pos) if input[..pos].ends_with('\r') => "\r\n",
+        _ => "\n",
+    }
+}
+
+/// Join output lines into a string, applying the trailing newline policy.
+///
+/// Lines are joined with the line ending of the input, so that files using
+/// `\r\n` keep it. Trailing blank lines of the input are preserved, i.e.
+/// the output ends in the same blank lines as the input. If content (such
+/// as source only sections when merging) was appended after them, they are
+/// kept before that content as well, so they appear twice.
+pub(crate) fn join_lines(mut lines: Vec<String>, input: &str, newline: TrailingNewline) -> String {
+    let mut input_blanks: Vec<_> = input
+        .lines()
+        .rev()
+        .take_while(|line| line.trim().is_empty())
+        .collect();
+    input_blanks.reverse();
+    while lines.last().is_some_and(|line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    lines.extend(input_blanks.into_iter().map(str::to_string));
+
+    let line_ending = line_ending(input);
+    let mut result = lines.join(line_ending);
+    let add_newline = match newline {
+        TrailingNewline::Preserve => input.ends_with('\n'),
+        TrailingNewline::Always => true,
+        TrailingNewline::Never => false,
+    };
+    if add_newline && !lines.is_empty() {
+        result.push_str(line_ending);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::join_lines;
+    use super::TrailingNewline;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn newline_policy() {
+        let lines = || vec!["a".to_string(), "b".to_string()];
+        assert_eq!(
+            join_lines(lines(), "x\n", TrailingNewline::Preserve),
+            "a\nb\n"
+        );
+        assert_eq!(join_lines(lines(), "x", TrailingNewline::Preserve), "a\nb");
+        assert_eq!(join_lines(lines(), "x", TrailingNewline::Always), "a\nb\n");
+        assert_eq!(join_lines(lines(), "x\n", TrailingNewline::Never), "a\nb");
+        assert_eq!(join_lines(vec![], "", TrailingNewline::Always), "");
+    }
+
+    #[test]
+    fn line_endings() {
+        let lines = || vec!["a".to_string(), "b".to_string()];
+        assert_eq!(
+            join_lines(lines(), "x\r\ny\r\n\r\n", TrailingNewline::Preserve),
+            "a\r\nb\r\n\r\n"
+        );
+        assert_eq!(
+            join_lines(lines(), "x\r\n", TrailingNewline::Never),
+            "a\r\nb"
+        );
+        assert_eq!(
+            join_lines(lines(), "x\ry\n", TrailingNewline::Always),
+            "a\nb\n"
+        );
+    }
+
+    #[test]
+    fn trim_whitespace() {
+        let mut line = "a = b \t ".to_string();
+        super::trim_trailing_whitespace(&mut line);
+        assert_eq!(line, "a = b");
+    }
+
+    #[test]
+    fn squash_blank_lines() {
+        let mut lines: Vec<_> = ["", "a", "", " ", "", "b", ""]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let mut prev_blank = true;
+        super::squash_blank_lines(&mut lines, &mut prev_blank);
+        assert_eq!(lines, vec!["a", "", "b", ""]);
+        assert!(prev_blank);
+    }
+
+    #[test]
+    fn trailing_blank_lines() {
+        // Blank lines that ended up before appended content stay there, and
+        // are repeated at the end
+        let lines = vec![
+            "a".to_string(),
+            String::new(),
+            "[appended]".to_string(),
+            "b".to_string(),
+        ];
+        assert_eq!(
+            join_lines(lines, "a\n\n", TrailingNewline::Preserve),
+            "a\n\n[appended]\nb\n\n"
+        );
+        // Blank lines not in the input are dropped
+        let lines = vec!["a".to_string(), String::new()];
+        assert_eq!(join_lines(lines, "a\n", TrailingNewline::Preserve), "a\n");
+    }
+}
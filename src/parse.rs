@@ -0,0 +1,144 @@
+//! Streaming INI parser
+//!
+//! Lines are parsed and classified according to a [`Dialect`] the same way
+//! as when merging and filtering, so the quirks (such as section name
+//! trimming and the handling of `][` in section headers) are identical. The
+//! exception is values continued over several lines (see
+//! [`Dialect::line_continuation`]): There is one event per line, so these
+//! are not joined.
+
+use crate::dialect::Dialect;
+use ini_roundtrip::Item;
+use ini_roundtrip::Parser;
+
+/// An item from the INI file, together with its line number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Event<'a> {
+    /// Line number (1-based)
+    ///
+    /// For [`Item::SectionEnd`] (which doesn't correspond to a line of its
+    /// own) this is the last line of the section, or 0 if there are no lines
+    /// before it.
+    pub line: usize,
+    /// The parsed item
+    pub item: Item<'a>,
+}
+
+/// Iterator over the events of an INI file, see [`events`]
+#[derive(Debug)]
+pub struct Events<'a> {
+    parser: Parser<'a>,
+    dialect: &'a Dialect,
+    line: usize,
+}
+
+impl<'a> Iterator for Events<'a> {
+    type Item = Event<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.dialect.classify_line(self.parser.next()?);
+        if !matches!(item, Item::SectionEnd) {
+            self.line += 1;
+        }
+        Some(Event {
+            line: self.line,
+            item,
+        })
+    }
+}
+
+/// Parse INI data in the standard dialect, giving the items annotated with
+/// line numbers
+#[must_use]
+pub fn events(data: &str) -> Events<'_> {
+    events_with_dialect(data, &Dialect::STANDARD)
+}
+
+/// Parse INI data in the given dialect, giving the items annotated with line
+/// numbers
+#[must_use]
+pub fn events_with_dialect<'a>(data: &'a str, dialect: &'a Dialect) -> Events<'a> {
+    Events {
+        parser: Parser::new(data),
+        dialect,
+        line: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::events;
+    use super::events_with_dialect;
+    use super::Event;
+    use crate::dialect::Dialect;
+    use ini_roundtrip::Item;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn line_numbers() {
+        let result: Vec<_> = events("a=1\n\n[ s ]\n; c\n").collect();
+        assert_eq!(
+            result,
+            vec![
+                Event {
+                    line: 1,
+                    item: Item::Property {
+                        key: "a",
+                        val: Some("1"),
+                        raw: "a=1"
+                    }
+                },
+                Event {
+                    line: 2,
+                    item: Item::Blank { raw: "" }
+                },
+                Event {
+                    line: 2,
+                    item: Item::SectionEnd
+                },
+                Event {
+                    line: 3,
+                    item: Item::Section {
+                        name: "s",
+                        raw: "[ s ]"
+                    }
+                },
+                Event {
+                    line: 4,
+                    item: Item::Comment { raw: "; c" }
+                },
+                Event {
+                    line: 4,
+                    item: Item::SectionEnd
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn dialect() {
+        let mut dialect = Dialect::new();
+        dialect.comment_prefixes(["#"]).trim_section_names(false);
+        let items: Vec<_> = events_with_dialect("; a\n# b\n[ s ]\n", &dialect)
+            .map(|event| event.item)
+            .collect();
+        assert_eq!(
+            items,
+            vec![
+                Item::Property {
+                    key: "; a",
+                    val: None,
+                    raw: "; a"
+                },
+                Item::Comment { raw: "# b" },
+                Item::SectionEnd,
+                Item::Section {
+                    name: " s ",
+                    raw: "[ s ]"
+                },
+                Item::SectionEnd,
+            ]
+        );
+    }
+}
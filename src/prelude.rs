@@ -0,0 +1,38 @@
+//! Commonly used types, for glob importing
+//!
+//! ```
+//! use ini_merge::prelude::*;
+//! ```
+//!
+//! Items are only removed from the prelude (or change meaning) in semver
+//! breaking releases, independent of where they are defined in the crate.
+//! New items may be added in minor releases, so a glob import can in rare
+//! cases become ambiguous with a local name.
+
+pub use crate::actions::ActionsBuilderError;
+pub use crate::combined::CombinedRules;
+pub use crate::dialect::Dialect;
+pub use crate::filter::filter_ini;
+pub use crate::filter::FilterAction;
+pub use crate::filter::FilterActions;
+pub use crate::filter::FilterActionsBuilder;
+pub use crate::filter::FilterError;
+pub use crate::limits::Limits;
+pub use crate::merge::merge_ini;
+pub use crate::merge::mutations::transforms::TransformArgs;
+pub use crate::merge::mutations::transforms::Transformer;
+pub use crate::merge::mutations::transforms::TransformerAction;
+pub use crate::merge::mutations::transforms::TransformerCallError;
+pub use crate::merge::mutations::transforms::TransformerConstructionError;
+pub use crate::merge::mutations::transforms::TransformerDispatch;
+pub use crate::merge::mutations::transforms::TransformerFactory;
+pub use crate::merge::mutations::Action;
+pub use crate::merge::mutations::MutationRule;
+pub use crate::merge::mutations::Mutations;
+pub use crate::merge::mutations::MutationsBuilder;
+pub use crate::merge::mutations::SectionAction;
+pub use crate::merge::MergeError;
+pub use crate::InputData;
+pub use crate::Property;
+pub use crate::SectionName;
+pub use crate::OUTSIDE_SECTION;
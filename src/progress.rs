@@ -0,0 +1,61 @@
+//! Progress reporting for merging and filtering of large inputs
+
+/// How far processing of the input has come, see
+/// [`crate::merge::merge_ini_with_progress`] and
+/// [`crate::filter::filter_ini_with_progress`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Progress {
+    /// Lines of the input (the target when merging) processed so far
+    pub lines: usize,
+    /// Bytes of the input processed so far
+    pub bytes: usize,
+    /// Total size of the input in bytes
+    pub total_bytes: usize,
+}
+
+/// Counts processed items, calling the callback every `every` lines and when
+/// done
+pub(crate) struct ProgressCounter<F> {
+    progress: Progress,
+    every: usize,
+    callback: F,
+}
+
+impl<F: FnMut(Progress)> ProgressCounter<F> {
+    pub(crate) fn new(total_bytes: usize, every: usize, callback: F) -> Self {
+        Self {
+            progress: Progress {
+                lines: 0,
+                bytes: 0,
+                total_bytes,
+            },
+            every: every.max(1),
+            callback,
+        }
+    }
+
+    /// Count an item of the input
+    pub(crate) fn item(&mut self, item: &ini_roundtrip::Item<'_>) {
+        let raw = match item {
+            ini_roundtrip::Item::Section { raw, .. }
+            | ini_roundtrip::Item::Property { raw, .. }
+            | ini_roundtrip::Item::Comment { raw }
+            | ini_roundtrip::Item::Blank { raw }
+            | ini_roundtrip::Item::Error(raw) => raw,
+            ini_roundtrip::Item::SectionEnd => return,
+        };
+        self.progress.lines += 1;
+        // Plus the line ending (assumed to be a single newline)
+        self.progress.bytes = (self.progress.bytes + raw.len() + 1).min(self.progress.total_bytes);
+        if self.progress.lines % self.every == 0 {
+            (self.callback)(self.progress);
+        }
+    }
+
+    /// Report that all of the input has been processed
+    pub(crate) fn finish(&mut self) {
+        self.progress.bytes = self.progress.total_bytes;
+        (self.callback)(self.progress);
+    }
+}
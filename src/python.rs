@@ -0,0 +1,313 @@
+//! Python bindings (requires the `python` feature)
+//!
+//! This module does not define a Python extension module on its own (that
+//! would require this crate to be built as a `cdylib`). Instead, create a
+//! small wrapper crate and call [`register`] from its `#[pymodule]`:
+//!
+//! ```rust,ignore
+//! use pyo3::prelude::*;
+//!
+//! #[pymodule]
+//! fn ini_merge(m: &Bound<'_, PyModule>) -> PyResult<()> {
+//!     ::ini_merge::python::register(m)
+//! }
+//! ```
+//!
+//! From Python it can then be used like this:
+//!
+//! ```python
+//! rules = ini_merge.MergeRules()
+//! rules.ignore_section("RecentFiles")
+//! rules.ignore("General", "geometry_.*", regex=True)
+//! rules.transform("Shortcuts", "playmedia", "kde_shortcut")
+//! merged = ini_merge.merge(target_text, source_text, rules)
+//! ```
+
+use crate::filter::filter_ini_to_string;
+use crate::filter::FilterAction;
+use crate::filter::FilterActions;
+use crate::filter::FilterActionsBuilder;
+use crate::filter::FilterRule;
+use crate::filter::FilterRuleSet;
+use crate::merge::merge_ini_to_string;
+use crate::merge::mutations::transforms::TransformerDispatch;
+use crate::merge::mutations::Action;
+use crate::merge::mutations::MutationRule;
+use crate::merge::mutations::Mutations;
+use crate::merge::mutations::MutationsBuilder;
+use crate::merge::mutations::MutationsRuleSet;
+use crate::merge::mutations::SectionAction;
+use crate::TrailingNewline;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use std::collections::HashMap;
+
+/// Register the functions and classes of the bindings in a Python module
+pub fn register(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<MergeRules>()?;
+    m.add_class::<FilterRules>()?;
+    m.add_function(wrap_pyfunction!(merge, m)?)?;
+    m.add_function(wrap_pyfunction!(filter, m)?)?;
+    Ok(())
+}
+
+/// Convert any error into a Python `ValueError`
+fn value_error(err: impl std::fmt::Display) -> PyErr {
+    PyValueError::new_err(err.to_string())
+}
+
+/// Rule set for merging, the Python counterpart of [`MutationsBuilder`]
+///
+/// The rules are recorded and compiled each time they are used, as
+/// [`MutationsBuilder::build`] consumes the builder.
+#[pyclass(module = "ini_merge")]
+#[derive(Debug, Clone)]
+pub struct MergeRules {
+    ruleset: MutationsRuleSet,
+}
+
+impl MergeRules {
+    fn push_key(&mut self, section: String, key: String, regex: bool, action: Action) {
+        self.ruleset.rules.push(if regex {
+            MutationRule::Regex {
+                section,
+                key,
+                action,
+            }
+        } else {
+            MutationRule::Literal {
+                section,
+                key,
+                action,
+            }
+        });
+    }
+
+    fn build(&self) -> PyResult<Mutations> {
+        MutationsBuilder::from_serialized(self.ruleset.clone())
+            .build()
+            .map_err(value_error)
+    }
+}
+
+#[pymethods]
+impl MergeRules {
+    #[new]
+    fn new() -> Self {
+        Self {
+            ruleset: MutationsRuleSet::new(Vec::new()),
+        }
+    }
+
+    /// Always keep the target version of a whole section
+    fn ignore_section(&mut self, section: String) {
+        self.ruleset.rules.push(MutationRule::Section {
+            section,
+            action: SectionAction::Ignore,
+        });
+    }
+
+    /// Remove a whole section
+    fn delete_section(&mut self, section: String) {
+        self.ruleset.rules.push(MutationRule::Section {
+            section,
+            action: SectionAction::Delete,
+        });
+    }
+
+    /// Always keep the target value of a key
+    #[pyo3(signature = (section, key, regex = false))]
+    fn ignore(&mut self, section: String, key: String, regex: bool) {
+        self.push_key(section, key, regex, Action::Ignore);
+    }
+
+    /// Keep the target value of a key, but use the source value if the
+    /// target lacks the key
+    #[pyo3(signature = (section, key, regex = false))]
+    fn ignore_but_require(&mut self, section: String, key: String, regex: bool) {
+        self.push_key(section, key, regex, Action::IgnoreButRequire);
+    }
+
+    /// Remove a key
+    #[pyo3(signature = (section, key, regex = false))]
+    fn delete(&mut self, section: String, key: String, regex: bool) {
+        self.push_key(section, key, regex, Action::Delete);
+    }
+
+    /// Apply a named transform (`unsorted_lists`, `id_lists`,
+    /// `append_only_list`, `kde_shortcut`, `float_format`, `geometry`,
+    /// `numeric_locale`, `qt_state_blob`, `set_value`, `per_machine`,
+    /// `keyring`, `script` or `external`) to a key
+    #[pyo3(signature = (section, key, name, args = None, regex = false))]
+    fn transform(
+        &mut self,
+        section: String,
+        key: String,
+        name: &str,
+        args: Option<HashMap<String, String>>,
+        regex: bool,
+    ) -> PyResult<()> {
+        let transform =
+            TransformerDispatch::from_name(name, &args.unwrap_or_default().into_iter().collect())
+                .map_err(value_error)?;
+        self.push_key(section, key, regex, Action::Transform(transform));
+        Ok(())
+    }
+
+    /// Force a key to a specific value
+    #[pyo3(signature = (section, key, value, separator = "=".to_string()))]
+    fn set(&mut self, section: String, key: String, value: String, separator: String) {
+        self.ruleset.rules.push(MutationRule::Set {
+            section,
+            key,
+            value,
+            separator,
+        });
+    }
+
+    /// Set if there should be a warning on multiple regex matches
+    fn warn_on_multiple_matches(&mut self, warn: bool) {
+        self.ruleset.warn_on_multiple_matches = warn;
+    }
+}
+
+/// Rule set for filtering, the Python counterpart of [`FilterActionsBuilder`]
+#[pyclass(module = "ini_merge")]
+#[derive(Debug, Clone)]
+pub struct FilterRules {
+    ruleset: FilterRuleSet,
+}
+
+impl FilterRules {
+    fn push(&mut self, section: String, key: Option<String>, regex: bool, action: FilterAction) {
+        self.ruleset.rules.push(match key {
+            Some(key) if regex => FilterRule::Regex {
+                section,
+                key,
+                action,
+            },
+            Some(key) => FilterRule::Literal {
+                section,
+                key,
+                action,
+            },
+            None => FilterRule::Section { section, action },
+        });
+    }
+
+    fn build(&self) -> PyResult<FilterActions> {
+        FilterActionsBuilder::from_serialized(self.ruleset.clone())
+            .build()
+            .map_err(value_error)
+    }
+}
+
+#[pymethods]
+impl FilterRules {
+    #[new]
+    fn new() -> Self {
+        Self {
+            ruleset: FilterRuleSet::new(Vec::new()),
+        }
+    }
+
+    /// Remove a key (or a whole section if `key` is not given)
+    #[pyo3(signature = (section, key = None, regex = false))]
+    fn remove(&mut self, section: String, key: Option<String>, regex: bool) {
+        self.push(section, key, regex, FilterAction::Remove);
+    }
+
+    /// Replace the value of a key (or all keys in a section if `key` is not
+    /// given)
+    #[pyo3(signature = (section, replacement, key = None, regex = false))]
+    fn replace(&mut self, section: String, replacement: String, key: Option<String>, regex: bool) {
+        self.push(
+            section,
+            key,
+            regex,
+            FilterAction::Replace(replacement.into()),
+        );
+    }
+
+    /// Set if there should be a warning on multiple regex matches
+    fn warn_on_multiple_matches(&mut self, warn: bool) {
+        self.ruleset.warn_on_multiple_matches = warn;
+    }
+}
+
+/// Merge two INI files (given as text), returning the merged text
+#[pyfunction]
+fn merge(target: &str, source: &str, rules: &MergeRules) -> PyResult<String> {
+    let mutations = rules.build()?;
+    merge_ini_to_string(
+        &mut target.as_bytes(),
+        &mut source.as_bytes(),
+        &mutations,
+        TrailingNewline::Preserve,
+    )
+    .map_err(value_error)
+}
+
+/// Filter an INI file (given as text), returning the filtered text
+#[pyfunction]
+fn filter(text: &str, rules: &FilterRules) -> PyResult<String> {
+    let actions = rules.build()?;
+    filter_ini_to_string(&mut text.as_bytes(), &actions, TrailingNewline::Preserve)
+        .map_err(value_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::register;
+    use pyo3::ffi::c_str;
+    use pyo3::prelude::*;
+    use pyo3::types::PyDict;
+
+    /// Run a Python snippet with the bindings available as `ini_merge`
+    fn run(code: &std::ffi::CStr) {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "ini_merge").unwrap();
+            register(&module).unwrap();
+            let globals = PyDict::new(py);
+            globals.set_item("ini_merge", module).unwrap();
+            py.run(code, Some(&globals), None).unwrap();
+        });
+    }
+
+    #[test]
+    fn merge() {
+        run(c_str!(
+            r#"
+rules = ini_merge.MergeRules()
+rules.ignore("s", "ignored")
+rules.set("s", "forced", "1")
+target = "[s]\nignored = target\nkey = target\n"
+source = "[s]\nignored = source\nkey = source\n"
+merged = ini_merge.merge(target, source, rules)
+assert merged == "[s]\nignored = target\nkey = source\nforced=1\n", merged
+
+try:
+    rules.transform("s", "key", "no_such_transform")
+except ValueError:
+    pass
+else:
+    raise AssertionError("expected an error for an unknown transform")
+"#
+        ));
+    }
+
+    #[test]
+    fn filter() {
+        run(c_str!(
+            r#"
+rules = ini_merge.FilterRules()
+rules.remove("removed")
+rules.replace("s", "HIDDEN", key="password")
+text = "[s]\npassword = secret\nkey = value\n[removed]\na = b\n"
+filtered = ini_merge.filter(text, rules)
+assert filtered == "[s]\npassword = HIDDEN\nkey = value\n", filtered
+"#
+        ));
+    }
+}
@@ -1,7 +1,13 @@
 //! This module handles loading the source INI into a form that is easy for
 //! random access (instead of the linear processing we do with the target state
 //! INI file).
-use lending_iterator::prelude::*;
+use crate::dialect::Dialect;
+use crate::limits::LimitError;
+use crate::limits::Limits;
+use crate::loader::Includes;
+use crate::loader::IniBuffer;
+use crate::loader::LoadError;
+use crate::loader::MAX_INCLUDE_DEPTH;
 use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
@@ -28,13 +34,34 @@ pub(crate) struct SourceValue {
     val: Option<String>,
 }
 
-/// Error type for loading the source.
+/// Error type for loading the source, see [`crate::merge::MergeError::SourceLoad`]
 #[derive(Debug, Error)]
-pub(crate) enum SourceLoaderError {
+#[non_exhaustive]
+pub enum SourceLoaderError {
+    /// An IO error (including the data not being valid UTF-8)
     #[error("Failed to load due to IO error: {0}")]
     Load(#[source] std::io::Error),
+    /// A resource limit was exceeded
+    #[error(transparent)]
+    Limit(#[from] LimitError),
+    /// The source could not be parsed
     #[error("Parse error {0}")]
     Parse(String),
+    /// An included file could not be read
+    #[error("Failed to include {0}: {1}")]
+    Include(String, #[source] std::io::Error),
+    /// Includes are nested too deep
+    #[error("Includes nested too deep at {0}")]
+    IncludeDepth(String),
+}
+
+impl From<LoadError> for SourceLoaderError {
+    fn from(value: LoadError) -> Self {
+        match value {
+            LoadError::Io(err) => Self::Load(err),
+            LoadError::Limit(err) => Self::Limit(err),
+        }
+    }
 }
 
 impl SourceValue {
@@ -62,6 +89,32 @@ pub(crate) struct SourceIni {
     section_headers: HashMap<String, String>,
     /// A mapping for all the keys to their parsed value and raw lines
     values: BTreeMap<SectionAndKey<'static>, SourceValue>,
+    /// The order of the keys in each section
+    key_order: HashMap<String, SectionOrder>,
+    /// The first spelling of each lowercased section name (only when section
+    /// names are case insensitive)
+    folded_sections: HashMap<String, String>,
+}
+
+/// The order of the keys in a section of the source
+#[derive(Debug, Default)]
+pub(crate) struct SectionOrder {
+    /// Keys in the order they first appear
+    keys: Vec<String>,
+    /// Index of each key in `keys`
+    positions: HashMap<String, usize>,
+}
+
+impl SectionOrder {
+    /// Keys in the order they first appear
+    pub(crate) fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// Position of a key in the section
+    pub(crate) fn position(&self, key: &str) -> Option<usize> {
+        self.positions.get(key).copied()
+    }
 }
 
 impl SourceIni {
@@ -70,6 +123,22 @@ impl SourceIni {
         self.section_headers.iter()
     }
 
+    /// The raw header line of a section (the name itself for the top level)
+    pub(crate) fn section_header(&self, name: &str) -> Option<&str> {
+        self.section_headers.get(name).map(String::as_str)
+    }
+
+    /// The spelling of a section name used in the source (if the section
+    /// names are case insensitive)
+    pub(crate) fn canonical_section<'a>(&'a self, name: &'a str) -> &'a str {
+        if self.folded_sections.is_empty() {
+            return name;
+        }
+        self.folded_sections
+            .get(&name.to_lowercase())
+            .map_or(name, String::as_str)
+    }
+
     /// True if the section exists in the source
     pub(crate) fn has_section(&self, name: &str) -> bool {
         self.section_headers.contains_key(name)
@@ -90,6 +159,11 @@ impl SourceIni {
             .map(|(k, v)| (&k.1, v))
     }
 
+    /// Get the order of the keys in a section
+    pub(crate) fn section_order(&self, name: &str) -> Option<&SectionOrder> {
+        self.key_order.get(name)
+    }
+
     /// Get a specific entry for a section & key
     pub(crate) fn property<'result, 'key: 'result, 'this: 'result>(
         &'this self,
@@ -100,37 +174,146 @@ impl SourceIni {
 }
 
 /// Parses an INI file into a [`SourceIni`]
-pub(crate) fn load_source_ini(data: &mut impl Read) -> Result<SourceIni, SourceLoaderError> {
-    let mut loader = crate::loader::load_ini(data).map_err(SourceLoaderError::Load)?;
+pub(crate) fn load_source_ini(
+    data: &mut impl Read,
+    limits: &Limits,
+    dialect: &Dialect,
+) -> Result<SourceIni, SourceLoaderError> {
+    let buffer = IniBuffer::read_with_limits(data, limits)?;
+    source_from_str(buffer.as_str(), dialect)
+}
+
+/// Parses an INI file that is already in memory into a [`SourceIni`]
+pub(crate) fn source_from_str(
+    input: &str,
+    dialect: &Dialect,
+) -> Result<SourceIni, SourceLoaderError> {
     let mut result = SourceIni::default();
-    let mut cur_section = crate::OUTSIDE_SECTION.to_string();
-    result
-        .section_headers
-        .insert(cur_section.clone(), cur_section.clone());
-
-    while let Some(ref item) = loader.next() {
-        match *item {
-            ini_roundtrip::Item::Error(err) => return Err(SourceLoaderError::Parse(err.into())),
-            ini_roundtrip::Item::Section { name, raw } => {
-                result
-                    .section_headers
-                    .insert(name.to_string(), raw.to_string());
-                cur_section.clear();
-                cur_section.push_str(name);
-            }
-            ini_roundtrip::Item::SectionEnd => (),
-            ini_roundtrip::Item::Property { key, val, raw } => {
-                result.values.insert(
-                    SectionAndKey(cur_section.clone().into(), key.to_string().into()),
-                    SourceValue::new(raw.to_string(), val.map(str::to_string)),
-                );
-            }
-            ini_roundtrip::Item::Comment { raw: _ } => (),
-            ini_roundtrip::Item::Blank { raw: _ } => (),
+    result.add_input(input, dialect, None, None)?;
+    Ok(result)
+}
+
+/// Parses an INI file that is already in memory into a [`SourceIni`],
+/// skipping lines that fail to parse. The skipped lines are returned.
+pub(crate) fn source_from_str_lossy(input: &str, dialect: &Dialect) -> (SourceIni, Vec<String>) {
+    let mut result = SourceIni::default();
+    let mut skipped = Vec::new();
+    // Can not fail when skipping parse errors
+    let _ = result.add_input(input, dialect, None, Some(&mut skipped));
+    (result, skipped)
+}
+
+/// Parses an INI file into a [`SourceIni`], resolving includes
+pub(crate) fn load_source_ini_with_includes(
+    data: &mut impl Read,
+    limits: &Limits,
+    dialect: &Dialect,
+    includes: &mut Includes<'_>,
+) -> Result<SourceIni, SourceLoaderError> {
+    let mut result = SourceIni::default();
+    result.add_file(data, limits, dialect, includes, 0)?;
+    for path in includes.take_extra() {
+        result.add_include(&path, limits, dialect, includes, 0)?;
+    }
+    Ok(result)
+}
+
+impl SourceIni {
+    /// Add the contents of a file, and any files it includes
+    fn add_file(
+        &mut self,
+        data: &mut impl Read,
+        limits: &Limits,
+        dialect: &Dialect,
+        includes: &mut Includes<'_>,
+        depth: usize,
+    ) -> Result<(), SourceLoaderError> {
+        let buffer = IniBuffer::read_with_limits(data, limits)?;
+        let found = self.add_input(buffer.as_str(), dialect, includes.include_key(), None)?;
+        for path in found {
+            self.add_include(&path, limits, dialect, includes, depth + 1)?;
         }
+        Ok(())
     }
 
-    Ok(result)
+    /// Add an included file
+    fn add_include(
+        &mut self,
+        path: &str,
+        limits: &Limits,
+        dialect: &Dialect,
+        includes: &mut Includes<'_>,
+        depth: usize,
+    ) -> Result<(), SourceLoaderError> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(SourceLoaderError::IncludeDepth(path.into()));
+        }
+        let mut reader = includes
+            .open(path)
+            .map_err(|err| SourceLoaderError::Include(path.into(), err))?;
+        self.add_file(&mut reader, limits, dialect, includes, depth)
+    }
+
+    /// Parse and add an INI file, returning the values of any `include_key`
+    /// found. Lines that fail to parse are an error, unless `skipped` is
+    /// given to collect them in.
+    fn add_input(
+        &mut self,
+        input: &str,
+        dialect: &Dialect,
+        include_key: Option<&str>,
+        mut skipped: Option<&mut Vec<String>>,
+    ) -> Result<Vec<String>, SourceLoaderError> {
+        let mut found = Vec::new();
+        let mut cur_section = crate::OUTSIDE_SECTION.to_string();
+        self.section_headers
+            .entry(cur_section.clone())
+            .or_insert_with(|| cur_section.clone());
+
+        for item in dialect.parse(input).items() {
+            match item {
+                ini_roundtrip::Item::Error(err) => match skipped {
+                    Some(ref mut skipped) => skipped.push(err.into()),
+                    None => return Err(SourceLoaderError::Parse(err.into())),
+                },
+                ini_roundtrip::Item::Section { name, raw } => {
+                    let name = if dialect.folds_section_case() {
+                        self.folded_sections
+                            .entry(name.to_lowercase())
+                            .or_insert_with(|| name.to_string())
+                            .as_str()
+                    } else {
+                        name
+                    };
+                    self.section_headers
+                        .insert(name.to_string(), raw.to_string());
+                    cur_section.clear();
+                    cur_section.push_str(name);
+                }
+                ini_roundtrip::Item::SectionEnd => (),
+                ini_roundtrip::Item::Property { key, val, raw } => {
+                    if let (Some(include_key), Some(path)) = (include_key, val) {
+                        if key == include_key {
+                            found.push(path.to_string());
+                        }
+                    }
+                    let order = self.key_order.entry(cur_section.clone()).or_default();
+                    if !order.positions.contains_key(key) {
+                        order.positions.insert(key.to_string(), order.keys.len());
+                        order.keys.push(key.to_string());
+                    }
+                    self.values.insert(
+                        SectionAndKey(cur_section.clone().into(), key.to_string().into()),
+                        SourceValue::new(raw.to_string(), val.map(str::to_string)),
+                    );
+                }
+                ini_roundtrip::Item::Comment { raw: _ } => (),
+                ini_roundtrip::Item::Blank { raw: _ } => (),
+            }
+        }
+
+        Ok(found)
+    }
 }
 
 #[cfg(test)]
@@ -159,7 +342,9 @@ mod tests {
     #[test]
     fn load_basic_ini() {
         let mut mut_data: VecDeque<_> = TEST_DATA.as_bytes().to_owned().into();
-        let result = super::load_source_ini(&mut mut_data).unwrap();
+        let result =
+            super::load_source_ini(&mut mut_data, &Default::default(), &Default::default())
+                .unwrap();
 
         assert_eq!(result.section_headers.len(), 3);
         assert_eq!(
@@ -0,0 +1,128 @@
+//! Helpers for testing rule sets (requires the `test-util` feature)
+//!
+//! ```
+//! use ini_merge::assert_merge;
+//! use ini_merge::merge::mutations::SectionAction;
+//!
+//! assert_merge!(
+//!     "[a]\nk=source\n",
+//!     "[a]\nk=target\n",
+//!     |rules| {
+//!         rules.add_section_action("a", SectionAction::Ignore);
+//!     },
+//!     "[a]\nk=target\n"
+//! );
+//! ```
+//!
+//! All helpers panic on errors, as is appropriate in tests.
+
+use crate::filter::filter_ini_from_str;
+use crate::filter::FilterActionsBuilder;
+use crate::merge::merge_ini_from_str;
+use crate::merge::mutations::MutationsBuilder;
+use crate::output::join_lines;
+use crate::TrailingNewline;
+
+/// Merge `source` into `target` with rules set up by `configure`, returning
+/// the result as a string (keeping the trailing newline of the target).
+#[must_use]
+pub fn merge_str(
+    source: &str,
+    target: &str,
+    configure: impl FnOnce(&mut MutationsBuilder),
+) -> String {
+    let mut builder = MutationsBuilder::new();
+    configure(&mut builder);
+    let mutations = builder.build().expect("Failed to build mutations");
+    let lines = merge_ini_from_str(target, source, &mutations).expect("Failed to merge");
+    join_lines(lines, target, TrailingNewline::Preserve)
+}
+
+/// Filter `input` with rules set up by `configure`, returning the result as
+/// a string (keeping the trailing newline of the input).
+#[must_use]
+pub fn filter_str(input: &str, configure: impl FnOnce(&mut FilterActionsBuilder)) -> String {
+    let mut builder = FilterActionsBuilder::new();
+    configure(&mut builder);
+    let actions = builder.build().expect("Failed to build filter actions");
+    let lines = filter_ini_from_str(input, &actions).expect("Failed to filter");
+    join_lines(lines, input, TrailingNewline::Preserve)
+}
+
+/// Assert that merging `source` into `target` with the rules set up by the
+/// closure gives `expected`. See [`merge_str`].
+#[macro_export]
+macro_rules! assert_merge {
+    ($source:expr, $target:expr, $configure:expr, $expected:expr $(,)?) => {
+        ::std::assert_eq!(
+            $crate::test_util::merge_str($source, $target, $configure),
+            $expected
+        )
+    };
+}
+
+/// Assert that filtering `input` with the rules set up by the closure gives
+/// `expected`. See [`filter_str`].
+#[macro_export]
+macro_rules! assert_filter {
+    ($input:expr, $configure:expr, $expected:expr $(,)?) => {
+        ::std::assert_eq!($crate::test_util::filter_str($input, $configure), $expected)
+    };
+}
+
+/// Serialize a rule set to JSON and back, asserting that it serializes the
+/// same way the second time. Returns the deserialized rule set.
+///
+/// Requires the `serde` feature as well.
+#[cfg(feature = "serde")]
+pub fn roundtrip_ruleset<T>(ruleset: &T) -> T
+where
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    let json = serde_json::to_string(ruleset).expect("Failed to serialize rule set");
+    let result: T = serde_json::from_str(&json).expect("Failed to deserialize rule set");
+    let again = serde_json::to_string(&result).expect("Failed to serialize rule set");
+    assert_eq!(json, again, "Rule set changed when round tripped");
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filter::FilterAction;
+    use crate::merge::mutations::Action;
+
+    #[test]
+    fn helpers() {
+        assert_merge!(
+            "[a]\nk=1\nd=1\n",
+            "[a]\nk=2\nd=2\n\n",
+            |rules| {
+                rules.add_literal_action("a", "d", Action::Delete);
+            },
+            "[a]\nk=1\n\n",
+        );
+        assert_filter!(
+            "[a]\nk=1\nd=1",
+            |rules| {
+                rules.add_literal_action("a", "d", FilterAction::Remove);
+            },
+            "[a]\nk=1",
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn roundtrip() {
+        use crate::merge::mutations::MutationRule;
+        use crate::merge::mutations::MutationsRuleSet;
+
+        let ruleset = MutationsRuleSet::new(vec![MutationRule::Set {
+            section: "a".into(),
+            key: "b".into(),
+            value: "c".into(),
+            separator: "=".into(),
+        }]);
+        let result = super::roundtrip_ruleset(&ruleset);
+        assert_eq!(result.rules.len(), 1);
+    }
+}